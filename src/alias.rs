@@ -0,0 +1,80 @@
+use std::{fs, path::Path};
+
+/// Whether the two paths name the same file, like `a.png` and `./dir/../a.png` or a
+/// symbolic link and its target. A path that does not exist yet names no file.
+pub fn same_file(first: &Path, second: &Path) -> bool {
+
+    let identical = match (fs::metadata(first), fs::metadata(second)) {
+        (Ok(first), Ok(second)) => same_identity(&first, &second),
+        _ => false,
+    };
+
+    identical || canonical_match(first, second)
+}
+
+/// The same device and inode, which also catches hard links
+#[cfg(unix)]
+fn same_identity(first: &fs::Metadata, second: &fs::Metadata) -> bool {
+
+    use std::os::unix::fs::MetadataExt;
+
+    first.dev() == second.dev() && first.ino() == second.ino()
+}
+
+#[cfg(not(unix))]
+fn same_identity(_first: &fs::Metadata, _second: &fs::Metadata) -> bool {
+
+    false
+}
+
+fn canonical_match(first: &Path, second: &Path) -> bool {
+
+    match (fs::canonicalize(first), fs::canonicalize(second)) {
+        (Ok(first), Ok(second)) => first == second,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> std::path::PathBuf {
+        let directory = std::env::temp_dir().join(format!("pngme-test-{}-{}", std::process::id(), name));
+        fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    #[test]
+    fn test_relative_alias() {
+        let directory = temp_dir("alias-relative");
+        let file = directory.join("a.png");
+        fs::write(&file, b"png").unwrap();
+
+        let alias = directory.join("..").join(directory.file_name().unwrap()).join(".").join("a.png");
+        let other = directory.join("b.png");
+        fs::write(&other, b"png").unwrap();
+
+        let results = (same_file(&file, &alias), same_file(&file, &other), same_file(&file, &directory.join("new.png")));
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(results, (true, false, false));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlinked_alias() {
+        let directory = temp_dir("alias-symlink");
+        let file = directory.join("a.png");
+        let link = directory.join("link.png");
+        let hard_link = directory.join("hard.png");
+        fs::write(&file, b"png").unwrap();
+        std::os::unix::fs::symlink(&file, &link).unwrap();
+        fs::hard_link(&file, &hard_link).unwrap();
+
+        let results = (same_file(&file, &link), same_file(&link, &hard_link));
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(results, (true, true));
+    }
+}
@@ -1,6 +1,9 @@
-use std::path::PathBuf;
+use std::{path::PathBuf, time::Duration};
 
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use crc::{Crc, CRC_32_BZIP2, CRC_32_ISO_HDLC, CRC_32_JAMCRC};
+
+use crate::{detect::Layer, filter::ChunkFilter, history::{Recording, DEFAULT_HISTORY_LIMIT}, inspect, png::{CrcSkip, Duplicates, Keep}, retry::{self, Retry}, size};
 
 /// An image message encoder CLI program for PNG files
 #[derive(Debug, Parser)]
@@ -9,6 +12,62 @@ use clap::{Parser, Subcommand};
 pub struct Cli {
     #[command(subcommand)]
     pub command: Commands,
+    /// The crc algorithm used to verify and generate chunk crcs, for PNG-like formats
+    /// that do not follow the specification
+    #[arg(long, global = true, value_enum, default_value_t = CrcAlgorithm::IsoHdlc)]
+    pub crc_algo: CrcAlgorithm,
+    /// Record changes in the history log of the file
+    #[arg(long, global = true)]
+    pub history: bool,
+    /// Largest size of the history log in bytes, the oldest entries are dropped past it
+    #[arg(long, global = true, value_name = "BYTES", default_value_t = DEFAULT_HISTORY_LIMIT)]
+    pub history_limit: usize,
+    /// Print where the time went afterwards: bytes read, chunks parsed, time spent parsing,
+    /// checking crcs, compressing or encrypting, and writing
+    #[arg(long, global = true)]
+    pub explain: bool,
+    /// Try again this many times to write a file another program holds open, like an
+    /// image viewer on Windows
+    #[arg(long, global = true, value_name = "N", default_value_t = 0)]
+    pub retry: u32,
+    /// How long to wait before the first retry, doubled after every attempt
+    #[arg(long, global = true, value_name = "DELAY", value_parser = retry::parse_delay, default_value = "200ms")]
+    pub retry_delay: Duration,
+}
+
+impl Cli {
+    pub fn recording(&self) -> Recording {
+
+        Recording { enabled: self.history, limit: self.history_limit }
+    }
+
+    pub fn retry(&self) -> Retry {
+
+        Retry { attempts: self.retry, delay: self.retry_delay }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum CrcAlgorithm {
+    /// The algorithm of the PNG specification
+    IsoHdlc,
+    Bzip2,
+    Jamcrc,
+}
+
+impl CrcAlgorithm {
+    pub fn crc(&self) -> &'static Crc<u32> {
+
+        static ISO_HDLC: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+        static BZIP2: Crc<u32> = Crc::<u32>::new(&CRC_32_BZIP2);
+        static JAMCRC: Crc<u32> = Crc::<u32>::new(&CRC_32_JAMCRC);
+
+        match self {
+            CrcAlgorithm::IsoHdlc => &ISO_HDLC,
+            CrcAlgorithm::Bzip2 => &BZIP2,
+            CrcAlgorithm::Jamcrc => &JAMCRC,
+        }
+    }
 }
 
 #[derive(Debug, Subcommand)]
@@ -19,11 +78,80 @@ pub enum Commands {
         /// Path to the PNG file
         file_path: PathBuf,
         /// The type of the chunk
-        chunk_type: String,
+        #[arg(required_unless_present = "raw_chunk")]
+        chunk_type: Option<String>,
         /// The message to encode in the PNG file
-        message: String,
+        #[arg(required_unless_present_any = ["raw_chunk", "template", "from_clipboard", "embed_file"])]
+        message: Option<String>,
         /// The output file
-        output_file: Option<PathBuf>
+        output_file: Option<PathBuf>,
+        /// Append a complete chunk (length, type, data and crc) read from this file
+        /// instead of building one from a chunk type and a message
+        #[arg(long, value_name = "FILE", conflicts_with_all = ["chunk_type", "message"])]
+        raw_chunk: Option<PathBuf>,
+        /// Build the message from a template: {date}, {hostname}, {env:NAME}, {file} and
+        /// {basename} are expanded, {{ and }} are literal braces
+        #[arg(long, conflicts_with_all = ["message", "raw_chunk"])]
+        template: Option<String>,
+        /// Take the message from the system clipboard, so that it never shows up in the
+        /// shell history (needs the `clipboard` feature)
+        #[arg(long, conflicts_with_all = ["message", "raw_chunk", "template"])]
+        from_clipboard: bool,
+        /// Hide this file, along with its name and MIME type, for `decode --extract-file`
+        #[arg(long, value_name = "FILE", conflicts_with_all = ["message", "raw_chunk", "template", "from_clipboard"])]
+        embed_file: Option<PathBuf>,
+        /// Read the message as hex digits (whitespace is ignored), for keys and hashes
+        #[arg(long, requires = "message")]
+        hex: bool,
+        /// Record the change in the file so that `undo` can revert it
+        #[arg(long)]
+        undoable: bool,
+        /// Uppercase the third letter of a chunk type whose reserved bit is set, so that
+        /// the chunk is valid for the PNG specification
+        #[arg(long, conflicts_with = "raw_chunk")]
+        fix_type: bool,
+        /// Refuse, instead of only warning about, a chunk type of the PNG specification
+        /// such as IDAT, whose messages can corrupt the image
+        #[arg(long)]
+        strict_type: bool,
+        /// Refuse to add a chunk of a type the file already has, so that encode never duplicates one
+        #[arg(long)]
+        append_only: bool,
+        /// Insert the chunk right after IHDR instead of at the end, for viewers that only
+        /// read the first few ancillary chunks
+        #[arg(long, conflicts_with = "undoable")]
+        prepend: bool,
+        /// Refuse payloads larger than this, in bytes or with a K, M or G suffix (10K, 2M)
+        #[arg(long, value_name = "SIZE", value_parser = size::parse, conflicts_with = "raw_chunk")]
+        max_size: Option<u64>,
+        /// Refuse to write a file larger than this, in bytes or with a K, M or G suffix, so that
+        /// a huge payload appended by mistake never reaches the disk
+        #[arg(long, value_name = "SIZE", value_parser = size::parse)]
+        limit_output_bytes: Option<u64>,
+        /// Spread the message over chunks of the type holding at most this many bytes each,
+        /// numbered so that `decode --join` puts them back together
+        #[arg(long, value_name = "BYTES", value_parser = clap::value_parser!(u32).range(1..), conflicts_with_all = ["raw_chunk", "undoable"])]
+        split: Option<u32>,
+        /// Report the payload size, the bytes added to the file and its size before and after
+        #[arg(long, short)]
+        verbose: bool,
+        /// Print the size report as JSON instead of the usual message
+        #[arg(long)]
+        json: bool,
+        /// Show each chunk about to be added (type, length, crc and the start of its data)
+        /// before writing the file
+        #[arg(long, conflicts_with = "json")]
+        preview: bool,
+        #[command(flatten)]
+        hiding: HidingOptions,
+        #[command(flatten)]
+        chaff: ChaffOptions,
+        /// Encrypt the message with ChaCha20-Poly1305, under the key of --keyfile or one
+        /// derived from a passphrase
+        #[arg(long, requires = "key_source", conflicts_with = "raw_chunk")]
+        encrypt: bool,
+        #[command(flatten)]
+        key: KeyOptions,
     },
 
     /// Decodes the message in the PNG file
@@ -31,17 +159,409 @@ pub enum Commands {
     Decode {
         /// Path to the PNG file
         file_path: PathBuf,
-        /// The type of the chunk
-        chunk_type: String,
+        /// The type of the chunk, the one of the last encode when left out
+        chunk_type: Option<String>,
+        /// Write the raw message to this file instead of printing it
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+        #[command(flatten)]
+        hiding: HidingOptions,
+        #[command(flatten)]
+        display: DisplayOptions,
+        #[command(flatten)]
+        payload: PayloadOptions,
+        /// Reassemble a message spread over several chunks with `encode --split`
+        #[arg(long)]
+        join: bool,
+        /// Recreate the file hidden with `encode --embed-file` under its original name
+        /// in the current directory, or at --output
+        #[arg(long)]
+        extract_file: bool,
+        #[command(flatten)]
+        key: KeyOptions,
+        /// When there is no chunk of the type, say only that, without listing the private chunks there are
+        #[arg(long, short)]
+        quiet: bool,
+        /// When there is no chunk of the type, print the private chunks there are as JSON
+        #[arg(long, conflicts_with = "quiet")]
+        json: bool,
     },
 
     /// Removes the message in the PNG file
     #[command(arg_required_else_help = true)]
     Remove {
+        /// Path to the PNG file
+        file_path: PathBuf,
+        /// The type of the chunk, the one of the last encode when left out
+        chunk_type: Option<String>,
+        /// Succeed without changes when there is no chunk of this type
+        #[arg(long)]
+        ignore_missing: bool,
+        /// Show the message being removed, the same way decode does
+        #[arg(long)]
+        print: bool,
+        /// Write the raw removed message to this file (with --print)
+        #[arg(long, short, requires = "print")]
+        output: Option<PathBuf>,
+        /// Record the change in the file so that `undo` can revert it
+        #[arg(long)]
+        undoable: bool,
+        /// Overwrite the data and crc of the chunk with zeroes in the original file before
+        /// replacing it. This is best effort: copy-on-write filesystems (btrfs, ZFS, APFS),
+        /// SSDs, snapshots and backups may still keep the old bytes
+        #[arg(long, conflicts_with = "undoable")]
+        shred: bool,
+    },
+
+    /// Reverts the most recent change made with --undoable
+    #[command(arg_required_else_help = true)]
+    Undo {
+        /// Path to the PNG file
+        file_path: PathBuf,
+        /// Revert every recorded change, most recent first
+        #[arg(long)]
+        all: bool,
+    },
+
+    /// Prints the history log of the changes made to the PNG file
+    #[command(arg_required_else_help = true)]
+    AuditLog {
+        /// Path to the PNG file
+        file_path: PathBuf,
+        /// Remove the history log from the file
+        #[arg(long)]
+        clear: bool,
+    },
+
+    /// Keeps or drops chunks of the PNG file by type or class
+    #[command(arg_required_else_help = true)]
+    Filter {
+        /// Path to the PNG file
+        file_path: PathBuf,
+        /// Keep only these chunks, a comma separated list of chunk types and classes
+        /// (critical, ancillary, private, standard)
+        #[arg(long, value_name = "TYPES", value_delimiter = ',', required_unless_present = "drop_types", conflicts_with = "drop_types")]
+        keep_types: Vec<ChunkFilter>,
+        /// Drop these chunks, a comma separated list of chunk types and classes
+        #[arg(long, value_name = "TYPES", value_delimiter = ',')]
+        drop_types: Vec<ChunkFilter>,
+        /// Write the filtered file here instead of changing the file in place
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+        /// Allow critical chunks (IHDR, PLTE, IDAT, IEND) to be dropped
+        #[arg(long)]
+        allow_critical: bool,
+    },
+
+    /// Overwrites the data of an existing chunk, keeping its position in the file
+    #[command(arg_required_else_help = true)]
+    SetData {
+        /// Path to the PNG file
+        file_path: PathBuf,
+        /// The type of the chunk
+        chunk_type: String,
+        /// Which chunk of that type to change, counting from 0
+        #[arg(long, default_value_t = 0)]
+        index: usize,
+        /// Read the new data from this file
+        #[arg(long, value_name = "FILE", required_unless_present = "data", conflicts_with = "data")]
+        data_file: Option<PathBuf>,
+        /// The new data, as a string
+        #[arg(long)]
+        data: Option<String>,
+        /// Allow the data of critical chunks (IHDR, PLTE, IDAT, IEND) to be changed
+        #[arg(long)]
+        allow_critical: bool,
+    },
+
+    /// Opens the text of a chunk in $VISUAL or $EDITOR and saves the edited text back
+    /// into the same chunk
+    #[command(arg_required_else_help = true)]
+    Edit {
         /// Path to the PNG file
         file_path: PathBuf,
         /// The type of the chunk
         chunk_type: String,
+        /// Start from an empty chunk, added before IEND, when the file has none of this type
+        #[arg(long)]
+        create: bool,
+    },
+
+    /// Generates a key for `encode --encrypt --keyfile`
+    #[command(arg_required_else_help = true)]
+    Keygen {
+        /// A 32-byte key for ChaCha20-Poly1305, the only kind there is so far
+        #[arg(long, required = true)]
+        symmetric: bool,
+        /// Where to write the key. It is created readable by its owner only and never overwritten
+        #[arg(long, short)]
+        output: PathBuf,
+    },
+
+    /// Writes a complete chunk (length, type, data and crc) as it is stored in the file,
+    /// ready for `encode --raw-chunk`
+    #[command(arg_required_else_help = true)]
+    CatChunk {
+        /// Path to the PNG file
+        file_path: PathBuf,
+        /// The type of the chunk
+        chunk_type: String,
+        /// Which chunk of that type to write, counting from 0
+        #[arg(long, default_value_t = 0, conflicts_with = "all")]
+        index: usize,
+        /// Write every chunk of that type one after the other, in file order
+        #[arg(long)]
+        all: bool,
+        /// Write the chunk to this file instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Write the binary chunk even when stdout is a terminal
+        #[arg(long, conflicts_with = "output")]
+        force_tty: bool,
+    },
+
+    /// Removes empty and duplicate ancillary chunks from the PNG file
+    #[command(arg_required_else_help = true)]
+    Optimize {
+        /// Path to the PNG file
+        file_path: PathBuf,
+        /// Write the optimized file here instead of changing the file in place
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+        /// Show the chunks that would be removed without writing anything
+        #[arg(long)]
+        dry_run: bool,
+        /// Also remove the decoy chunks added with `encode --chaff --chaff-key SECRET`
+        #[arg(long, value_name = "SECRET")]
+        drop_chaff: Option<String>,
+    },
+
+    /// Moves the ancillary chunks of the PNG file to canonical positions, leaving their data untouched
+    #[command(arg_required_else_help = true)]
+    Normalize {
+        /// Path to the PNG file
+        file_path: PathBuf,
+        /// Write the normalized file here instead of changing the file in place
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+
+    /// Copies the ancillary chunks of another PNG file that are safe to copy into the PNG file
+    #[command(arg_required_else_help = true)]
+    CopyChunks {
+        /// The PNG file to copy the chunks from
+        from: PathBuf,
+        /// Path to the PNG file to copy them into
+        file_path: PathBuf,
+        /// What to do with a chunk the file already has (the same type, or the same
+        /// keyword for text chunks)
+        #[arg(long, value_enum, default_value_t = Duplicates::Skip)]
+        on_duplicate: Duplicates,
+        /// Also copy the tEXt, zTXt and iTXt chunks
+        #[arg(long)]
+        include_text: bool,
+        /// Write the merged file here instead of changing the file in place
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+
+    /// Spreads a payload over several PNG files, one part in each, for `join-files`
+    #[command(arg_required_else_help = true)]
+    SplitFiles {
+        /// The file holding the payload
+        payload: PathBuf,
+        /// The PNG files to hide the parts in, changed in place
+        #[arg(required = true)]
+        covers: Vec<PathBuf>,
+        /// The type of the chunks holding the parts
+        #[arg(long = "type", value_name = "TYPE", default_value = "pmSh")]
+        chunk_type: String,
+    },
+
+    /// Puts a payload spread with `split-files` back together, from its files in any order
+    #[command(arg_required_else_help = true)]
+    JoinFiles {
+        /// The PNG files holding the parts
+        #[arg(required = true)]
+        parts: Vec<PathBuf>,
+        /// Where to write the payload
+        #[arg(long, short)]
+        output: PathBuf,
+        /// The type of the chunks holding the parts
+        #[arg(long = "type", value_name = "TYPE", default_value = "pmSh")]
+        chunk_type: String,
+    },
+
+    /// Removes ancillary chunks with the same type and data as another
+    #[command(arg_required_else_help = true)]
+    Dedupe {
+        /// Path to the PNG file
+        file_path: PathBuf,
+        /// Which of the identical chunks survives
+        #[arg(long, value_enum, default_value_t = Keep::First)]
+        keep: Keep,
+        /// Write the deduplicated file here instead of changing the file in place
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+
+    /// Reads a PNG file from stdin, changes it and writes it to stdout, for use in pipelines
+    #[command(arg_required_else_help = true)]
+    Transform {
+        /// Remove every chunk of this type. Repeat to remove several types
+        #[arg(long, value_name = "TYPE", required = true)]
+        remove: Vec<String>,
+    },
+
+    /// Manages the passphrases stored in the OS keychain with --use-keyring
+    Keyring {
+        #[command(subcommand)]
+        action: KeyringAction,
+    },
+
+    /// Strips chunks from PNG files as git stores them and puts them back on checkout,
+    /// to be set up as a clean and smudge filter in .gitattributes
+    GitFilter {
+        #[command(subcommand)]
+        mode: GitFilterMode,
+    },
+
+    /// Summarizes the eXIf chunk of the PNG file
+    #[command(arg_required_else_help = true)]
+    Exif {
+        /// Path to the PNG file
+        file_path: PathBuf,
+        /// Write the raw TIFF-format eXIf data to this file
+        #[arg(long, value_name = "FILE")]
+        dump: Option<PathBuf>,
+    },
+
+    /// Records the type, length and crc of every chunk of the PNG file, to verify it against later
+    #[command(arg_required_else_help = true)]
+    Baseline {
+        /// Path to the PNG file
+        file_path: PathBuf,
+        /// Write the baseline to this file instead of printing it
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+
+    /// Reports the chunks added, removed or modified since a baseline was recorded
+    #[command(arg_required_else_help = true)]
+    VerifyAgainst {
+        /// Path to the PNG file
+        file_path: PathBuf,
+        /// Baseline written by the `baseline` command
+        baseline: PathBuf,
+    },
+
+    /// Estimates how many bytes of message the PNG file can hide
+    #[command(arg_required_else_help = true)]
+    Capacity {
+        /// Path to the PNG file
+        file_path: PathBuf,
+        #[command(flatten)]
+        hiding: HidingOptions,
+    },
+
+    /// Checks the crc and the type of every chunk of the PNG file
+    #[command(arg_required_else_help = true)]
+    Validate {
+        /// Path to the PNG file
+        file_path: PathBuf,
+        /// Also check that the chunks appear in the order the PNG specification requires
+        #[arg(long)]
+        order_check: bool,
+        /// Only print how many problems were found, like "3 problems"
+        #[arg(long)]
+        count_only: bool,
+    },
+
+    /// Checks the crc of every chunk, on several threads for large files
+    #[command(arg_required_else_help = true)]
+    Verify {
+        /// Path to the PNG file
+        file_path: PathBuf,
+        /// How many threads check crcs, all available cores by default. 1 checks
+        /// the chunks one after the other
+        #[arg(long, value_name = "N", value_parser = clap::value_parser!(u16).range(1..))]
+        threads: Option<u16>,
+        /// Also decompress the image data and check that it holds the scanlines IHDR
+        /// declares, which crcs alone cannot prove
+        #[arg(long)]
+        deep: bool,
+        /// Print every chunk with the result of its crc check in this format, instead
+        /// of the damaged chunks only. The summary then goes to stderr
+        #[arg(long, value_enum, default_value_t = VerifyFormat::Text)]
+        format: VerifyFormat,
+    },
+
+    /// Fails when a PNG file under a directory holds chunks a policy denies, for CI
+    #[command(arg_required_else_help = true)]
+    CheckTree {
+        /// The directory to scan recursively. A pngme-policy.toml at its root adds its rules
+        directory: PathBuf,
+        /// Deny chunks matching this rule: a chunk type, type:XXXX, a class (critical,
+        /// ancillary, private, standard) or text-keyword:KEYWORD. Repeat for several rules
+        #[arg(long, value_name = "RULE")]
+        deny: Vec<ChunkFilter>,
+        /// Allow chunks matching this rule even when a deny rule matches them
+        #[arg(long, value_name = "RULE")]
+        allow: Vec<ChunkFilter>,
+        /// Also check the PNG files inside ZIP based archives (.zip, .apk, .jar, .docx, ...)
+        /// without extracting them. A single file given with --archive is always read as one
+        #[arg(long)]
+        archive: bool,
+    },
+
+    /// Prints the standard chunk types with what they hold and their flags
+    Types,
+
+    /// Converts a BMP, JPEG, GIF or WEBP image to PNG, optionally encoding a message in it.
+    /// Needs a build with `--features image`
+    #[command(arg_required_else_help = true)]
+    Convert {
+        /// Path to the image to convert
+        input: PathBuf,
+        /// The PNG file to write
+        #[arg(long, short)]
+        output: PathBuf,
+        /// Encode a message in a chunk of this type in the same pass
+        #[arg(long, num_args = 2, value_names = ["TYPE", "MESSAGE"])]
+        encode: Option<Vec<String>>,
+    },
+
+    /// Tells the format of a file (PNG, JPEG, GIF, WEBP or BMP) from its first bytes
+    #[command(arg_required_else_help = true)]
+    Identify {
+        /// Path to the file, which does not have to be a PNG
+        file_path: PathBuf,
+    },
+
+    /// Tells which chunk, and which field of it, a byte offset of the PNG file falls in
+    #[command(arg_required_else_help = true)]
+    Inspect {
+        /// Path to the PNG file
+        file_path: PathBuf,
+        /// The offset, in decimal or in hex with a 0x prefix, as pngcheck and viewers report them
+        #[arg(long, value_parser = inspect::parse_offset)]
+        offset: u64,
+        /// Print the location as JSON, without the hexdump
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Prints how many chunks of a type the PNG file holds
+    #[command(arg_required_else_help = true)]
+    Count {
+        /// Path to the PNG file
+        file_path: PathBuf,
+        /// The type of the chunks to count
+        chunk_type: String,
+        /// Print the bytes of data the chunks hold in total instead, the size of the
+        /// payload they carry without their length, type and crc
+        #[arg(long)]
+        bytes: bool,
     },
 
     /// Prints the PNG file
@@ -49,5 +569,261 @@ pub enum Commands {
     Print {
         /// Path to the PNG file
         file_path: PathBuf,
+        /// Print every chunk on its own line, prefixed with its byte offset in the file
+        #[arg(long)]
+        offsets: bool,
+        /// Print the chunks from last to first, keeping their offsets in the file
+        #[arg(long)]
+        reverse: bool,
+        #[command(flatten)]
+        skip: CrcSkipOptions,
+    },
+
+    /// Applies the operations listed in a manifest file to the PNG file, writing it once
+    #[command(arg_required_else_help = true)]
+    Apply {
+        /// Path to the PNG file
+        file_path: PathBuf,
+        /// YAML list of operations (encode, remove, text-set, strip, rename) to apply in order
+        #[arg(long, value_name = "FILE")]
+        manifest: PathBuf,
+        /// Write the result to this file instead of the PNG file
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+        /// Show what every operation would do without writing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Lists the chunks in the PNG file
+    #[command(arg_required_else_help = true)]
+    List {
+        /// Path to the PNG file
+        file_path: PathBuf,
+        #[command(flatten)]
+        options: ListOptions,
+    }
+}
+
+#[derive(Debug, Default, Args)]
+pub struct ListOptions {
+    /// Show the stored and the recomputed crc of every chunk and flag mismatches
+    #[arg(long)]
+    pub compare_crc: bool,
+    /// Only show chunks that are critical, ancillary, private, standard or type:XXXX.
+    /// Repeat to show chunks matching any of the filters
+    #[arg(long = "filter", value_name = "FILTER")]
+    pub filters: Vec<ChunkFilter>,
+    /// Only show chunks holding at least this many bytes of data
+    #[arg(long, value_name = "BYTES", alias = "min-chunk-data")]
+    pub min_data: Option<u32>,
+    /// Only show chunks holding at most this many bytes of data
+    #[arg(long, value_name = "BYTES", alias = "max-chunk-data")]
+    pub max_data: Option<u32>,
+    /// Order the chunks by this key instead of their offset in the file
+    #[arg(long, value_enum)]
+    pub sort: Option<SortKey>,
+    /// Reverse the order of the chunks
+    #[arg(long)]
+    pub reverse: bool,
+    /// Only show the N largest chunks
+    #[arg(long, value_name = "N")]
+    pub top: Option<usize>,
+    /// Print the chunks in this format
+    #[arg(long, value_enum, default_value_t = OutputFormat::Table)]
+    pub format: OutputFormat,
+    /// Print the chunks as JSON, same as --format json
+    #[arg(long, conflicts_with = "format")]
+    pub json: bool,
+    /// Show the first N bytes of every chunk's data as ASCII, like a hexdump's right column
+    #[arg(long, value_name = "N", num_args = 0..=1, default_missing_value = "32")]
+    pub preview: Option<usize>,
+    /// Show the Shannon entropy of every chunk's data in bits per byte. Close to 8 hints at
+    /// encrypted or compressed data, which is suspicious in an ancillary chunk
+    #[arg(long)]
+    pub entropy: bool,
+    /// Also preview IDAT chunks, which are skipped by default
+    #[arg(long, requires = "preview")]
+    pub include_idat: bool,
+    /// Show the total bytes taken by each chunk type instead of every chunk
+    #[arg(long, conflicts_with_all = ["compare_crc", "preview"])]
+    pub sizes: bool,
+    #[command(flatten)]
+    pub skip: CrcSkipOptions,
+}
+
+impl ListOptions {
+    pub fn output_format(&self) -> OutputFormat {
+
+        if self.json { OutputFormat::Json } else { self.format }
+    }
+}
+
+#[derive(Debug, Default, Args)]
+pub struct CrcSkipOptions {
+    /// Do not check the crc of IDAT chunks, which takes time on large images
+    #[arg(long)]
+    pub skip_idat_crc: bool,
+    /// Do not check the crc of any critical chunk (IHDR, PLTE, IDAT, IEND)
+    #[arg(long, conflicts_with = "skip_idat_crc")]
+    pub skip_critical_crc: bool,
+}
+
+impl CrcSkipOptions {
+    pub fn crc_skip(&self) -> CrcSkip {
+
+        if self.skip_critical_crc {
+            CrcSkip::Critical
+        } else if self.skip_idat_crc {
+            CrcSkip::Idat
+        } else {
+            CrcSkip::Nothing
+        }
     }
+}
+
+#[derive(Debug, Subcommand)]
+pub enum KeyringAction {
+    /// Removes the passphrase stored for this service name
+    Forget {
+        service: String,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum GitFilterMode {
+    /// Reads a PNG file on stdin and writes it to stdout without the selected ancillary chunks
+    Clean {
+        /// Strip these chunks, a comma separated list of chunk types and classes. Defaults
+        /// to the pngme.cleanTypes git config
+        #[arg(long, value_name = "TYPES", value_delimiter = ',')]
+        types: Vec<ChunkFilter>,
+        /// Save the stripped chunks to this file, for `smudge --restore-from`
+        #[arg(long, value_name = "FILE")]
+        save_to: Option<PathBuf>,
+    },
+    /// Reads a PNG file on stdin and writes it to stdout unchanged, or with the chunks
+    /// saved by `clean` put back before IEND
+    Smudge {
+        /// The file `clean --save-to` wrote. A missing file restores nothing
+        #[arg(long, value_name = "FILE")]
+        restore_from: Option<PathBuf>,
+    },
+}
+
+/// Decoy chunks added next to the message, so that it does not stand out as the
+/// only private chunk of the file
+#[derive(Debug, Default, Args)]
+pub struct ChaffOptions {
+    /// Add this many decoy chunks of random private types and data before IEND, and
+    /// put the message among them. `optimize --drop-chaff` removes them again
+    #[arg(long, value_name = "COUNT", default_value_t = 0, requires = "chaff_key")]
+    pub chaff: u16,
+    /// The secret the decoys are tagged with. Without it they cannot be told apart
+    /// from the message, so `optimize --drop-chaff` needs the same one
+    #[arg(long, value_name = "SECRET")]
+    pub chaff_key: Option<String>,
+    /// Seed the random decoys, so that the same file comes out every time
+    #[arg(long, hide = true, requires = "chaff")]
+    pub seed: Option<u64>,
+}
+
+/// Where the key of an encrypted message comes from
+#[derive(Debug, Default, Args)]
+pub struct KeyOptions {
+    /// A file of 32 random bytes, made with `keygen --symmetric`, used as the key
+    #[arg(long, value_name = "FILE", group = "key_source")]
+    pub keyfile: Option<PathBuf>,
+    /// Derive the key from a passphrase, asked for on the terminal
+    #[arg(long, group = "key_source")]
+    pub passphrase: bool,
+    /// Like --passphrase, but keep the passphrase in the OS keychain under this service
+    /// name, so that it is only asked for the first time (needs the `keyring` feature)
+    #[arg(long, value_name = "SERVICE", group = "key_source")]
+    pub use_keyring: Option<String>,
+    /// Use a keyfile that every user can read. Without this it is refused on Unix
+    #[arg(long, requires = "keyfile")]
+    pub allow_insecure_keyfile: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct HidingOptions {
+    /// Where the message is hidden. The lsb mode takes IDAT as the chunk type
+    #[arg(long, value_enum, default_value_t = HidingMode::Chunk)]
+    pub mode: HidingMode,
+    /// How many low bits of every color sample the lsb mode uses
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u8).range(1..=2))]
+    pub bits: u8,
+}
+
+/// How a message that is not written to a file gets printed. Binary data is
+/// never dumped raw to a terminal.
+#[derive(Debug, Default, Args)]
+pub struct DisplayOptions {
+    /// Print the message as lowercase hex, whatever it holds
+    #[arg(long, conflicts_with_all = ["output", "base64"])]
+    pub hex: bool,
+    /// Print the message as base64
+    #[arg(long, conflicts_with = "output")]
+    pub base64: bool,
+    /// Refuse to print binary data even when stdout is a pipe or a file
+    #[arg(long)]
+    pub ascii_only: bool,
+    /// Leave out the newline after the message, for command substitution in scripts
+    #[arg(long, conflicts_with = "output")]
+    pub no_newline: bool,
+}
+
+/// Transforms to reverse before the message is shown
+#[derive(Debug, Default, Args)]
+pub struct PayloadOptions {
+    /// Guess which transforms (hex, base64, zlib) the message is wrapped in, reverse them and list them
+    #[arg(long)]
+    pub detect: bool,
+    /// Reverse these transforms, outermost first, as listed by --detect
+    #[arg(long, value_enum, value_name = "LAYERS", value_delimiter = ',', conflicts_with = "detect")]
+    pub unwrap: Vec<Layer>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, ValueEnum)]
+pub enum HidingMode {
+    /// In a chunk of its own
+    #[default]
+    Chunk,
+    /// In the least significant bits of the pixels, inside the IDAT chunks
+    Lsb,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, ValueEnum)]
+pub enum OutputFormat {
+    /// Aligned columns for reading in a terminal
+    #[default]
+    Table,
+    Json,
+    /// Comma separated values with a header row
+    Csv,
+    /// JSON shaped like the output of `exiftool -j`, with PNG: and PNGME: tags
+    Exiftool,
+}
+
+/// How `verify` prints its findings
+#[derive(Debug, Default, Clone, Copy, PartialEq, ValueEnum)]
+pub enum VerifyFormat {
+    /// The damaged chunks and a summary
+    #[default]
+    Text,
+    /// An array of every chunk, with a crc_ok field
+    Json,
+    /// Comma separated values with the columns of `list --format csv`
+    Csv,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum SortKey {
+    /// The length of the chunk data, largest first
+    Size,
+    /// The chunk type, alphabetically
+    Type,
+    /// The position of the chunk in the file
+    Offset,
 }
\ No newline at end of file
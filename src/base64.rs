@@ -0,0 +1,86 @@
+use crate::Result;
+
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Encodes the bytes with the standard alphabet, padded with `=`
+pub fn encode(data: &[u8]) -> String {
+
+    let mut result = String::with_capacity(data.len().div_ceil(3) * 4);
+
+    for group in data.chunks(3) {
+        let bytes = [group[0], *group.get(1).unwrap_or(&0), *group.get(2).unwrap_or(&0)];
+        let bits = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+
+        for position in 0..4 {
+            if position <= group.len() {
+                result.push(ALPHABET[(bits >> (18 - 6 * position) & 0x3f) as usize] as char);
+            } else {
+                result.push('=');
+            }
+        }
+    }
+
+    result
+}
+
+/// Decodes padded base64 in the standard alphabet. Whitespace around the text is ignored.
+pub fn decode(text: &str) -> Result<Vec<u8>> {
+
+    let text = text.trim().as_bytes();
+
+    if !text.len().is_multiple_of(4) {
+        return Err("Base64 text is made of groups of 4 characters".into())
+    }
+
+    let mut result = Vec::with_capacity(text.len() / 4 * 3);
+
+    for (number, group) in text.chunks(4).enumerate() {
+        let padding = group.iter().rev().take_while(|character| **character == b'=').count();
+        let last = number == text.len() / 4 - 1;
+
+        if padding > 2 || (padding > 0 && !last) {
+            return Err("Misplaced '=' padding in base64 text".into())
+        }
+
+        let mut bits = 0u32;
+
+        for character in &group[..4 - padding] {
+            let value = ALPHABET.iter().position(|letter| letter == character)
+                .ok_or_else(|| format!("'{}' is not a base64 character", *character as char))?;
+
+            bits = bits << 6 | value as u32;
+        }
+
+        bits <<= 6 * padding;
+        result.extend(&bits.to_be_bytes()[1..4 - padding]);
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode() {
+        assert_eq!(encode(b""), "");
+        assert_eq!(encode(b"f"), "Zg==");
+        assert_eq!(encode(b"fo"), "Zm8=");
+        assert_eq!(encode(b"foo"), "Zm9v");
+        assert_eq!(encode(b"foobar"), "Zm9vYmFy");
+        assert_eq!(encode(&[0xff, 0xfe, 0x00]), "//4A");
+    }
+
+    #[test]
+    fn test_decode() {
+        for data in [&b""[..], b"f", b"fo", b"foo", b"foobar", &[0xff, 0xfe, 0x00]] {
+            assert_eq!(decode(&encode(data)).unwrap(), data);
+        }
+
+        assert_eq!(decode("Zm9v\n").unwrap(), b"foo");
+        assert!(decode("Zm9").is_err());
+        assert!(decode("Zg==Zm9v").is_err());
+        assert!(decode("Zm9*").is_err());
+    }
+}
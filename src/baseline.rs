@@ -0,0 +1,233 @@
+use std::{fmt, str::FromStr};
+
+use crate::{chunk::Chunk, chunk_type::ChunkType, json::{self, Value}, png::Png, sha256, Result};
+
+/// Written to every baseline, so that later formats can still read older files
+pub const BASELINE_VERSION: u64 = 1;
+
+/// What a baseline records about one chunk
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    pub chunk_type: String,
+    pub length: u32,
+    pub crc: u32,
+    /// SHA-256 of the data of private chunks, whose crc is easy to forge
+    pub sha256: Option<String>,
+}
+
+impl Record {
+    pub fn of(chunk: &Chunk) -> Record {
+
+        let chunk_type = chunk.chunk_type();
+
+        Record {
+            chunk_type: chunk_type.to_string(),
+            length: chunk.length(),
+            crc: chunk.crc(),
+            sha256: (!chunk_type.is_public()).then(|| sha256::hex_digest(chunk.data())),
+        }
+    }
+
+    fn to_json(&self) -> String {
+
+        let mut fields = vec![
+            format!("\"type\": {}", json::string(&self.chunk_type)),
+            format!("\"length\": {}", self.length),
+            format!("\"crc\": {}", self.crc),
+        ];
+
+        if let Some(sha256) = &self.sha256 {
+            fields.push(format!("\"sha256\": {}", json::string(sha256)));
+        }
+
+        format!("{{{}}}", fields.join(", "))
+    }
+
+    fn from_json(value: &Value) -> Result<Record> {
+
+        let chunk_type = value.get("type").and_then(Value::as_str).ok_or("a chunk has no \"type\"")?;
+        ChunkType::from_str(chunk_type)?;
+
+        let number = |key: &str| -> Result<u32> {
+            let number = value.get(key).and_then(Value::as_u64).ok_or_else(|| format!("the {} chunk has no \"{}\"", chunk_type, key))?;
+
+            Ok(u32::try_from(number).map_err(|_| format!("the {} of the {} chunk is out of range", key, chunk_type))?)
+        };
+
+        let sha256 = match value.get("sha256") {
+            None | Some(Value::Null) => None,
+            Some(sha256) => Some(sha256.as_str().ok_or_else(|| format!("the sha256 of the {} chunk is not a string", chunk_type))?.to_string()),
+        };
+
+        Ok(Record { chunk_type: chunk_type.to_string(), length: number("length")?, crc: number("crc")?, sha256 })
+    }
+
+    /// How a change to this chunk should be read
+    fn kind(&self) -> &'static str {
+
+        match ChunkType::from_str(&self.chunk_type) {
+            _ if self.chunk_type == "IDAT" => "image data",
+            Ok(chunk_type) if !chunk_type.is_public() => "private payload",
+            Ok(chunk_type) if chunk_type.is_critical() => "critical",
+            _ => "ancillary",
+        }
+    }
+}
+
+/// The chunk inventory of a file at one point in time
+#[derive(Debug, PartialEq)]
+pub struct Baseline {
+    pub chunks: Vec<Record>,
+}
+
+impl Baseline {
+    pub fn of(png: &Png) -> Baseline {
+
+        Baseline { chunks: png.chunks().iter().map(Record::of).collect() }
+    }
+
+    pub fn to_json(&self) -> String {
+
+        let chunks: Vec<String> = self.chunks.iter().map(Record::to_json).collect();
+
+        format!("{{\"version\": {}, \"chunks\": {}}}\n", BASELINE_VERSION, json::array(&chunks))
+    }
+
+    pub fn from_json(text: &str) -> Result<Baseline> {
+
+        let value = json::parse(text)?;
+
+        match value.get("version").and_then(Value::as_u64) {
+            Some(BASELINE_VERSION) => {},
+            Some(version) => return Err(format!("baseline version {} is not supported, expected {}", version, BASELINE_VERSION).into()),
+            None => return Err("the baseline has no \"version\"".into()),
+        }
+
+        let chunks = value.get("chunks").and_then(Value::as_array).ok_or("the baseline has no \"chunks\" list")?;
+
+        Ok(Baseline { chunks: chunks.iter().map(Record::from_json).collect::<Result<_>>()? })
+    }
+}
+
+/// A chunk that differs between a baseline and the file. `occurrence`
+/// counts the chunks of the same type before it, from 0.
+#[derive(Debug, PartialEq)]
+pub enum Difference {
+    Added { occurrence: usize, after: Record },
+    Removed { occurrence: usize, before: Record },
+    Modified { occurrence: usize, before: Record, after: Record },
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+
+        match self {
+            Difference::Added { occurrence, after } => write!(
+                f, "added    {} #{} ({}): {} bytes, crc {:#010x}", after.chunk_type, occurrence, after.kind(), after.length, after.crc
+            ),
+            Difference::Removed { occurrence, before } => write!(
+                f, "removed  {} #{} ({}): {} bytes, crc {:#010x}", before.chunk_type, occurrence, before.kind(), before.length, before.crc
+            ),
+            Difference::Modified { occurrence, before, after } => {
+                let label = match before.kind() {
+                    "image data" => "image edited",
+                    "private payload" => "payload tampered",
+                    _ => "modified",
+                };
+
+                write!(
+                    f, "modified {} #{} ({}): {} -> {} bytes, crc {:#010x} -> {:#010x}",
+                    before.chunk_type, occurrence, label, before.length, after.length, before.crc, after.crc
+                )
+            },
+        }
+    }
+}
+
+/// Pairs the chunks of each type in file order and reports the ones that were
+/// added, removed or changed, grouped by type in the order the types first appear
+pub fn compare(baseline: &Baseline, current: &Baseline) -> Vec<Difference> {
+
+    let mut types: Vec<&str> = vec![];
+
+    for record in baseline.chunks.iter().chain(&current.chunks) {
+        if !types.contains(&record.chunk_type.as_str()) {
+            types.push(&record.chunk_type);
+        }
+    }
+
+    let mut differences = vec![];
+
+    for chunk_type in types {
+        let of_type = |baseline: &Baseline| -> Vec<Record> {
+            baseline.chunks.iter().filter(|record| record.chunk_type == chunk_type).cloned().collect()
+        };
+        let (before, after) = (of_type(baseline), of_type(current));
+
+        for occurrence in 0..before.len().max(after.len()) {
+            match (before.get(occurrence), after.get(occurrence)) {
+                (Some(before), Some(after)) if before != after => differences.push(
+                    Difference::Modified { occurrence, before: before.clone(), after: after.clone() }
+                ),
+                (Some(before), None) => differences.push(Difference::Removed { occurrence, before: before.clone() }),
+                (None, Some(after)) => differences.push(Difference::Added { occurrence, after: after.clone() }),
+                _ => {},
+            }
+        }
+    }
+
+    differences
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(chunk_type: &str, data: &str) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.as_bytes().to_vec())
+    }
+
+    fn testing_png() -> Png {
+        Png::from_chunks(vec![chunk("IHDR", "header"), chunk("ruSt", "secret"), chunk("IDAT", "pixels"), chunk("IEND", "")])
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let baseline = Baseline::of(&testing_png());
+
+        assert_eq!(baseline.chunks[0].sha256, None);
+        assert_eq!(baseline.chunks[1].sha256.as_deref(), Some(sha256::hex_digest(b"secret").as_str()));
+        assert_eq!(Baseline::from_json(&baseline.to_json()).unwrap(), baseline);
+    }
+
+    #[test]
+    fn test_unsupported_version() {
+        let error = Baseline::from_json("{\"version\": 2, \"chunks\": []}").unwrap_err();
+
+        assert!(error.to_string().contains("version 2 is not supported"));
+        assert!(Baseline::from_json("{\"chunks\": []}").is_err());
+    }
+
+    #[test]
+    fn test_compare() {
+        let baseline = Baseline::of(&testing_png());
+        assert!(compare(&baseline, &baseline).is_empty());
+
+        let mut png = testing_png();
+        png.replace_all_of_type("ruSt", b"SECRET".to_vec());
+        png.replace_all_of_type("IDAT", b"edited pixels".to_vec());
+        png.remove_chunk("IEND").unwrap();
+        png.append_chunk(chunk("tEXt", "note"));
+
+        let report: Vec<String> = compare(&baseline, &Baseline::of(&png)).iter()
+            .map(|difference| difference.to_string().split(':').next().unwrap().to_string())
+            .collect();
+
+        assert_eq!(report, vec![
+            "modified ruSt #0 (payload tampered)",
+            "modified IDAT #0 (image edited)",
+            "removed  IEND #0 (critical)",
+            "added    tEXt #0 (ancillary)",
+        ]);
+    }
+}
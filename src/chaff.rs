@@ -0,0 +1,260 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crc::Crc;
+
+use crate::{chunk::Chunk, chunk_type::ChunkType, crypt, png::Png};
+
+/// Length of the tag that ends the data of every decoy. A single marker byte would
+/// also match 1 in 256 real chunks, which dropping chaff would then delete.
+pub const TAG_LEN: usize = 4;
+
+/// A small xorshift64* generator, good enough to place decoys but not for keys
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    /// The same seed always gives the same numbers, for tests and reproducible files
+    pub fn seeded(seed: u64) -> Rng {
+
+        // splitmix64 spreads the seed out, so that 0 and small seeds work as well as any
+        let mut state = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        state = (state ^ (state >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        state = (state ^ (state >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        state ^= state >> 31;
+
+        Rng { state: if state == 0 { 1 } else { state } }
+    }
+
+    /// Seeded from the clock and the process id
+    pub fn from_entropy() -> Rng {
+
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_nanos() as u64).unwrap_or_default();
+
+        Rng::seeded(nanos ^ ((std::process::id() as u64) << 32))
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+
+        self.state.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// A number from 0 up to, but not including, `bound`
+    pub fn below(&mut self, bound: usize) -> usize {
+
+        (self.next_u64() % bound.max(1) as u64) as usize
+    }
+
+    pub fn fill(&mut self, bytes: &mut [u8]) {
+
+        for byte in bytes {
+            *byte = self.next_u64() as u8;
+        }
+    }
+}
+
+/// A random ancillary, private type with a valid reserved bit, so that
+/// decoders skip it and nothing tells it apart from any other private chunk
+pub fn random_private_type(rng: &mut Rng) -> ChunkType {
+
+    let safe_to_copy = rng.below(2) == 0;
+    let bytes = [letter(rng, false), letter(rng, false), letter(rng, true), letter(rng, !safe_to_copy)];
+
+    ChunkType::try_from(bytes).expect("Only ASCII letters were picked")
+}
+
+fn letter(rng: &mut Rng, uppercase: bool) -> u8 {
+
+    let letter = b'a' + rng.below(26) as u8;
+
+    if uppercase { letter.to_ascii_uppercase() } else { letter }
+}
+
+/// An HMAC of the type and data under the secret of the user, so that only who
+/// knows the secret can tell the decoys from the message
+fn tag(key: &[u8], chunk_type: &ChunkType, body: &[u8]) -> [u8; TAG_LEN] {
+
+    let digest = crypt::hmac_sha256(key, &[&chunk_type.bytes()[..], body].concat());
+
+    [digest[0], digest[1], digest[2], digest[3]]
+}
+
+/// A decoy of `size` bytes of data: random bytes followed by the tag under `key`
+pub fn decoy(rng: &mut Rng, key: &[u8], chunk_type: ChunkType, size: usize, crc: &Crc<u32>) -> Chunk {
+
+    let mut data = vec![0; size.max(TAG_LEN + 1) - TAG_LEN];
+    rng.fill(&mut data);
+
+    let tag = tag(key, &chunk_type, &data);
+    data.extend_from_slice(&tag);
+
+    Chunk::new_with_crc(chunk_type, data, crc)
+}
+
+/// Whether the chunk is a decoy added with `add` under the same key
+pub fn is_chaff(chunk: &Chunk, key: &[u8]) -> bool {
+
+    let data = chunk.data();
+
+    !chunk.chunk_type().is_public()
+        && data.len() > TAG_LEN
+        && tag(key, &chunk.chunk_type(), &data[..data.len() - TAG_LEN]) == data[data.len() - TAG_LEN..]
+}
+
+/// Inserts `count` decoys at random places before IEND, never splitting the IDAT
+/// chunks apart, then moves the chunks of the message at `message` to random places
+/// among them, keeping their order. Each decoy gets a type the file does not have yet
+/// and between half and one and a half times `payload` bytes of data. Returns the types added.
+pub fn add(png: &mut Png, count: usize, payload: usize, message: &[usize], key: &[u8], rng: &mut Rng, crc: &Crc<u32>) -> Vec<String> {
+
+    let mut sorted = message.to_vec();
+    sorted.sort_unstable();
+
+    let mut message_chunks: Vec<Chunk> = sorted.iter().rev()
+        .map(|&index| png.remove_chunk_at(index).expect("The message is inside the file"))
+        .collect();
+    message_chunks.reverse();
+
+    let mut added = vec![];
+
+    for _ in 0..count {
+        let chunk_type = loop {
+            let chunk_type = random_private_type(rng);
+
+            if !png.contains_type(&chunk_type.to_string()) {
+                break chunk_type
+            }
+        };
+
+        let size = payload / 2 + rng.below(payload + 1);
+        let positions = insert_positions(png);
+        let position = positions[rng.below(positions.len())];
+
+        added.push(chunk_type.to_string());
+        png.insert_chunk(position, decoy(rng, key, chunk_type, size, crc)).expect("The position is inside the file");
+    }
+
+    // Each piece of the message goes somewhere after the one before it
+    let mut previous = 0;
+
+    for chunk in message_chunks {
+        let positions: Vec<usize> = insert_positions(png).into_iter().filter(|&position| position > previous).collect();
+        let position = positions[rng.below(positions.len())];
+
+        png.insert_chunk(position, chunk).expect("The position is inside the file");
+        previous = position;
+    }
+
+    added
+}
+
+/// Where a chunk can go: after the first chunk, up to IEND, and not between two IDATs
+fn insert_positions(png: &Png) -> Vec<usize> {
+
+    let chunks = png.chunks();
+    let end = png.position_of_type("IEND").unwrap_or(chunks.len());
+    let is_idat = |index: usize| chunks[index].chunk_type() == ChunkType::IDAT;
+
+    let positions: Vec<usize> = (1..=end)
+        .filter(|&position| position == chunks.len() || !(is_idat(position - 1) && is_idat(position)))
+        .collect();
+
+    if positions.is_empty() { vec![chunks.len()] } else { positions }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, str::FromStr};
+
+    use super::*;
+
+    const PNG_CRC: Crc<u32> = Crc::<u32>::new(&crc::CRC_32_ISO_HDLC);
+
+    const KEY: &[u8] = b"a chaff secret";
+
+    fn chunk(chunk_type: &str, data: &[u8]) -> Chunk {
+        Chunk::new_with_crc(ChunkType::from_str(chunk_type).unwrap(), data.to_vec(), &PNG_CRC)
+    }
+
+    #[test]
+    fn test_seeded_rng_is_deterministic() {
+        let first: Vec<u64> = (0..4).map({ let mut rng = Rng::seeded(7); move |_| rng.next_u64() }).collect();
+        let second: Vec<u64> = (0..4).map({ let mut rng = Rng::seeded(7); move |_| rng.next_u64() }).collect();
+
+        assert_eq!(first, second);
+        assert_ne!(Rng::seeded(7).next_u64(), Rng::seeded(8).next_u64());
+        assert_ne!(Rng::seeded(0).next_u64(), 0);
+    }
+
+    #[test]
+    fn test_random_private_type() {
+        let mut rng = Rng::seeded(1);
+
+        for _ in 0..100 {
+            let chunk_type = random_private_type(&mut rng);
+
+            assert!(chunk_type.is_valid());
+            assert!(!chunk_type.is_critical());
+            assert!(!chunk_type.is_public());
+        }
+    }
+
+    #[test]
+    fn test_is_chaff() {
+        let mut rng = Rng::seeded(2);
+        let decoy = decoy(&mut rng, KEY, ChunkType::from_str("abCd").unwrap(), 20, &PNG_CRC);
+
+        assert_eq!(decoy.data().len(), 20);
+        assert!(is_chaff(&decoy, KEY));
+        assert!(!is_chaff(&decoy, b"another secret"));
+        assert!(!is_chaff(&chunk("abCd", decoy.data().iter().rev().copied().collect::<Vec<u8>>().as_slice()), KEY));
+        assert!(!is_chaff(&chunk("ruSt", b"a real message"), KEY));
+    }
+
+    #[test]
+    fn test_add_keeps_idat_together() {
+        let mut png = Png::from_chunks(vec![
+            chunk("IHDR", &[0; 13]), chunk("IDAT", b"a"), chunk("IDAT", b"b"), chunk("ruSt", b"message"), chunk("IEND", b""),
+        ]);
+
+        let added = add(&mut png, 5, 7, &[3], KEY, &mut Rng::seeded(3), &PNG_CRC);
+        let types: Vec<String> = png.chunks().iter().map(|chunk| chunk.chunk_type().to_string()).collect();
+
+        assert_eq!(added.len(), 5);
+        assert_eq!(types.first().unwrap(), "IHDR");
+        assert_eq!(types.last().unwrap(), "IEND");
+        assert!(types.contains(&"ruSt".to_string()));
+        assert!(types.windows(2).any(|pair| pair == ["IDAT", "IDAT"]));
+        assert_eq!(png.chunks().iter().filter(|chunk| is_chaff(chunk, KEY)).count(), 5);
+        assert!(png.chunks().iter().filter(|chunk| is_chaff(chunk, KEY)).all(|chunk| (5..=10).contains(&chunk.data().len())));
+    }
+
+    #[test]
+    fn test_add_moves_the_message_among_the_decoys() {
+        let original = Png::from_chunks(vec![
+            chunk("IHDR", &[0; 13]), chunk("IDAT", b"a"), chunk("ruSt", b"\0\0first"), chunk("ruSt", b"\0\x01second"), chunk("IEND", b""),
+        ]);
+        let mut last_positions = HashSet::new();
+
+        for seed in 0..20 {
+            let mut png = Png::from_chunks(original.chunks().to_vec());
+            add(&mut png, 4, 8, &[2, 3], KEY, &mut Rng::seeded(seed), &PNG_CRC);
+
+            let message: Vec<(usize, &[u8])> = png.chunks().iter().enumerate()
+                .filter(|(_, chunk)| chunk.chunk_type().to_string() == "ruSt")
+                .map(|(index, chunk)| (index, chunk.data()))
+                .collect();
+
+            assert_eq!(message.iter().map(|(_, data)| *data).collect::<Vec<_>>(), [&b"\0\0first"[..], b"\0\x01second"]);
+            assert_eq!(png.chunks().last().unwrap().chunk_type().to_string(), "IEND");
+            last_positions.insert(message[1].0);
+        }
+
+        // The message does not always sit right before IEND
+        assert!(last_positions.len() > 1);
+    }
+}
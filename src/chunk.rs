@@ -1,9 +1,9 @@
-use std::fmt;
+use std::{fmt, str::FromStr};
 
-use crate::{Error, Result, chunk_type::ChunkType};
+use crate::{Error, Result, chunk_type::ChunkType, error::PngMeError, hex, metrics::{self, Stage}};
 use crc::{Crc, CRC_32_ISO_HDLC};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Chunk {
     length: u32,
     chunk_type: [u8; 4],
@@ -11,52 +11,110 @@ pub struct Chunk {
     crc: u32 // (Cyclic Redundancy Check)
 }
 
+/// The PNG specification limits the data of a chunk to 2^31 - 1 bytes
+pub const MAX_LENGTH: u32 = (1 << 31) - 1;
+
+/// How many bytes of data `Display` shows
+const DISPLAY_PREVIEW: usize = 32;
+
+const CASTAGNOLI: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+
 impl TryFrom<&[u8]> for Chunk {
     type Error = Error;
     fn try_from(value: &[u8]) -> Result<Self> {
 
-        pub const CASTAGNOLI: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+        Chunk::try_from_with_crc(value, &CASTAGNOLI)
+    }
+}
 
-        // The first 4 bytes represent the length
-        let length_bytes: [u8; 4] = value[..4].try_into().unwrap();
-        let length = u32::from_be_bytes(length_bytes);
+impl Chunk {
+    pub fn new(chunk_type: ChunkType, data: Vec<u8>) -> Chunk {
 
-        // The next 4 bytes represent the chunk_type
-        let chunk_type: [u8; 4] = value[4..8].try_into().unwrap();
+        Chunk::new_with_crc(chunk_type, data, &CASTAGNOLI)
+    }
 
-        // The next bytes of length "length" represent the data
-        let end = 8 + length;
-        let chunk_data: Vec<u8> = value[8..end as usize].try_into().unwrap();
+    /// Parses the chunk type and copies the data into a new chunk
+    pub fn with_type_and_data(chunk_type: &str, data: &[u8]) -> Result<Chunk> {
 
-        // The remaining bytes are for the crc
-        let chunk_length = value.len();
-        let start = chunk_length - 4;
-        let crc_bytes: [u8; 4] = value[start..].try_into().unwrap();
-        let crc = u32::from_be_bytes(crc_bytes);
+        Ok(Chunk::new(ChunkType::from_str(chunk_type)?, data.to_vec()))
+    }
+
+    /// Creates a chunk whose crc is generated with another algorithm than the one of the PNG specification.
+    /// Panics when the data is longer than the 4 bytes of the length field can count, which
+    /// callers rule out by checking against the 2^31 - 1 bytes the specification allows.
+    pub fn new_with_crc(chunk_type: ChunkType, data: Vec<u8>, crc: &Crc<u32>) -> Chunk {
+
+        let length: u32 = data.len().try_into().expect("The chunk data is longer than u32::MAX bytes");
+
+        let mut chunk = Chunk { length, chunk_type: chunk_type.bytes(), chunk_data: data, crc: 0 };
+        chunk.crc = chunk.computed_crc_with(crc);
+
+        chunk
+    }
+
+    /// Parses a chunk whose crc was generated with another algorithm than the one of the PNG specification
+    pub fn try_from_with_crc(value: &[u8], crc: &Crc<u32>) -> Result<Chunk> {
 
-        let correct_crc = CASTAGNOLI.checksum(&value[4..end as usize]);
+        let chunk = Chunk::try_from_unverified(value)?;
 
-        if crc != correct_crc {
+        if chunk.crc != chunk.computed_crc_with(crc) {
             return Err("Invalid crc (Cyclic Redundancy Check)".into())
         }
 
-        Ok( Chunk { length, chunk_type, chunk_data, crc } )
+        Ok(chunk)
     }
-}
 
-impl Chunk {
-    pub fn new(chunk_type: ChunkType, data: Vec<u8>) -> Chunk {
+    /// Parses the chunk at the start of `buf`, which may hold more bytes after it,
+    /// and returns it with the number of bytes it took so that the caller can
+    /// read the next chunk from there
+    pub fn read_one(buf: &[u8]) -> Result<(Chunk, usize)> {
 
-        pub const CASTAGNOLI: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
-        
-        let length: u32 = data.len().try_into().unwrap();
-        let new_chunk_data = &data[..];
+        Chunk::read_one_with_crc(buf, &CASTAGNOLI)
+    }
 
-        let new_data = [&chunk_type.bytes()[..], new_chunk_data].concat();
+    pub fn read_one_with_crc(buf: &[u8], crc: &Crc<u32>) -> Result<(Chunk, usize)> {
 
-        let crc = CASTAGNOLI.checksum(&new_data[..]);
+        let length_bytes: [u8; 4] = buf.get(..4).ok_or("A chunk takes at least 12 bytes")?.try_into().map_err(PngMeError::from)?;
 
-        Chunk { length, chunk_type: chunk_type.bytes(), chunk_data: data, crc }
+        let end = framed_end(0, u32::from_be_bytes(length_bytes)).ok_or("The chunk is too large for this platform")?;
+
+        if buf.len() < end {
+            return Err("Unexpected end of data while reading a chunk".into())
+        }
+
+        Ok((Chunk::try_from_with_crc(&buf[..end], crc)?, end))
+    }
+
+    /// Parses a chunk without checking its stored crc against its contents,
+    /// so that damaged chunks can still be inspected
+    pub fn try_from_unverified(value: &[u8]) -> Result<Chunk> {
+
+        if value.len() < 12 {
+            return Err("A chunk takes at least 12 bytes".into())
+        }
+
+        // The first 4 bytes represent the length
+        let length_bytes: [u8; 4] = value[..4].try_into().map_err(PngMeError::from)?;
+        let length = u32::from_be_bytes(length_bytes);
+
+        // The next 4 bytes represent the chunk_type
+        let chunk_type: [u8; 4] = value[4..8].try_into().map_err(PngMeError::from)?;
+        ChunkType::try_from(chunk_type)?;
+
+        if framed_end(0, length) != Some(value.len()) {
+            return Err("The chunk length does not match the number of bytes given".into())
+        }
+
+        // The next bytes of length "length" represent the data
+        let end = value.len() - 4;
+
+        let chunk_data: Vec<u8> = value[8..end].to_vec();
+
+        // The remaining bytes are for the crc
+        let crc_bytes: [u8; 4] = value[end..].try_into().map_err(PngMeError::from)?;
+        let crc = u32::from_be_bytes(crc_bytes);
+
+        Ok( Chunk { length, chunk_type, chunk_data, crc } )
     }
 
     pub fn length(&self) -> u32 {
@@ -69,44 +127,120 @@ impl Chunk {
         self.crc
     }
 
+    /// Recomputes the crc from the chunk type and data, which differs from
+    /// `crc` when the chunk was damaged
+    pub fn computed_crc(&self) -> u32 {
+
+        self.computed_crc_with(&CASTAGNOLI)
+    }
+
+    pub fn computed_crc_with(&self, crc: &Crc<u32>) -> u32 {
+
+        metrics::time(Stage::Crc, || {
+            // Fed in two parts so that the data, which can be large, is not copied
+            let mut digest = crc.digest();
+            digest.update(&self.chunk_type);
+            digest.update(&self.chunk_data);
+
+            digest.finalize()
+        })
+    }
+
     pub fn chunk_type(&self) -> ChunkType {
 
         ChunkType::try_from(self.chunk_type).unwrap()
     }
 
+    pub fn data(&self) -> &[u8] {
+
+        &self.chunk_data
+    }
+
+    /// Replaces the data of the chunk, updating its length and crc
+    pub fn set_data(&mut self, data: Vec<u8>) {
+
+        self.set_data_with_crc(data, &CASTAGNOLI)
+    }
+
+    pub fn set_data_with_crc(&mut self, data: Vec<u8>, crc: &Crc<u32>) {
+
+        *self = Chunk::new_with_crc(self.chunk_type(), data, crc);
+    }
+
     pub fn data_as_string(&self) -> Result<String> {
 
-        let data = std::str::from_utf8(&self.chunk_data).expect("Invalid UTF-8").to_string();
+        let data = std::str::from_utf8(&self.chunk_data).map_err(PngMeError::from)?.to_string();
 
         Ok(data)
     }
 
+    /// The bytes the chunk takes in a file: its length, type, data and crc
+    pub fn size(&self) -> usize {
+
+        12 + self.chunk_data.len()
+    }
+
     pub fn as_bytes(&self) -> Vec<u8> {
 
-        let result = self.length
-                            .to_be_bytes()
-                            .iter()
-                            .cloned()
-                            .chain(self.chunk_type.iter().cloned())
-                            .chain(self.chunk_data.iter().cloned())
-                            .chain(self.crc.to_be_bytes().iter().cloned())
-                            .collect();
+        let mut bytes = Vec::with_capacity(self.size());
+        self.extend_bytes(&mut bytes);
+
+        bytes
+    }
+
+    /// Appends the bytes of `as_bytes` to `bytes`, so that a whole file can be
+    /// serialized into a single buffer
+    pub fn extend_bytes(&self, bytes: &mut Vec<u8>) {
 
-        result
+        bytes.extend_from_slice(&self.length.to_be_bytes());
+        bytes.extend_from_slice(&self.chunk_type);
+        bytes.extend_from_slice(&self.chunk_data);
+        bytes.extend_from_slice(&self.crc.to_be_bytes());
     }
+
+    /// The bytes of `as_bytes` as lowercase hex, to paste into a test or a message
+    pub fn to_hex(&self) -> String {
+
+        hex::encode(&self.as_bytes())
+    }
+
+    /// Parses a complete chunk (length, type, data and crc) written as hex digits,
+    /// such as `to_hex` gives. Whitespace between the digits is ignored.
+    pub fn from_hex(text: &str) -> Result<Chunk> {
+
+        Chunk::try_from(hex::decode(text)?.as_slice())
+    }
+}
+
+/// Where the chunk starting at `start` with `length` bytes of data ends: its length,
+/// type and crc take 4 bytes each around the data. `None` when that end cannot be
+/// counted in a `usize`, as with a length near 4 GB on a 32-bit target.
+pub fn framed_end(start: usize, length: u32) -> Option<usize> {
+
+    start.checked_add(12)?.checked_add(usize::try_from(length).ok()?)
 }
 
 impl fmt::Display for Chunk {
 
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "Chunk {{").unwrap();
-        write!(f, " Length: {}", self.length).unwrap();
-        write!(f, " crc: {}", self.crc).unwrap();
-        write!(f, " data: {:?}", self.chunk_data).unwrap();
-        write!(f, " type: {:?}", self.chunk_type).unwrap();
-        write!(f, "}}").unwrap();
 
-        Ok(())
+        // Bytes outside printable ASCII show as dots, so that binary data cannot garble the terminal
+        let preview: String = self.chunk_data.iter()
+            .take(DISPLAY_PREVIEW)
+            .map(|byte| if (0x20..0x7f).contains(byte) { *byte as char } else { '.' })
+            .collect();
+        let ellipsis = if self.chunk_data.len() > DISPLAY_PREVIEW { "..." } else { "" };
+
+        write!(
+            f,
+            "{} chunk, {} {}, crc {:#010x}: \"{}{}\"",
+            self.chunk_type(),
+            self.length,
+            if self.length == 1 { "byte" } else { "bytes" },
+            self.crc,
+            preview,
+            ellipsis
+        )
     }
 }
 
@@ -165,6 +299,84 @@ mod tests {
         assert_eq!(chunk_string, expected_chunk_string);
     }
 
+    #[test]
+    fn test_binary_chunk_string() {
+        let chunk_type = ChunkType::from_str("RuSt").unwrap();
+        let chunk = Chunk::new(chunk_type, vec![0xff, 0xfe, 0x00]);
+        let error = chunk.data_as_string().unwrap_err();
+        assert!(matches!(error.downcast_ref::<PngMeError>(), Some(PngMeError::Utf8(_))));
+        assert_eq!(chunk.data(), &[0xff, 0xfe, 0x00]);
+    }
+
+    #[test]
+    fn test_chunk_with_other_crc_algorithm() {
+        const BZIP2: Crc<u32> = Crc::<u32>::new(&crc::CRC_32_BZIP2);
+
+        let chunk_type = ChunkType::from_str("RuSt").unwrap();
+        let data = "This is where your secret message will be!".as_bytes().to_vec();
+        let chunk = Chunk::new_with_crc(chunk_type, data, &BZIP2);
+
+        assert_ne!(chunk.crc(), 2882656334);
+        assert_eq!(chunk.crc(), chunk.computed_crc_with(&BZIP2));
+
+        let bytes = chunk.as_bytes();
+        assert!(Chunk::try_from_with_crc(&bytes, &BZIP2).is_ok());
+        assert!(Chunk::try_from(bytes.as_ref()).is_err());
+    }
+
+    #[test]
+    fn test_as_bytes_matches_the_chained_framing() {
+        let empty = Chunk::new(ChunkType::from_str("IEND").unwrap(), vec![]);
+        let large = Chunk::new(ChunkType::from_str("IDAT").unwrap(), (0..100_000).map(|byte| byte as u8).collect());
+
+        for chunk in [testing_chunk(), empty, large] {
+            let chained: Vec<u8> = chunk.length()
+                .to_be_bytes()
+                .iter()
+                .chain(chunk.chunk_type().bytes().iter())
+                .chain(chunk.data().iter())
+                .chain(chunk.crc().to_be_bytes().iter())
+                .copied()
+                .collect();
+
+            assert_eq!(chunk.as_bytes(), chained);
+            assert_eq!(chunk.as_bytes().len(), chunk.size());
+        }
+    }
+
+    #[test]
+    fn test_incremental_crc_matches_concatenated_crc() {
+        const BZIP2: Crc<u32> = Crc::<u32>::new(&crc::CRC_32_BZIP2);
+
+        let payloads: [Vec<u8>; 4] = [vec![], vec![0], b"This is where your secret message will be!".to_vec(), vec![0xa5; 70_000]];
+
+        for data in payloads {
+            for algorithm in [&CASTAGNOLI, &BZIP2] {
+                let chunk = Chunk::new_with_crc(ChunkType::from_str("RuSt").unwrap(), data.clone(), algorithm);
+                let concatenated = algorithm.checksum(&[&b"RuSt"[..], &data[..]].concat());
+
+                assert_eq!(chunk.crc(), concatenated);
+                assert!(Chunk::try_from_with_crc(&chunk.as_bytes(), algorithm).is_ok());
+            }
+        }
+    }
+
+    #[test]
+    fn test_with_type_and_data() {
+        let chunk = Chunk::with_type_and_data("RuSt", b"This is where your secret message will be!").unwrap();
+
+        assert_eq!(chunk.chunk_type().to_string(), "RuSt");
+        assert_eq!(chunk.length(), 42);
+        assert_eq!(chunk.crc(), 2882656334);
+    }
+
+    #[test]
+    fn test_with_invalid_type() {
+        assert!(Chunk::with_type_and_data("Ru1t", b"data").is_err());
+        assert!(Chunk::with_type_and_data("RuStt", b"data").is_err());
+        assert!(Chunk::with_type_and_data("", b"").is_err());
+    }
+
     #[test]
     fn test_chunk_crc() {
         let chunk = testing_chunk();
@@ -219,6 +431,79 @@ mod tests {
         assert!(chunk.is_err());
     }
 
+    #[test]
+    fn test_unverified_chunk_keeps_bad_crc() {
+        let data_length: u32 = 42;
+        let chunk_type = "RuSt".as_bytes();
+        let message_bytes = "This is where your secret message will be!".as_bytes();
+        let crc: u32 = 2882656333;
+
+        let chunk_data: Vec<u8> = data_length
+            .to_be_bytes()
+            .iter()
+            .chain(chunk_type.iter())
+            .chain(message_bytes.iter())
+            .chain(crc.to_be_bytes().iter())
+            .copied()
+            .collect();
+
+        let chunk = Chunk::try_from_unverified(chunk_data.as_ref()).unwrap();
+
+        assert_eq!(chunk.crc(), 2882656333);
+        assert_eq!(chunk.computed_crc(), 2882656334);
+    }
+
+    #[test]
+    fn test_hex_round_trip() {
+        let chunk = testing_chunk();
+
+        assert_eq!(Chunk::from_hex(&chunk.to_hex()).unwrap(), chunk);
+        assert!(chunk.to_hex().starts_with("0000002a52755374"));
+
+        let empty = Chunk::new(ChunkType::IEND, vec![]);
+        assert_eq!(empty.to_hex(), "0000000049454e44ae426082");
+        assert_eq!(Chunk::from_hex("00000000 49454e44\n ae426082").unwrap(), empty);
+    }
+
+    #[test]
+    fn test_from_hex_errors() {
+        assert!(Chunk::from_hex("0000000049454e44ae42608").unwrap_err().to_string().starts_with("Odd number of hex digits"));
+        assert!(Chunk::from_hex("0000000049454e44ae426083").unwrap_err().to_string().starts_with("Invalid crc"));
+        assert!(Chunk::from_hex("zz").is_err());
+    }
+
+    #[test]
+    fn test_read_one_back_to_back() {
+        let first = Chunk::new(ChunkType::from_str("RuSt").unwrap(), b"first".to_vec());
+        let second = Chunk::new(ChunkType::from_str("teSt").unwrap(), b"second message".to_vec());
+        let buf = [first.as_bytes(), second.as_bytes()].concat();
+
+        let (chunk, consumed) = Chunk::read_one(&buf).unwrap();
+        assert_eq!(consumed, 17);
+        assert_eq!(chunk.data(), b"first");
+
+        let (chunk, consumed) = Chunk::read_one(&buf[17..]).unwrap();
+        assert_eq!(consumed, buf.len() - 17);
+        assert_eq!(chunk.chunk_type().to_string(), "teSt");
+        assert_eq!(chunk.data(), b"second message");
+
+        assert!(Chunk::read_one(&buf[17..buf.len() - 1]).is_err());
+        assert!(Chunk::read_one(&buf[..3]).is_err());
+    }
+
+    #[test]
+    fn test_equal_chunks_hash_the_same() {
+        use std::collections::HashSet;
+
+        let mut chunks = HashSet::new();
+        chunks.insert(Chunk::new(ChunkType::from_str("RuSt").unwrap(), b"same".to_vec()));
+        chunks.insert(Chunk::new(ChunkType::from_str("RuSt").unwrap(), b"same".to_vec()));
+        assert_eq!(chunks.len(), 1);
+
+        chunks.insert(Chunk::new(ChunkType::from_str("RuSt").unwrap(), b"other".to_vec()));
+        assert_eq!(chunks.len(), 2);
+    }
+
     #[test]
     pub fn test_chunk_trait_impls() {
         let data_length: u32 = 42;
@@ -237,7 +522,13 @@ mod tests {
         
         let chunk: Chunk = TryFrom::try_from(chunk_data.as_ref()).unwrap();
         
-        let _chunk_string = format!("{}", chunk);
+        let chunk_string = format!("{}", chunk);
+
+        assert_eq!(chunk_string, "RuSt chunk, 42 bytes, crc 0xabd1d84e: \"This is where your secret messag...\"");
+        assert_eq!(
+            Chunk::new(ChunkType::from_str("ruSt").unwrap(), vec![b'a', 0, 0xff]).to_string(),
+            "ruSt chunk, 3 bytes, crc 0x428d4e44: \"a..\""
+        );
     }
 }
 // }
\ No newline at end of file
@@ -1,11 +1,20 @@
 use crate::{Error, Result};
 use std::{str::FromStr, fmt};
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ChunkType {
     chunk_type: [u8; 4], // Specifies the type of the chunk in a png file and it is not more than 4 bytes
 }
 
+/// The chunk types defined by the PNG specification (including the extensions registered since)
+pub const STANDARD_TYPES: [&str; 25] = [
+    "IHDR", "PLTE", "IDAT", "IEND",
+    "cHRM", "gAMA", "iCCP", "sBIT", "sRGB", "cICP", "mDCv", "cLLi",
+    "tEXt", "zTXt", "iTXt",
+    "bKGD", "hIST", "tRNS", "pHYs", "sPLT", "eXIf", "tIME",
+    "acTL", "fcTL", "fdAT",
+];
+
 impl TryFrom<[u8; 4]> for ChunkType {
     type Error = Error;
 
@@ -14,13 +23,8 @@ impl TryFrom<[u8; 4]> for ChunkType {
             return Err("ChunkType only accepts an array of 4 elements".into())
         }
         
-        let lower_range = 65..91; // This range represents the ASCII codes to upper case A - Z
-        let upper_range = 97..123; // This range represents the ASCII codes to lower case a - z
-
-        for byte in &value {
-            if !lower_range.contains(byte) && !upper_range.contains(byte) {
-                return Err("ChunkType only accepts bytes with the range(65 - 90) and (97 - 122)".into())
-            }
+        if !value.iter().all(|byte| is_valid_byte(*byte)) {
+            return Err("ChunkType only accepts bytes with the range(65 - 90) and (97 - 122)".into())
         }
 
         Ok(ChunkType{ chunk_type: value })
@@ -39,13 +43,8 @@ impl FromStr for ChunkType {
             return Err("ChunkType takes ASCII characters only".into())
         }
 
-        let lower_range = 65..91;
-        let upper_range = 97..123;
-
-        for byte in s.as_bytes() {
-            if !lower_range.contains(byte) && !upper_range.contains(byte) {
-                return Err("ChunkType only accepts bytes with the range(65 - 90) and (97 - 122)".into())
-            }
+        if !s.bytes().all(is_valid_byte) {
+            return Err("ChunkType only accepts bytes with the range(65 - 90) and (97 - 122)".into())
         }
 
         let chunk_type: [u8; 4] = s.as_bytes().try_into()?;
@@ -56,6 +55,22 @@ impl FromStr for ChunkType {
 }
 
 impl ChunkType {
+    /// The image header, always the first chunk
+    pub const IHDR: ChunkType = ChunkType::new_unchecked(*b"IHDR");
+    /// The palette of indexed-color images
+    pub const PLTE: ChunkType = ChunkType::new_unchecked(*b"PLTE");
+    /// The compressed image data, possibly spread over several consecutive chunks
+    pub const IDAT: ChunkType = ChunkType::new_unchecked(*b"IDAT");
+    /// The end of the image, always the last chunk
+    pub const IEND: ChunkType = ChunkType::new_unchecked(*b"IEND");
+
+    /// Builds a chunk type without checking that the bytes are ASCII letters, for
+    /// the constants above. Use `TryFrom` or `FromStr` for anything else.
+    pub(crate) const fn new_unchecked(chunk_type: [u8; 4]) -> ChunkType {
+
+        ChunkType { chunk_type }
+    }
+
     pub fn bytes(&self) -> [u8; 4] {
 
         self.chunk_type
@@ -68,11 +83,6 @@ impl ChunkType {
         self.chunk_type[0].is_ascii_uppercase()
     }
 
-    pub fn to_string(&self) -> String {
-
-        std::str::from_utf8(&self.chunk_type).expect("Invalid utf-8").to_string()
-    }
-
     /// If the second byte is an uppercase ASCII letter, then the type is public
     /// otherwise it is private
     pub fn is_public(&self) -> bool {
@@ -90,30 +100,115 @@ impl ChunkType {
         self.chunk_type[3].is_ascii_lowercase()
     }
 
+    /// The same type with the reserved bit cleared (the third letter uppercased),
+    /// which makes a type that only failed on that bit valid
+    pub fn with_reserved_bit_cleared(&self) -> ChunkType {
+
+        let mut chunk_type = self.chunk_type;
+        chunk_type[2] = chunk_type[2].to_ascii_uppercase();
+
+        ChunkType { chunk_type }
+    }
+
+    /// Parses every type in the list, failing on the first invalid one
+    pub fn parse_all(types: &[&str]) -> Result<Vec<ChunkType>> {
+
+        types.iter()
+            .map(|chunk_type| {
+                ChunkType::from_str(chunk_type)
+                    .map_err(|error| format!("Invalid chunk type '{}': {}", chunk_type, error).into())
+            })
+            .collect()
+    }
+
+    /// Parses the type like `FromStr`, and also requires the reserved bit to be valid, for
+    /// callers that only want types the PNG specification accepts. `FromStr` stays lenient.
+    pub fn parse_strict(chunk_type: &str) -> Result<ChunkType> {
+
+        let parsed = ChunkType::from_str(chunk_type)?;
+        let validity = parsed.validity();
+
+        if !validity.is_valid() {
+            return Err(format!("'{}' is not a valid chunk type: {}", chunk_type, validity).into())
+        }
+
+        Ok(parsed)
+    }
+
+    /// Checks if the type is one of the types defined by the PNG specification
+    pub fn is_standard(&self) -> bool {
+
+        STANDARD_TYPES.iter().any(|standard| standard.as_bytes() == self.chunk_type)
+    }
+
     pub fn is_valid(&self) -> bool {
 
-        let mut result: Vec<bool> = vec![];
+        self.validity().is_valid()
+    }
+
+    /// Runs every check `is_valid` is made of and reports each one, so that
+    /// tools can explain why a type is invalid
+    pub fn validity(&self) -> ChunkTypeValidity {
+
+        ChunkTypeValidity {
+            bytes_out_of_range: self.chunk_type.iter()
+                .enumerate()
+                .filter(|(_, byte)| !is_valid_byte(**byte))
+                .map(|(index, byte)| (index, *byte))
+                .collect(),
+            reserved_bit_valid: self.is_reserved_bit_valid(),
+        }
+    }
+}
 
-        let lower_range = 67..91;
-        let upper_range = 97..123;
+/// The outcome of each check of a chunk type
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChunkTypeValidity {
+    /// The position and value of every byte that is not an ASCII letter
+    pub bytes_out_of_range: Vec<(usize, u8)>,
+    /// Whether the third letter, which holds the reserved bit, is uppercase
+    pub reserved_bit_valid: bool,
+}
+
+impl ChunkTypeValidity {
+    pub fn is_valid(&self) -> bool {
+
+        self.bytes_out_of_range.is_empty() && self.reserved_bit_valid
+    }
+}
+
+impl fmt::Display for ChunkTypeValidity {
+
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
 
-        for byte in &self.chunk_type {
-            if !lower_range.contains(byte) && !upper_range.contains(byte) {
-                result.push(false)
-            }
+        if self.is_valid() {
+            return write!(f, "valid")
         }
 
-        let invalid_byte = result.into_iter().any(|val| val == false);
+        let mut problems: Vec<String> = self.bytes_out_of_range.iter()
+            .map(|(index, byte)| format!("byte {} ({:#04x}) is not an ASCII letter", index, byte))
+            .collect();
 
-        self.is_reserved_bit_valid() && !invalid_byte
+        if !self.reserved_bit_valid {
+            problems.push("the reserved bit is set (the third letter must be uppercase)".to_string());
+        }
+
+        write!(f, "{}", problems.join(", "))
     }
 }
 
+/// Chunk types are made of ASCII letters only, A-Z (65 - 90) and a-z (97 - 122)
+fn is_valid_byte(byte: u8) -> bool {
+
+    byte.is_ascii_alphabetic()
+}
+
 impl fmt::Display for ChunkType {
 
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         
-        write!(f, "{:?}", self.chunk_type)
+        // The bytes are always ASCII letters, see `TryFrom<[u8; 4]>` and `FromStr`
+        write!(f, "{}", std::str::from_utf8(&self.chunk_type).expect("Invalid utf-8"))
     }
 }
 
@@ -133,6 +228,18 @@ mod tests {
         assert_eq!(expected, actual.bytes());
     }
 
+    #[test]
+    pub fn test_critical_constants() {
+        assert_eq!(ChunkType::IEND.to_string(), "IEND");
+        assert_eq!(ChunkType::IHDR, ChunkType::from_str("IHDR").unwrap());
+
+        for chunk_type in [ChunkType::IHDR, ChunkType::PLTE, ChunkType::IDAT, ChunkType::IEND] {
+            assert!(chunk_type.is_valid());
+            assert!(chunk_type.is_critical());
+            assert!(STANDARD_TYPES.contains(&chunk_type.to_string().as_str()));
+        }
+    }
+
     #[test]
     pub fn test_chunk_type_from_str() {
         let expected = ChunkType::try_from([82, 117, 83, 116]).unwrap();
@@ -188,6 +295,43 @@ mod tests {
         assert!(!chunk.is_safe_to_copy());
     }
 
+    #[test]
+    pub fn test_with_reserved_bit_cleared() {
+        let chunk = ChunkType::from_str("rust").unwrap().with_reserved_bit_cleared();
+        assert_eq!(chunk.to_string(), "ruSt");
+        assert!(chunk.is_valid());
+
+        let unchanged = ChunkType::from_str("RuSt").unwrap().with_reserved_bit_cleared();
+        assert_eq!(unchanged.to_string(), "RuSt");
+    }
+
+    #[test]
+    pub fn test_parse_all() {
+        let chunk_types = ChunkType::parse_all(&["RuSt", "IDAT"]).unwrap();
+        assert_eq!(chunk_types, vec![ChunkType::from_str("RuSt").unwrap(), ChunkType::from_str("IDAT").unwrap()]);
+
+        let error = ChunkType::parse_all(&["RuSt", "Ru1t", "IDAT"]).unwrap_err();
+        assert!(error.to_string().contains("'Ru1t'"));
+    }
+
+    #[test]
+    pub fn test_parse_strict() {
+        assert_eq!(ChunkType::parse_strict("RuSt").unwrap(), ChunkType::from_str("RuSt").unwrap());
+
+        assert!(ChunkType::from_str("Rust").is_ok());
+        let error = ChunkType::parse_strict("Rust").unwrap_err();
+        assert!(error.to_string().starts_with("'Rust' is not a valid chunk type"));
+
+        assert!(ChunkType::parse_strict("Ru1t").is_err());
+    }
+
+    #[test]
+    pub fn test_chunk_type_is_standard() {
+        assert!(ChunkType::from_str("IDAT").unwrap().is_standard());
+        assert!(ChunkType::from_str("tEXt").unwrap().is_standard());
+        assert!(!ChunkType::from_str("RuSt").unwrap().is_standard());
+    }
+
     #[test]
     pub fn test_valid_chunk_is_valid() {
         let chunk = ChunkType::from_str("RuSt").unwrap();
@@ -203,6 +347,33 @@ mod tests {
         assert!(chunk.is_err());
     }
 
+    #[test]
+    pub fn test_types_starting_with_a_or_b_are_valid() {
+        assert!(ChunkType::from_str("AbCd").unwrap().is_valid());
+        assert!(ChunkType::from_str("BBBB").unwrap().is_valid());
+    }
+
+    #[test]
+    pub fn test_validity_reserved_bit() {
+        let validity = ChunkType::from_str("Rust").unwrap().validity();
+
+        assert_eq!(validity, ChunkTypeValidity { bytes_out_of_range: vec![], reserved_bit_valid: false });
+        assert_eq!(validity.to_string(), "the reserved bit is set (the third letter must be uppercase)");
+    }
+
+    #[test]
+    pub fn test_validity_out_of_range() {
+        // Only reachable by skipping the checks of the constructors
+        let chunk = ChunkType { chunk_type: *b"Ru1T" };
+        let validity = chunk.validity();
+
+        assert!(!chunk.is_valid());
+        assert_eq!(validity.bytes_out_of_range, vec![(2, b'1')]);
+        assert!(!validity.reserved_bit_valid);
+        assert!(validity.to_string().starts_with("byte 2 (0x31) is not an ASCII letter, the reserved bit"));
+        assert_eq!(ChunkType::from_str("RuST").unwrap().validity().to_string(), "valid");
+    }
+
     #[test]
     pub fn test_chunk_type_string() {
         let chunk = ChunkType::from_str("RuSt").unwrap();
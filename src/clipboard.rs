@@ -0,0 +1,76 @@
+use std::process::Command;
+
+use crate::{error::PngMeError, Result};
+
+/// Reads the text on the system clipboard with the clipboard tool of the platform:
+/// `pbpaste` on macOS, PowerShell on Windows and `wl-paste`, `xclip` or `xsel` elsewhere
+pub fn read_text() -> Result<String> {
+
+    let output = tool()?.output().map_err(|error| format!("Cannot run the clipboard tool: {}", PngMeError::from(error)))?;
+
+    if !output.status.success() {
+        return Err(format!("The clipboard tool failed: {}", String::from_utf8_lossy(&output.stderr).trim()).into())
+    }
+
+    text_from_output(output.stdout)
+}
+
+fn tool() -> Result<Command> {
+
+    if cfg!(target_os = "macos") {
+        return Ok(Command::new("pbpaste"))
+    }
+
+    if cfg!(windows) {
+        let mut command = Command::new("powershell");
+        command.args(["-NoProfile", "-Command", "Get-Clipboard"]);
+
+        return Ok(command)
+    }
+
+    let mut command = if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+        let mut command = Command::new("wl-paste");
+        command.arg("--no-newline");
+        command
+    } else if std::env::var_os("DISPLAY").is_some() {
+        let mut command = Command::new("xclip");
+        command.args(["-selection", "clipboard", "-out"]);
+        command
+    } else {
+        return Err("No display found, there is no clipboard to read on a headless system".into())
+    };
+
+    // xsel is the fallback when xclip is not installed
+    if command.get_program() == "xclip" && Command::new("xclip").arg("-version").output().is_err() {
+        command = Command::new("xsel");
+        command.args(["--clipboard", "--output"]);
+    }
+
+    Ok(command)
+}
+
+/// The tools end the text with a newline, which would otherwise end up in the message
+fn text_from_output(output: Vec<u8>) -> Result<String> {
+
+    let text = String::from_utf8(output).map_err(|_| "The clipboard does not hold text")?;
+    let text = text.trim_end_matches(['\r', '\n']);
+
+    if text.is_empty() {
+        return Err("The clipboard is empty".into())
+    }
+
+    Ok(text.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_from_output() {
+        assert_eq!(text_from_output(b"token\r\n".to_vec()).unwrap(), "token");
+        assert_eq!(text_from_output(b"two\nlines".to_vec()).unwrap(), "two\nlines");
+        assert!(text_from_output(b"\n".to_vec()).unwrap_err().to_string().contains("empty"));
+        assert!(text_from_output(vec![0xff]).is_err());
+    }
+}
@@ -1,124 +1,3394 @@
-use std::{path::PathBuf, fs, str::FromStr, process};
+use std::{path::{Path, PathBuf}, fs, io::{self, IsTerminal, Read, Write}, str::FromStr};
 
-use crate::{args::{Cli, Commands}, Result, chunk_type::ChunkType, chunk::Chunk, png::Png};
+use crc::Crc;
+
+use crate::{alias, args::{ChaffOptions, Cli, Commands, DisplayOptions, GitFilterMode, HidingMode, HidingOptions, KeyOptions, KeyringAction, ListOptions, PayloadOptions, VerifyFormat}, base64, baseline::{self, Baseline}, chaff, crypt, data_uri, detect::{self, Layer}, editor, embed::EmbeddedFile, exif, hex, history::{self, Entry, Log, Recording}, inspect, json, known_types, last_type, list, lsb, manifest, optimize, ordering, shred, split, template, text, undo, verify::{self, ImageData}, Result, chunk_type::ChunkType, chunk::{self, Chunk}, error::{PathContext, PngMeError}, filter::{ChunkFilter, Selection}, format, git_filter, interrupt, png::{self, CrcSkip, Keep, MergePolicy, Png}, metrics::{self, Metrics, Stage}, policy::{self, Outcome, Policy}, prompt, retry};
 
 pub fn run(args: &Cli) -> Result<()> {
 
+    let crc = args.crc_algo.crc();
+    let recording = args.recording();
+
+    if args.explain {
+        metrics::enable();
+    }
+
+    retry::configure(args.retry());
+
     match &args.command {
         Commands::Encode { 
             file_path, 
             chunk_type, 
             message, 
-            output_file 
+            output_file,
+            raw_chunk,
+            template,
+            from_clipboard,
+            embed_file,
+            hex,
+            undoable,
+            append_only,
+            prepend,
+            fix_type,
+            strict_type,
+            max_size,
+            limit_output_bytes,
+            split,
+            verbose,
+            json,
+            preview,
+            hiding,
+            chaff,
+            encrypt,
+            key
         } => {
-            encode(file_path, chunk_type, message, output_file)?
+            // The template is expanded before the file is even read, so that a mistake leaves it untouched
+            let message = if let Some(template) = template {
+                Some(expand_template(template, file_path).with_path(file_path)?.into_bytes())
+            } else if *from_clipboard {
+                Some(read_clipboard()?.into_bytes())
+            } else if let Some(embed_file) = embed_file {
+                Some(EmbeddedFile::read(embed_file).and_then(|embedded| embedded.to_bytes()).with_path(embed_file)?)
+            } else if *hex {
+                message.as_deref().map(hex::decode).transpose()?
+            } else {
+                message.clone().map(String::into_bytes)
+            };
+
+            let chunk_type = chunk_type.as_deref().map(|chunk_type| checked_chunk_type(chunk_type, *fix_type)).transpose()?;
+
+            // The lsb mode writes into IDAT on purpose
+            if let (Some(chunk_type), HidingMode::Chunk) = (&chunk_type, hiding.mode) {
+                check_standard_type(chunk_type, *strict_type)?;
+            }
+
+            if let (Some(max_size), Some(message)) = (max_size, &message) {
+                check_max_size(message.len(), *max_size)?;
+            }
+
+            let message = match (message, encrypt) {
+                (Some(message), true) => Some(encrypt_message(&message, key)?),
+                (message, _) => message,
+            };
+
+            let report = encode(file_path, chunk_type.as_deref(), message.as_deref(), raw_chunk, output_file, *split, *limit_output_bytes, *undoable, *append_only, *prepend, *preview, hiding, chaff, recording, crc).with_path(file_path)?;
+
+            if let Some(chunk_type) = &chunk_type {
+                remember_chunk_type(chunk_type);
+            }
+
+            if *json {
+                println!("{}", report.to_json(metrics::take().as_ref()));
+            } else {
+                match output_file {
+                    Some(_) => println!("New file has been created and message encoded successfully!"),
+                    None => println!("Message encoded successfully!"),
+                }
+
+                if *verbose {
+                    println!("{}", report);
+                }
+            }
         },
         
         Commands::Decode {
             file_path,
-            chunk_type
+            chunk_type,
+            output,
+            hiding,
+            display,
+            payload,
+            join,
+            extract_file,
+            key,
+            quiet,
+            json
         } => {
-            decode(file_path, chunk_type)?
+            let miss = Miss { quiet: *quiet, json: *json };
+            let chunk_type = chunk_type_or_last(chunk_type)?;
+
+            decode(file_path, &chunk_type, output, hiding, display, payload, *join, *extract_file, key, miss, crc).with_path(file_path)?
         },
         
         Commands::Remove {
             file_path,
-            chunk_type
+            chunk_type,
+            ignore_missing,
+            print,
+            output,
+            undoable,
+            shred
         } => {
-            remove(&file_path, &chunk_type)?
+            let chunk_type = chunk_type_or_last(chunk_type)?;
+
+            remove(file_path, &chunk_type, *ignore_missing, *print, output, *undoable, *shred, recording, crc).with_path(file_path)?
+        },
+
+        Commands::Undo { file_path, all } => {
+            undo(file_path, *all, crc).with_path(file_path)?
+        },
+
+        Commands::AuditLog { file_path, clear } => {
+            audit_log(file_path, *clear, crc).with_path(file_path)?
+        },
+
+        Commands::Filter { file_path, keep_types, drop_types, output, allow_critical } => {
+            let selection = if keep_types.is_empty() { Selection::Drop(drop_types) } else { Selection::Keep(keep_types) };
+
+            filter(file_path, selection, output, *allow_critical, recording, crc).with_path(file_path)?
+        },
+
+        Commands::SetData { file_path, chunk_type, index, data_file, data, allow_critical } => {
+            let data = match data_file {
+                Some(data_file) => fs::read(data_file).map_err(|error| PngMeError::from(error).into()).with_path(data_file)?,
+                None => data.clone().unwrap_or_default().into_bytes(),
+            };
+
+            set_data(file_path, chunk_type, *index, data, *allow_critical, recording, crc).with_path(file_path)?
+        },
+
+        Commands::Edit { file_path, chunk_type, create } => {
+            edit(file_path, chunk_type, *create, &editor::from_env()?, recording, crc).with_path(file_path)?
+        },
+
+        Commands::Keygen { symmetric: _, output } => {
+            keygen(output).with_path(output)?
+        },
+
+        Commands::CatChunk { file_path, chunk_type, index, all, output, force_tty } => {
+            cat_chunk(file_path, chunk_type, *index, *all, output, *force_tty, crc).with_path(file_path)?
+        },
+
+        Commands::Optimize { file_path, output, dry_run, drop_chaff } => {
+            optimize(file_path, output, *dry_run, drop_chaff.as_deref(), recording, crc).with_path(file_path)?
+        },
+
+        Commands::CopyChunks { from, file_path, on_duplicate, include_text, output } => {
+            let policy = MergePolicy { duplicates: *on_duplicate, include_text: *include_text };
+
+            copy_chunks(from, file_path, policy, output, recording, crc).with_path(file_path)?
+        },
+
+        // Every file is named in the errors about it, there is no single path to add
+        Commands::SplitFiles { payload, covers, chunk_type } => {
+            split_files(payload, covers, chunk_type, recording, crc)?
+        },
+
+        Commands::JoinFiles { parts, output, chunk_type } => {
+            join_files(parts, output, chunk_type, crc)?
+        },
+
+        Commands::Dedupe { file_path, keep, output } => {
+            dedupe(file_path, *keep, output, recording, crc).with_path(file_path)?
+        },
+
+        Commands::Normalize { file_path, output } => {
+            normalize(file_path, output, recording, crc).with_path(file_path)?
+        },
+
+        Commands::Transform { remove } => {
+            transform(&mut io::stdin().lock(), &mut io::stdout().lock(), remove, recording, crc)?
+        },
+
+        Commands::Keyring { action } => {
+            keyring(action)?
+        },
+
+        Commands::GitFilter { mode } => {
+            git_filter(&mut io::stdin().lock(), &mut io::stdout().lock(), mode, crc)?
+        },
+
+        Commands::Exif { file_path, dump } => {
+            exif(file_path, dump, crc).with_path(file_path)?
+        },
+
+        Commands::Baseline { file_path, output } => {
+            baseline(file_path, output, crc).with_path(file_path)?
+        },
+
+        Commands::VerifyAgainst { file_path, baseline } => {
+            verify_against(file_path, baseline, crc).with_path(file_path)?
+        },
+
+        Commands::Capacity { file_path, hiding } => {
+            capacity(file_path, hiding, crc).with_path(file_path)?
+        },
+
+        Commands::Validate { file_path, order_check, count_only } => {
+            validate(file_path, *order_check, *count_only, crc).with_path(file_path)?
+        },
+
+        Commands::Verify { file_path, threads, deep, format } => {
+            verify(file_path, *threads, *deep, *format, crc).with_path(file_path)?
         },
 
-        Commands::Print { file_path } => {
-            print(file_path)?
+        Commands::CheckTree { directory, deny, allow, archive } => {
+            check_tree(directory, deny, allow, *archive, crc).with_path(directory)?
+        },
+
+        Commands::Types => {
+            for line in known_types::table() {
+                println!("{}", line);
+            }
+        },
+
+        Commands::Convert { input, output, encode } => {
+            convert(input, output, encode.as_deref(), recording, crc).with_path(input)?
+        },
+
+        Commands::Identify { file_path } => {
+            identify(file_path).with_path(file_path)?
+        },
+
+        Commands::Inspect { file_path, offset, json } => {
+            inspect(file_path, *offset, *json, crc).with_path(file_path)?
+        },
+
+        Commands::Count { file_path, chunk_type, bytes } => {
+            println!("{}", count(file_path, chunk_type, *bytes, crc).with_path(file_path)?)
+        },
+
+        Commands::Print { file_path, offsets, reverse, skip } => {
+            print(file_path, *offsets, *reverse, skip.crc_skip(), crc).with_path(file_path)?
+        },
+
+        Commands::Apply { file_path, manifest, output, dry_run } => {
+            apply(file_path, manifest, output, *dry_run, recording, crc).with_path(file_path)?
+        },
+
+        Commands::List { file_path, options } => {
+            list(file_path, options, crc).with_path(file_path)?
         }
     }
 
-    Ok(())
+    // On stderr, so that it never mixes with output meant for a pipe
+    if let Some(metrics) = metrics::take() {
+        eprintln!("{}", metrics);
+    }
+
+    // A command with nothing left to write after the Ctrl-C still ends with its exit code
+    interrupt::check()
 }
 
-fn encode(file_path: &PathBuf, chunk_type: &str, message: &str, output_file: &Option<PathBuf>) -> Result<()> {
+#[allow(clippy::too_many_arguments)]
+fn encode(file_path: &Path, chunk_type: Option<&str>, message: Option<&[u8]>, raw_chunk: &Option<PathBuf>, output_file: &Option<PathBuf>, split: Option<u32>, limit_output: Option<u64>, undoable: bool, append_only: bool, prepend: bool, preview: bool, hiding: &HidingOptions, chaff: &ChaffOptions, recording: Recording, crc: &Crc<u32>) -> Result<SizeReport> {
     
-    if file_path.extension().unwrap() != "png" {
-        return Err("This program takes only PNG files".into())
+    let mut png = read_png(file_path, crc)?;
+    let before = png.total_size();
+
+    check_bits(hiding)?;
+
+    let payload = if hiding.mode == HidingMode::Lsb {
+        if raw_chunk.is_some() || split.is_some() || undoable || append_only || prepend || preview || chaff.chaff > 0 {
+            return Err("--raw-chunk, --split, --undoable, --append-only, --prepend, --preview and --chaff cannot be used with --mode lsb".into())
+        }
+
+        check_lsb_chunk_type(chunk_type.unwrap_or_default())?;
+
+        let message = message.ok_or("A message is required")?;
+
+        // Nothing is recorded in the history log, it would give the message away
+        lsb::embed(&mut png, message, hiding.bits, crc)?;
+        message.len()
+    } else {
+        let (payload, message_chunks) = encode_chunks(&mut png, chunk_type, message, raw_chunk, split, undoable, append_only, prepend, preview, recording, crc)?;
+
+        // The decoys stay out of the history log, which would give them away
+        if let (1.., Some(key)) = (chaff.chaff, &chaff.chaff_key) {
+            let mut rng = chaff.seed.map(chaff::Rng::seeded).unwrap_or_else(chaff::Rng::from_entropy);
+            chaff::add(&mut png, chaff.chaff as usize, payload, &message_chunks, key.as_bytes(), &mut rng, crc);
+        }
+
+        payload
+    };
+
+    if let Some(limit) = limit_output {
+        check_output_size(png.total_size(), limit)?;
+    }
+
+    let bytes = png.as_bytes();
+
+    write_output(file_path, output_file, &bytes)?;
+
+    Ok(SizeReport { payload, before, after: bytes.len() })
+}
+
+/// Warns about a chunk type whose reserved bit is set, which the PNG specification
+/// does not allow, and with `fix` clears the bit
+fn checked_chunk_type(chunk_type: &str, fix: bool) -> Result<String> {
+
+    let parsed = ChunkType::from_str(chunk_type)?;
+
+    let Some(warning) = reserved_bit_warning(&parsed) else {
+        return Ok(chunk_type.to_string())
+    };
+
+    let fixed = parsed.with_reserved_bit_cleared().to_string();
+
+    if fix {
+        eprintln!("Warning: the chunk type '{}' has the reserved bit set, encoding it as '{}'", chunk_type, fixed);
+
+        return Ok(fixed)
+    }
+
+    eprintln!("Warning: {}", warning);
+
+    Ok(chunk_type.to_string())
+}
+
+/// Why a chunk type with the reserved bit set is a mistake, and the type that was likely meant
+fn reserved_bit_warning(chunk_type: &ChunkType) -> Option<String> {
+
+    if chunk_type.is_reserved_bit_valid() {
+        return None
+    }
+
+    Some(format!(
+        "'{}' is a non-conformant chunk type: its third letter is lowercase, which sets the reserved bit the PNG \
+         specification requires to be unset. Use '{}' or pass --fix-type",
+        chunk_type, chunk_type.with_reserved_bit_cleared()
+    ))
+}
+
+/// Warns about a chunk type the PNG specification defines, since a message in
+/// one can corrupt the image, and with `strict` refuses it
+fn check_standard_type(chunk_type: &str, strict: bool) -> Result<()> {
+
+    let Some(description) = known_types::describe(chunk_type) else {
+        return Ok(())
+    };
+
+    if strict {
+        return Err(format!("{} is a standard chunk type ({}), --strict-type refuses to encode a message in it", chunk_type, description).into())
+    }
+
+    eprintln!("Warning: {} is a standard chunk type ({}), a message in it can corrupt the image", chunk_type, description);
+
+    Ok(())
+}
+
+/// Rejects a payload larger than `max_size` bytes
+fn check_max_size(payload: usize, max_size: u64) -> Result<()> {
+
+    if payload as u64 > max_size {
+        return Err(format!("The payload is {} bytes, more than the --max-size of {} bytes", payload, max_size).into())
+    }
+
+    Ok(())
+}
+
+/// Rejects a file that would be larger than `limit` bytes once written
+fn check_output_size(size: usize, limit: u64) -> Result<()> {
+
+    if size as u64 > limit {
+        return Err(format!("The file would be {} bytes, more than the --limit-output-bytes of {} bytes, nothing was written", size, limit).into())
+    }
+
+    Ok(())
+}
+
+/// What an encode did to the size of the file, measured on the bytes written, so that
+/// the chunk framing, the history log and recompressed image data are all accounted for
+#[derive(Debug, PartialEq)]
+struct SizeReport {
+    payload: usize,
+    before: usize,
+    after: usize,
+}
+
+impl SizeReport {
+    /// Negative when the file shrank, which recompressing the image data can do
+    fn added(&self) -> i64 {
+
+        self.after as i64 - self.before as i64
+    }
+
+    /// With --explain the metrics of the command are included as a `metrics` object
+    fn to_json(&self, metrics: Option<&Metrics>) -> String {
+
+        let metrics = metrics.map(|metrics| format!(", \"metrics\": {}", metrics.to_json())).unwrap_or_default();
+
+        format!(
+            "{{\"payload_bytes\": {}, \"added_bytes\": {}, \"size_before\": {}, \"size_after\": {}{}}}",
+            self.payload, self.added(), self.before, self.after, metrics
+        )
     }
+}
 
-    let file = fs::read(file_path)?;
+impl std::fmt::Display for SizeReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
 
-    let mut png = Png::try_from(file.as_slice())?;
+        writeln!(f, "Payload: {} bytes", self.payload)?;
+        writeln!(f, "Added to the file: {} bytes", self.added())?;
+        write!(f, "File size: {} -> {} bytes", self.before, self.after)
+    }
+}
 
-    let chunk_type = ChunkType::from_str(chunk_type)?;
-    let chunk = Chunk::new(chunk_type, message.as_bytes().to_vec());
+/// What `encode --preview` shows for a chunk it is about to add
+fn preview_line(chunk: &Chunk) -> String {
 
-    png.append_chunk(chunk);
+    format!("Adding {}", chunk)
+}
 
-    match output_file {
+/// Adds the chunks of the message, returning the bytes of the payload and where the chunks ended up
+#[allow(clippy::too_many_arguments)]
+fn encode_chunks(png: &mut Png, chunk_type: Option<&str>, message: Option<&[u8]>, raw_chunk: &Option<PathBuf>, split: Option<u32>, undoable: bool, append_only: bool, prepend: bool, preview: bool, recording: Recording, crc: &Crc<u32>) -> Result<(usize, Vec<usize>)> {
 
-        Some(output_file) => { 
-            let _ = fs::write(output_file, png.as_bytes());
+    let (chunks, payload) = match raw_chunk {
+        Some(raw_chunk) => {
+            let chunk = read_raw_chunk(raw_chunk, crc)?;
+            let payload = chunk.data().len();
 
-            println!("New file has been created and message encoded successfully!");
+            (vec![chunk], payload)
         },
         None => {
-            let _ = fs::write(file_path, png.as_bytes());
+            let chunk_type = ChunkType::from_str(chunk_type.ok_or("A chunk type is required")?)?;
+            let message = message.ok_or("A message is required")?;
+
+            let pieces = match split {
+                Some(piece_size) => split::split(message, piece_size as usize)?,
+                None => vec![message.to_vec()],
+            };
+            let chunks = pieces.into_iter().map(|piece| Chunk::new_with_crc(chunk_type.clone(), piece, crc)).collect();
+
+            (chunks, message.len())
+        }
+    };
+
+    let chunk_type = chunks[0].chunk_type().to_string();
+
+    if append_only && png.contains_type(&chunk_type) {
+        return Err(format!(
+            "The file already has a {} chunk and --append-only is set, use set-data to change its data instead",
+            chunk_type
+        ).into())
+    }
+
+    if preview {
+        for chunk in &chunks {
+            println!("{}", preview_line(chunk));
+        }
+    }
+
+    let count = chunks.len();
+
+    with_history(png, recording, crc, |png| {
+        let entries = chunks.iter().map(|chunk| Entry::new("encode", &chunk_type, chunk.data().len())).collect();
+
+        // Inserted last to first, so that the pieces of a split message keep their order after IHDR
+        if prepend {
+            for chunk in chunks.into_iter().rev() {
+                png.insert_after_type("IHDR", chunk)?;
+            }
+        } else {
+            for chunk in chunks {
+                if undoable {
+                    undo::append_undoable(png, chunk, crc)?;
+                } else {
+                    png.insert_before_iend(chunk);
+                }
+            }
+        }
 
-            println!("Message encoded successfully!");
+        Ok(((), entries))
+    })?;
+
+    // The new chunks are the first of their type after IHDR when prepended, the last ones otherwise
+    let mut positions: Vec<usize> = png.chunks().iter().enumerate()
+        .filter(|(_, chunk)| chunk.chunk_type().to_string() == chunk_type)
+        .map(|(index, _)| index)
+        .collect();
+
+    if prepend {
+        positions.truncate(count);
+    } else {
+        positions.drain(..positions.len().saturating_sub(count));
+    }
+
+    Ok((payload, positions))
+}
+
+#[allow(clippy::too_many_arguments)]
+fn decode(file_path: &Path, chunk_type: &str, output: &Option<PathBuf>, hiding: &HidingOptions, display: &DisplayOptions, payload: &PayloadOptions, join: bool, extract_file: bool, key: &KeyOptions, miss: Miss, crc: &Crc<u32>) -> Result<()> {
+
+    let png = read_png(file_path, crc)?;
+
+    check_bits(hiding)?;
+
+    let data = if hiding.mode == HidingMode::Lsb {
+        if join {
+            return Err("--join cannot be used with --mode lsb".into())
+        }
+
+        check_lsb_chunk_type(chunk_type)?;
+
+        lsb::extract(&png, hiding.bits)?
+    } else if join {
+        let pieces: Vec<&[u8]> = png.chunks().iter()
+            .filter(|chunk| chunk.chunk_type().to_string() == chunk_type)
+            .map(Chunk::data)
+            .collect();
+
+        if pieces.is_empty() {
+            return Err(missing_message(&png, chunk_type, miss))
+        }
+
+        split::join(&pieces)?
+    } else {
+        match png.chunk_by_type(chunk_type) {
+            Some(chunk) => message_data(chunk)?,
+            None => return Err(missing_message(&png, chunk_type, miss)),
+        }
+    };
+
+    let data = decrypt_message(data, key)?;
+    let data = unwrap_payload(data, payload)?;
+
+    if extract_file {
+        match EmbeddedFile::from_bytes(&data) {
+            Some(embedded) => return write_embedded_file(&embedded, output),
+            None => eprintln!("The message holds no embedded file header, showing it as is"),
         }
+    }
+
+    show_data(data, output, display)
+}
+
+/// Writes an embedded file to `output`, or under its own name in the current
+/// directory, which it never overwrites
+fn write_embedded_file(embedded: &EmbeddedFile, output: &Option<PathBuf>) -> Result<()> {
 
+    let path = match output {
+        Some(output) => output.clone(),
+        None => PathBuf::from(&embedded.name),
+    };
+
+    if output.is_none() && path.exists() {
+        return Err(format!("{} already exists, use --output to write the embedded file elsewhere", path.display()).into())
     }
 
+    write_file(&path, &embedded.data)?;
+
+    println!("Extracted {} ({}, {} bytes) to {}", embedded.name, embedded.mime_type, embedded.data.len(), path.display());
+
     Ok(())
 }
 
-fn decode(file_path: &PathBuf, chunk_type: &str) -> Result<()> {
+/// Encrypts the message under the key of --keyfile or a passphrase, with a fresh nonce every time
+fn encrypt_message(message: &[u8], key: &KeyOptions) -> Result<Vec<u8>> {
 
-    if file_path.extension().unwrap() != "png" {
-        return Err("This program takes only PNG files".into())
+    let nonce = crypt::random()?;
+
+    if let Some(keyfile) = &key.keyfile {
+        let key = crypt::read_keyfile(keyfile, key.allow_insecure_keyfile).with_path(keyfile)?;
+
+        return Ok(crypt::seal(&key, &crypt::Header { source: crypt::KeySource::Keyfile, nonce }, message))
     }
 
-    let file = fs::read(file_path)?;
+    let (passphrase, remember) = ask_passphrase(key, true)?;
+    let salt = crypt::random()?;
+    let derived = crypt::derive_key(&passphrase, &salt, crypt::PASSPHRASE_ITERATIONS);
+    let source = crypt::KeySource::Passphrase { salt, iterations: crypt::PASSPHRASE_ITERATIONS };
+
+    if remember {
+        remember_passphrase(key, &passphrase);
+    }
+
+    Ok(crypt::seal(&derived, &crypt::Header { source, nonce }, message))
+}
+
+/// Decrypts the message when a key source is given, and otherwise points out an encrypted one
+fn decrypt_message(data: Vec<u8>, key: &KeyOptions) -> Result<Vec<u8>> {
 
-    let png = Png::try_from(file.as_slice())?;
+    if key.keyfile.is_none() && !key.passphrase && key.use_keyring.is_none() {
+        if let Ok((header, _)) = crypt::Header::parse(&data) {
+            let needs = match header.source {
+                crypt::KeySource::Keyfile => "--keyfile <file>",
+                crypt::KeySource::Passphrase { .. } => "--passphrase or --use-keyring <service>",
+            };
 
-    match png.chunk_by_type(chunk_type) {
-        Some(chunk) => {
-            println!("Message: {:?}", chunk.data_as_string().unwrap());
+            eprintln!("The message is encrypted, decrypt it with {}", needs);
         }
-        None => println!("No message hidden in this image with this chunk type")
+
+        return Ok(data)
     }
 
-    Ok(())
+    let mut asked = None;
+
+    let opened = crypt::open(&data, |source| match (source, &key.keyfile) {
+        (crypt::KeySource::Keyfile, Some(keyfile)) => crypt::read_keyfile(keyfile, key.allow_insecure_keyfile).with_path(keyfile),
+        (crypt::KeySource::Passphrase { salt, iterations }, None) => {
+            let (passphrase, remember) = ask_passphrase(key, false)?;
+            let derived = crypt::derive_key(&passphrase, salt, *iterations);
+
+            asked = remember.then_some(passphrase);
+            Ok(derived)
+        },
+        (crypt::KeySource::Keyfile, None) => Err("The message was encrypted with a keyfile, decrypt it with --keyfile <file>".into()),
+        (crypt::KeySource::Passphrase { .. }, Some(_)) => {
+            Err("The message was encrypted with a passphrase, decrypt it with --passphrase or --use-keyring <service>".into())
+        },
+    })?;
+
+    // Only a passphrase that worked is worth keeping
+    if let Some(passphrase) = asked {
+        remember_passphrase(key, &passphrase);
+    }
+
+    Ok(opened)
 }
 
-fn remove(file_path: &PathBuf, chunk_type: &str) -> Result<()> {
+/// The passphrase from the keychain of --use-keyring, or else typed on the terminal. Also
+/// tells whether it should be stored in the keychain once it has been used.
+fn ask_passphrase(key: &KeyOptions, confirm: bool) -> Result<(String, bool)> {
 
-    if file_path.extension().unwrap() != "png" {
-        return Err("This program takes only PNG files".into())
+    let Some(service) = &key.use_keyring else {
+        return Ok((prompt::passphrase(confirm)?, false))
+    };
+
+    match read_keyring(service) {
+        Ok(Some(passphrase)) => Ok((passphrase, false)),
+        Ok(None) => Ok((prompt::passphrase(confirm)?, true)),
+        Err(error) => {
+            // Never anywhere else: a passphrase kept on disk in the clear protects nothing
+            eprintln!("Warning: cannot reach the keychain ({}), asking for the passphrase instead", error);
+
+            Ok((prompt::passphrase(confirm)?, false))
+        }
+    }
+}
+
+fn remember_passphrase(key: &KeyOptions, passphrase: &str) {
+
+    if let Some(service) = &key.use_keyring {
+        match store_keyring(service, passphrase) {
+            Ok(()) => eprintln!("Passphrase stored in the keychain as '{}'", service),
+            Err(error) => eprintln!("Warning: cannot store the passphrase in the keychain ({}), it will be asked for again", error),
+        }
     }
+}
 
-    let file = fs::read(file_path)?;
+#[cfg(feature = "keyring")]
+fn read_keyring(service: &str) -> Result<Option<String>> {
 
-    let mut png = Png::try_from(file.as_slice())?;
+    crate::keyring::get(service)
+}
+
+#[cfg(not(feature = "keyring"))]
+fn read_keyring(_service: &str) -> Result<Option<String>> {
 
-    png.remove_chunk(chunk_type)?;
+    Err("this pngme was built without keyring support, rebuild it with `--features keyring`".into())
+}
 
-    let _ = fs::write(file_path, png.as_bytes())?;
+#[cfg(feature = "keyring")]
+fn store_keyring(service: &str, passphrase: &str) -> Result<()> {
 
-    println!("Message has been removed successfully!");
+    crate::keyring::store(service, passphrase)
+}
+
+#[cfg(not(feature = "keyring"))]
+fn store_keyring(_service: &str, _passphrase: &str) -> Result<()> {
+
+    Err("this pngme was built without keyring support".into())
+}
+
+#[cfg(feature = "keyring")]
+fn keyring(action: &KeyringAction) -> Result<()> {
+
+    match action {
+        KeyringAction::Forget { service } => {
+            if crate::keyring::forget(service)? {
+                println!("Forgot the passphrase stored as '{}'", service);
+            } else {
+                println!("No passphrase is stored as '{}'", service);
+            }
+        }
+    }
 
     Ok(())
 }
 
-fn print(file_path: &PathBuf) -> Result<()> {
+#[cfg(not(feature = "keyring"))]
+fn keyring(_action: &KeyringAction) -> Result<()> {
 
-    if file_path.extension().unwrap() != "png" {
-        return Err("This program takes only PNG files".into())
+    Err("This pngme was built without keyring support, rebuild it with `--features keyring`".into())
+}
+
+/// Reverses the transforms given with --unwrap, or the ones --detect finds
+fn unwrap_payload(data: Vec<u8>, payload: &PayloadOptions) -> Result<Vec<u8>> {
+
+    if payload.detect {
+        let detection = detect::detect(&data);
+
+        if let Some(frame) = detection.frame {
+            let layers: Vec<String> = detection.layers.iter().map(Layer::to_string).collect();
+
+            println!("Detected: {}", layers.into_iter().chain([frame.to_string()]).collect::<Vec<_>>().join(" -> "));
+        } else if detection.layers.is_empty() {
+            println!(
+                "Detected: no transform, {} (entropy {:.2} bits/byte)",
+                if detect::printable_text(&data).is_some() { "text" } else { "binary data" }, detect::entropy(&data)
+            );
+        } else {
+            let layers: Vec<String> = detection.layers.iter().map(Layer::to_string).collect();
+            let kind = if detect::printable_text(&detection.data).is_some() { "text" } else { "binary data" };
+
+            println!("Detected: {} -> {} (reproduce with --unwrap {})", layers.join(" -> "), kind, layers.join(","));
+        }
+
+        return Ok(detection.data)
+    }
+
+    payload.unwrap.iter().try_fold(data, |data, layer| {
+        layer.unwrap(&data).map_err(|error| format!("Cannot reverse the {} layer: {}", layer, error).into())
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+fn remove(file_path: &Path, chunk_type: &str, ignore_missing: bool, print: bool, output: &Option<PathBuf>, undoable: bool, shred: bool, recording: Recording, crc: &Crc<u32>) -> Result<()> {
+
+    let mut png = read_png(file_path, crc)?;
+
+    if png.chunk_by_type(chunk_type).is_none() {
+
+        if ignore_missing {
+            eprintln!("No chunk of type '{}' to remove, leaving the file unchanged", chunk_type);
+
+            return Ok(())
+        }
+
+        return Err(PngMeError::NotFound(missing_chunk_message(&png, chunk_type)).into())
+    }
+
+    // Where the data of the removed chunk sits in the file as it is on disk, past its length and type
+    let shredded_range = png.position_of_type(chunk_type)
+        .map(|position| png.chunk_positions()[position].range.clone())
+        .map(|range| (range.start + 8, (range.end - range.start - 8) as usize));
+
+    let removed = with_history(&mut png, recording, crc, |png| {
+        let removed = if undoable {
+            undo::remove_undoable(png, chunk_type, crc)?
+        } else {
+            png.remove_chunk(chunk_type)?
+        };
+        let entry = Entry::new("remove", chunk_type, removed.data().len());
+
+        Ok((removed, vec![entry]))
+    })?;
+
+    if print {
+        // The confirmation follows on stdout, so binary data has to go to a file
+        show_message(&removed, output, &DisplayOptions { ascii_only: true, ..Default::default() })?;
     }
 
-    let file = fs::read(file_path)?;
+    match shredded_range {
+        Some((offset, length)) if shred => {
+            shred::zero_range(file_path, offset, length)?;
+            write_atomically(file_path, &png.as_bytes())?;
 
-    let png = Png::try_from(file.as_slice())?;
+            println!("Overwrote the {} bytes of the removed chunk with zeroes before replacing the file", length);
+        },
+        _ => write_file(file_path, png.as_bytes())?,
+    }
 
-    println!("{}", png);
+    println!("Message has been removed successfully!");
 
     Ok(())
-}
\ No newline at end of file
+}
+
+fn undo(file_path: &Path, all: bool, crc: &Crc<u32>) -> Result<()> {
+
+    let mut png = read_png(file_path, crc)?;
+
+    let reverted = with_history(&mut png, Recording { enabled: false, limit: history::DEFAULT_HISTORY_LIMIT }, crc, |png| {
+        Ok((undo::undo(png, all, crc)?, vec![]))
+    })?;
+
+    for reverted in reverted {
+        println!("Undone: {}", reverted);
+    }
+
+    write_file(file_path, png.as_bytes())?;
+
+    Ok(())
+}
+
+fn audit_log(file_path: &Path, clear: bool, crc: &Crc<u32>) -> Result<()> {
+
+    let mut png = read_png(file_path, crc)?;
+    let log = Log::take_from(&mut png)?;
+
+    if clear {
+        if log.entries().is_empty() {
+            println!("There is no history log to clear");
+
+            return Ok(())
+        }
+
+        write_file(file_path, png.as_bytes())?;
+
+        println!("Cleared {} history entries", log.entries().len());
+
+        return Ok(())
+    }
+
+    if log.entries().is_empty() {
+        println!("No history recorded in this file");
+    }
+
+    for entry in log.entries() {
+        println!("{}", entry);
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "clipboard")]
+fn read_clipboard() -> Result<String> {
+
+    crate::clipboard::read_text()
+}
+
+#[cfg(not(feature = "clipboard"))]
+fn read_clipboard() -> Result<String> {
+
+    Err("This pngme was built without clipboard support, rebuild it with `--features clipboard`".into())
+}
+
+/// Expands a message template for encoding `file_path`
+fn expand_template(template: &str, file_path: &Path) -> Result<String> {
+
+    let pieces = template::parse(template)?;
+
+    template::render(&pieces, &template::Context::current(file_path))
+}
+
+fn filter(file_path: &Path, selection: Selection, output: &Option<PathBuf>, allow_critical: bool, recording: Recording, crc: &Crc<u32>) -> Result<()> {
+
+    let mut png = read_png(file_path, crc)?;
+
+    let critical: Vec<String> = png.chunks().iter()
+        .enumerate()
+        .filter(|(_, chunk)| chunk.chunk_type().is_critical() && selection.removes(chunk))
+        .map(|(index, chunk)| format!("{} (index {})", chunk.chunk_type(), index))
+        .collect();
+
+    if !critical.is_empty() && !allow_critical {
+        return Err(format!("This would drop the critical chunks {}, pass --allow-critical to drop them anyway", critical.join(", ")).into())
+    }
+
+    let removed = with_history(&mut png, recording, crc, |png| {
+        let removed = png.retain_chunks(|chunk| !selection.removes(chunk));
+
+        let entries = removed.iter()
+            .map(|chunk| Entry::new("filter", &chunk.chunk_type().to_string(), chunk.data().len()))
+            .collect();
+
+        Ok((removed, entries))
+    })?;
+
+    write_output(file_path, output, &png.as_bytes())?;
+
+    let types: Vec<String> = removed.iter().map(|chunk| chunk.chunk_type().to_string()).collect();
+    println!("Removed {} chunks{}{}", removed.len(), if types.is_empty() { "" } else { ": " }, types.join(", "));
+
+    Ok(())
+}
+
+fn split_files(payload_path: &Path, covers: &[PathBuf], chunk_type: &str, recording: Recording, crc: &Crc<u32>) -> Result<()> {
+
+    let chunk_type = ChunkType::from_str(&checked_chunk_type(chunk_type, false)?)?;
+
+    for (position, cover) in covers.iter().enumerate() {
+        if covers[..position].iter().any(|earlier| alias::same_file(earlier, cover)) {
+            return Err(format!("{} is given twice, every part needs a file of its own", cover.display()).into())
+        }
+    }
+
+    let payload = fs::read(payload_path).map_err(|error| PngMeError::from(error).into()).with_path(payload_path)?;
+    // A random id, as one derived from the payload would tell which files carry the same one
+    let id = chaff::Rng::from_entropy().next_u64().to_be_bytes();
+    let parts = split::split_parts(&payload, covers.len(), id)?;
+
+    // Every file is read before any is written, so that a bad one leaves them all unchanged
+    let mut pngs = vec![];
+
+    for cover in covers {
+        let png = read_png(cover, crc).with_path(cover)?;
+
+        if png.contains_type(&chunk_type.to_string()) {
+            return Err(PngMeError::WithPath {
+                path: cover.clone(),
+                source: format!("The file already has a {} chunk, whose part would be mixed up with the new one", chunk_type).into(),
+            }.into())
+        }
+
+        pngs.push(png);
+    }
+
+    for ((cover, mut png), part) in covers.iter().zip(pngs).zip(parts) {
+        with_history(&mut png, recording, crc, |png| {
+            let entry = Entry::new("split-files", &chunk_type.to_string(), part.len());
+            png.insert_before_iend(Chunk::new_with_crc(chunk_type.clone(), part, crc));
+
+            Ok(((), vec![entry]))
+        }).with_path(cover)?;
+
+        write_output(cover, &None, &png.as_bytes()).with_path(cover)?;
+    }
+
+    println!("Split {} bytes into {} parts in {} chunks", payload.len(), covers.len(), chunk_type);
+
+    Ok(())
+}
+
+fn join_files(files: &[PathBuf], output: &Path, chunk_type: &str, crc: &Crc<u32>) -> Result<()> {
+
+    let mut parts = vec![];
+
+    for file in files {
+        let png = read_png(file, crc).with_path(file)?;
+        let found: Vec<Vec<u8>> = png.chunks().iter()
+            .filter(|chunk| chunk.chunk_type().to_string() == chunk_type)
+            .map(|chunk| chunk.data().to_vec())
+            .collect();
+
+        if found.is_empty() {
+            return Err(PngMeError::WithPath {
+                path: file.clone(),
+                source: PngMeError::NotFound(format!("No {} chunk holding a part in this image", chunk_type)).into(),
+            }.into())
+        }
+
+        parts.extend(found);
+    }
+
+    let payload = split::join_parts(&parts.iter().map(Vec::as_slice).collect::<Vec<_>>())?;
+
+    write_file(output, &payload).with_path(output)?;
+
+    println!("Joined {} parts into {} bytes, written to {}", parts.len(), payload.len(), output.display());
+
+    Ok(())
+}
+
+fn dedupe(file_path: &Path, keep: Keep, output: &Option<PathBuf>, recording: Recording, crc: &Crc<u32>) -> Result<()> {
+
+    let mut png = read_png(file_path, crc)?;
+
+    let removed = with_history(&mut png, recording, crc, |png| {
+        let removed = png.dedupe(keep);
+
+        let entries = removed.iter()
+            .map(|chunk| Entry::new("dedupe", &chunk.chunk_type().to_string(), chunk.data().len()))
+            .collect();
+
+        Ok((removed, entries))
+    })?;
+
+    write_output(file_path, output, &png.as_bytes())?;
+
+    let types: Vec<String> = removed.iter().map(|chunk| chunk.chunk_type().to_string()).collect();
+    println!("Removed {} duplicate chunks{}{}", removed.len(), if types.is_empty() { "" } else { ": " }, types.join(", "));
+
+    Ok(())
+}
+
+fn set_data(file_path: &Path, chunk_type: &str, index: usize, data: Vec<u8>, allow_critical: bool, recording: Recording, crc: &Crc<u32>) -> Result<()> {
+
+    let mut png = read_png(file_path, crc)?;
+
+    let old_length = with_history(&mut png, recording, crc, |png| {
+        let chunk = png.chunk_by_type_mut(chunk_type, index).ok_or_else(|| {
+            PngMeError::NotFound(format!("No chunk with type '{}' at index {} in this image", chunk_type, index))
+        })?;
+
+        if chunk.chunk_type().is_critical() && !allow_critical {
+            return Err(format!("{} is a critical chunk, pass --allow-critical to change its data anyway", chunk_type).into())
+        }
+
+        let old_length = chunk.data().len();
+        let entry = Entry::new("set-data", chunk_type, data.len());
+
+        chunk.set_data_with_crc(data.clone(), crc);
+
+        Ok((old_length, vec![entry]))
+    })?;
+
+    write_file(file_path, png.as_bytes())?;
+
+    println!("Replaced the {} bytes of {} (index {}) with {} bytes", old_length, chunk_type, index, data.len());
+
+    Ok(())
+}
+
+fn keygen(output: &Path) -> Result<()> {
+
+    crypt::write_keyfile(output, &crypt::random()?)?;
+
+    println!("Key written to {}, keep it secret", output.display());
+
+    Ok(())
+}
+
+fn edit(file_path: &Path, chunk_type: &str, create: bool, editor: &str, recording: Recording, crc: &Crc<u32>) -> Result<()> {
+
+    let mut png = read_png(file_path, crc)?;
+
+    let text = match png.chunk_by_type(chunk_type) {
+        Some(chunk) => String::from_utf8(chunk.data().to_vec()).map_err(|_| {
+            format!("The {} chunk does not hold UTF-8 text, use decode --output to extract it and set-data --data-file to change it", chunk_type)
+        })?,
+        None if create => String::new(),
+        None => return Err(PngMeError::NotFound(format!("{}, pass --create to start an empty one", missing_chunk_message(&png, chunk_type))).into()),
+    };
+
+    let Some(edited) = editor::edit(editor, &text)? else {
+        println!("The text is unchanged, leaving the file as it is");
+
+        return Ok(())
+    };
+
+    with_history(&mut png, recording, crc, |png| {
+        let entry = Entry::new("edit", chunk_type, edited.len());
+
+        match png.chunk_by_type_mut(chunk_type, 0) {
+            Some(chunk) => chunk.set_data_with_crc(edited.clone().into_bytes(), crc),
+            None => {
+                png.insert_before_iend(Chunk::new_with_crc(ChunkType::from_str(chunk_type)?, edited.clone().into_bytes(), crc));
+            },
+        }
+
+        Ok(((), vec![entry]))
+    })?;
+
+    write_atomically(file_path, &png.as_bytes())?;
+
+    println!("Saved {} bytes of edited text to {}", edited.len(), chunk_type);
+
+    Ok(())
+}
+
+/// Writes the file next to its destination first and renames it into place,
+/// so that a failure halfway never leaves a truncated file behind
+fn write_atomically(file_path: &Path, bytes: &[u8]) -> Result<()> {
+
+    refuse_data_uri(file_path)?;
+
+    let name = file_path.file_name().ok_or("The path does not name a file")?;
+    let staging = file_path.with_file_name(format!(".{}.pngme-tmp", name.to_string_lossy()));
+
+    interrupt::check()?;
+
+    // A Ctrl-C from here on waits for the rename, or for the staging file to be removed
+    let written = interrupt::deferred(|| {
+        let written = metrics::time(Stage::Write, || {
+            fs::write(&staging, bytes)?;
+
+            // A viewer holding the file open makes the rename fail on Windows until it lets go
+            retry::retrying(retry::current(), retry::is_sharing_violation, || fs::rename(&staging, file_path))
+        });
+
+        // Whatever failed, the staging file is not left behind
+        if written.is_err() {
+            let _ = fs::remove_file(&staging);
+        }
+
+        written
+    });
+
+    written.map_err(|error| retry::explain(file_path, error, retry::is_sharing_violation))?;
+    metrics::update(|metrics| metrics.bytes_written += bytes.len());
+
+    Ok(())
+}
+
+fn cat_chunk(file_path: &Path, chunk_type: &str, index: usize, all: bool, output: &Option<PathBuf>, force_tty: bool, crc: &Crc<u32>) -> Result<()> {
+
+    let png = read_png(file_path, crc)?;
+    let (bytes, count) = framed_chunks(&png, chunk_type, index, all)?;
+
+    match output {
+        Some(output) => {
+            write_file(output, &bytes)?;
+
+            println!("{} {} of {} bytes written to {}", count, if count == 1 { "chunk" } else { "chunks" }, bytes.len(), output.display());
+        },
+        None if io::stdout().is_terminal() && !force_tty => {
+            return Err("A chunk is binary data, use --output <file> or pass --force-tty to write it to the terminal anyway".into())
+        },
+        None => io::stdout().write_all(&bytes).map_err(PngMeError::from)?,
+    }
+
+    Ok(())
+}
+
+/// The framed bytes of the chunk of this type at `index`, or of every chunk
+/// of this type with `all`, along with how many chunks they hold
+fn framed_chunks(png: &Png, chunk_type: &str, index: usize, all: bool) -> Result<(Vec<u8>, usize)> {
+
+    let matching: Vec<&Chunk> = png.chunks().iter()
+        .filter(|chunk| chunk.chunk_type().to_string() == chunk_type)
+        .collect();
+
+    let selected = if all { &matching[..] } else { matching.get(index..=index).unwrap_or_default() };
+
+    if selected.is_empty() {
+        let position = if all { String::new() } else { format!(" at index {}", index) };
+
+        return Err(PngMeError::NotFound(format!("No chunk with type '{}'{} in this image", chunk_type, position)).into())
+    }
+
+    let mut bytes = Vec::with_capacity(selected.iter().map(|chunk| chunk.size()).sum());
+
+    for chunk in selected {
+        chunk.extend_bytes(&mut bytes);
+    }
+
+    Ok((bytes, selected.len()))
+}
+
+fn optimize(file_path: &Path, output: &Option<PathBuf>, dry_run: bool, drop_chaff: Option<&str>, recording: Recording, crc: &Crc<u32>) -> Result<()> {
+
+    let mut png = read_png(file_path, crc)?;
+
+    let plan = with_history(&mut png, recording, crc, |png| {
+        let plan = optimize::plan(png, drop_chaff.map(str::as_bytes));
+
+        if !dry_run {
+            optimize::apply(png, &plan);
+        }
+
+        let entries = plan.iter()
+            .map(|removal| Entry::new("optimize", &removal.chunk_type, removal.size - 12))
+            .collect();
+
+        Ok((plan, entries))
+    })?;
+
+    for removal in &plan {
+        println!("{}", removal);
+    }
+
+    let saved: usize = plan.iter().map(|removal| removal.size).sum();
+
+    if dry_run {
+        println!("Dry run, {} chunks ({} bytes) would be removed", plan.len(), saved);
+
+        return Ok(())
+    }
+
+    if plan.is_empty() {
+        println!("Nothing to optimize");
+
+        return Ok(())
+    }
+
+    write_output(file_path, output, &png.as_bytes())?;
+
+    println!("Removed {} chunks, saving {} bytes", plan.len(), saved);
+
+    Ok(())
+}
+
+fn copy_chunks(from: &Path, file_path: &Path, policy: MergePolicy, output: &Option<PathBuf>, recording: Recording, crc: &Crc<u32>) -> Result<()> {
+
+    let source = read_png(from, crc).with_path(from)?;
+    let mut png = read_png(file_path, crc)?;
+
+    // The history log and undo records of the other file say nothing true about this one
+    let source = Png::from_chunks(source.into_chunks().into_iter()
+        .filter(|chunk| ![history::HISTORY_CHUNK_TYPE, undo::UNDO_CHUNK_TYPE].contains(&chunk.chunk_type().to_string().as_str()))
+        .collect());
+
+    let report = with_history(&mut png, recording, crc, |png| {
+        let report = png.merge(&source, policy);
+
+        let entries = report.positions.iter()
+            .map(|&index| &png.chunks()[index])
+            .map(|chunk| Entry::new("copy", &chunk.chunk_type().to_string(), chunk.data().len()))
+            .collect();
+
+        Ok((report, entries))
+    })?;
+
+    write_output(file_path, output, &png.as_bytes())?;
+
+    println!(
+        "Copied {} chunks, replaced {} and skipped {}{}",
+        report.taken.len(), report.replaced.len(), report.skipped.len(),
+        if report.skipped.is_empty() { String::new() } else { format!(": {}", report.skipped.join(", ")) }
+    );
+
+    Ok(())
+}
+
+fn normalize(file_path: &Path, output: &Option<PathBuf>, recording: Recording, crc: &Crc<u32>) -> Result<()> {
+
+    let mut png = read_png(file_path, crc)?;
+
+    // Nothing is added or removed, so there is nothing to record
+    let moved = with_history(&mut png, recording, crc, |png| {
+        let before: Vec<Vec<u8>> = png.chunks().iter().map(Chunk::as_bytes).collect();
+
+        ordering::normalize(png)?;
+
+        let moved = png.chunks().iter().zip(&before).filter(|(chunk, before)| chunk.as_bytes() != **before).count();
+
+        Ok((moved, vec![]))
+    })?;
+
+    let output = write_output(file_path, output, &png.as_bytes())?;
+
+    println!("Normalized {}, {} chunks changed position", output.display(), moved);
+
+    Ok(())
+}
+
+/// Reads a PNG file from `input`, removes every chunk of the given types and
+/// writes the result to `output`. Nothing but the PNG file is written to `output`.
+fn transform<R: Read, W: Write>(input: &mut R, output: &mut W, remove: &[String], recording: Recording, crc: &Crc<u32>) -> Result<()> {
+
+    let mut png = Png::read_from(input, crc)?;
+
+    with_history(&mut png, recording, crc, |png| {
+        let removed = png.retain_chunks(|chunk| !remove.contains(&chunk.chunk_type().to_string()));
+
+        let entries = removed.iter()
+            .map(|chunk| Entry::new("remove", &chunk.chunk_type().to_string(), chunk.data().len()))
+            .collect();
+
+        Ok(((), entries))
+    })?;
+
+    png.write_to(output)
+}
+
+/// Runs a git clean or smudge filter from `input` to `output`. Anything that goes
+/// wrong with the file is only warned about on stderr and the file passes through
+/// unchanged, so that git never fails because of pngme.
+fn git_filter<R: Read, W: Write>(input: &mut R, output: &mut W, mode: &GitFilterMode, crc: &Crc<u32>) -> Result<()> {
+
+    let mut bytes = vec![];
+    input.read_to_end(&mut bytes).map_err(PngMeError::from)?;
+
+    let filtered = if !png::is_png(&bytes) {
+        Ok(None)
+    } else {
+        match mode {
+            GitFilterMode::Clean { types, save_to } => git_filter_clean(&bytes, types, save_to, crc).map(Some),
+            GitFilterMode::Smudge { restore_from } => git_filter_smudge(&bytes, restore_from, crc),
+        }
+    };
+
+    let filtered = filtered.unwrap_or_else(|error| {
+        eprintln!("pngme: passing the file through unchanged: {}", error);
+        None
+    });
+
+    output.write_all(filtered.as_deref().unwrap_or(&bytes)).map_err(PngMeError::from)?;
+    output.flush().map_err(PngMeError::from)?;
+
+    Ok(())
+}
+
+fn git_filter_clean(bytes: &[u8], types: &[ChunkFilter], save_to: &Option<PathBuf>, crc: &Crc<u32>) -> Result<Vec<u8>> {
+
+    let configured;
+    let types = if types.is_empty() {
+        configured = configured_clean_types()?;
+        &configured
+    } else {
+        types
+    };
+
+    if types.is_empty() {
+        return Err(format!("No chunks to strip, pass --types or set the {} git config", git_filter::CLEAN_TYPES_KEY).into())
+    }
+
+    let (cleaned, removed) = git_filter::clean(bytes, types, crc)?;
+
+    if let Some(save_to) = save_to {
+        write_file(save_to, git_filter::to_sidecar(&removed))?;
+    }
+
+    Ok(cleaned)
+}
+
+/// Returns `None` when there is nothing to restore
+fn git_filter_smudge(bytes: &[u8], restore_from: &Option<PathBuf>, crc: &Crc<u32>) -> Result<Option<Vec<u8>>> {
+
+    let Some(restore_from) = restore_from else {
+        return Ok(None)
+    };
+
+    // A fresh clone has no saved chunks yet
+    let sidecar = match fs::read(restore_from) {
+        Ok(sidecar) => sidecar,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(error) => return Err(PngMeError::from(error).into()),
+    };
+
+    git_filter::smudge(bytes, &sidecar, crc).map(Some)
+}
+
+/// The chunk filters listed in the git config, none when git or the key is missing
+fn configured_clean_types() -> Result<Vec<ChunkFilter>> {
+
+    let Ok(output) = std::process::Command::new("git").args(["config", "--get", git_filter::CLEAN_TYPES_KEY]).output() else {
+        return Ok(vec![])
+    };
+
+    String::from_utf8_lossy(&output.stdout)
+        .split(',')
+        .map(str::trim)
+        .filter(|filter| !filter.is_empty())
+        .map(|filter| ChunkFilter::from_str(filter).map_err(|error| format!("{} in the {} git config", error, git_filter::CLEAN_TYPES_KEY).into()))
+        .collect()
+}
+
+fn exif(file_path: &Path, dump: &Option<PathBuf>, crc: &Crc<u32>) -> Result<()> {
+
+    let png = read_png(file_path, crc)?;
+
+    let chunk = png.chunk_by_type("eXIf")
+        .ok_or_else(|| PngMeError::NotFound("No eXIf chunk in this file".to_string()))?;
+
+    println!("eXIf: {}", exif::describe(chunk.data()));
+
+    if let Some(dump) = dump {
+        write_file(dump, chunk.data())?;
+
+        println!("Raw eXIf data of {} bytes written to {}", chunk.length(), dump.display());
+    }
+
+    Ok(())
+}
+
+fn baseline(file_path: &Path, output: &Option<PathBuf>, crc: &Crc<u32>) -> Result<()> {
+
+    let png = read_png(file_path, crc)?;
+    let baseline = Baseline::of(&png).to_json();
+
+    match output {
+        Some(output) => {
+            write_file(output, baseline)?;
+
+            println!("Baseline of {} chunks written to {}", png.chunks().len(), output.display());
+        },
+        None => print!("{}", baseline),
+    }
+
+    Ok(())
+}
+
+fn verify_against(file_path: &Path, baseline_path: &Path, crc: &Crc<u32>) -> Result<()> {
+
+    let baseline = fs::read_to_string(baseline_path).map_err(PngMeError::from)?;
+    let baseline = Baseline::from_json(&baseline)
+        .map_err(|error| format!("invalid baseline {}: {}", baseline_path.display(), error))?;
+
+    let png = read_png(file_path, crc)?;
+    let differences = baseline::compare(&baseline, &Baseline::of(&png));
+
+    if differences.is_empty() {
+        println!("No changes since the baseline");
+
+        return Ok(())
+    }
+
+    for difference in &differences {
+        println!("{}", difference);
+    }
+
+    let chunks = if differences.len() == 1 { "chunk differs" } else { "chunks differ" };
+
+    Err(PngMeError::Changed(format!("{} {} from the baseline", differences.len(), chunks)).into())
+}
+
+fn capacity(file_path: &Path, hiding: &HidingOptions, crc: &Crc<u32>) -> Result<()> {
+
+    let png = read_png(file_path, crc)?;
+
+    check_bits(hiding)?;
+
+    match hiding.mode {
+        HidingMode::Chunk => {
+            println!("Mode: chunk");
+            println!("Capacity: {} bytes per chunk", chunk::MAX_LENGTH);
+            println!("Assumptions:");
+            println!("  - the message is stored as is in a chunk of its own, whose data the PNG specification limits to 2^31 - 1 bytes");
+            println!("  - every chunk adds 12 bytes (length, type and crc) to the file on top of the message");
+            println!("  - the image itself does not limit the message, the file is {} bytes now", png.total_size());
+        },
+        HidingMode::Lsb => {
+            let header = lsb::supported_header(&png)?;
+
+            println!("Mode: lsb, {} bits per color sample", hiding.bits);
+            println!("Image: {}x{} {}, {}-bit", header.width, header.height, header.color_type_name(), header.bit_depth);
+            println!("Capacity: {} bytes", lsb::capacity(&header, hiding.bits));
+            println!("Assumptions:");
+            println!("  - {} color samples per pixel hide data, alpha is never changed", lsb::COLOR_SAMPLES);
+            println!("  - {} of the {} hidden bytes hold the message length", lsb::LENGTH_PREFIX, lsb::hidden_bytes(&header, hiding.bits));
+            println!("  - more bits per sample hide more data but change the pixels more visibly");
+
+            if header.interlace_method != 0 {
+                println!("  - the image is interlaced, the data is spread over its {} non-empty Adam7 passes in order", header.sub_images()?.len());
+            }
+        },
+    }
+
+    Ok(())
+}
+
+fn validate(file_path: &Path, order_check: bool, count_only: bool, crc: &Crc<u32>) -> Result<()> {
+
+    // A bad crc is one more problem to report, rather than a file that cannot be read
+    let png = read_png_skipping_crc(file_path, crc, CrcSkip::All)?;
+
+    let mut problems: Vec<String> = png.verify_all_crcs_with(crc).into_iter()
+        .enumerate()
+        .filter(|(_, (_, valid))| !valid)
+        .map(|(index, (chunk_type, _))| format!("{} at index {} has a bad crc", chunk_type, index))
+        .collect();
+    problems.extend(validate_structure(&png, order_check));
+
+    if count_only {
+        println!("{}", problem_tally(problems.len()));
+    } else if problems.is_empty() {
+        println!("No problems found");
+    } else {
+        for problem in &problems {
+            println!("{}", problem);
+        }
+    }
+
+    if problems.is_empty() {
+        return Ok(())
+    }
+
+    Err(format!("{} found", problem_tally(problems.len())).into())
+}
+
+/// Every problem with the structure of the file, in the order of its chunks
+fn validate_structure(png: &Png, order_check: bool) -> Vec<String> {
+
+    let mut problems: Vec<String> = png.chunks().iter()
+        .enumerate()
+        .filter_map(|(index, chunk)| {
+            let validity = chunk.chunk_type().validity();
+
+            (!validity.is_valid()).then(|| format!("{} at index {} has an invalid type: {}", chunk.chunk_type(), index, validity))
+        })
+        .collect();
+
+    if order_check {
+        problems.extend(ordering::violations(png));
+    }
+
+    problems
+}
+
+fn problem_tally(count: usize) -> String {
+
+    format!("{} {}", count, if count == 1 { "problem" } else { "problems" })
+}
+
+fn verify(file_path: &Path, threads: Option<u16>, deep: bool, format: VerifyFormat, crc: &Crc<u32>) -> Result<()> {
+
+    let bytes = fs::read(file_path).map_err(PngMeError::from)?;
+    let verify::Scan { spans, trailing } = verify::scan(&bytes)?;
+
+    let threads = threads.map(usize::from).unwrap_or_else(verify::default_threads);
+
+    let mismatches = verify::check(&bytes, &spans, crc, threads);
+
+    // With a machine readable format on stdout, the human readable lines go to stderr
+    let note = |line: String| if format == VerifyFormat::Text { println!("{}", line) } else { eprintln!("{}", line) };
+
+    match format {
+        VerifyFormat::Text => {
+            for mismatch in &mismatches {
+                println!(
+                    "{:#010x}  {}  stored crc {:08x}, computed {:08x}",
+                    mismatch.offset, mismatch.chunk_type, mismatch.stored, mismatch.computed
+                );
+            }
+        },
+        VerifyFormat::Json => println!("{}", verify::to_json(&spans, &mismatches)),
+        VerifyFormat::Csv => print!("{}", verify::to_csv(&spans, &mismatches)),
+    }
+
+    let summary = format!("{} of {} {} with a bad crc", mismatches.len(), spans.len(), if spans.len() == 1 { "chunk" } else { "chunks" });
+
+    if trailing > 0 {
+        note(format!("{} bytes after IEND, kept as they are and not checked", trailing));
+    }
+
+    if !deep {
+        if !mismatches.is_empty() {
+            return Err(summary.into())
+        }
+
+        note(summary);
+
+        return Ok(())
+    }
+
+    note(summary.clone());
+
+    // The crcs were just checked, a bad one is reported above rather than stopping the image data check
+    let image_data = Png::try_from_unverified(&bytes).and_then(|png| verify::check_image_data(&png));
+
+    match &image_data {
+        Ok(ImageData::Complete { length }) => note(format!("Image data: {} bytes, as IHDR declares", length)),
+        Ok(ImageData::Interlaced { length }) => note(format!("Image data: {} bytes, interlaced: length check skipped", length)),
+        Err(error) => note(format!("Image data: {}", error)),
+    }
+
+    match (mismatches.is_empty(), image_data) {
+        (true, Ok(_)) => Ok(()),
+        (false, Ok(_)) => Err(summary.into()),
+        (true, Err(_)) => Err("The image data does not decode".into()),
+        (false, Err(_)) => Err(format!("{}, and the image data does not decode", summary).into()),
+    }
+}
+
+fn check_tree(directory: &Path, deny: &[ChunkFilter], allow: &[ChunkFilter], archive: bool, crc: &Crc<u32>) -> Result<()> {
+
+    let mut policy = Policy { deny: deny.to_vec(), allow: allow.to_vec() };
+
+    let policy_path = directory.join(policy::POLICY_FILE);
+
+    if policy_path.is_file() {
+        let text = fs::read_to_string(&policy_path).map_err(|error| PngMeError::from(error).into()).with_path(&policy_path)?;
+        policy.extend(Policy::parse(&text).with_path(&policy_path)?);
+    }
+
+    if policy.deny.is_empty() {
+        return Err(format!("No deny rules, pass --deny or list them in {}", policy::POLICY_FILE).into())
+    }
+
+    // A single file given with --archive is read as an archive, whatever its extension
+    let force_archive = archive && directory.is_file();
+
+    let files = policy::find_files(directory, archive)?;
+    let outcomes = policy::check_files(&files, &policy, crc, verify::default_threads(), archive, force_archive, &interrupt::INTERRUPTED);
+
+    let mut violations = 0;
+    let mut failing_files = 0;
+    let mut checked_files = 0;
+    let mut unchecked_files = 0;
+
+    for checked in &outcomes {
+        match &checked.outcome {
+            Outcome::Violations(found) => {
+                for violation in found {
+                    println!("{}: {} ({} bytes) denied by {}", checked.label, violation.chunk_type, violation.length, violation.rule);
+                }
+
+                violations += found.len();
+                failing_files += usize::from(!found.is_empty());
+            },
+            Outcome::Unreadable(error) => {
+                println!("{}: cannot be read: {}", checked.label, error);
+                failing_files += 1;
+            },
+            Outcome::Skipped(reason) => {
+                println!("{}: skipped, {}", checked.label, reason);
+                continue
+            },
+            Outcome::Interrupted => {
+                unchecked_files += 1;
+                continue
+            },
+        }
+
+        checked_files += 1;
+    }
+
+    let checked = format!("{} {} checked", checked_files, if checked_files == 1 { "file" } else { "files" });
+
+    if unchecked_files > 0 {
+        return Err(PngMeError::Interrupted(format!(
+            "Interrupted: {} of {} files checked, {} failing with {} {}",
+            checked_files, checked_files + unchecked_files, failing_files, violations, if violations == 1 { "violation" } else { "violations" }
+        )).into())
+    }
+
+    if failing_files > 0 {
+        return Err(format!(
+            "{}, {} failing with {} {}",
+            checked, failing_files, violations, if violations == 1 { "violation" } else { "violations" }
+        ).into())
+    }
+
+    println!("{}, no violations", checked);
+
+    Ok(())
+}
+
+/// Writes the image as a PNG file, with the message of `encode` (a type and a message) in it
+fn convert(input: &Path, output: &Path, encode: Option<&[String]>, recording: Recording, crc: &Crc<u32>) -> Result<()> {
+
+    let bytes = fs::read(input).map_err(PngMeError::from)?;
+    let mut png = convert_to_png(&bytes, crc)?;
+
+    if let Some([chunk_type, message]) = encode {
+        let chunk_type = checked_chunk_type(chunk_type, false)?;
+        check_standard_type(&chunk_type, false)?;
+
+        encode_chunks(&mut png, Some(&chunk_type), Some(message.as_bytes()), &None, None, false, false, false, false, recording, crc)?;
+    }
+
+    write_file(output, png.as_bytes())?;
+
+    println!("Converted {} to {}", input.display(), output.display());
+
+    Ok(())
+}
+
+#[cfg(feature = "image")]
+fn convert_to_png(bytes: &[u8], crc: &Crc<u32>) -> Result<Png> {
+
+    crate::convert::to_png(bytes, crc)
+}
+
+#[cfg(not(feature = "image"))]
+fn convert_to_png(_bytes: &[u8], _crc: &Crc<u32>) -> Result<Png> {
+
+    Err("This pngme was built without image conversion, rebuild it with `--features image`".into())
+}
+
+fn identify(file_path: &Path) -> Result<()> {
+
+    // The longest magic, WEBP's, ends at byte 12
+    let mut magic = vec![];
+    fs::File::open(file_path)
+        .and_then(|file| file.take(16).read_to_end(&mut magic))
+        .map_err(PngMeError::from)?;
+
+    println!("{}", format::identify(&magic));
+
+    Ok(())
+}
+
+/// How many chunks of this type the file holds, or with `bytes` how much data they hold
+fn count(file_path: &Path, chunk_type: &str, bytes: bool, crc: &Crc<u32>) -> Result<usize> {
+
+    let png = read_png(file_path, crc)?;
+
+    if bytes {
+        return Ok(png.data_size_of_type(chunk_type))
+    }
+
+    Ok(png.chunks().iter().filter(|chunk| chunk.chunk_type().to_string() == chunk_type).count())
+}
+
+fn print(file_path: &Path, offsets: bool, reverse: bool, skip: CrcSkip, crc: &Crc<u32>) -> Result<()> {
+
+    let png = read_png_skipping_crc(file_path, crc, skip)?;
+
+    if offsets {
+        for line in offset_lines(&png, reverse) {
+            println!("{}", line);
+        }
+    } else if reverse {
+        println!(" header: {:?}  chunks: {:?} ", png.header(), png.chunks().iter().rev().collect::<Vec<_>>());
+    } else {
+        println!(" header: {:?}  chunks: {:?} ", png.header(), png.chunks());
+    }
+
+    Ok(())
+}
+
+fn inspect(file_path: &Path, offset: u64, json: bool, crc: &Crc<u32>) -> Result<()> {
+
+    for line in inspect_lines(&read_source(file_path)?, offset, json, crc)? {
+        println!("{}", line);
+    }
+
+    Ok(())
+}
+
+/// What `inspect` prints: the location, then a hexdump around it unless `json` is set
+fn inspect_lines(bytes: &[u8], offset: u64, json: bool, crc: &Crc<u32>) -> Result<Vec<String>> {
+
+    // The offset may well point at the bad crc that made a tool report it
+    let png = Png::try_from_skipping_crc(bytes, crc, CrcSkip::All)?;
+    let location = inspect::locate(&png, offset);
+
+    if json {
+        return Ok(vec![location.to_json(offset)])
+    }
+
+    let mut lines = vec![format!("{:#x} is {}", offset, location)];
+
+    // The line holding the offset, with one line of context on either side
+    if offset < bytes.len() as u64 {
+        let start = (offset / 16).saturating_sub(1) * 16;
+        let end = ((offset / 16 + 2) * 16).min(bytes.len() as u64);
+
+        lines.extend(hex::dump(start, &bytes[start as usize..end as usize]));
+    }
+
+    Ok(lines)
+}
+
+/// One line for the header and one for every chunk, prefixed with the
+/// offset of its first byte (the length field) in hex. With `reverse` the
+/// chunks come last to first, still with their offsets in the file.
+fn offset_lines(png: &Png, reverse: bool) -> Vec<String> {
+
+    let header = format!("{:#010x}  header: {:?}", 0, png.header());
+    let mut chunks: Vec<String> = png.chunks().iter()
+        .zip(png.chunk_positions())
+        .map(|(chunk, span)| format!("{:#010x}  {:?}", span.range.start, chunk))
+        .collect();
+
+    if reverse {
+        chunks.reverse();
+    }
+
+    std::iter::once(header).chain(chunks).collect()
+}
+
+fn apply(file_path: &Path, manifest_path: &Path, output: &Option<PathBuf>, dry_run: bool, recording: Recording, crc: &Crc<u32>) -> Result<()> {
+
+    // The whole manifest is validated before the PNG file is even read
+    let manifest = fs::read_to_string(manifest_path).map_err(PngMeError::from)?;
+    let steps = manifest::parse(&manifest)
+        .map_err(|error| format!("invalid manifest {}: {}", manifest_path.display(), error))?;
+
+    let mut png = read_png(file_path, crc)?;
+
+    with_history(&mut png, recording, crc, |png| {
+        let mut entries = vec![];
+
+        for (number, step) in steps.iter().enumerate() {
+            entries.extend(history::entries_for(&step.operation, png));
+
+            let result = manifest::apply(png, &step.operation, crc)
+                .map_err(|error| format!("operation {} (manifest line {}) failed, nothing was written: {}", number + 1, step.line, error))?;
+
+            println!("[{}/{}] {}", number + 1, steps.len(), result);
+        }
+
+        Ok(((), entries))
+    })?;
+
+    if dry_run {
+        println!("Dry run, nothing was written");
+
+        return Ok(())
+    }
+
+    let output = write_output(file_path, output, &png.as_bytes())?;
+
+    println!("Applied {} operations to {}", steps.len(), output.display());
+
+    Ok(())
+}
+
+/// Makes a change with the history log set aside, so that it never shifts the
+/// chunk indices the change works with, then records the entries the change returns
+fn with_history<T>(png: &mut Png, recording: Recording, crc: &Crc<u32>, change: impl FnOnce(&mut Png) -> Result<(T, Vec<Entry>)>) -> Result<T> {
+
+    let mut log = Log::take_from(png)?;
+    let (result, entries) = change(png)?;
+
+    if recording.enabled {
+        for entry in entries {
+            log.record(entry, recording.limit);
+        }
+    }
+
+    log.store(png, crc)?;
+
+    Ok(result)
+}
+
+fn check_bits(hiding: &HidingOptions) -> Result<()> {
+
+    if hiding.mode == HidingMode::Chunk && hiding.bits != 1 {
+        return Err("--bits only applies to --mode lsb".into())
+    }
+
+    Ok(())
+}
+
+/// The lsb mode hides messages inside the image data, so IDAT is the only chunk type it takes
+fn check_lsb_chunk_type(chunk_type: &str) -> Result<()> {
+
+    if chunk_type != "IDAT" {
+        return Err(format!("The lsb mode hides the message inside the IDAT chunks, pass IDAT instead of '{}' as the chunk type", chunk_type).into())
+    }
+
+    Ok(())
+}
+
+/// Stores the type of an encode for a later decode or remove without one. This is only a
+/// convenience, so a state directory that cannot be written to is not an error.
+fn remember_chunk_type(chunk_type: &str) {
+
+    if let Some(path) = last_type::location() {
+        let _ = last_type::write(&path, chunk_type);
+    }
+}
+
+/// The chunk type given, or the one of the last encode
+fn chunk_type_or_last(chunk_type: &Option<String>) -> Result<String> {
+
+    match chunk_type {
+        Some(chunk_type) => Ok(chunk_type.clone()),
+        None => last_type::location()
+            .and_then(|path| last_type::read(&path))
+            .ok_or_else(|| "Give the chunk type, there is no type of an earlier encode to use".into()),
+    }
+}
+
+fn check_extension(file_path: &Path) -> Result<()> {
+
+    if file_path.extension().is_none_or(|extension| extension != "png") {
+        return Err("This program takes only PNG files".into())
+    }
+
+    Ok(())
+}
+
+/// The bytes of the PNG file, or of the `data:image/png;base64,...` URI given in its place
+fn read_source(file_path: &Path) -> Result<Vec<u8>> {
+
+    if data_uri::is_data_uri(file_path) {
+        return data_uri::decode(&file_path.to_string_lossy())
+    }
+
+    check_extension(file_path)?;
+
+    Ok(fs::read(file_path).map_err(PngMeError::from)?)
+}
+
+/// A data URI can be read in place of a file, but there is nothing to write back to
+fn refuse_data_uri(file_path: &Path) -> Result<()> {
+
+    if data_uri::is_data_uri(file_path) {
+        return Err("A data URI cannot be written to, pass --output <file>".into())
+    }
+
+    Ok(())
+}
+
+/// Reads and parses the PNG file, verifying chunk crcs with the given algorithm
+fn read_png(file_path: &Path, crc: &Crc<u32>) -> Result<Png> {
+
+    read_png_skipping_crc(file_path, crc, CrcSkip::Nothing)
+}
+
+/// Reads and parses the PNG file, leaving the crcs `skip` names unchecked
+fn read_png_skipping_crc(file_path: &Path, crc: &Crc<u32>, skip: CrcSkip) -> Result<Png> {
+
+    let file = metrics::time(Stage::Read, || read_source(file_path))?;
+    interrupt::check()?;
+    let png = metrics::time(Stage::Parse, || Png::try_from_skipping_crc(file.as_slice(), crc, skip))?;
+    interrupt::check()?;
+
+    metrics::update(|metrics| {
+        metrics.bytes_read += file.len();
+        metrics.chunks_parsed += png.chunk_count();
+    });
+
+    Ok(png)
+}
+
+/// Writes the changed file to `output`, or back over `file_path` without one, and
+/// returns where it went. An output that is the input under another path, through
+/// a symbolic link or `..`, is replaced through a temporary file rather than
+/// written over while it could still be read from.
+fn write_output<'a>(file_path: &'a Path, output: &'a Option<PathBuf>, bytes: &[u8]) -> Result<&'a Path> {
+
+    match output.as_deref() {
+        Some(output) if alias::same_file(file_path, output) => {
+            eprintln!("Note: {} is {} under another path, replacing it in place", output.display(), file_path.display());
+            write_atomically(file_path, bytes)?;
+
+            Ok(file_path)
+        },
+        Some(output) => write_file(output, bytes).map(|()| output),
+        None => write_file(file_path, bytes).map(|()| file_path),
+    }
+}
+
+/// Writes a whole file, counted by --explain. A Ctrl-C before it starts stops it,
+/// one while it is under way waits for it to finish.
+fn write_file(file_path: &Path, bytes: impl AsRef<[u8]>) -> Result<()> {
+
+    refuse_data_uri(file_path)?;
+    interrupt::check()?;
+    interrupt::deferred(|| metrics::time(Stage::Write, || {
+        retry::retrying(retry::current(), retry::is_sharing_violation, || fs::write(file_path, &bytes))
+    }))
+    .map_err(|error| retry::explain(file_path, error, retry::is_sharing_violation))?;
+    metrics::update(|metrics| metrics.bytes_written += bytes.as_ref().len());
+
+    Ok(())
+}
+
+/// Reads a complete, already built chunk from a file
+fn read_raw_chunk(file_path: &Path, crc: &Crc<u32>) -> Result<Chunk> {
+
+    let bytes = fs::read(file_path).map_err(PngMeError::from)?;
+
+    Chunk::try_from_with_crc(&bytes, crc)
+        .map_err(|error| format!("{} does not hold a valid chunk: {}", file_path.display(), error).into())
+}
+
+/// Prints the data of a chunk as a message, or writes the raw data to `output`
+fn show_message(chunk: &Chunk, output: &Option<PathBuf>, display: &DisplayOptions) -> Result<()> {
+
+    show_data(message_data(chunk)?, output, display)
+}
+
+/// The message a chunk holds. Standard text chunks are unframed first, so
+/// only their text is kept, and their keyword is printed.
+fn message_data(chunk: &Chunk) -> Result<Vec<u8>> {
+
+    match text::decode_framed(chunk)? {
+        Some((keyword, text)) => {
+            println!("Keyword: {:?}", keyword);
+
+            Ok(text.into_bytes())
+        },
+        None => Ok(chunk.data().to_vec()),
+    }
+}
+
+fn show_data(data: Vec<u8>, output: &Option<PathBuf>, display: &DisplayOptions) -> Result<()> {
+
+    if let Some(output) = output {
+        write_file(output, &data)?;
+
+        println!("Message of {} bytes written to {}", data.len(), output.display());
+
+        return Ok(())
+    }
+
+    let to_terminal = io::stdout().is_terminal();
+
+    write_message(&mut io::stdout().lock(), &data, to_terminal, display)
+}
+
+/// Writes the message the way `display` asks for, ending the line unless --no-newline is given
+fn write_message<W: Write>(out: &mut W, data: &[u8], to_terminal: bool, display: &DisplayOptions) -> Result<()> {
+
+    let line = if display.hex {
+        format!("Message (hex): {}", hex::encode(data))
+    } else if display.base64 {
+        format!("Message (base64): {}", base64::encode(data))
+    } else {
+        match printed_form(data, to_terminal, display.ascii_only) {
+            Printed::Text(message) => format!("Message: {:?}", message),
+            Printed::Raw => return out.write_all(data).map_err(|error| PngMeError::from(error).into()),
+            Printed::Refused => return Err("The message is binary data, use --hex, --base64 or --output <file> to see it".into()),
+        }
+    };
+
+    let newline = if display.no_newline { "" } else { "\n" };
+
+    write!(out, "{}{}", line, newline).map_err(PngMeError::from)?;
+
+    Ok(())
+}
+
+/// How a message gets to stdout
+#[derive(Debug, PartialEq)]
+enum Printed {
+    Text(String),
+    /// The bytes as they are, for a pipe or a file
+    Raw,
+    /// Binary data that would garble a terminal
+    Refused,
+}
+
+fn printed_form(data: &[u8], to_terminal: bool, ascii_only: bool) -> Printed {
+
+    match detect::printable_text(data) {
+        Some(text) => Printed::Text(text.to_string()),
+        None if to_terminal || ascii_only => Printed::Refused,
+        None => Printed::Raw,
+    }
+}
+
+fn list(file_path: &Path, options: &ListOptions, crc: &Crc<u32>) -> Result<()> {
+
+    if let (Some(min), Some(max)) = (options.min_data, options.max_data) {
+        if min > max {
+            return Err(format!("--min-data {} is larger than --max-data {}, no chunk could be listed", min, max).into())
+        }
+    }
+
+    // Comparing crcs only makes sense when damaged chunks are kept around
+    let png = if options.compare_crc {
+        Png::try_from_unverified(read_source(file_path)?.as_slice())?
+    } else {
+        read_png_skipping_crc(file_path, crc, options.skip.crc_skip())?
+    };
+
+    let view = list::view(&png, options);
+
+    if options.sizes {
+        for line in list::sizes_table(&list::sizes(&png, &view)) {
+            println!("{}", line);
+        }
+
+        return Ok(())
+    }
+
+    print!("{}", list::render(file_path, &png, &view, options, crc));
+
+    Ok(())
+}
+
+/// Describes a missing chunk type along with the private chunk types the file does contain
+fn missing_chunk_message(png: &Png, chunk_type: &str) -> String {
+
+    let mut private_types: Vec<String> = vec![];
+
+    for chunk in png.chunks() {
+        let chunk_type = chunk.chunk_type();
+
+        if !chunk_type.is_public() && !private_types.contains(&chunk_type.to_string()) {
+            private_types.push(chunk_type.to_string());
+        }
+    }
+
+    if private_types.is_empty() {
+        format!("No chunk of type '{}' in this file; it contains no private chunks", chunk_type)
+    } else {
+        format!("No chunk of type '{}' in this file; private chunks present: {}", chunk_type, private_types.join(", "))
+    }
+}
+
+/// How many private chunks a decode that misses lists
+const MAX_CANDIDATES: usize = 10;
+
+/// What a decode that finds no chunk of the type prints besides the error
+#[derive(Debug, Default, Clone, Copy)]
+struct Miss {
+    quiet: bool,
+    json: bool,
+}
+
+/// A private chunk that a decode that misses points out instead
+#[derive(Debug, PartialEq)]
+struct Candidate {
+    chunk_type: String,
+    size: usize,
+    utf8: bool,
+}
+
+impl Candidate {
+    fn to_json(&self) -> String {
+
+        format!("{{\"type\": {}, \"size\": {}, \"utf8\": {}}}", json::string(&self.chunk_type), self.size, self.utf8)
+    }
+}
+
+impl std::fmt::Display for Candidate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+
+        write!(f, "{} ({} B, {})", self.chunk_type, self.size, if self.utf8 { "utf-8" } else { "binary" })
+    }
+}
+
+/// Every private chunk of the file, with its data classified once, the way `list --preview` shows text
+fn candidates(png: &Png) -> Vec<Candidate> {
+
+    png.chunks().iter()
+        .filter(|chunk| !chunk.chunk_type().is_public())
+        .map(|chunk| Candidate {
+            chunk_type: chunk.chunk_type().to_string(),
+            size: chunk.data().len(),
+            utf8: detect::printable_text(chunk.data()).is_some(),
+        })
+        .collect()
+}
+
+/// The error of a decode that finds no chunk of the type, listing up to ten private
+/// chunks unless `miss` asks for quiet, or printing them as JSON
+fn missing_message(png: &Png, chunk_type: &str, miss: Miss) -> crate::Error {
+
+    let plain = format!("No '{}' chunk", chunk_type);
+
+    if miss.quiet {
+        return PngMeError::NotFound(plain).into()
+    }
+
+    let candidates = candidates(png);
+
+    if miss.json {
+        let listed: Vec<String> = candidates.iter().take(MAX_CANDIDATES).map(Candidate::to_json).collect();
+        println!("{{\"missing\": {}, \"candidates\": {}}}", json::string(chunk_type), json::array(&listed));
+
+        return PngMeError::NotFound(plain).into()
+    }
+
+    PngMeError::NotFound(candidates_message(&plain, &candidates)).into()
+}
+
+fn candidates_message(plain: &str, candidates: &[Candidate]) -> String {
+
+    if candidates.is_empty() {
+        return format!("{}; this file contains no private chunks", plain)
+    }
+
+    let listed: Vec<String> = candidates.iter().take(MAX_CANDIDATES).map(Candidate::to_string).collect();
+    let more = match candidates.len().saturating_sub(MAX_CANDIDATES) {
+        0 => String::new(),
+        more => format!(", and {} more", more),
+    };
+
+    format!("{}; this file contains private chunks: {}{}", plain, listed.join(", "), more)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+    use crate::zlib;
+
+    const PNG_CRC: Crc<u32> = Crc::<u32>::new(&crc::CRC_32_ISO_HDLC);
+
+    fn testing_png() -> Png {
+        Png::from_chunks(vec![
+            Chunk::new(ChunkType::from_str("IHDR").unwrap(), vec![0; 13]),
+            Chunk::new(ChunkType::from_str("IEND").unwrap(), vec![]),
+        ])
+    }
+
+    /// A path in the temporary directory, unique to the test
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("pngme-test-{}-{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn test_encode_raw_chunk() {
+        let file_path = temp_path("encode-raw-chunk.png");
+        let chunk_path = temp_path("encode-raw-chunk.bin");
+        fs::write(&file_path, testing_png().as_bytes()).unwrap();
+
+        let chunk = Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"raw message".to_vec());
+        fs::write(&chunk_path, chunk.as_bytes()).unwrap();
+
+        let args = Cli::parse_from(["pngme", "encode", "--raw-chunk", chunk_path.to_str().unwrap(), file_path.to_str().unwrap()]);
+        run(&args).unwrap();
+
+        let png = read_png(&file_path, &PNG_CRC).unwrap();
+        fs::remove_file(&file_path).unwrap();
+        fs::remove_file(&chunk_path).unwrap();
+
+        assert_eq!(png.chunks().len(), 3);
+        assert_eq!(png.chunk_by_type("ruSt").unwrap().data(), b"raw message");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_edit() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let file_path = temp_path("edit.png");
+        let script = temp_path("edit-editor.sh");
+        fs::write(&script, "#!/bin/sh\nprintf '%s, edited' \"$(cat \"$1\")\" > \"$1\"\n").unwrap();
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o700)).unwrap();
+        let editor = script.to_str().unwrap();
+
+        let mut png = testing_png();
+        png.insert_chunk(1, Chunk::new_with_crc(ChunkType::from_str("ruSt").unwrap(), b"note".to_vec(), &PNG_CRC)).unwrap();
+        png.insert_chunk(2, Chunk::new_with_crc(ChunkType::from_str("biNa").unwrap(), vec![0xff, 0xfe], &PNG_CRC)).unwrap();
+        fs::write(&file_path, png.as_bytes()).unwrap();
+
+        let disabled = Recording { enabled: false, limit: history::DEFAULT_HISTORY_LIMIT };
+        edit(&file_path, "ruSt", false, editor, disabled, &PNG_CRC).unwrap();
+        edit(&file_path, "neWw", true, editor, disabled, &PNG_CRC).unwrap();
+        let failed = edit(&file_path, "ruSt", false, "false", disabled, &PNG_CRC).unwrap_err();
+        let unchanged = edit(&file_path, "ruSt", false, "true", disabled, &PNG_CRC);
+        let binary = edit(&file_path, "biNa", false, editor, disabled, &PNG_CRC).unwrap_err();
+        let absent = edit(&file_path, "abSe", false, editor, disabled, &PNG_CRC).unwrap_err();
+
+        let png = read_png(&file_path, &PNG_CRC).unwrap();
+        fs::remove_file(&file_path).unwrap();
+        fs::remove_file(&script).unwrap();
+
+        let types: Vec<String> = png.chunks().iter().map(|chunk| chunk.chunk_type().to_string()).collect();
+        assert_eq!(types, ["IHDR", "ruSt", "biNa", "neWw", "IEND"]);
+        assert_eq!(png.chunks()[1].data(), b"note, edited");
+        assert_eq!(png.chunks()[3].data(), b", edited");
+        assert!(failed.to_string().contains("leaving the file unchanged"));
+        assert!(unchanged.is_ok());
+        assert!(binary.to_string().contains("set-data"));
+        assert_eq!(crate::error::exit_code(&absent), crate::error::EXIT_NOT_FOUND);
+    }
+
+    #[test]
+    fn test_cat_chunk_round_trip() {
+        let source_path = temp_path("cat-chunk-source.png");
+        let target_path = temp_path("cat-chunk-target.png");
+        let chunk_path = temp_path("cat-chunk.bin");
+
+        let mut png = testing_png();
+        png.insert_chunk(1, Chunk::new_with_crc(ChunkType::from_str("ruSt").unwrap(), b"first".to_vec(), &PNG_CRC)).unwrap();
+        png.insert_chunk(2, Chunk::new_with_crc(ChunkType::from_str("ruSt").unwrap(), b"second".to_vec(), &PNG_CRC)).unwrap();
+        fs::write(&source_path, png.as_bytes()).unwrap();
+        fs::write(&target_path, testing_png().as_bytes()).unwrap();
+
+        run(&Cli::parse_from(["pngme", "cat-chunk", source_path.to_str().unwrap(), "ruSt", "--index", "1", "-o", chunk_path.to_str().unwrap()])).unwrap();
+        run(&Cli::parse_from(["pngme", "encode", "--raw-chunk", chunk_path.to_str().unwrap(), target_path.to_str().unwrap()])).unwrap();
+        run(&Cli::parse_from(["pngme", "decode", target_path.to_str().unwrap(), "ruSt"])).unwrap();
+
+        let chunk = fs::read(&chunk_path).unwrap();
+        let target = read_png(&target_path, &PNG_CRC).unwrap();
+        let missing = run(&Cli::parse_from(["pngme", "cat-chunk", source_path.to_str().unwrap(), "ruSt", "--index", "2", "-o", chunk_path.to_str().unwrap()])).unwrap_err();
+        fs::remove_file(&source_path).unwrap();
+        fs::remove_file(&target_path).unwrap();
+        fs::remove_file(&chunk_path).unwrap();
+
+        assert_eq!(chunk, png.chunks()[2].as_bytes());
+        assert_eq!(target.chunks().len(), 3);
+        assert_eq!(target.chunk_by_type("ruSt").unwrap().data(), b"second");
+        assert_eq!(crate::error::exit_code(&missing), crate::error::EXIT_NOT_FOUND);
+
+        let (all, count) = framed_chunks(&png, "ruSt", 0, true).unwrap();
+        assert_eq!(count, 2);
+        assert_eq!(all, [png.chunks()[1].as_bytes(), png.chunks()[2].as_bytes()].concat());
+    }
+
+    #[test]
+    fn test_encode_template() {
+        let file_path = temp_path("encode-template.png");
+        fs::write(&file_path, testing_png().as_bytes()).unwrap();
+
+        let args = Cli::parse_from(["pngme", "encode", file_path.to_str().unwrap(), "ruSt", "--template", "{{{basename}}}"]);
+        run(&args).unwrap();
+
+        let args = Cli::parse_from(["pngme", "encode", file_path.to_str().unwrap(), "ruSt", "--template", "{nope}"]);
+        assert!(run(&args).is_err());
+
+        let png = read_png(&file_path, &PNG_CRC).unwrap();
+        fs::remove_file(&file_path).unwrap();
+
+        assert_eq!(png.chunks().len(), 3);
+        let expected = format!("{{{}}}", file_path.file_name().unwrap().to_str().unwrap());
+        assert_eq!(png.chunk_by_type("ruSt").unwrap().data(), expected.as_bytes());
+    }
+
+    #[test]
+    fn test_offset_lines() {
+        let png = testing_png();
+        let lines = offset_lines(&png, false);
+        let first = 12 + png.chunks()[0].length() as usize;
+
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("0x00000000  header: [137, 80, 78, 71"));
+        assert!(lines[1].starts_with("0x00000008  Chunk {"));
+        assert!(lines[2].starts_with(&format!("{:#010x}  Chunk {{", 8 + first)));
+    }
+
+    #[test]
+    fn test_offset_lines_reverse() {
+        let png = testing_png();
+        let lines = offset_lines(&png, true);
+        let iend = *png.byte_offsets().last().unwrap();
+
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("0x00000000  header:"));
+        assert!(lines[1].starts_with(&format!("{:#010x}  Chunk {{ length: 0, chunk_type: [73, 69, 78, 68]", iend)));
+        assert!(lines[2].starts_with("0x00000008  Chunk { length: 13, chunk_type: [73, 72, 68, 82]"));
+    }
+
+    #[test]
+    fn test_verify_against_baseline() {
+        let file_path = temp_path("verify-against.png");
+        let baseline_path = temp_path("verify-against.baseline.json");
+        fs::write(&file_path, testing_png().as_bytes()).unwrap();
+
+        run(&Cli::parse_from(["pngme", "baseline", file_path.to_str().unwrap(), "-o", baseline_path.to_str().unwrap()])).unwrap();
+
+        let verify = Cli::parse_from(["pngme", "verify-against", file_path.to_str().unwrap(), baseline_path.to_str().unwrap()]);
+        run(&verify).unwrap();
+
+        run(&Cli::parse_from(["pngme", "encode", file_path.to_str().unwrap(), "ruSt", "added"])).unwrap();
+        let error = run(&verify).unwrap_err();
+
+        fs::remove_file(&file_path).unwrap();
+        fs::remove_file(&baseline_path).unwrap();
+
+        assert_eq!(crate::error::exit_code(&error), crate::error::EXIT_CHANGED);
+    }
+
+    #[test]
+    fn test_filter_keep_and_drop() {
+        let file_path = temp_path("filter.png");
+        let output = temp_path("filter-out.png");
+        let path = file_path.to_str().unwrap();
+
+        let mut png = testing_png();
+        png.insert_chunk(1, Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"secret".to_vec())).unwrap();
+        png.insert_chunk(1, Chunk::new(ChunkType::from_str("tEXt").unwrap(), b"Comment\0text".to_vec())).unwrap();
+        fs::write(&file_path, png.as_bytes()).unwrap();
+
+        // Keeping only tEXt and private chunks would drop IHDR and IEND
+        let keep = Cli::parse_from(["pngme", "filter", path, "--keep-types", "tEXt,private", "-o", output.to_str().unwrap()]);
+        let error = run(&keep).unwrap_err();
+        assert!(error.to_string().contains("IHDR (index 0), IEND (index 3)"));
+        assert!(!output.exists());
+
+        run(&Cli::parse_from(["pngme", "filter", path, "--drop-types", "tEXt"])).unwrap();
+        let types: Vec<String> = read_png(&file_path, &PNG_CRC).unwrap().chunks().iter().map(|chunk| chunk.chunk_type().to_string()).collect();
+
+        fs::remove_file(&file_path).unwrap();
+
+        assert_eq!(types, ["IHDR", "ruSt", "IEND"]);
+    }
+
+    #[test]
+    fn test_write_message_no_newline() {
+        let mut out = vec![];
+        write_message(&mut out, b"hello", false, &DisplayOptions::default()).unwrap();
+        assert_eq!(out, b"Message: \"hello\"\n");
+
+        let no_newline = DisplayOptions { no_newline: true, ..Default::default() };
+        let mut out = vec![];
+        write_message(&mut out, b"hello", false, &no_newline).unwrap();
+        assert_eq!(out, b"Message: \"hello\"");
+
+        let mut out = vec![];
+        write_message(&mut out, &[0xff, 0x01], false, &DisplayOptions { hex: true, ..no_newline }).unwrap();
+        assert_eq!(out, b"Message (hex): ff01");
+
+        assert!(Cli::try_parse_from(["pngme", "decode", "image.png", "ruSt", "--no-newline"]).is_ok());
+    }
+
+    #[test]
+    fn test_printed_form() {
+        assert_eq!(printed_form(b"hello\nworld", true, false), Printed::Text("hello\nworld".to_string()));
+        assert_eq!(printed_form(b"hello\nworld", false, true), Printed::Text("hello\nworld".to_string()));
+
+        for binary in [&[0xff, 0xfe][..], b"escape\x1b[2J"] {
+            assert_eq!(printed_form(binary, true, false), Printed::Refused);
+            assert_eq!(printed_form(binary, false, true), Printed::Refused);
+            assert_eq!(printed_form(binary, false, false), Printed::Raw);
+        }
+    }
+
+    #[test]
+    fn test_unwrap_payload() {
+        let wrapped = base64::encode(&zlib::compress(b"hidden")).into_bytes();
+
+        let detect = PayloadOptions { detect: true, unwrap: vec![] };
+        assert_eq!(unwrap_payload(wrapped.clone(), &detect).unwrap(), b"hidden");
+
+        let explicit = PayloadOptions { detect: false, unwrap: vec![Layer::Base64, Layer::Zlib] };
+        assert_eq!(unwrap_payload(wrapped.clone(), &explicit).unwrap(), b"hidden");
+
+        let wrong = PayloadOptions { detect: false, unwrap: vec![Layer::Zlib] };
+        assert!(unwrap_payload(wrapped, &wrong).unwrap_err().to_string().contains("zlib layer"));
+    }
+
+    #[test]
+    fn test_embed_and_extract_file() {
+        let file_path = temp_path("embed-file.png");
+        let secret = temp_path("embed-file-secret.txt");
+        let extracted = temp_path("embed-file-extracted.txt");
+        fs::write(&file_path, testing_png().as_bytes()).unwrap();
+        fs::write(&secret, b"top secret\n").unwrap();
+
+        run(&Cli::parse_from(["pngme", "encode", file_path.to_str().unwrap(), "ruSt", "--embed-file", secret.to_str().unwrap()])).unwrap();
+        run(&Cli::parse_from(["pngme", "decode", file_path.to_str().unwrap(), "ruSt", "--extract-file", "-o", extracted.to_str().unwrap()])).unwrap();
+
+        let png = read_png(&file_path, &PNG_CRC).unwrap();
+        let embedded = EmbeddedFile::from_bytes(png.chunk_by_type("ruSt").unwrap().data()).unwrap();
+        let contents = fs::read(&extracted).unwrap();
+
+        for path in [&file_path, &secret, &extracted] {
+            fs::remove_file(path).unwrap();
+        }
+
+        assert_eq!(embedded.name, secret.file_name().unwrap().to_str().unwrap());
+        assert_eq!(embedded.mime_type, "text/plain");
+        assert_eq!(contents, b"top secret\n");
+    }
+
+    #[test]
+    fn test_encode_hex() {
+        let file_path = temp_path("encode-hex.png");
+        fs::write(&file_path, testing_png().as_bytes()).unwrap();
+
+        run(&Cli::parse_from(["pngme", "encode", file_path.to_str().unwrap(), "ruSt", "--hex", "de ad 00 be ef 00"])).unwrap();
+        let odd = run(&Cli::parse_from(["pngme", "encode", file_path.to_str().unwrap(), "teSt", "--hex", "abc"]));
+
+        let png = read_png(&file_path, &PNG_CRC).unwrap();
+        fs::remove_file(&file_path).unwrap();
+
+        let data = png.chunk_by_type("ruSt").unwrap().data();
+        assert_eq!(data, [0xde, 0xad, 0x00, 0xbe, 0xef, 0x00]);
+        assert_eq!(hex::encode(data), "dead00beef00");
+        assert!(odd.is_err());
+        assert!(png.chunk_by_type("teSt").is_none());
+    }
+
+    #[test]
+    fn test_validate_order_check() {
+        let file_path = temp_path("validate.png");
+        let path = file_path.to_str().unwrap();
+
+        let mut png = testing_png();
+        png.insert_chunk(1, Chunk::new(ChunkType::from_str("IDAT").unwrap(), vec![])).unwrap();
+        fs::write(&file_path, png.as_bytes()).unwrap();
+
+        run(&Cli::parse_from(["pngme", "validate", path, "--order-check"])).unwrap();
+
+        png.insert_chunk(2, Chunk::new(ChunkType::from_str("PLTE").unwrap(), vec![])).unwrap();
+        fs::write(&file_path, png.as_bytes()).unwrap();
+
+        run(&Cli::parse_from(["pngme", "validate", path])).unwrap();
+        let error = run(&Cli::parse_from(["pngme", "validate", path, "--order-check"])).unwrap_err();
+
+        fs::remove_file(&file_path).unwrap();
+
+        assert!(error.to_string().contains("1 problem found"));
+    }
+
+    #[test]
+    fn test_validate_reports_bad_crcs() {
+        let file_path = temp_path("validate-bad-crc.png");
+        let path = file_path.to_str().unwrap();
+
+        let mut bytes = testing_png().as_bytes();
+        let ihdr_crc_end = 8 + 12 + 13;
+        bytes[ihdr_crc_end - 1] ^= 0xff;
+        fs::write(&file_path, &bytes).unwrap();
+
+        let error = run(&Cli::parse_from(["pngme", "validate", path])).unwrap_err();
+        fs::remove_file(&file_path).unwrap();
+
+        assert!(error.to_string().contains("1 problem found"));
+    }
+
+    #[test]
+    fn test_validate_count_only() {
+        let file_path = temp_path("validate-count-only.png");
+        let path = file_path.to_str().unwrap();
+
+        let mut png = testing_png();
+        png.insert_chunk(1, Chunk::new(ChunkType::from_str("IDAT").unwrap(), vec![])).unwrap();
+        png.insert_chunk(2, Chunk::new(ChunkType::from_str("PLTE").unwrap(), vec![])).unwrap();
+        png.insert_chunk(3, Chunk::new(ChunkType::from_str("IDAT").unwrap(), vec![])).unwrap();
+        fs::write(&file_path, png.as_bytes()).unwrap();
+
+        let error = run(&Cli::parse_from(["pngme", "validate", path, "--order-check", "--count-only"])).unwrap_err();
+        fs::remove_file(&file_path).unwrap();
+
+        let problems = validate_structure(&png, true);
+        assert!(problems.len() > 1);
+        assert!(error.to_string().ends_with(&format!("{} problems found", problems.len())));
+        assert_eq!(problem_tally(1), "1 problem");
+    }
+
+    #[test]
+    fn test_encode_append_only() {
+        let file_path = temp_path("encode-append-only.png");
+        let path = file_path.to_str().unwrap();
+        fs::write(&file_path, testing_png().as_bytes()).unwrap();
+
+        run(&Cli::parse_from(["pngme", "encode", path, "ruSt", "first", "--append-only"])).unwrap();
+        let error = run(&Cli::parse_from(["pngme", "encode", path, "ruSt", "second", "--append-only"])).unwrap_err();
+
+        let png = read_png(&file_path, &PNG_CRC).unwrap();
+        fs::remove_file(&file_path).unwrap();
+
+        assert!(error.to_string().contains("already has a ruSt chunk"));
+        assert_eq!(png.chunk_count(), 3);
+        assert_eq!(png.chunk_by_type("ruSt").unwrap().data(), b"first");
+    }
+
+    #[test]
+    fn test_verify_threads() {
+        let file_path = temp_path("verify.png");
+        let path = file_path.to_str().unwrap();
+
+        let mut png = testing_png();
+        for index in 0..8 {
+            png.insert_chunk(1, Chunk::new(ChunkType::from_str("IDAT").unwrap(), vec![index; 64])).unwrap();
+        }
+        let mut bytes = png.as_bytes();
+        fs::write(&file_path, &bytes).unwrap();
+
+        run(&Cli::parse_from(["pngme", "verify", path, "--threads", "4"])).unwrap();
+
+        // Damage the data of the fourth IDAT chunk
+        let offset = png.byte_offsets()[4] as usize + 8;
+        bytes[offset] ^= 0xff;
+        fs::write(&file_path, &bytes).unwrap();
+
+        let sequential = run(&Cli::parse_from(["pngme", "verify", path, "--threads", "1"])).unwrap_err();
+        let parallel = run(&Cli::parse_from(["pngme", "verify", path, "--threads", "4"])).unwrap_err();
+
+        fs::remove_file(&file_path).unwrap();
+
+        assert!(sequential.to_string().ends_with("1 of 10 chunks with a bad crc"));
+        assert_eq!(sequential.to_string(), parallel.to_string());
+    }
+
+    #[test]
+    fn test_verify_formats() {
+        let file_path = temp_path("verify-formats.png");
+        let path = file_path.to_str().unwrap();
+        fs::write(&file_path, testing_png().as_bytes()).unwrap();
+
+        run(&Cli::parse_from(["pngme", "verify", path, "--format", "csv"])).unwrap();
+        run(&Cli::parse_from(["pngme", "verify", path, "--format", "json", "--deep"])).unwrap_err();
+        let unknown = Cli::try_parse_from(["pngme", "verify", path, "--format", "xml"]);
+
+        fs::remove_file(&file_path).unwrap();
+
+        assert!(unknown.is_err());
+        assert!(matches!(Cli::parse_from(["pngme", "verify", path, "--format", "csv"]).command, Commands::Verify { format: VerifyFormat::Csv, .. }));
+    }
+
+    #[test]
+    fn test_verify_keeps_data_after_iend() {
+        let file_path = temp_path("verify-polyglot.png");
+        let mut bytes = testing_png().as_bytes();
+        bytes.extend_from_slice(b"PK\x03\x04 a zip archive");
+        fs::write(&file_path, &bytes).unwrap();
+
+        let result = run(&Cli::parse_from(["pngme", "verify", file_path.to_str().unwrap()]));
+
+        fs::remove_file(&file_path).unwrap();
+
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_verify_deep() {
+        let file_path = temp_path("verify-deep.png");
+        let path = file_path.to_str().unwrap();
+
+        // One row of one gray pixel, but the stream holds two rows
+        let mut ihdr = [1u32.to_be_bytes(), 1u32.to_be_bytes()].concat();
+        ihdr.extend_from_slice(&[8, 0, 0, 0, 0]);
+        let mut png = Png::from_chunks(vec![
+            Chunk::new_with_crc(ChunkType::from_str("IHDR").unwrap(), ihdr, &PNG_CRC),
+            Chunk::new_with_crc(ChunkType::from_str("IDAT").unwrap(), zlib::compress(&[0, 7]), &PNG_CRC),
+            Chunk::new_with_crc(ChunkType::from_str("IEND").unwrap(), vec![], &PNG_CRC),
+        ]);
+        fs::write(&file_path, png.as_bytes()).unwrap();
+        run(&Cli::parse_from(["pngme", "verify", path, "--deep"])).unwrap();
+
+        png.chunks_mut()[1].set_data_with_crc(zlib::compress(&[0, 7, 0, 8]), &PNG_CRC);
+        fs::write(&file_path, png.as_bytes()).unwrap();
+        let too_long = run(&Cli::parse_from(["pngme", "verify", path, "--deep"])).unwrap_err();
+        let shallow = run(&Cli::parse_from(["pngme", "verify", path]));
+
+        fs::remove_file(&file_path).unwrap();
+
+        assert!(too_long.to_string().ends_with("The image data does not decode"));
+        assert!(shallow.is_ok());
+    }
+
+    #[test]
+    fn test_encode_preview() {
+        let chunk = Chunk::new_with_crc(ChunkType::from_str("ruSt").unwrap(), b"secret message".to_vec(), &PNG_CRC);
+        let line = preview_line(&chunk);
+
+        assert!(line.contains("ruSt"));
+        assert!(line.contains("14 bytes"));
+        assert!(line.contains("\"secret message\""));
+
+        // Unlike a dry run, the file is still written
+        let file_path = temp_path("encode-preview.png");
+        fs::write(&file_path, testing_png().as_bytes()).unwrap();
+
+        run(&Cli::parse_from(["pngme", "encode", file_path.to_str().unwrap(), "ruSt", "secret message", "--preview"])).unwrap();
+        let png = read_png(&file_path, &PNG_CRC).unwrap();
+        fs::remove_file(&file_path).unwrap();
+
+        assert_eq!(png.chunk_by_type("ruSt").unwrap().data(), b"secret message");
+    }
+
+    #[test]
+    fn test_encode_fix_type() {
+        let file_path = temp_path("encode-fix-type.png");
+        let path = file_path.to_str().unwrap();
+        fs::write(&file_path, testing_png().as_bytes()).unwrap();
+
+        run(&Cli::parse_from(["pngme", "encode", path, "rust", "unfixed"])).unwrap();
+        run(&Cli::parse_from(["pngme", "encode", path, "tost", "fixed", "--fix-type"])).unwrap();
+
+        let png = read_png(&file_path, &PNG_CRC).unwrap();
+        fs::remove_file(&file_path).unwrap();
+
+        assert_eq!(png.chunk_by_type("rust").unwrap().data(), b"unfixed");
+        assert_eq!(png.chunk_by_type("toSt").unwrap().data(), b"fixed");
+        assert!(png.chunk_by_type("tost").is_none());
+        assert_eq!(checked_chunk_type("ruSt", true).unwrap(), "ruSt");
+    }
+
+    #[test]
+    fn test_reserved_bit_warning() {
+        let warning = reserved_bit_warning(&ChunkType::from_str("Rust").unwrap()).unwrap();
+
+        assert!(warning.starts_with("'Rust' is a non-conformant chunk type"));
+        assert!(warning.contains("Use 'RuSt'"));
+        assert_eq!(reserved_bit_warning(&ChunkType::from_str("RuSt").unwrap()), None);
+    }
+
+    #[test]
+    fn test_encode_max_size() {
+        let file_path = temp_path("encode-max-size.png");
+        fs::write(&file_path, testing_png().as_bytes()).unwrap();
+
+        let too_large = run(&Cli::parse_from(["pngme", "encode", file_path.to_str().unwrap(), "ruSt", "0123456789A", "--max-size", "10"]));
+        let unchanged = fs::read(&file_path).unwrap();
+        run(&Cli::parse_from(["pngme", "encode", file_path.to_str().unwrap(), "ruSt", "0123456789", "--max-size", "10"])).unwrap();
+
+        let png = read_png(&file_path, &PNG_CRC).unwrap();
+        fs::remove_file(&file_path).unwrap();
+
+        assert!(too_large.unwrap_err().to_string().contains("more than the --max-size of 10 bytes"));
+        assert_eq!(unchanged, testing_png().as_bytes());
+        assert_eq!(png.chunk_by_type("ruSt").unwrap().data(), b"0123456789");
+    }
+
+    #[test]
+    fn test_encode_limit_output_bytes() {
+        let file_path = temp_path("encode-limit-output-bytes.png");
+        let path = file_path.to_str().unwrap();
+        let size = testing_png().as_bytes().len();
+        fs::write(&file_path, testing_png().as_bytes()).unwrap();
+
+        // The chunk adds 12 bytes of framing to its 5 of data
+        let limit = (size + 16).to_string();
+        let too_large = run(&Cli::parse_from(["pngme", "encode", path, "ruSt", "hello", "--limit-output-bytes", &limit]));
+        let unchanged = fs::read(&file_path).unwrap();
+
+        let limit = (size + 17).to_string();
+        run(&Cli::parse_from(["pngme", "encode", path, "ruSt", "hello", "--limit-output-bytes", &limit])).unwrap();
+        let written = fs::read(&file_path).unwrap();
+        fs::remove_file(&file_path).unwrap();
+
+        assert!(too_large.unwrap_err().to_string().contains(&format!("The file would be {} bytes", size + 17)));
+        assert_eq!(unchanged, testing_png().as_bytes());
+        assert_eq!(written.len(), size + 17);
+    }
+
+    #[test]
+    fn test_encode_explain_metrics() {
+        let file_path = temp_path("encode-explain.png");
+        let before = testing_png().as_bytes().len();
+        fs::write(&file_path, testing_png().as_bytes()).unwrap();
+
+        metrics::enable();
+        let report = encode(&file_path, Some("ruSt"), Some(b"hello"), &None, &None, None, None, false, false, false, false, &HidingOptions { mode: HidingMode::Chunk, bits: 1 }, &ChaffOptions::default(), Recording { enabled: false, limit: 0 }, &PNG_CRC).unwrap();
+        let metrics = metrics::take().unwrap();
+        fs::remove_file(&file_path).unwrap();
+
+        assert_eq!(metrics.bytes_read, before);
+        assert_eq!(metrics.chunks_parsed, 2);
+        assert_eq!(metrics.bytes_written, before + 17);
+        assert!(report.to_json(Some(&metrics)).contains(", \"metrics\": {\"bytes_read\": "));
+        assert!(Cli::try_parse_from(["pngme", "list", "image.png", "--explain"]).unwrap().explain);
+    }
+
+    #[test]
+    fn test_encode_size_report() {
+        let file_path = temp_path("encode-size-report.png");
+        fs::write(&file_path, testing_png().as_bytes()).unwrap();
+
+        let report = encode(&file_path, Some("ruSt"), Some(b"hello"), &None, &None, None, None, false, false, false, false, &HidingOptions { mode: HidingMode::Chunk, bits: 1 }, &ChaffOptions::default(), Recording { enabled: false, limit: 0 }, &PNG_CRC).unwrap();
+        let size = fs::metadata(&file_path).unwrap().len() as usize;
+        fs::remove_file(&file_path).unwrap();
+
+        assert_eq!(report, SizeReport { payload: 5, before: testing_png().as_bytes().len(), after: size });
+        assert_eq!(report.added(), 5 + 12);
+        assert_eq!(report.to_json(None), format!("{{\"payload_bytes\": 5, \"added_bytes\": 17, \"size_before\": {}, \"size_after\": {}}}", size - 17, size));
+    }
+
+    #[test]
+    fn test_set_data() {
+        let file_path = temp_path("set-data.png");
+        let data_path = temp_path("set-data.bin");
+        let path = file_path.to_str().unwrap();
+
+        let mut png = testing_png();
+        png.insert_chunk(1, Chunk::new_with_crc(ChunkType::from_str("ruSt").unwrap(), b"first".to_vec(), &PNG_CRC)).unwrap();
+        png.insert_chunk(2, Chunk::new_with_crc(ChunkType::from_str("ruSt").unwrap(), b"second".to_vec(), &PNG_CRC)).unwrap();
+        fs::write(&file_path, png.as_bytes()).unwrap();
+        fs::write(&data_path, [0, 1, 2]).unwrap();
+
+        run(&Cli::parse_from(["pngme", "set-data", path, "ruSt", "--index", "1", "--data", "changed"])).unwrap();
+        run(&Cli::parse_from(["pngme", "set-data", path, "ruSt", "--data-file", data_path.to_str().unwrap()])).unwrap();
+        let missing = run(&Cli::parse_from(["pngme", "set-data", path, "ruSt", "--index", "2", "--data", "x"])).unwrap_err();
+        let critical = run(&Cli::parse_from(["pngme", "set-data", path, "IHDR", "--data", "x"]));
+
+        let png = read_png(&file_path, &PNG_CRC).unwrap();
+        fs::remove_file(&file_path).unwrap();
+        fs::remove_file(&data_path).unwrap();
+
+        let types: Vec<String> = png.chunks().iter().map(|chunk| chunk.chunk_type().to_string()).collect();
+        assert_eq!(types, ["IHDR", "ruSt", "ruSt", "IEND"]);
+        assert_eq!(png.chunks()[1].data(), [0, 1, 2]);
+        assert_eq!(png.chunks()[2].data(), b"changed");
+        assert_eq!(crate::error::exit_code(&missing), crate::error::EXIT_NOT_FOUND);
+        assert!(critical.is_err());
+        assert_eq!(png.chunks()[0].data(), [0; 13]);
+    }
+
+    #[test]
+    fn test_encode_split_and_decode_join() {
+        let file_path = temp_path("encode-split.png");
+        let output_path = temp_path("encode-split.txt");
+        let path = file_path.to_str().unwrap();
+        fs::write(&file_path, testing_png().as_bytes()).unwrap();
+
+        run(&Cli::parse_from(["pngme", "encode", path, "ruSt", "one two three", "--split", "5"])).unwrap();
+        run(&Cli::parse_from(["pngme", "decode", path, "ruSt", "--join", "-o", output_path.to_str().unwrap()])).unwrap();
+
+        let png = read_png(&file_path, &PNG_CRC).unwrap();
+        let joined = fs::read(&output_path).unwrap();
+
+        let mut shuffled = testing_png();
+        for index in [2, 1] {
+            shuffled.insert_before_iend(Chunk::new_with_crc(ChunkType::from_str("ruSt").unwrap(), png.chunks()[index].data().to_vec(), &PNG_CRC));
+        }
+        fs::write(&file_path, shuffled.as_bytes()).unwrap();
+        let swapped = run(&Cli::parse_from(["pngme", "decode", path, "ruSt", "--join"])).unwrap_err();
+
+        fs::remove_file(&file_path).unwrap();
+        fs::remove_file(&output_path).unwrap();
+
+        let pieces: Vec<&[u8]> = png.chunks().iter().filter(|chunk| chunk.chunk_type().to_string() == "ruSt").map(Chunk::data).collect();
+        assert_eq!(pieces, [&b"\0\0one t"[..], b"\0\x01wo th", b"\0\x02ree"]);
+        assert_eq!(joined, b"one two three");
+        assert!(swapped.to_string().contains("piece 1 is stored where piece 0 belongs"));
+    }
+
+    #[test]
+    fn test_encode_chaff_and_drop_it() {
+        let file_path = temp_path("encode-chaff.png");
+        let path = file_path.to_str().unwrap();
+        fs::write(&file_path, testing_png().as_bytes()).unwrap();
+
+        run(&Cli::parse_from(["pngme", "encode", path, "ruSt", "the real message", "--chaff", "5", "--chaff-key", "secret", "--seed", "42"])).unwrap();
+        let chaffed = fs::read(&file_path).unwrap();
+        run(&Cli::parse_from(["pngme", "decode", path, "ruSt"])).unwrap();
+
+        fs::write(&file_path, testing_png().as_bytes()).unwrap();
+        run(&Cli::parse_from(["pngme", "encode", path, "ruSt", "the real message", "--chaff", "5", "--chaff-key", "secret", "--seed", "42"])).unwrap();
+        let again = fs::read(&file_path).unwrap();
+
+        run(&Cli::parse_from(["pngme", "optimize", path, "--drop-chaff", "another secret"])).unwrap();
+        let unchanged = read_png(&file_path, &PNG_CRC).unwrap().chunks().len();
+        run(&Cli::parse_from(["pngme", "optimize", path, "--drop-chaff", "secret"])).unwrap();
+        let png = read_png(&file_path, &PNG_CRC).unwrap();
+        fs::remove_file(&file_path).unwrap();
+
+        let chaffed = Png::try_from(chaffed.as_slice()).unwrap();
+        assert_eq!(chaffed.chunks().len(), 8);
+        assert_eq!(chaffed.chunks().iter().filter(|chunk| chaff::is_chaff(chunk, b"secret")).count(), 5);
+        assert_eq!(chaffed.as_bytes(), again);
+        assert_eq!(unchanged, 8);
+
+        let types: Vec<String> = png.chunks().iter().map(|chunk| chunk.chunk_type().to_string()).collect();
+        assert_eq!(types, ["IHDR", "ruSt", "IEND"]);
+        assert!(Cli::try_parse_from(["pngme", "encode", path, "ruSt", "message", "--seed", "1"]).is_err());
+        assert!(Cli::try_parse_from(["pngme", "encode", path, "ruSt", "message", "--chaff", "2"]).is_err());
+    }
+
+    #[test]
+    fn test_encode_strict_type() {
+        let file_path = temp_path("encode-strict-type.png");
+        let path = file_path.to_str().unwrap();
+        fs::write(&file_path, testing_png().as_bytes()).unwrap();
+
+        let refused = run(&Cli::parse_from(["pngme", "encode", path, "IDAT", "hidden", "--strict-type"])).unwrap_err();
+        let png = read_png(&file_path, &PNG_CRC).unwrap();
+        run(&Cli::parse_from(["pngme", "encode", path, "tEXt", "warned"])).unwrap();
+        run(&Cli::parse_from(["pngme", "encode", path, "ruSt", "private", "--strict-type"])).unwrap();
+
+        let warned = read_png(&file_path, &PNG_CRC).unwrap();
+        fs::remove_file(&file_path).unwrap();
+
+        assert!(refused.to_string().contains("IDAT is a standard chunk type"));
+        assert_eq!(png.chunks().len(), 2);
+        assert_eq!(warned.chunks().len(), 4);
+    }
+
+    #[test]
+    fn test_remove_shred() {
+        let file_path = temp_path("remove-shred.png");
+        let link_path = temp_path("remove-shred-link.png");
+        let path = file_path.to_str().unwrap();
+
+        let mut png = testing_png();
+        png.insert_chunk(1, Chunk::new_with_crc(ChunkType::from_str("ruSt").unwrap(), b"top secret payload".to_vec(), &PNG_CRC)).unwrap();
+        fs::write(&file_path, png.as_bytes()).unwrap();
+        // The link keeps the original file around after it is replaced
+        fs::hard_link(&file_path, &link_path).unwrap();
+
+        run(&Cli::parse_from(["pngme", "remove", path, "ruSt", "--shred"])).unwrap();
+
+        let original = fs::read(&link_path).unwrap();
+        let png = read_png(&file_path, &PNG_CRC).unwrap();
+        fs::remove_file(&file_path).unwrap();
+        fs::remove_file(&link_path).unwrap();
+
+        assert!(!original.windows(6).any(|window| window == b"secret"));
+        assert_eq!(original.len(), testing_png().as_bytes().len() + 12 + 18);
+        assert_eq!(png.chunks().len(), 2);
+        assert!(Cli::try_parse_from(["pngme", "remove", path, "ruSt", "--shred", "--undoable"]).is_err());
+    }
+
+    #[test]
+    fn test_encrypt_with_keyfile() {
+        let file_path = temp_path("encrypt-keyfile.png");
+        let keyfile = temp_path("encrypt-keyfile.key");
+        let other_keyfile = temp_path("encrypt-keyfile-other.key");
+        let output = temp_path("encrypt-keyfile.txt");
+        fs::write(&file_path, testing_png().as_bytes()).unwrap();
+        let path = file_path.to_str().unwrap();
+
+        run(&Cli::parse_from(["pngme", "keygen", "--symmetric", "-o", keyfile.to_str().unwrap()])).unwrap();
+        run(&Cli::parse_from(["pngme", "keygen", "--symmetric", "-o", other_keyfile.to_str().unwrap()])).unwrap();
+        let overwrite = run(&Cli::parse_from(["pngme", "keygen", "--symmetric", "-o", keyfile.to_str().unwrap()]));
+
+        run(&Cli::parse_from(["pngme", "encode", path, "ruSt", "secret message", "--encrypt", "--keyfile", keyfile.to_str().unwrap()])).unwrap();
+        let stored = read_png(&file_path, &PNG_CRC).unwrap().chunk_by_type("ruSt").unwrap().data().to_vec();
+
+        run(&Cli::parse_from(["pngme", "decode", path, "ruSt", "--keyfile", keyfile.to_str().unwrap(), "-o", output.to_str().unwrap()])).unwrap();
+        let message = fs::read(&output).unwrap();
+        let wrong_key = run(&Cli::parse_from(["pngme", "decode", path, "ruSt", "--keyfile", other_keyfile.to_str().unwrap()]));
+
+        for path in [&file_path, &keyfile, &other_keyfile, &output] {
+            fs::remove_file(path).unwrap();
+        }
+
+        assert!(overwrite.is_err());
+        assert!(crypt::is_encrypted(&stored));
+        assert!(!stored.windows(6).any(|window| window == b"secret"));
+        assert_eq!(message, b"secret message");
+        assert!(wrong_key.unwrap_err().to_string().contains("the keyfile is wrong"));
+        assert!(Cli::try_parse_from(["pngme", "encode", "image.png", "ruSt", "message", "--encrypt"]).is_err());
+    }
+
+    #[test]
+    fn test_key_sources_exclude_each_other() {
+        let parse = |args: &[&str]| Cli::try_parse_from([&["pngme", "decode", "image.png", "ruSt"], args].concat());
+
+        assert!(parse(&["--passphrase"]).is_ok());
+        assert!(parse(&["--use-keyring", "backups"]).is_ok());
+        assert!(parse(&["--keyfile", "key", "--passphrase"]).is_err());
+        assert!(parse(&["--passphrase", "--use-keyring", "backups"]).is_err());
+        assert!(Cli::try_parse_from(["pngme", "encode", "image.png", "ruSt", "message", "--encrypt", "--use-keyring", "backups"]).is_ok());
+    }
+
+    #[cfg(not(feature = "keyring"))]
+    #[test]
+    fn test_keyring_compiled_out() {
+        let error = run(&Cli::parse_from(["pngme", "keyring", "forget", "backups"])).unwrap_err();
+
+        assert!(error.to_string().contains("--features keyring"));
+        assert!(read_keyring("backups").is_err());
+    }
+
+    #[test]
+    fn test_decode_miss_lists_private_chunks() {
+        let file_path = temp_path("decode-miss.png");
+        let mut png = testing_png();
+        png.insert_chunk(1, Chunk::new(ChunkType::from_str("teSt").unwrap(), vec![b'a'; 42])).unwrap();
+        png.insert_chunk(2, Chunk::new(ChunkType::from_str("pmKv").unwrap(), vec![0; 128])).unwrap();
+        png.insert_chunk(3, Chunk::new(ChunkType::from_str("tEXt").unwrap(), b"Comment\0public".to_vec())).unwrap();
+        fs::write(&file_path, png.as_bytes()).unwrap();
+        let path = file_path.to_str().unwrap();
+
+        let error = run(&Cli::parse_from(["pngme", "decode", path, "ruSt"])).unwrap_err();
+        let quiet = run(&Cli::parse_from(["pngme", "decode", path, "ruSt", "--quiet"])).unwrap_err();
+        let joined = run(&Cli::parse_from(["pngme", "decode", path, "ruSt", "--join"])).unwrap_err();
+        let candidates = candidates(&read_png(&file_path, &PNG_CRC).unwrap());
+        fs::remove_file(&file_path).unwrap();
+
+        assert_eq!(crate::error::exit_code(&error), crate::error::EXIT_NOT_FOUND);
+        assert!(error.to_string().ends_with("No 'ruSt' chunk; this file contains private chunks: teSt (42 B, utf-8), pmKv (128 B, binary)"));
+        assert!(quiet.to_string().ends_with("No 'ruSt' chunk"));
+        assert!(joined.to_string().contains("teSt (42 B, utf-8)"));
+        assert_eq!(candidates[1].to_json(), "{\"type\": \"pmKv\", \"size\": 128, \"utf8\": false}");
+    }
+
+    #[test]
+    fn test_candidates_message_is_capped() {
+        let many: Vec<Candidate> = (0..12).map(|index| Candidate { chunk_type: format!("pr{:02}", index), size: 1, utf8: true }).collect();
+        let message = candidates_message("No 'ruSt' chunk", &many);
+
+        assert!(message.contains("pr09 (1 B, utf-8), and 2 more"));
+        assert!(!message.contains("pr10"));
+        assert_eq!(candidates_message("No 'ruSt' chunk", &[]), "No 'ruSt' chunk; this file contains no private chunks");
+    }
+
+    #[test]
+    fn test_interrupted_before_reading() {
+        let file_path = temp_path("interrupted-encode.png");
+        let original = testing_png().as_bytes();
+        fs::write(&file_path, &original).unwrap();
+
+        let args = Cli::parse_from(["pngme", "encode", file_path.to_str().unwrap(), "ruSt", "message"]);
+        interrupt::simulate(true);
+        let error = run(&args).unwrap_err();
+        interrupt::simulate(false);
+
+        let after = fs::read(&file_path).unwrap();
+        fs::remove_file(&file_path).unwrap();
+
+        assert_eq!(crate::error::exit_code(&error), crate::error::EXIT_INTERRUPTED);
+        assert_eq!(after, original);
+    }
+
+    #[test]
+    fn test_interrupted_before_writing() {
+        let file_path = temp_path("interrupted-write.png");
+        let output = temp_path("interrupted-write-new.png");
+        fs::write(&file_path, b"before").unwrap();
+
+        interrupt::simulate(true);
+        let atomic = write_atomically(&file_path, b"after");
+        let plain = write_file(&output, b"after");
+        interrupt::simulate(false);
+
+        let name = file_path.file_name().unwrap().to_string_lossy();
+        let staging = file_path.with_file_name(format!(".{}.pngme-tmp", name));
+        let kept = fs::read(&file_path).unwrap();
+        fs::remove_file(&file_path).unwrap();
+
+        assert_eq!(crate::error::exit_code(&atomic.unwrap_err()), crate::error::EXIT_INTERRUPTED);
+        assert_eq!(crate::error::exit_code(&plain.unwrap_err()), crate::error::EXIT_INTERRUPTED);
+        assert_eq!(kept, b"before");
+        assert!(!staging.exists());
+        assert!(!output.exists());
+    }
+
+    #[test]
+    fn test_failed_rename_removes_the_staging_file() {
+        // Renaming a file over a directory that is not empty fails everywhere
+        let directory = temp_path("rename-over-directory.png");
+        fs::create_dir_all(directory.join("inside")).unwrap();
+
+        let result = write_atomically(&directory, b"image");
+
+        let staging = directory.with_file_name(format!(".{}.pngme-tmp", directory.file_name().unwrap().to_string_lossy()));
+        let staging_left = staging.exists();
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert!(result.is_err());
+        assert!(!staging_left);
+    }
+
+    #[test]
+    fn test_encode_output_aliasing_the_input() {
+        let file_path = temp_path("alias-encode.png");
+        fs::write(&file_path, testing_png().as_bytes()).unwrap();
+
+        // The same file through `..`, which a plain comparison of the paths would miss
+        let directory = file_path.parent().unwrap();
+        let alias = directory.join("..").join(directory.file_name().unwrap()).join(file_path.file_name().unwrap());
+
+        let args = Cli::parse_from(["pngme", "encode", file_path.to_str().unwrap(), "ruSt", "message", alias.to_str().unwrap()]);
+        run(&args).unwrap();
+
+        let png = read_png(&file_path, &PNG_CRC).unwrap();
+        let staging = file_path.with_file_name(format!(".{}.pngme-tmp", file_path.file_name().unwrap().to_string_lossy()));
+        fs::remove_file(&file_path).unwrap();
+
+        assert_eq!(png.chunk_by_type("ruSt").unwrap().data(), b"message");
+        assert!(!staging.exists());
+    }
+
+    #[test]
+    fn test_encode_prepend() {
+        let file_path = temp_path("encode-prepend.png");
+        fs::write(&file_path, testing_png().as_bytes()).unwrap();
+
+        let args = Cli::parse_from(["pngme", "encode", file_path.to_str().unwrap(), "ruSt", "first in line", "--prepend"]);
+        run(&args).unwrap();
+
+        let png = read_png(&file_path, &PNG_CRC).unwrap();
+
+        let headless = Png::from_chunks(vec![Chunk::new(ChunkType::IEND, vec![])]);
+        fs::write(&file_path, headless.as_bytes()).unwrap();
+        let error = run(&args).unwrap_err();
+        fs::remove_file(&file_path).unwrap();
+
+        assert_eq!(png.chunks()[1].chunk_type().to_string(), "ruSt");
+        assert_eq!(png.chunks()[1].data(), b"first in line");
+        assert!(error.to_string().contains("There is no IHDR chunk"));
+    }
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn test_convert_and_encode() {
+        let input = temp_path("convert.bmp");
+        let output = temp_path("convert.png");
+        fs::write(&input, crate::convert::tests::testing_bmp()).unwrap();
+
+        let args = Cli::parse_from(["pngme", "convert", input.to_str().unwrap(), "-o", output.to_str().unwrap(), "--encode", "ruSt", "converted"]);
+        run(&args).unwrap();
+
+        let png = read_png(&output, &PNG_CRC).unwrap();
+        fs::remove_file(&input).unwrap();
+        fs::remove_file(&output).unwrap();
+
+        assert_eq!(png.chunks()[0].chunk_type(), ChunkType::IHDR);
+        assert_eq!(png.chunk_by_type("ruSt").unwrap().data(), b"converted");
+    }
+
+    #[test]
+    fn test_copy_chunks() {
+        let from = temp_path("copy-chunks-from.png");
+        let file_path = temp_path("copy-chunks-to.png");
+
+        let mut source = testing_png();
+        source.insert_chunk(1, Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"copied".to_vec())).unwrap();
+        source.insert_chunk(1, Chunk::new(ChunkType::from_str("tIME").unwrap(), vec![7; 7])).unwrap();
+        fs::write(&from, source.as_bytes()).unwrap();
+        fs::write(&file_path, testing_png().as_bytes()).unwrap();
+
+        let args = Cli::parse_from(["pngme", "--history", "copy-chunks", from.to_str().unwrap(), file_path.to_str().unwrap()]);
+        run(&args).unwrap();
+
+        let mut png = read_png(&file_path, &PNG_CRC).unwrap();
+        let types: Vec<String> = png.chunks().iter().map(|chunk| chunk.chunk_type().to_string()).collect();
+        let log = history::Log::take_from(&mut png).unwrap();
+        fs::remove_file(&from).unwrap();
+        fs::remove_file(&file_path).unwrap();
+
+        assert_eq!(types, ["IHDR", "ruSt", history::HISTORY_CHUNK_TYPE, "IEND"]);
+        assert_eq!(log.entries().last().unwrap().command, "copy");
+    }
+
+    #[test]
+    fn test_decode_without_type_needs_an_earlier_encode() {
+        let args = Cli::parse_from(["pngme", "decode", "image.png"]);
+        let error = run(&args).unwrap_err();
+
+        assert!(error.to_string().contains("Give the chunk type"));
+        assert!(Cli::try_parse_from(["pngme", "remove", "image.png", "--ignore-missing"]).is_ok());
+    }
+
+    #[test]
+    fn test_dedupe_keep_last() {
+        let file_path = temp_path("dedupe.png");
+        let output = temp_path("dedupe-output.png");
+
+        let mut png = testing_png();
+        for message in ["same", "other", "same", "same"] {
+            png.insert_chunk(png.chunk_count() - 1, Chunk::new(ChunkType::from_str("ruSt").unwrap(), message.as_bytes().to_vec())).unwrap();
+        }
+        fs::write(&file_path, png.as_bytes()).unwrap();
+
+        let args = Cli::parse_from(["pngme", "dedupe", file_path.to_str().unwrap(), "--keep", "last", "-o", output.to_str().unwrap()]);
+        run(&args).unwrap();
+
+        let deduped = read_png(&output, &PNG_CRC).unwrap();
+        fs::remove_file(&file_path).unwrap();
+        fs::remove_file(&output).unwrap();
+
+        let messages: Vec<String> = deduped.chunks().iter()
+            .filter(|chunk| chunk.chunk_type().to_string() == "ruSt")
+            .map(|chunk| chunk.data_as_string().unwrap())
+            .collect();
+        assert_eq!(messages, ["other", "same"]);
+        assert_eq!(deduped.chunk_count(), 4);
+    }
+
+    #[test]
+    fn test_split_files_and_join_files() {
+        let payload = temp_path("split-files-payload.bin");
+        let joined = temp_path("split-files-joined.bin");
+        let covers: Vec<PathBuf> = (0..3).map(|index| temp_path(&format!("split-files-{}.png", index))).collect();
+        let names: Vec<&str> = covers.iter().map(|cover| cover.to_str().unwrap()).collect();
+
+        fs::write(&payload, b"a payload too big for one cover").unwrap();
+        for cover in &covers {
+            fs::write(cover, testing_png().as_bytes()).unwrap();
+        }
+
+        run(&Cli::parse_from([&["pngme", "split-files", payload.to_str().unwrap()], &names[..]].concat())).unwrap();
+        let types: Vec<String> = read_png(&covers[0], &PNG_CRC).unwrap().chunks().iter().map(|chunk| chunk.chunk_type().to_string()).collect();
+        let again = run(&Cli::parse_from([&["pngme", "split-files", payload.to_str().unwrap()], &names[..]].concat()));
+
+        run(&Cli::parse_from(["pngme", "join-files", names[2], names[0], names[1], "-o", joined.to_str().unwrap()])).unwrap();
+        let missing = run(&Cli::parse_from(["pngme", "join-files", names[0], names[2], "-o", joined.to_str().unwrap()]));
+
+        let joined_payload = fs::read(&joined).unwrap();
+        for path in covers.iter().chain([&payload, &joined]) {
+            fs::remove_file(path).unwrap();
+        }
+
+        assert_eq!(joined_payload, b"a payload too big for one cover");
+        assert_eq!(types, ["IHDR", "pmSh", "IEND"]);
+        assert!(again.unwrap_err().to_string().contains("already has a pmSh chunk"));
+        assert!(missing.unwrap_err().to_string().contains("Piece 1 of the split message is missing"));
+    }
+
+    #[test]
+    fn test_inspect_offset() {
+        let file_path = temp_path("inspect.png");
+        fs::write(&file_path, testing_png().as_bytes()).unwrap();
+        let path = file_path.to_str().unwrap();
+
+        run(&Cli::parse_from(["pngme", "inspect", path, "--offset", "0x10"])).unwrap();
+        let bad_offset = Cli::try_parse_from(["pngme", "inspect", path, "--offset", "0xZZ"]);
+
+        fs::remove_file(&file_path).unwrap();
+
+        // IHDR starts right after the signature, its data at 0x10
+        let bytes = testing_png().as_bytes();
+        let lines = inspect_lines(&bytes, 0x10, false, &PNG_CRC).unwrap();
+        assert_eq!(lines[0], "0x10 is in chunk 0 (IHDR) starting at 0x8, byte 0 of its data");
+        assert_eq!(lines.len(), 4);
+
+        let json = json::parse(&inspect_lines(&bytes, 0x1d, true, &PNG_CRC).unwrap()[0]).unwrap();
+        assert_eq!(json.get("index").and_then(json::Value::as_u64), Some(0));
+        assert_eq!(json.get("field").and_then(json::Value::as_str), Some("crc"));
+        assert_eq!(json.get("field_offset").and_then(json::Value::as_u64), Some(0));
+
+        let past_end = json::parse(&inspect_lines(&bytes, 1000, true, &PNG_CRC).unwrap()[0]).unwrap();
+        assert_eq!(past_end.get("region").and_then(json::Value::as_str), Some("past_end"));
+
+        assert!(bad_offset.is_err());
+    }
+
+    #[test]
+    fn test_count_bytes() {
+        let file_path = temp_path("count-bytes.png");
+
+        let mut png = testing_png();
+        png.insert_chunk(1, Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"first".to_vec())).unwrap();
+        png.insert_chunk(2, Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"and second".to_vec())).unwrap();
+        fs::write(&file_path, png.as_bytes()).unwrap();
+
+        let chunks = count(&file_path, "ruSt", false, &PNG_CRC).unwrap();
+        let bytes = count(&file_path, "ruSt", true, &PNG_CRC).unwrap();
+        let none = count(&file_path, "teSt", true, &PNG_CRC).unwrap();
+        fs::remove_file(&file_path).unwrap();
+
+        assert_eq!(chunks, 2);
+        assert_eq!(bytes, 5 + 10);
+        assert_eq!(none, 0);
+    }
+
+    #[test]
+    fn test_read_data_uri() {
+        let uri = format!("data:image/png;base64,{}", base64::encode(&testing_png().as_bytes()));
+
+        let png = read_png(Path::new(&uri), &PNG_CRC).unwrap();
+        assert_eq!(png.chunk_count(), 2);
+
+        let args = Cli::parse_from(["pngme", "encode", &uri, "ruSt", "message"]);
+        assert!(run(&args).unwrap_err().to_string().contains("A data URI cannot be written to"));
+
+        let args = Cli::parse_from(["pngme", "decode", "data:image/png;base64,iVBOR", "ruSt"]);
+        assert!(run(&args).unwrap_err().to_string().contains("not valid base64"));
+    }
+
+    #[test]
+    fn test_decode_unframes_ztxt() {
+        let file_path = temp_path("decode-ztxt.png");
+        let output = temp_path("decode-ztxt.txt");
+
+        let mut png = testing_png();
+        png.insert_chunk(1, Chunk::new(ChunkType::from_str("zTXt").unwrap(), text::encode_ztxt("Comment", "hidden").unwrap())).unwrap();
+        fs::write(&file_path, png.as_bytes()).unwrap();
+
+        let args = Cli::parse_from(["pngme", "decode", file_path.to_str().unwrap(), "zTXt", "-o", output.to_str().unwrap()]);
+        run(&args).unwrap();
+
+        let message = fs::read(&output).unwrap();
+        fs::remove_file(&file_path).unwrap();
+        fs::remove_file(&output).unwrap();
+
+        assert_eq!(message, b"hidden");
+    }
+
+    #[test]
+    fn test_history_log() {
+        let file_path = temp_path("history-log.png");
+        fs::write(&file_path, testing_png().as_bytes()).unwrap();
+        let path = file_path.to_str().unwrap();
+
+        // Nothing is recorded unless asked for
+        run(&Cli::parse_from(["pngme", "encode", path, "ruSt", "unrecorded"])).unwrap();
+        run(&Cli::parse_from(["pngme", "remove", path, "ruSt"])).unwrap();
+        assert_eq!(read_png(&file_path, &PNG_CRC).unwrap().as_bytes(), testing_png().as_bytes());
+
+        run(&Cli::parse_from(["pngme", "--history", "encode", path, "ruSt", "secret message"])).unwrap();
+        run(&Cli::parse_from(["pngme", "--history", "remove", path, "ruSt", "--undoable"])).unwrap();
+        run(&Cli::parse_from(["pngme", "--history", "undo", path])).unwrap();
+        run(&Cli::parse_from(["pngme", "remove", path, "ruSt"])).unwrap();
+
+        let mut png = read_png(&file_path, &PNG_CRC).unwrap();
+        let log = Log::take_from(&mut png).unwrap();
+        let recorded: Vec<(&str, &str, usize)> = log.entries()
+            .iter()
+            .map(|entry| (entry.command.as_str(), entry.chunk_type.as_str(), entry.length))
+            .collect();
+
+        assert_eq!(recorded, [("encode", "ruSt", 14), ("remove", "ruSt", 14)]);
+        assert!(!String::from_utf8_lossy(&read_png(&file_path, &PNG_CRC).unwrap().as_bytes()).contains("secret"));
+
+        run(&Cli::parse_from(["pngme", "audit-log", path, "--clear"])).unwrap();
+        let png = read_png(&file_path, &PNG_CRC).unwrap();
+        fs::remove_file(&file_path).unwrap();
+
+        assert!(png.chunk_by_type(history::HISTORY_CHUNK_TYPE).is_none());
+        assert_eq!(png.as_bytes(), testing_png().as_bytes());
+    }
+
+    #[test]
+    fn test_transform_removes_chunks() {
+        let mut png = testing_png();
+        png.insert_chunk(1, Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"first".to_vec())).unwrap();
+        png.insert_chunk(2, Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"second".to_vec())).unwrap();
+
+        let recording = Recording { enabled: false, limit: history::DEFAULT_HISTORY_LIMIT };
+        let mut output = vec![];
+        transform(&mut png.as_bytes().as_slice(), &mut output, &["ruSt".to_string()], recording, &PNG_CRC).unwrap();
+
+        assert_eq!(output, testing_png().as_bytes());
+    }
+
+    #[test]
+    fn test_git_filter() {
+        let sidecar_path = temp_path("git-filter.chunks");
+        let mut png = testing_png();
+        png.insert_chunk(1, Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"secret".to_vec())).unwrap();
+
+        let clean = GitFilterMode::Clean { types: vec![ChunkFilter::Type("ruSt".to_string())], save_to: Some(sidecar_path.clone()) };
+        let mut cleaned = vec![];
+        git_filter(&mut png.as_bytes().as_slice(), &mut cleaned, &clean, &PNG_CRC).unwrap();
+
+        let smudge = GitFilterMode::Smudge { restore_from: Some(sidecar_path.clone()) };
+        let mut smudged = vec![];
+        git_filter(&mut cleaned.as_slice(), &mut smudged, &smudge, &PNG_CRC).unwrap();
+
+        fs::remove_file(&sidecar_path).unwrap();
+
+        let mut unchanged = vec![];
+        git_filter(&mut cleaned.as_slice(), &mut unchanged, &smudge, &PNG_CRC).unwrap();
+
+        assert_eq!(cleaned, testing_png().as_bytes());
+        assert_eq!(smudged, png.as_bytes());
+        assert_eq!(unchanged, cleaned);
+    }
+
+    #[test]
+    fn test_git_filter_passes_through_what_it_cannot_handle() {
+        let clean = GitFilterMode::Clean { types: vec![ChunkFilter::Private], save_to: None };
+
+        let mut output = vec![];
+        git_filter(&mut &b"GIF89a not a png"[..], &mut output, &clean, &PNG_CRC).unwrap();
+        assert_eq!(output, b"GIF89a not a png");
+
+        let mut truncated = testing_png().as_bytes();
+        truncated.pop();
+
+        let mut output = vec![];
+        git_filter(&mut truncated.as_slice(), &mut output, &clean, &PNG_CRC).unwrap();
+        assert_eq!(output, truncated);
+    }
+
+    #[test]
+    fn test_check_tree() {
+        let root = temp_path("check-tree");
+        fs::create_dir_all(root.join("icons")).unwrap();
+
+        let mut private = testing_png();
+        private.insert_chunk(1, Chunk::with_type_and_data("ruSt", b"secret").unwrap()).unwrap();
+        fs::write(root.join("logo.png"), testing_png().as_bytes()).unwrap();
+        fs::write(root.join("icons").join("icon.PNG"), private.as_bytes()).unwrap();
+        fs::write(root.join("notes.txt"), b"not a png").unwrap();
+
+        let path = root.to_str().unwrap();
+        let denied = run(&Cli::parse_from(["pngme", "check-tree", path, "--deny", "private"]));
+        let allowed = run(&Cli::parse_from(["pngme", "check-tree", path, "--deny", "private", "--allow", "ruSt"]));
+        let no_rules = run(&Cli::parse_from(["pngme", "check-tree", path]));
+
+        fs::write(root.join(policy::POLICY_FILE), "deny = [\"type:ruSt\"]\n").unwrap();
+        let from_policy_file = run(&Cli::parse_from(["pngme", "check-tree", path]));
+
+        let bundle = root.join("bundle.bin");
+        fs::write(&bundle, crate::zip::tests::build_zip(&[("inner/icon.png", &private.as_bytes(), true, false)])).unwrap();
+        let archived = run(&Cli::parse_from(["pngme", "check-tree", bundle.to_str().unwrap(), "--archive", "--deny", "private"]));
+
+        fs::remove_dir_all(&root).unwrap();
+
+        assert!(denied.unwrap_err().to_string().ends_with("2 files checked, 1 failing with 1 violation"));
+        allowed.unwrap();
+        assert!(no_rules.unwrap_err().to_string().contains("No deny rules"));
+        assert!(from_policy_file.is_err());
+        assert!(archived.unwrap_err().to_string().ends_with("1 file checked, 1 failing with 1 violation"));
+    }
+
+    #[test]
+    fn test_missing_file_is_an_io_error() {
+        let Err(error) = read_png(&temp_path("missing.png"), &PNG_CRC) else { panic!("a missing file was read") };
+
+        assert!(matches!(error.downcast_ref::<PngMeError>(), Some(PngMeError::Io(error)) if error.kind() == io::ErrorKind::NotFound));
+    }
+
+    #[test]
+    fn test_encode_rejects_corrupt_raw_chunk() {
+        let chunk_path = temp_path("corrupt-raw-chunk.bin");
+
+        let mut bytes = Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"raw message".to_vec()).as_bytes();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xff;
+        fs::write(&chunk_path, bytes).unwrap();
+
+        let error = read_raw_chunk(&chunk_path, &PNG_CRC).unwrap_err();
+        fs::remove_file(&chunk_path).unwrap();
+
+        assert!(error.to_string().contains("does not hold a valid chunk"));
+    }
+
+    #[test]
+    fn test_apply_dry_run_leaves_file_untouched() {
+        let file_path = temp_path("apply-dry-run.png");
+        let manifest_path = temp_path("apply-dry-run.yaml");
+        fs::write(&file_path, testing_png().as_bytes()).unwrap();
+        fs::write(&manifest_path, "- op: encode\n  type: ruSt\n  message: hi\n").unwrap();
+
+        let args = Cli::parse_from(["pngme", "apply", file_path.to_str().unwrap(), "--manifest", manifest_path.to_str().unwrap(), "--dry-run"]);
+        run(&args).unwrap();
+        assert_eq!(fs::read(&file_path).unwrap(), testing_png().as_bytes());
+
+        let args = Cli::parse_from(["pngme", "apply", file_path.to_str().unwrap(), "--manifest", manifest_path.to_str().unwrap()]);
+        run(&args).unwrap();
+        let png = read_png(&file_path, &PNG_CRC).unwrap();
+
+        fs::remove_file(&file_path).unwrap();
+        fs::remove_file(&manifest_path).unwrap();
+
+        assert_eq!(png.chunk_by_type("ruSt").unwrap().data(), b"hi");
+    }
+
+    #[test]
+    fn test_error_names_the_file() {
+        let file_path = std::env::temp_dir().join("pngme-test-error-names-the-file.png");
+        fs::write(&file_path, b"definitely not a png").unwrap();
+
+        let args = Cli::parse_from(["pngme", "list", file_path.to_str().unwrap()]);
+        let error = run(&args).unwrap_err();
+        fs::remove_file(&file_path).unwrap();
+
+        assert!(error.to_string().contains(file_path.to_str().unwrap()));
+        assert!(error.to_string().contains("Invalid header"));
+    }
+}
@@ -0,0 +1,263 @@
+use std::{io::Write, process::{Command, Stdio}};
+
+use crc::Crc;
+
+use crate::{chunk::Chunk, chunk_type::ChunkType, error::PngMeError, format::{self, Format}, ihdr, png::Png, scanline, zlib, Result};
+
+/// The pixels of a decoded image, rows top to bottom, as 8-bit RGB or RGBA samples
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pixels {
+    pub width: u32,
+    pub height: u32,
+    pub alpha: bool,
+    pub samples: Vec<u8>,
+}
+
+/// Turns an image into a PNG. BMP files are decoded here; JPEG, GIF and WEBP files
+/// are handed to ImageMagick, which has to be on the PATH. A PNG is only parsed.
+pub fn to_png(bytes: &[u8], crc: &Crc<u32>) -> Result<Png> {
+
+    match format::identify(bytes) {
+        Format::Png => Png::try_from_with_crc(bytes, crc),
+        Format::Bmp => {
+            let pixels = decode_bmp(bytes).map_err(|error| format!("Cannot convert this BMP file: {}", error))?;
+
+            Ok(build_png(&pixels, crc))
+        },
+        format @ (Format::Jpeg | Format::Gif | Format::Webp) => {
+            let png = with_imagemagick(bytes, format)?;
+
+            Png::try_from_with_crc(&png, crc).map_err(|error| format!("ImageMagick wrote an invalid PNG for this {} file: {}", format, error).into())
+        },
+        Format::Unknown => Err("Cannot convert this file, its format is not one of BMP, JPEG, GIF or WEBP".into()),
+    }
+}
+
+/// A PNG holding the pixels in a single IDAT chunk, every row filtered with None
+pub fn build_png(pixels: &Pixels, crc: &Crc<u32>) -> Png {
+
+    let color_type = if pixels.alpha { ihdr::COLOR_RGBA } else { ihdr::COLOR_RGB };
+    let row_length = pixels.width as usize * if pixels.alpha { 4 } else { 3 };
+
+    let mut header = [pixels.width.to_be_bytes(), pixels.height.to_be_bytes()].concat();
+    header.extend_from_slice(&[8, color_type, 0, 0, 0]);
+
+    let data = zlib::compress(&scanline::filter_none(&pixels.samples, row_length));
+
+    Png::from_chunks(vec![
+        Chunk::new_with_crc(ChunkType::IHDR, header, crc),
+        Chunk::new_with_crc(ChunkType::IDAT, data, crc),
+        Chunk::new_with_crc(ChunkType::IEND, vec![], crc),
+    ])
+}
+
+/// Decodes an uncompressed BMP file of 8 (with a palette), 24 or 32 bits per pixel,
+/// stored bottom-up or top-down
+pub fn decode_bmp(bytes: &[u8]) -> Result<Pixels> {
+
+    let u16_at = |offset: usize| bytes.get(offset..offset + 2).map(|field| u16::from_le_bytes([field[0], field[1]]));
+    let u32_at = |offset: usize| bytes.get(offset..offset + 4).map(|field| u32::from_le_bytes([field[0], field[1], field[2], field[3]]));
+
+    let truncated = || "the file is truncated";
+
+    let pixel_offset = u32_at(10).ok_or_else(truncated)? as usize;
+    let header_size = u32_at(14).ok_or_else(truncated)?;
+
+    if header_size < 40 {
+        return Err("only BMP files with a BITMAPINFOHEADER or later header are supported".into())
+    }
+
+    let width = u32_at(18).ok_or_else(truncated)? as i32;
+    let height = u32_at(22).ok_or_else(truncated)? as i32;
+    let bits = u16_at(28).ok_or_else(truncated)?;
+    let compression = u32_at(30).ok_or_else(truncated)?;
+
+    // BI_RGB, or BI_BITFIELDS with the usual BGRA masks of a 32-bit file
+    if compression != 0 && !(compression == 3 && bits == 32) {
+        return Err(format!("compressed BMP files (compression {}) are not supported", compression).into())
+    }
+
+    if width <= 0 || height == 0 {
+        return Err(format!("the image is {} by {} pixels", width, height).into())
+    }
+
+    let (width, top_down) = (width as usize, height < 0);
+    let height = height.unsigned_abs() as usize;
+
+    let palette = match bits {
+        8 => {
+            let colors = match u32_at(46).ok_or_else(truncated)? {
+                0 => 256,
+                colors => colors as usize,
+            };
+            let start = 14 + header_size as usize;
+
+            bytes.get(start..start + colors * 4).ok_or_else(truncated)?.chunks(4).map(|entry| [entry[2], entry[1], entry[0]]).collect()
+        },
+        24 | 32 => vec![],
+        bits => return Err(format!("BMP files with {} bits per pixel are not supported", bits).into()),
+    };
+
+    // Every row is padded to a multiple of 4 bytes
+    let stride = (width * bits as usize / 8).div_ceil(4) * 4;
+    let stored_channels = bits as usize / 8;
+
+    // The fourth byte of a BI_RGB pixel is unused, often left at 0, so only an alpha
+    // mask (in a BITMAPV3INFOHEADER or later) makes it alpha
+    let alpha = bits == 32 && compression == 3 && header_size >= 56 && u32_at(66) == Some(0xff00_0000);
+    let channels = if alpha { 4 } else { 3 };
+
+    let size = stride.checked_mul(height).ok_or("the image is too large")?;
+    let stored = bytes.get(pixel_offset..).and_then(|data| data.get(..size)).ok_or_else(truncated)?;
+
+    let mut samples = Vec::with_capacity(width * height * channels);
+
+    for row in 0..height {
+        let stored_row = if top_down { row } else { height - 1 - row };
+        let line = &stored[stored_row * stride..];
+
+        for column in 0..width {
+            match bits {
+                8 => {
+                    let color = palette.get(line[column] as usize).ok_or("a pixel refers to a color past the palette")?;
+                    samples.extend_from_slice(color);
+                },
+                _ => {
+                    let pixel = &line[column * stored_channels..column * stored_channels + stored_channels];
+                    samples.extend_from_slice(&[pixel[2], pixel[1], pixel[0]]);
+
+                    if alpha {
+                        samples.push(pixel[3]);
+                    }
+                },
+            }
+        }
+    }
+
+    Ok(Pixels { width: width as u32, height: height as u32, alpha, samples })
+}
+
+/// Converts the image to PNG with `magick`, or the `convert` of ImageMagick 6
+fn with_imagemagick(bytes: &[u8], format: Format) -> Result<Vec<u8>> {
+
+    let missing = || format!("Converting {} files needs ImageMagick (magick or convert) on the PATH", format);
+
+    let mut child = ["magick", "convert"].iter()
+        .find_map(|tool| {
+            Command::new(tool)
+                .args(["-", "png:-"])
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .spawn()
+                .ok()
+        })
+        .ok_or_else(missing)?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(bytes).map_err(PngMeError::from)?;
+    }
+
+    let output = child.wait_with_output().map_err(PngMeError::from)?;
+
+    if !output.status.success() {
+        return Err(format!("ImageMagick cannot read this {} file: {}", format, String::from_utf8_lossy(&output.stderr).trim()).into())
+    }
+
+    Ok(output.stdout)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    const PNG_CRC: Crc<u32> = Crc::<u32>::new(&crc::CRC_32_ISO_HDLC);
+
+    /// A 2 by 2 BMP of 24 bits per pixel, bottom-up: blue and green, then red and white
+    pub(crate) fn testing_bmp() -> Vec<u8> {
+        let mut bmp = b"BM".to_vec();
+        bmp.extend(70u32.to_le_bytes());
+        bmp.extend([0; 4]);
+        bmp.extend(54u32.to_le_bytes());
+        bmp.extend(40u32.to_le_bytes());
+        bmp.extend(2i32.to_le_bytes());
+        bmp.extend(2i32.to_le_bytes());
+        bmp.extend(1u16.to_le_bytes());
+        bmp.extend(24u16.to_le_bytes());
+        bmp.extend([0; 24]);
+
+        // Rows are stored bottom first, pixels as BGR, each row padded to 8 bytes
+        bmp.extend([0, 0, 255, 255, 255, 255, 0, 0]);
+        bmp.extend([255, 0, 0, 0, 255, 0, 0, 0]);
+
+        bmp
+    }
+
+    #[test]
+    fn test_decode_bmp() {
+        let pixels = decode_bmp(&testing_bmp()).unwrap();
+
+        assert_eq!((pixels.width, pixels.height, pixels.alpha), (2, 2, false));
+        assert_eq!(pixels.samples, [0, 0, 255, 0, 255, 0, 255, 0, 0, 255, 255, 255]);
+    }
+
+    /// A 1 by 1 BMP of 32 bits per pixel, with a half transparent red pixel
+    fn bmp_32(compression: u32, alpha_mask: u32) -> Vec<u8> {
+        let mut bmp = b"BM".to_vec();
+        bmp.extend(74u32.to_le_bytes());
+        bmp.extend([0; 4]);
+        bmp.extend(70u32.to_le_bytes());
+        bmp.extend(56u32.to_le_bytes());
+        bmp.extend(1i32.to_le_bytes());
+        bmp.extend(1i32.to_le_bytes());
+        bmp.extend(1u16.to_le_bytes());
+        bmp.extend(32u16.to_le_bytes());
+        bmp.extend(compression.to_le_bytes());
+        bmp.extend([0; 20]);
+        bmp.extend([0x00ff_0000u32, 0x0000_ff00, 0x0000_00ff, alpha_mask].iter().flat_map(|mask| mask.to_le_bytes()));
+        bmp.extend([0, 0, 255, 128]);
+
+        bmp
+    }
+
+    #[test]
+    fn test_decode_32_bit_bmp_alpha() {
+        let with_mask = decode_bmp(&bmp_32(3, 0xff00_0000)).unwrap();
+        assert!(with_mask.alpha);
+        assert_eq!(with_mask.samples, [255, 0, 0, 128]);
+
+        // BI_RGB leaves the fourth byte unused, the pixel stays opaque
+        let plain = decode_bmp(&bmp_32(0, 0xff00_0000)).unwrap();
+        assert!(!plain.alpha);
+        assert_eq!(plain.samples, [255, 0, 0]);
+
+        let no_mask = decode_bmp(&bmp_32(3, 0)).unwrap();
+        assert!(!no_mask.alpha);
+        assert_eq!(no_mask.samples, [255, 0, 0]);
+    }
+
+    #[test]
+    fn test_bmp_to_png() {
+        let png = to_png(&testing_bmp(), &PNG_CRC).unwrap();
+        let header = ihdr::ImageHeader::from_png(&png).unwrap();
+
+        assert_eq!((header.width, header.height, header.color_type), (2, 2, ihdr::COLOR_RGB));
+        assert!(png.order_violations().is_empty());
+
+        let data = zlib::decompress(png.chunk_by_type("IDAT").unwrap().data(), 1024).unwrap();
+        assert_eq!(data, [0, 0, 0, 255, 0, 255, 0, 0, 255, 0, 0, 255, 255, 255]);
+    }
+
+    #[test]
+    fn test_unsupported_inputs() {
+        let mut truncated = testing_bmp();
+        truncated.truncate(60);
+        assert_eq!(to_png(&truncated, &PNG_CRC).unwrap_err().to_string(), "Cannot convert this BMP file: the file is truncated");
+
+        let mut compressed = testing_bmp();
+        compressed[30] = 1;
+        assert!(to_png(&compressed, &PNG_CRC).unwrap_err().to_string().contains("compression 1"));
+
+        assert!(to_png(b"plain text", &PNG_CRC).unwrap_err().to_string().contains("not one of BMP, JPEG, GIF or WEBP"));
+    }
+}
@@ -0,0 +1,608 @@
+use std::{fs::{self, File}, io::{Read, Write}, path::Path};
+
+use crate::{editor, error::PngMeError, metrics::{self, Stage}, sha256, Result};
+
+/// Every encrypted message starts with these bytes, followed by the header version
+pub const MAGIC: &[u8; 4] = b"PMEC";
+
+const VERSION: u8 = 1;
+
+/// ChaCha20-Poly1305 (RFC 8439) takes a 256-bit key and a 96-bit nonce
+pub const KEY_LEN: usize = 32;
+pub const NONCE_LEN: usize = 12;
+pub const TAG_LEN: usize = 16;
+
+pub const SALT_LEN: usize = 16;
+
+/// PBKDF2-HMAC-SHA256 iterations for new passphrase messages, as OWASP recommends. The
+/// count is stored in the header, so raising it later still opens older messages.
+pub const PASSPHRASE_ITERATIONS: u32 = 600_000;
+
+/// The most iterations a header may ask for. The count comes from the file, and a
+/// crafted one could otherwise keep the key derivation busy for hours.
+pub const MAX_PASSPHRASE_ITERATIONS: u32 = 10_000_000;
+
+/// How the key of an encrypted message is obtained. The header names it, so that a
+/// file tells which of --keyfile or a passphrase it needs.
+#[derive(Debug, Clone, PartialEq)]
+pub enum KeySource {
+    /// Derived from a passphrase with this salt and iteration count
+    Passphrase { salt: [u8; SALT_LEN], iterations: u32 },
+    /// The 32 bytes of a keyfile, used as they are
+    Keyfile,
+}
+
+impl KeySource {
+    fn id(&self) -> u8 {
+
+        match self {
+            KeySource::Passphrase { .. } => 1,
+            KeySource::Keyfile => 2,
+        }
+    }
+}
+
+/// The plaintext start of an encrypted message: magic, version, key source, the
+/// parameters of the key source and the nonce. It is authenticated along with the message.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Header {
+    pub source: KeySource,
+    pub nonce: [u8; NONCE_LEN],
+}
+
+impl Header {
+    pub fn to_bytes(&self) -> Vec<u8> {
+
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(VERSION);
+        bytes.push(self.source.id());
+
+        if let KeySource::Passphrase { salt, iterations } = &self.source {
+            bytes.extend_from_slice(salt);
+            bytes.extend_from_slice(&iterations.to_be_bytes());
+        }
+
+        bytes.extend_from_slice(&self.nonce);
+        bytes
+    }
+
+    /// Reads the header at the start of `data`, returning it and its length
+    pub fn parse(data: &[u8]) -> Result<(Header, usize)> {
+
+        if !is_encrypted(data) {
+            return Err("The message is not encrypted".into())
+        }
+
+        let version = *data.get(MAGIC.len()).ok_or("The encryption header is truncated")?;
+
+        if version != VERSION {
+            return Err(format!("The message was encrypted with header version {}, this version of pngme reads version {}", version, VERSION).into())
+        }
+
+        let mut position = MAGIC.len() + 2;
+        let mut take = |length: usize| {
+            let bytes = data.get(position..position + length).ok_or("The encryption header is truncated");
+            position += length;
+            bytes
+        };
+
+        let source = match data.get(MAGIC.len() + 1) {
+            Some(1) => {
+                let salt = take(SALT_LEN)?.try_into().expect("The slice has the length of a salt");
+                let iterations = u32::from_be_bytes(take(4)?.try_into().expect("The slice has 4 bytes"));
+
+                if iterations == 0 || iterations > MAX_PASSPHRASE_ITERATIONS {
+                    return Err(format!(
+                        "The encryption header asks for {} key derivation iterations, pngme accepts 1 to {}",
+                        iterations, MAX_PASSPHRASE_ITERATIONS
+                    ).into())
+                }
+
+                KeySource::Passphrase { salt, iterations }
+            },
+            Some(2) => KeySource::Keyfile,
+            Some(other) => return Err(format!("The encryption header names an unknown key source {}", other).into()),
+            None => return Err("The encryption header is truncated".into()),
+        };
+
+        let nonce = take(NONCE_LEN)?.try_into().expect("The slice has the length of a nonce");
+
+        Ok((Header { source, nonce }, position))
+    }
+}
+
+/// Whether the message starts like one written by `seal`
+pub fn is_encrypted(data: &[u8]) -> bool {
+
+    data.starts_with(MAGIC)
+}
+
+/// Encrypts the message: the header, then the ciphertext and its tag
+pub fn seal(key: &[u8; KEY_LEN], header: &Header, message: &[u8]) -> Vec<u8> {
+
+    let mut sealed = header.to_bytes();
+    let header_len = sealed.len();
+
+    sealed.extend_from_slice(message);
+
+    let tag = aead_encrypt(key, &header.nonce, &mut sealed, header_len);
+    sealed.extend_from_slice(&tag);
+    sealed
+}
+
+/// Decrypts a message written by `seal`, with the key `key_for` finds for the source its
+/// header names. Fails when the key is wrong or the message changed.
+pub fn open(data: &[u8], key_for: impl FnOnce(&KeySource) -> Result<[u8; KEY_LEN]>) -> Result<Vec<u8>> {
+
+    let (header, header_len) = Header::parse(data)?;
+
+    if data.len() < header_len + TAG_LEN {
+        return Err("The encrypted message is truncated, it is too short to hold its tag".into())
+    }
+
+    let key = key_for(&header.source)?;
+    let (sealed, tag) = data.split_at(data.len() - TAG_LEN);
+    let mut opened = sealed.to_vec();
+
+    if !aead_decrypt(&key, &header.nonce, &mut opened, header_len, tag) {
+        let wrong = match header.source {
+            KeySource::Passphrase { .. } => "passphrase",
+            KeySource::Keyfile => "keyfile",
+        };
+
+        return Err(format!("Cannot decrypt the message: the {} is wrong, or the message was changed", wrong).into())
+    }
+
+    Ok(opened.split_off(header_len))
+}
+
+/// The key for a passphrase, with PBKDF2-HMAC-SHA256 (RFC 8018)
+pub fn derive_key(passphrase: &str, salt: &[u8], iterations: u32) -> [u8; KEY_LEN] {
+
+    metrics::time(Stage::Transform, || pbkdf2(passphrase, salt, iterations))
+}
+
+fn pbkdf2(passphrase: &str, salt: &[u8], iterations: u32) -> [u8; KEY_LEN] {
+
+    // One block of output is exactly one key
+    let mut block = hmac_sha256(passphrase.as_bytes(), &[salt, &1u32.to_be_bytes()].concat());
+    let mut key = block;
+
+    for _ in 1..iterations {
+        block = hmac_sha256(passphrase.as_bytes(), &block);
+
+        for (byte, next) in key.iter_mut().zip(block) {
+            *byte ^= next;
+        }
+    }
+
+    key
+}
+
+/// HMAC-SHA-256 of the message under the key
+pub fn hmac_sha256(key: &[u8], message: &[u8]) -> [u8; 32] {
+
+    let mut padded = [0; 64];
+
+    if key.len() > padded.len() {
+        padded[..32].copy_from_slice(&sha256::digest(key));
+    } else {
+        padded[..key.len()].copy_from_slice(key);
+    }
+
+    let inner_key: Vec<u8> = padded.iter().map(|byte| byte ^ 0x36).collect();
+    let outer_key: Vec<u8> = padded.iter().map(|byte| byte ^ 0x5c).collect();
+
+    let inner = sha256::digest(&[&inner_key, message].concat());
+
+    sha256::digest(&[&outer_key[..], &inner].concat())
+}
+
+/// Fresh random bytes, for keys, salts and nonces. A nonce must never be used twice with the same key.
+pub fn random<const N: usize>() -> Result<[u8; N]> {
+
+    let mut bytes = [0; N];
+    random_bytes(&mut bytes)?;
+
+    Ok(bytes)
+}
+
+#[cfg(unix)]
+fn random_bytes(bytes: &mut [u8]) -> Result<()> {
+
+    File::open("/dev/urandom")
+        .and_then(|mut random| random.read_exact(bytes))
+        .map_err(|error| format!("Cannot read random bytes from /dev/urandom: {}", error).into())
+}
+
+#[cfg(not(unix))]
+fn random_bytes(_bytes: &mut [u8]) -> Result<()> {
+
+    Err("Keys and nonces need /dev/urandom, which this platform does not have".into())
+}
+
+/// Writes a new keyfile, readable by its owner only. An existing file is never overwritten.
+pub fn write_keyfile(file_path: &Path, key: &[u8; KEY_LEN]) -> Result<()> {
+
+    let mut file = editor::private_options().open(file_path).map_err(PngMeError::from)?;
+
+    file.write_all(key).map_err(PngMeError::from)?;
+    file.sync_all().map_err(PngMeError::from)?;
+
+    Ok(())
+}
+
+/// Reads the key of a keyfile. On Unix a keyfile that every user can read is refused
+/// unless `allow_insecure` is set.
+pub fn read_keyfile(file_path: &Path, allow_insecure: bool) -> Result<[u8; KEY_LEN]> {
+
+    let metadata = fs::metadata(file_path).map_err(PngMeError::from)?;
+
+    if !allow_insecure && world_readable(&metadata) {
+        return Err(format!(
+            "The keyfile {} is readable by every user, restrict it with `chmod 600` or pass --allow-insecure-keyfile",
+            file_path.display()
+        ).into())
+    }
+
+    let key = fs::read(file_path).map_err(PngMeError::from)?;
+
+    key.as_slice().try_into().map_err(|_| format!(
+        "The keyfile {} holds {} bytes, a key is exactly {} bytes (create one with `keygen --symmetric`)",
+        file_path.display(), key.len(), KEY_LEN
+    ).into())
+}
+
+#[cfg(unix)]
+fn world_readable(metadata: &fs::Metadata) -> bool {
+
+    use std::os::unix::fs::PermissionsExt;
+
+    metadata.permissions().mode() & 0o004 != 0
+}
+
+#[cfg(not(unix))]
+fn world_readable(_metadata: &fs::Metadata) -> bool {
+
+    false
+}
+
+/// Encrypts `buffer[aad_len..]` in place and returns the tag over both parts of the buffer
+fn aead_encrypt(key: &[u8; KEY_LEN], nonce: &[u8; NONCE_LEN], buffer: &mut [u8], aad_len: usize) -> [u8; TAG_LEN] {
+
+    metrics::time(Stage::Transform, || {
+        chacha20_xor(key, nonce, 1, &mut buffer[aad_len..]);
+
+        aead_tag(key, nonce, &buffer[..aad_len], &buffer[aad_len..])
+    })
+}
+
+/// Checks the tag and only then decrypts `buffer[aad_len..]` in place
+fn aead_decrypt(key: &[u8; KEY_LEN], nonce: &[u8; NONCE_LEN], buffer: &mut [u8], aad_len: usize, tag: &[u8]) -> bool {
+
+    metrics::time(Stage::Transform, || check_and_decrypt(key, nonce, buffer, aad_len, tag))
+}
+
+fn check_and_decrypt(key: &[u8; KEY_LEN], nonce: &[u8; NONCE_LEN], buffer: &mut [u8], aad_len: usize, tag: &[u8]) -> bool {
+
+    let expected = aead_tag(key, nonce, &buffer[..aad_len], &buffer[aad_len..]);
+
+    // Compared without an early exit, so that the time taken tells nothing about the tag
+    let difference = expected.iter().zip(tag).fold(0, |difference, (a, b)| difference | (a ^ b));
+
+    if tag.len() != TAG_LEN || difference != 0 {
+        return false
+    }
+
+    chacha20_xor(key, nonce, 1, &mut buffer[aad_len..]);
+    true
+}
+
+fn aead_tag(key: &[u8; KEY_LEN], nonce: &[u8; NONCE_LEN], aad: &[u8], ciphertext: &[u8]) -> [u8; TAG_LEN] {
+
+    let mut one_time_key = [0; 32];
+    chacha20_xor(key, nonce, 0, &mut one_time_key);
+
+    let padding = |length: usize| vec![0; (16 - length % 16) % 16];
+
+    let mut authenticated = aad.to_vec();
+    authenticated.extend(padding(aad.len()));
+    authenticated.extend_from_slice(ciphertext);
+    authenticated.extend(padding(ciphertext.len()));
+    authenticated.extend((aad.len() as u64).to_le_bytes());
+    authenticated.extend((ciphertext.len() as u64).to_le_bytes());
+
+    poly1305(&one_time_key, &authenticated)
+}
+
+fn quarter_round(state: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+
+    state[a] = state[a].wrapping_add(state[b]); state[d] = (state[d] ^ state[a]).rotate_left(16);
+    state[c] = state[c].wrapping_add(state[d]); state[b] = (state[b] ^ state[c]).rotate_left(12);
+    state[a] = state[a].wrapping_add(state[b]); state[d] = (state[d] ^ state[a]).rotate_left(8);
+    state[c] = state[c].wrapping_add(state[d]); state[b] = (state[b] ^ state[c]).rotate_left(7);
+}
+
+fn chacha20_block(key: &[u8; KEY_LEN], nonce: &[u8; NONCE_LEN], counter: u32) -> [u8; 64] {
+
+    let word = |bytes: &[u8]| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+
+    // "expand 32-byte k", the key, the block counter and the nonce
+    let mut initial = [0x6170_7865, 0x3320_646e, 0x7962_2d32, 0x6b20_6574, 0, 0, 0, 0, 0, 0, 0, 0, counter, 0, 0, 0];
+
+    for index in 0..8 {
+        initial[4 + index] = word(&key[index * 4..]);
+    }
+
+    for index in 0..3 {
+        initial[13 + index] = word(&nonce[index * 4..]);
+    }
+
+    let mut state = initial;
+
+    for _ in 0..10 {
+        quarter_round(&mut state, 0, 4, 8, 12);
+        quarter_round(&mut state, 1, 5, 9, 13);
+        quarter_round(&mut state, 2, 6, 10, 14);
+        quarter_round(&mut state, 3, 7, 11, 15);
+        quarter_round(&mut state, 0, 5, 10, 15);
+        quarter_round(&mut state, 1, 6, 11, 12);
+        quarter_round(&mut state, 2, 7, 8, 13);
+        quarter_round(&mut state, 3, 4, 9, 14);
+    }
+
+    let mut block = [0; 64];
+
+    for (index, (word, start)) in state.iter().zip(initial).enumerate() {
+        block[index * 4..index * 4 + 4].copy_from_slice(&word.wrapping_add(start).to_le_bytes());
+    }
+
+    block
+}
+
+/// XORs the ChaCha20 key stream, starting at block `counter`, into `data`
+fn chacha20_xor(key: &[u8; KEY_LEN], nonce: &[u8; NONCE_LEN], counter: u32, data: &mut [u8]) {
+
+    for (index, piece) in data.chunks_mut(64).enumerate() {
+        let block = chacha20_block(key, nonce, counter.wrapping_add(index as u32));
+
+        for (byte, stream) in piece.iter_mut().zip(block) {
+            *byte ^= stream;
+        }
+    }
+}
+
+/// The Poly1305 tag of `message`, computed with five 26-bit limbs so that every
+/// product fits in a u64
+fn poly1305(key: &[u8; 32], message: &[u8]) -> [u8; TAG_LEN] {
+
+    const MASK: u32 = 0x3ff_ffff;
+
+    let word = |bytes: &[u8]| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+
+    // r is clamped as the specification requires
+    let r = [
+        word(&key[0..]) & 0x3ff_ffff,
+        (word(&key[3..]) >> 2) & 0x3ff_ff03,
+        (word(&key[6..]) >> 4) & 0x3ff_c0ff,
+        (word(&key[9..]) >> 6) & 0x3f0_3fff,
+        (word(&key[12..]) >> 8) & 0x00f_ffff,
+    ].map(u64::from);
+    let s = [r[1] * 5, r[2] * 5, r[3] * 5, r[4] * 5];
+
+    let mut h = [0u32; 5];
+
+    for piece in message.chunks(16) {
+        let mut block = [0; 17];
+        block[..piece.len()].copy_from_slice(piece);
+        block[piece.len()] = 1;
+
+        let high_bit = u32::from(block[16]) << 24;
+
+        h[0] += word(&block[0..]) & MASK;
+        h[1] += (word(&block[3..]) >> 2) & MASK;
+        h[2] += (word(&block[6..]) >> 4) & MASK;
+        h[3] += (word(&block[9..]) >> 6) & MASK;
+        h[4] += (word(&block[12..]) >> 8) | high_bit;
+
+        let h64 = h.map(u64::from);
+
+        let mut d = [
+            h64[0] * r[0] + h64[1] * s[3] + h64[2] * s[2] + h64[3] * s[1] + h64[4] * s[0],
+            h64[0] * r[1] + h64[1] * r[0] + h64[2] * s[3] + h64[3] * s[2] + h64[4] * s[1],
+            h64[0] * r[2] + h64[1] * r[1] + h64[2] * r[0] + h64[3] * s[3] + h64[4] * s[2],
+            h64[0] * r[3] + h64[1] * r[2] + h64[2] * r[1] + h64[3] * r[0] + h64[4] * s[3],
+            h64[0] * r[4] + h64[1] * r[3] + h64[2] * r[2] + h64[3] * r[1] + h64[4] * r[0],
+        ];
+
+        for index in 0..4 {
+            d[index + 1] += d[index] >> 26;
+            h[index] = d[index] as u32 & MASK;
+        }
+
+        h[4] = d[4] as u32 & MASK;
+        h[0] += (d[4] >> 26) as u32 * 5;
+        h[1] += h[0] >> 26;
+        h[0] &= MASK;
+    }
+
+    // Fully carry h, then compute h - p and keep it when it does not go negative
+    for index in 1..5 {
+        h[index] += h[index - 1] >> 26;
+        h[index - 1] &= MASK;
+    }
+
+    h[0] += (h[4] >> 26) * 5;
+    h[4] &= MASK;
+    h[1] += h[0] >> 26;
+    h[0] &= MASK;
+
+    let mut g = [0u32; 5];
+    let mut carry = 5;
+
+    for index in 0..5 {
+        g[index] = h[index].wrapping_add(carry);
+        carry = g[index] >> 26;
+        g[index] &= MASK;
+    }
+
+    g[4] = g[4].wrapping_add(carry << 26).wrapping_sub(1 << 26);
+
+    let keep_g = (g[4] >> 31).wrapping_sub(1);
+
+    for index in 0..5 {
+        h[index] = (h[index] & !keep_g) | (g[index] & keep_g);
+    }
+
+    let packed = [
+        h[0] | (h[1] << 26),
+        (h[1] >> 6) | (h[2] << 20),
+        (h[2] >> 12) | (h[3] << 14),
+        (h[3] >> 18) | (h[4] << 8),
+    ];
+
+    // Then add s, the second half of the key, modulo 2^128
+    let mut tag = [0; TAG_LEN];
+    let mut sum = 0u64;
+
+    for (index, limb) in packed.iter().enumerate() {
+        sum = (sum >> 32) + u64::from(*limb) + u64::from(word(&key[16 + index * 4..]));
+        tag[index * 4..index * 4 + 4].copy_from_slice(&(sum as u32).to_le_bytes());
+    }
+
+    tag
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hex;
+
+    const KEY: [u8; KEY_LEN] = [7; KEY_LEN];
+
+    #[test]
+    fn test_poly1305() {
+        // RFC 8439, section 2.5.2
+        let key = hex::decode("85d6be7857556d337f4452fe42d506a80103808afb0db2fd4abff6af4149f51b").unwrap();
+
+        assert_eq!(
+            hex::encode(&poly1305(&key.try_into().unwrap(), b"Cryptographic Forum Research Group")),
+            "a8061dc1305136c6c22b8baf0c0127a9"
+        );
+    }
+
+    #[test]
+    fn test_aead() {
+        // RFC 8439, section 2.8.2
+        let key: [u8; KEY_LEN] = std::array::from_fn(|index| 0x80 + index as u8);
+        let nonce = [7, 0, 0, 0, 0x40, 0x41, 0x42, 0x43, 0x44, 0x45, 0x46, 0x47];
+        let message = b"Ladies and Gentlemen of the class of '99: If I could offer you only one tip for the future, sunscreen would be it.";
+
+        let mut buffer = hex::decode("50515253c0c1c2c3c4c5c6c7").unwrap();
+        buffer.extend_from_slice(message);
+
+        let tag = aead_encrypt(&key, &nonce, &mut buffer, 12);
+
+        assert_eq!(hex::encode(&buffer[12..28]), "d31a8d34648e60db7b86afbc53ef7ec2");
+        assert_eq!(hex::encode(&tag), "1ae10b594f09e26a7e902ecbd0600691");
+
+        assert!(aead_decrypt(&key, &nonce, &mut buffer, 12, &tag));
+        assert_eq!(&buffer[12..], message);
+    }
+
+    fn with_key(key: [u8; KEY_LEN]) -> impl FnOnce(&KeySource) -> Result<[u8; KEY_LEN]> {
+        move |_| Ok(key)
+    }
+
+    #[test]
+    fn test_derive_key() {
+        // Checked against Python's hashlib.pbkdf2_hmac
+        assert_eq!(hex::encode(&derive_key("password", b"salt", 1)), "120fb6cffcf8b32c43e7225256c4f837a86548c92ccc35480805987cb70be17b");
+        assert_eq!(hex::encode(&derive_key("password", b"salt", 4096)), "c5e478d59288c841aa530db6845c4c8d962893a001ce4e11a4963873aa98134a");
+    }
+
+    #[test]
+    fn test_seal_and_open() {
+        let sealed = seal(&KEY, &Header { source: KeySource::Keyfile, nonce: [1; NONCE_LEN] }, b"a secret");
+
+        assert!(is_encrypted(&sealed));
+        assert_eq!(sealed.len(), MAGIC.len() + 2 + NONCE_LEN + 8 + TAG_LEN);
+        assert_eq!(open(&sealed, with_key(KEY)).unwrap(), b"a secret");
+
+        let wrong_key = open(&sealed, with_key([8; KEY_LEN])).unwrap_err();
+        assert!(wrong_key.to_string().contains("the keyfile is wrong"));
+
+        let mut tampered = sealed.clone();
+        tampered[MAGIC.len() + 2] ^= 1;
+        assert!(open(&tampered, with_key(KEY)).is_err());
+
+        assert!(open(&sealed[..sealed.len() - TAG_LEN], with_key(KEY)).unwrap_err().to_string().contains("truncated"));
+        assert!(open(b"plain text", with_key(KEY)).unwrap_err().to_string().contains("not encrypted"));
+    }
+
+    #[test]
+    fn test_header_names_key_source() {
+        let keyfile = Header { source: KeySource::Keyfile, nonce: [2; NONCE_LEN] };
+        let passphrase = Header { source: KeySource::Passphrase { salt: [3; SALT_LEN], iterations: 600_000 }, nonce: [4; NONCE_LEN] };
+
+        for header in [&keyfile, &passphrase] {
+            let bytes = header.to_bytes();
+
+            assert_eq!(Header::parse(&bytes).unwrap(), (header.clone(), bytes.len()));
+            assert!(Header::parse(&bytes[..bytes.len() - 1]).unwrap_err().to_string().contains("truncated"));
+        }
+
+        // The same reader tells the two apart, and hands the source to the key lookup
+        let key = derive_key("correct horse", &[3; SALT_LEN], 10);
+        let sealed = seal(&key, &passphrase, b"a secret");
+        let mut seen = None;
+
+        let opened = open(&sealed, |source| {
+            seen = Some(source.clone());
+            Ok(key)
+        });
+
+        assert_eq!(opened.unwrap(), b"a secret");
+        assert_eq!(seen, Some(passphrase.source.clone()));
+        assert!(open(&sealed, with_key(KEY)).unwrap_err().to_string().contains("the passphrase is wrong"));
+    }
+
+    #[test]
+    fn test_iterations_are_capped() {
+        let header = |iterations| Header { source: KeySource::Passphrase { salt: [3; SALT_LEN], iterations }, nonce: [4; NONCE_LEN] }.to_bytes();
+
+        assert!(Header::parse(&header(MAX_PASSPHRASE_ITERATIONS)).is_ok());
+        assert!(Header::parse(&header(MAX_PASSPHRASE_ITERATIONS + 1)).unwrap_err().to_string().contains("iterations"));
+        assert!(Header::parse(&header(u32::MAX)).is_err());
+        assert!(Header::parse(&header(0)).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_keyfile() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = std::env::temp_dir().join(format!("pngme-test-{}-keyfile", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        write_keyfile(&path, &KEY).unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        let read = read_keyfile(&path, false);
+        let again = write_keyfile(&path, &KEY);
+
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+        let insecure = read_keyfile(&path, false);
+        let allowed = read_keyfile(&path, true);
+
+        fs::write(&path, &KEY[..16]).unwrap();
+        let truncated = read_keyfile(&path, true);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(mode & 0o777, 0o600);
+        assert_eq!(read.unwrap(), KEY);
+        assert!(again.is_err());
+        assert!(insecure.unwrap_err().to_string().contains("--allow-insecure-keyfile"));
+        assert_eq!(allowed.unwrap(), KEY);
+        assert!(truncated.unwrap_err().to_string().contains("holds 16 bytes"));
+    }
+}
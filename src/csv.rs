@@ -0,0 +1,81 @@
+use crate::Result;
+
+/// Quotes a field when it contains a separator, a quote or a line break, as RFC 4180 asks
+pub fn field(value: &str) -> String {
+
+    if value.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Joins the fields into one record, terminated by CRLF
+pub fn row<T: AsRef<str>>(fields: &[T]) -> String {
+
+    let fields: Vec<String> = fields.iter().map(|value| field(value.as_ref())).collect();
+
+    format!("{}\r\n", fields.join(","))
+}
+
+/// Splits RFC 4180 text back into records of unquoted fields
+pub fn parse(text: &str) -> Result<Vec<Vec<String>>> {
+
+    let mut records = vec![];
+    let mut record = vec![];
+    let mut value = String::new();
+    let mut quoted = false;
+    let mut characters = text.chars().peekable();
+
+    while let Some(character) = characters.next() {
+        match (quoted, character) {
+            (true, '"') if characters.peek() == Some(&'"') => {
+                characters.next();
+                value.push('"');
+            },
+            (true, '"') => quoted = false,
+            (true, character) => value.push(character),
+            (false, '"') if value.is_empty() => quoted = true,
+            (false, '"') => return Err("Unexpected quote inside an unquoted field".into()),
+            (false, ',') => record.push(std::mem::take(&mut value)),
+            (false, '\r') if characters.peek() == Some(&'\n') => {},
+            (false, '\n') => {
+                record.push(std::mem::take(&mut value));
+                records.push(std::mem::take(&mut record));
+            },
+            (false, character) => value.push(character),
+        }
+    }
+
+    if quoted {
+        return Err("Unterminated quoted field".into())
+    }
+
+    if !value.is_empty() || !record.is_empty() {
+        record.push(value);
+        records.push(record);
+    }
+
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_field_quoting() {
+        assert_eq!(field("ruSt"), "ruSt");
+        assert_eq!(field("a,b"), "\"a,b\"");
+        assert_eq!(field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(field("two\nlines"), "\"two\nlines\"");
+    }
+
+    #[test]
+    fn test_row_round_trip() {
+        let fields = ["plain", "with,comma", "with \"quotes\"", "multi\r\nline", ""];
+        let text = [row(&fields), row(&["second"])].concat();
+
+        assert_eq!(parse(&text).unwrap(), vec![fields.to_vec(), vec!["second"]]);
+    }
+}
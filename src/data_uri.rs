@@ -0,0 +1,46 @@
+use std::path::Path;
+
+use crate::{base64, Result};
+
+/// The only kind of data URI read in place of a file
+const PREFIX: &str = "data:image/png;base64,";
+
+/// Whether the path given on the command line is a data URI rather than a file
+pub fn is_data_uri(path: &Path) -> bool {
+
+    path.to_str().is_some_and(|path| path.starts_with("data:"))
+}
+
+/// The bytes of a `data:image/png;base64,...` URI, as a web page embeds a PNG
+pub fn decode(uri: &str) -> Result<Vec<u8>> {
+
+    let (header, payload) = uri.split_once(',').ok_or("The data URI has no ',' before its data")?;
+
+    // The media type and its parameters are case-insensitive
+    if !PREFIX.eq_ignore_ascii_case(&format!("{},", header)) {
+        return Err(format!("Only {}... data URIs hold a PNG, not {}", PREFIX, header).into())
+    }
+
+    base64::decode(payload).map_err(|error| format!("The data URI is not valid base64: {}", error).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(decode("data:image/png;base64,iVBORw==").unwrap(), b"\x89PNG");
+        assert_eq!(decode("DATA:Image/PNG;Base64,iVBORw==").unwrap(), b"\x89PNG");
+        assert!(is_data_uri(Path::new("data:image/png;base64,iVBORw==")));
+        assert!(!is_data_uri(Path::new("image.png")));
+    }
+
+    #[test]
+    fn test_decode_malformed() {
+        assert!(decode("data:image/png;base64").unwrap_err().to_string().contains("no ','"));
+        assert!(decode("data:image/jpeg;base64,/9j/").unwrap_err().to_string().contains("not data:image/jpeg;base64"));
+        assert!(decode("data:image/png,%89PNG").is_err());
+        assert!(decode("data:image/png;base64,iVBOR").unwrap_err().to_string().starts_with("The data URI is not valid base64"));
+    }
+}
@@ -0,0 +1,257 @@
+use std::fmt;
+
+use clap::ValueEnum;
+
+use crate::{base64, crypt, embed::EmbeddedFile, hex, zlib, Result};
+
+/// At most this many transforms are peeled off a payload
+pub const MAX_LAYERS: usize = 3;
+
+/// Inflating a layer stops at this many bytes, so a small payload cannot fill the memory
+pub const LAYER_LIMIT: usize = 16 * 1024 * 1024;
+
+/// A transform a payload can be wrapped in
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum Layer {
+    Base64,
+    Hex,
+    Zlib,
+}
+
+impl Layer {
+    /// Reverses the transform
+    pub fn unwrap(&self, data: &[u8]) -> Result<Vec<u8>> {
+
+        match self {
+            Layer::Base64 => base64::decode(std::str::from_utf8(data).map_err(|_| "Base64 text has to be ASCII")?),
+            Layer::Hex => hex::decode(std::str::from_utf8(data).map_err(|_| "Hex text has to be ASCII")?),
+            Layer::Zlib => zlib::decompress(data, LAYER_LIMIT),
+        }
+    }
+}
+
+impl fmt::Display for Layer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+
+        match self {
+            Layer::Base64 => write!(f, "base64"),
+            Layer::Hex => write!(f, "hex"),
+            Layer::Zlib => write!(f, "zlib"),
+        }
+    }
+}
+
+/// A format of pngme itself, told by its magic, which detection stops at: an
+/// encrypted message needs its key, and `--extract-file` reads the embedded file
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Frame {
+    /// Starts with `crypt::MAGIC`
+    Encrypted,
+    /// Starts with `embed::EMBED_MAGIC` and a sound header
+    EmbeddedFile,
+}
+
+impl Frame {
+    fn of(data: &[u8]) -> Option<Frame> {
+
+        if data.starts_with(crypt::MAGIC) {
+            Some(Frame::Encrypted)
+        } else if EmbeddedFile::from_bytes(data).is_some() {
+            Some(Frame::EmbeddedFile)
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+
+        match self {
+            Frame::Encrypted => write!(f, "an encrypted message, decode it with --keyfile or --passphrase"),
+            Frame::EmbeddedFile => write!(f, "an embedded file, decode it with --extract-file"),
+        }
+    }
+}
+
+/// The best interpretation of a payload
+#[derive(Debug, PartialEq)]
+pub struct Detection {
+    /// The transforms that were reversed, outermost first
+    pub layers: Vec<Layer>,
+    pub data: Vec<u8>,
+    /// The pngme format the data is in once the layers are reversed, if any
+    pub frame: Option<Frame>,
+}
+
+/// The data as text, when it is UTF-8 without control characters other than line breaks and tabs
+pub fn printable_text(data: &[u8]) -> Option<&str> {
+
+    std::str::from_utf8(data)
+        .ok()
+        .filter(|text| !text.chars().any(|character| character.is_control() && !matches!(character, '\n' | '\r' | '\t')))
+}
+
+/// The Shannon entropy of the data in bits per byte, from 0 for a single repeated
+/// byte to 8 for uniformly random bytes. Encrypted and compressed data sits close to 8,
+/// text well below.
+pub fn entropy(data: &[u8]) -> f64 {
+
+    if data.is_empty() {
+        return 0.0
+    }
+
+    let mut counts = [0usize; 256];
+
+    for byte in data {
+        counts[*byte as usize] += 1;
+    }
+
+    counts.iter()
+        .filter(|count| **count > 0)
+        .map(|count| {
+            let probability = *count as f64 / data.len() as f64;
+
+            -probability * probability.log2()
+        })
+        .sum()
+}
+
+/// Peels off the transforms a payload looks wrapped in, up to `MAX_LAYERS` of them
+pub fn detect(data: &[u8]) -> Detection {
+
+    let mut detection = Detection { layers: vec![], data: data.to_vec(), frame: Frame::of(data) };
+
+    while detection.layers.len() < MAX_LAYERS && detection.frame.is_none() {
+        let Some((layer, data)) = next_layer(&detection.data) else {
+            break
+        };
+
+        detection.layers.push(layer);
+        detection.frame = Frame::of(&data);
+        detection.data = data;
+    }
+
+    detection
+}
+
+/// A zlib stream is taken when it inflates without errors. Plain words are often
+/// valid hex or base64 as well, so those are only taken when they hide text, a zlib
+/// stream or one of pngme's own frames.
+fn next_layer(data: &[u8]) -> Option<(Layer, Vec<u8>)> {
+
+    if looks_like_zlib(data) {
+        if let Ok(inflated) = Layer::Zlib.unwrap(data) {
+            return Some((Layer::Zlib, inflated))
+        }
+    }
+
+    if data.len() < 4 {
+        return None
+    }
+
+    [Layer::Hex, Layer::Base64].into_iter().find_map(|layer| {
+        layer.unwrap(data).ok()
+            .filter(|decoded| {
+                !decoded.is_empty() && (printable_text(decoded).is_some() || looks_like_zlib(decoded) || Frame::of(decoded).is_some())
+            })
+            .map(|decoded| (layer, decoded))
+    })
+}
+
+/// Checks the compression method and the check bits of a zlib header
+fn looks_like_zlib(data: &[u8]) -> bool {
+
+    match data {
+        [method, flags, ..] => method & 0x0f == 8 && (u16::from(*method) << 8 | u16::from(*flags)).is_multiple_of(31),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_layers() {
+        let compressed = zlib::compress(b"hidden message");
+
+        let detection = detect(base64::encode(&compressed).as_bytes());
+        assert_eq!(detection, Detection { layers: vec![Layer::Base64, Layer::Zlib], data: b"hidden message".to_vec(), frame: None });
+
+        let detection = detect(b"aGlkZGVuIG1lc3NhZ2U=");
+        assert_eq!(detection.layers, vec![Layer::Base64]);
+        assert_eq!(detection.data, b"hidden message");
+
+        let detection = detect(hex::encode(b"hidden message").as_bytes());
+        assert_eq!(detection.layers, vec![Layer::Hex]);
+    }
+
+    #[test]
+    fn test_detect_encrypted_frame() {
+        let encrypted = [&crypt::MAGIC[..], &[1, 2], &[0xa5; 40]].concat();
+
+        let detection = detect(base64::encode(&encrypted).as_bytes());
+        assert_eq!(detection.layers, vec![Layer::Base64]);
+        assert_eq!(detection.frame, Some(Frame::Encrypted));
+        assert_eq!(detection.data, encrypted);
+
+        assert_eq!(detect(&encrypted).frame, Some(Frame::Encrypted));
+    }
+
+    #[test]
+    fn test_detect_embedded_file_frame() {
+        let embedded = EmbeddedFile { name: "notes.txt".to_string(), mime_type: "text/plain".to_string(), data: b"hidden".to_vec() };
+        let bytes = embedded.to_bytes().unwrap();
+
+        let detection = detect(hex::encode(&zlib::compress(&bytes)).as_bytes());
+        assert_eq!(detection.layers, vec![Layer::Hex, Layer::Zlib]);
+        assert_eq!(detection.frame, Some(Frame::EmbeddedFile));
+        assert_eq!(detection.data, bytes);
+
+        // Nothing under the frame is peeled off, --extract-file still finds the header
+        assert_eq!(detect(&bytes).layers, vec![]);
+        assert_eq!(detect(b"pmEF junk").frame, None);
+    }
+
+    #[test]
+    fn test_plain_text_is_left_alone() {
+        // Valid base64, but it decodes to binary junk
+        assert_eq!(detect(b"RuStRuSt").layers, vec![]);
+        assert_eq!(detect(b"plain message").data, b"plain message");
+    }
+
+    #[test]
+    fn test_layers_are_bounded() {
+        let mut data = b"deep".to_vec();
+
+        for _ in 0..5 {
+            data = base64::encode(&data).into_bytes();
+        }
+
+        let detection = detect(&data);
+        assert_eq!(detection.layers.len(), MAX_LAYERS);
+        assert!(detection.data.len() > 4);
+    }
+
+    #[test]
+    fn test_entropy() {
+        assert_eq!(entropy(b""), 0.0);
+        assert!(entropy(&[0x41; 1000]).abs() < 1e-9);
+        assert!((entropy(b"abab") - 1.0).abs() < 1e-9);
+
+        let every_byte: Vec<u8> = (0..=255).cycle().take(4096).collect();
+        assert!((entropy(&every_byte) - 8.0).abs() < 1e-9);
+
+        let random_ish: Vec<u8> = (0..4096u32).map(|value| (value.wrapping_mul(2654435761) >> 13) as u8).collect();
+        assert!(entropy(&random_ish) > 7.5);
+        assert!(entropy(b"plain English text is far from random") < 4.5);
+    }
+
+    #[test]
+    fn test_printable_text() {
+        assert_eq!(printable_text(b"two\nlines\t"), Some("two\nlines\t"));
+        assert_eq!(printable_text(b"bell\x07"), None);
+        assert_eq!(printable_text(&[0xff]), None);
+    }
+}
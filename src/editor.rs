@@ -0,0 +1,122 @@
+use std::{fs::{self, File, OpenOptions}, io::Write, path::{Path, PathBuf}, process::Command, time::{SystemTime, UNIX_EPOCH}};
+
+use crate::{error::PngMeError, Result};
+
+/// The editor the user picked, `$VISUAL` first and `$EDITOR` otherwise
+pub fn from_env() -> Result<String> {
+
+    ["VISUAL", "EDITOR"].iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .find(|editor| !editor.trim().is_empty())
+        .ok_or_else(|| "Neither $VISUAL nor $EDITOR is set, there is no editor to open".into())
+}
+
+/// Opens `text` in `editor` and returns what the user saved, or `None` when
+/// they saved it unchanged. The editor may carry arguments, such as `code --wait`.
+pub fn edit(editor: &str, text: &str) -> Result<Option<String>> {
+
+    let file = TempFile::create(text.as_bytes())?;
+
+    let mut words = editor.split_whitespace();
+    let program = words.next().ok_or("The editor command is empty")?;
+
+    let status = Command::new(program)
+        .args(words)
+        .arg(file.path())
+        .status()
+        .map_err(|error| format!("Cannot run the editor '{}': {}", program, PngMeError::from(error)))?;
+
+    if !status.success() {
+        return Err(format!("The editor exited with {}, leaving the file unchanged", status).into())
+    }
+
+    let edited = fs::read(file.path()).map_err(PngMeError::from)?;
+    let edited = String::from_utf8(edited).map_err(|_| "The edited text is not valid UTF-8, leaving the file unchanged")?;
+
+    Ok(if edited == text { None } else { Some(edited) })
+}
+
+/// A file only the current user can read, removed when dropped so that the
+/// payload never outlives the edit, whatever path the command takes
+struct TempFile {
+    path: PathBuf,
+}
+
+impl TempFile {
+    fn create(contents: &[u8]) -> Result<TempFile> {
+
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.subsec_nanos()).unwrap_or_default();
+        let path = std::env::temp_dir().join(format!("pngme-edit-{}-{}.txt", std::process::id(), nanos));
+
+        // create_new never follows a link someone else planted at the path
+        let mut file = private_options().open(&path).map_err(PngMeError::from)?;
+        let temp_file = TempFile { path };
+
+        file.write_all(contents).map_err(PngMeError::from)?;
+
+        Ok(temp_file)
+    }
+
+    fn path(&self) -> &Path {
+
+        &self.path
+    }
+}
+
+impl Drop for TempFile {
+
+    fn drop(&mut self) {
+
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(unix)]
+pub(crate) fn private_options() -> OpenOptions {
+
+    use std::os::unix::fs::OpenOptionsExt;
+
+    let mut options = File::options();
+    options.write(true).create_new(true).mode(0o600);
+    options
+}
+
+#[cfg(not(unix))]
+pub(crate) fn private_options() -> OpenOptions {
+
+    let mut options = File::options();
+    options.write(true).create_new(true);
+    options
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn test_temp_file_is_private_and_removed() {
+        let file = TempFile::create(b"secret").unwrap();
+        let path = file.path().to_path_buf();
+
+        assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        assert_eq!(fs::read(&path).unwrap(), b"secret");
+
+        drop(file);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_edit() {
+        let script = std::env::temp_dir().join(format!("pngme-test-{}-editor.sh", std::process::id()));
+        fs::write(&script, "#!/bin/sh\nprintf 'edited' > \"$1\"\n").unwrap();
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o700)).unwrap();
+
+        let edited = edit(script.to_str().unwrap(), "original");
+        fs::remove_file(&script).unwrap();
+
+        assert_eq!(edited.unwrap(), Some("edited".to_string()));
+        assert_eq!(edit("true", "original").unwrap(), None);
+        assert!(edit("false", "original").unwrap_err().to_string().contains("leaving the file unchanged"));
+    }
+}
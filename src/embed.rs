@@ -0,0 +1,137 @@
+use std::path::Path;
+
+use crate::{error::PngMeError, Result};
+
+/// Starts the data of a chunk holding an embedded file
+pub const EMBED_MAGIC: &[u8; 4] = b"pmEF";
+
+/// MIME type of the extensions people are likely to hide
+const MIME_TYPES: [(&str, &str); 14] = [
+    ("txt", "text/plain"),
+    ("md", "text/markdown"),
+    ("csv", "text/csv"),
+    ("html", "text/html"),
+    ("json", "application/json"),
+    ("xml", "application/xml"),
+    ("pdf", "application/pdf"),
+    ("zip", "application/zip"),
+    ("gz", "application/gzip"),
+    ("png", "image/png"),
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("gif", "image/gif"),
+    ("mp3", "audio/mpeg"),
+];
+
+/// Used when the extension is unknown
+const DEFAULT_MIME_TYPE: &str = "application/octet-stream";
+
+/// A file along with its original name
+#[derive(Debug, PartialEq)]
+pub struct EmbeddedFile {
+    pub name: String,
+    pub mime_type: String,
+    pub data: Vec<u8>,
+}
+
+impl EmbeddedFile {
+    /// Reads the file, keeping only its name, not the directories leading to it
+    pub fn read(path: &Path) -> Result<EmbeddedFile> {
+
+        let name = path.file_name()
+            .and_then(|name| name.to_str())
+            .ok_or("The file to embed has no UTF-8 file name")?;
+
+        Ok(EmbeddedFile {
+            name: name.to_string(),
+            mime_type: mime_type(path).to_string(),
+            data: std::fs::read(path).map_err(PngMeError::from)?,
+        })
+    }
+
+    /// The magic, then the name and the MIME type each prefixed with their
+    /// length (2 bytes and 1 byte, big endian), then the data
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+
+        let name_length = u16::try_from(self.name.len()).map_err(|_| "The file name is longer than 65535 bytes")?;
+        let mime_length = u8::try_from(self.mime_type.len()).map_err(|_| "The MIME type is longer than 255 bytes")?;
+
+        let mut bytes = EMBED_MAGIC.to_vec();
+        bytes.extend(name_length.to_be_bytes());
+        bytes.extend(self.name.as_bytes());
+        bytes.push(mime_length);
+        bytes.extend(self.mime_type.as_bytes());
+        bytes.extend(&self.data);
+
+        Ok(bytes)
+    }
+
+    /// Reads an embedded file back. Data without the header (or with a
+    /// damaged one) is not an embedded file, and gives None.
+    pub fn from_bytes(bytes: &[u8]) -> Option<EmbeddedFile> {
+
+        let rest = bytes.strip_prefix(EMBED_MAGIC)?;
+
+        let name_length = u16::from_be_bytes(rest.get(..2)?.try_into().ok()?) as usize;
+        let name = std::str::from_utf8(rest.get(2..2 + name_length)?).ok()?;
+        let rest = &rest[2 + name_length..];
+
+        let mime_length = *rest.first()? as usize;
+        let mime_type = std::str::from_utf8(rest.get(1..1 + mime_length)?).ok()?;
+
+        // A name with directories in it could write anywhere, so it is not trusted
+        if name.is_empty() || Path::new(name).file_name()?.to_str()? != name {
+            return None
+        }
+
+        Some(EmbeddedFile { name: name.to_string(), mime_type: mime_type.to_string(), data: rest[1 + mime_length..].to_vec() })
+    }
+}
+
+/// Guesses the MIME type of a file from its extension
+pub fn mime_type(path: &Path) -> &'static str {
+
+    let extension = path.extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_ascii_lowercase());
+
+    MIME_TYPES.iter()
+        .find(|(known, _)| Some(*known) == extension.as_deref())
+        .map(|(_, mime_type)| *mime_type)
+        .unwrap_or(DEFAULT_MIME_TYPE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let path = std::env::temp_dir().join(format!("pngme-test-{}-secret.pdf", std::process::id()));
+        std::fs::write(&path, b"%PDF-1.7 \x00\xff").unwrap();
+
+        let embedded = EmbeddedFile::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(embedded.mime_type, "application/pdf");
+        assert_eq!(embedded.data, b"%PDF-1.7 \x00\xff");
+        assert_eq!(EmbeddedFile::from_bytes(&embedded.to_bytes().unwrap()).unwrap(), embedded);
+    }
+
+    #[test]
+    fn test_missing_or_damaged_header() {
+        assert_eq!(EmbeddedFile::from_bytes(b"plain message"), None);
+        assert_eq!(EmbeddedFile::from_bytes(b"pmEF\x00\x20short"), None);
+
+        let escaping = EmbeddedFile { name: "../evil".to_string(), mime_type: "text/plain".to_string(), data: vec![] };
+        assert_eq!(EmbeddedFile::from_bytes(&escaping.to_bytes().unwrap()), None);
+    }
+
+    #[test]
+    fn test_mime_type() {
+        assert_eq!(mime_type(Path::new("notes.TXT")), "text/plain");
+        assert_eq!(mime_type(Path::new("archive.tar.gz")), "application/gzip");
+        assert_eq!(mime_type(Path::new("unknown.xyz")), DEFAULT_MIME_TYPE);
+        assert_eq!(mime_type(Path::new("no_extension")), DEFAULT_MIME_TYPE);
+    }
+}
@@ -0,0 +1,178 @@
+use std::{array::TryFromSliceError, fmt, io, path::{Path, PathBuf}, str::Utf8Error};
+
+use crate::{Error, Result};
+
+/// Exit code for any failure that has no more specific code
+pub const EXIT_FAILURE: i32 = 1;
+
+/// Exit code used when the requested chunk does not exist in the file
+pub const EXIT_NOT_FOUND: i32 = 2;
+
+/// Exit code used when the file no longer matches its recorded baseline
+pub const EXIT_CHANGED: i32 = 3;
+
+/// Exit code used when Ctrl-C stopped the command, the shell's 128 + SIGINT
+pub const EXIT_INTERRUPTED: i32 = 130;
+
+#[derive(Debug)]
+pub enum PngMeError {
+    /// The requested chunk (or message) could not be found in the PNG file
+    NotFound(String),
+    /// The file differs from what was recorded about it
+    Changed(String),
+    /// The user pressed Ctrl-C, and the command stopped before its next stage
+    Interrupted(String),
+    /// Any error that happened while processing the file at `path`
+    WithPath { path: PathBuf, source: Error },
+    /// Reading or writing a file failed
+    Io(io::Error),
+    /// Data that had to be UTF-8 was not
+    Utf8(Utf8Error),
+    /// A slice did not have the length of the array it was converted to
+    TryFromSlice(TryFromSliceError),
+}
+
+impl PngMeError {
+    pub fn exit_code(&self) -> i32 {
+
+        match self {
+            PngMeError::NotFound(_) => EXIT_NOT_FOUND,
+            PngMeError::Changed(_) => EXIT_CHANGED,
+            PngMeError::Interrupted(_) => EXIT_INTERRUPTED,
+            PngMeError::WithPath { source, .. } => exit_code(source),
+            PngMeError::Io(_) | PngMeError::Utf8(_) | PngMeError::TryFromSlice(_) => EXIT_FAILURE,
+        }
+    }
+}
+
+impl fmt::Display for PngMeError {
+
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+
+        match self {
+            PngMeError::NotFound(message) => write!(f, "{}", message),
+            PngMeError::Changed(message) => write!(f, "{}", message),
+            PngMeError::Interrupted(message) => write!(f, "{}", message),
+            PngMeError::WithPath { path, source } => write!(f, "failed to process {}: {}", path.display(), source),
+            PngMeError::Io(error) => write!(f, "{}", error),
+            PngMeError::Utf8(error) => write!(f, "{}", error),
+            PngMeError::TryFromSlice(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for PngMeError {
+
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+
+        match self {
+            PngMeError::WithPath { source, .. } => Some(source.as_ref()),
+            PngMeError::Io(error) => Some(error),
+            PngMeError::Utf8(error) => Some(error),
+            PngMeError::TryFromSlice(error) => Some(error),
+            PngMeError::NotFound(_) | PngMeError::Changed(_) | PngMeError::Interrupted(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for PngMeError {
+
+    fn from(error: io::Error) -> PngMeError {
+
+        PngMeError::Io(error)
+    }
+}
+
+impl From<Utf8Error> for PngMeError {
+
+    fn from(error: Utf8Error) -> PngMeError {
+
+        PngMeError::Utf8(error)
+    }
+}
+
+impl From<TryFromSliceError> for PngMeError {
+
+    fn from(error: TryFromSliceError) -> PngMeError {
+
+        PngMeError::TryFromSlice(error)
+    }
+}
+
+/// Adds the path of the file being processed to an error
+pub trait PathContext<T> {
+    fn with_path(self, path: &Path) -> Result<T>;
+}
+
+impl<T> PathContext<T> for Result<T> {
+
+    fn with_path(self, path: &Path) -> Result<T> {
+
+        self.map_err(|source| PngMeError::WithPath { path: path.to_path_buf(), source }.into())
+    }
+}
+
+/// Maps an error returned by a command to the exit code of the process
+pub fn exit_code(error: &Error) -> i32 {
+
+    match error.downcast_ref::<PngMeError>() {
+        Some(error) => error.exit_code(),
+        None => EXIT_FAILURE,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_not_found_exit_code() {
+        let error: Error = PngMeError::NotFound("No chunk".to_string()).into();
+        assert_eq!(exit_code(&error), EXIT_NOT_FOUND);
+    }
+
+    #[test]
+    fn test_with_path_message() {
+        let result: Result<()> = Err("Invalid crc (Cyclic Redundancy Check)".into());
+        let error = result.with_path(Path::new("foo.png")).unwrap_err();
+        assert_eq!(error.to_string(), "failed to process foo.png: Invalid crc (Cyclic Redundancy Check)");
+    }
+
+    #[test]
+    fn test_with_path_keeps_exit_code() {
+        let result: Result<()> = Err(PngMeError::NotFound("No chunk".to_string()).into());
+        let error = result.with_path(Path::new("foo.png")).unwrap_err();
+        assert_eq!(exit_code(&error), EXIT_NOT_FOUND);
+    }
+
+    #[test]
+    fn test_conversions_keep_the_error_type() {
+        let error = PngMeError::from(std::fs::read("does/not/exist.png").unwrap_err());
+        assert!(matches!(&error, PngMeError::Io(io) if io.kind() == io::ErrorKind::NotFound));
+        assert!(std::error::Error::source(&error).is_some());
+
+        let bytes = vec![0xff];
+        let error = PngMeError::from(std::str::from_utf8(&bytes).unwrap_err());
+        assert!(matches!(error, PngMeError::Utf8(_)));
+
+        let error = PngMeError::from(<[u8; 4]>::try_from(&[0u8; 3][..]).unwrap_err());
+        assert!(matches!(error, PngMeError::TryFromSlice(_)));
+        assert_eq!(error.exit_code(), EXIT_FAILURE);
+    }
+
+    #[test]
+    fn test_errors_cross_threads() {
+        let result: Result<()> = std::thread::spawn(|| Err(PngMeError::NotFound("No chunk".to_string()).into()))
+            .join()
+            .unwrap();
+
+        let error = result.with_path(Path::new("foo.png")).unwrap_err();
+        assert_eq!(exit_code(&error), EXIT_NOT_FOUND);
+    }
+
+    #[test]
+    fn test_other_error_exit_code() {
+        let error: Error = "Invalid header".into();
+        assert_eq!(exit_code(&error), EXIT_FAILURE);
+    }
+}
@@ -0,0 +1,185 @@
+use std::fmt;
+
+use crate::Result;
+
+const TAG_MAKE: u16 = 0x010f;
+const TAG_MODEL: u16 = 0x0110;
+const TAG_ORIENTATION: u16 = 0x0112;
+const TAG_DATE_TIME: u16 = 0x0132;
+
+const TYPE_ASCII: u16 = 2;
+const TYPE_SHORT: u16 = 3;
+
+/// A few IFD0 tags of the TIFF-format data of an eXIf chunk
+#[derive(Debug, Default, PartialEq)]
+pub struct Summary {
+    pub make: Option<String>,
+    pub model: Option<String>,
+    pub date_time: Option<String>,
+    pub orientation: Option<u16>,
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+
+        let fields = [
+            self.make.as_ref().map(|make| format!("make {}", make)),
+            self.model.as_ref().map(|model| format!("model {}", model)),
+            self.date_time.as_ref().map(|date_time| format!("taken {}", date_time)),
+            self.orientation.map(|orientation| format!("orientation {}", orientation)),
+        ];
+        let fields: Vec<String> = fields.into_iter().flatten().collect();
+
+        if fields.is_empty() {
+            write!(f, "no known tags")
+        } else {
+            write!(f, "{}", fields.join(", "))
+        }
+    }
+}
+
+/// Reads the Make, Model, DateTime and Orientation tags of the first IFD,
+/// in either byte order
+pub fn summarize(data: &[u8]) -> Result<Summary> {
+
+    let reader = match data.get(..4) {
+        Some(b"II*\0") => Reader { data, little_endian: true },
+        Some(b"MM\0*") => Reader { data, little_endian: false },
+        _ => return Err("The eXIf data does not start with a TIFF header".into()),
+    };
+
+    let ifd = reader.u32(4)? as usize;
+    let count = reader.u16(ifd)? as usize;
+    let mut summary = Summary::default();
+
+    for entry in 0..count {
+        let offset = ifd + 2 + entry * 12;
+        let tag = reader.u16(offset)?;
+        let field_type = reader.u16(offset + 2)?;
+        let value_count = reader.u32(offset + 4)? as usize;
+
+        match (tag, field_type) {
+            (TAG_MAKE, TYPE_ASCII) => summary.make = Some(reader.ascii(offset + 8, value_count)?),
+            (TAG_MODEL, TYPE_ASCII) => summary.model = Some(reader.ascii(offset + 8, value_count)?),
+            (TAG_DATE_TIME, TYPE_ASCII) => summary.date_time = Some(reader.ascii(offset + 8, value_count)?),
+            (TAG_ORIENTATION, TYPE_SHORT) => summary.orientation = Some(reader.u16(offset + 8)?),
+            _ => {},
+        }
+    }
+
+    Ok(summary)
+}
+
+/// Describes the eXIf data in one line. Data that cannot be parsed is still
+/// reported as present, so that it never stops a command.
+pub fn describe(data: &[u8]) -> String {
+
+    match summarize(data) {
+        Ok(summary) => format!("present, {} bytes, {}", data.len(), summary),
+        Err(_) => format!("present, {} bytes, unparseable", data.len()),
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    little_endian: bool,
+}
+
+impl Reader<'_> {
+    fn bytes<const N: usize>(&self, offset: usize) -> Result<[u8; N]> {
+
+        let bytes = self.data.get(offset..offset + N).ok_or("The eXIf data is truncated")?;
+
+        Ok(bytes.try_into()?)
+    }
+
+    fn u16(&self, offset: usize) -> Result<u16> {
+
+        let bytes = self.bytes(offset)?;
+
+        Ok(if self.little_endian { u16::from_le_bytes(bytes) } else { u16::from_be_bytes(bytes) })
+    }
+
+    fn u32(&self, offset: usize) -> Result<u32> {
+
+        let bytes = self.bytes(offset)?;
+
+        Ok(if self.little_endian { u32::from_le_bytes(bytes) } else { u32::from_be_bytes(bytes) })
+    }
+
+    /// Reads an ASCII value, stored in the entry itself when it fits in 4 bytes
+    fn ascii(&self, value_offset: usize, count: usize) -> Result<String> {
+
+        let start = if count <= 4 { value_offset } else { self.u32(value_offset)? as usize };
+        let bytes = self.data.get(start..start + count).ok_or("The eXIf data is truncated")?;
+
+        let text: String = bytes.iter()
+            .take_while(|byte| **byte != 0)
+            .map(|byte| *byte as char)
+            .collect();
+
+        Ok(text.trim().to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// TIFF data with an IFD0 holding Make, Model, Orientation and DateTime
+    fn tiff(little_endian: bool) -> Vec<u8> {
+        let u16_bytes = |value: u16| if little_endian { value.to_le_bytes() } else { value.to_be_bytes() };
+        let u32_bytes = |value: u32| if little_endian { value.to_le_bytes() } else { value.to_be_bytes() };
+
+        let mut data = if little_endian { b"II*\0".to_vec() } else { b"MM\0*".to_vec() };
+        data.extend(u32_bytes(8));
+        data.extend(u16_bytes(4));
+
+        // The values that do not fit in an entry follow the 4 entries and the next IFD offset
+        let strings_offset = 8 + 2 + 4 * 12 + 4;
+        let entries: [(u16, u16, u32, [u8; 4]); 4] = [
+            (TAG_MAKE, TYPE_ASCII, 4, *b"Ace\0"),
+            (TAG_MODEL, TYPE_ASCII, 9, u32_bytes(strings_offset)),
+            (TAG_ORIENTATION, TYPE_SHORT, 1, [u16_bytes(6), [0, 0]].concat().try_into().unwrap()),
+            (TAG_DATE_TIME, TYPE_ASCII, 20, u32_bytes(strings_offset + 9)),
+        ];
+
+        for (tag, field_type, count, value) in entries {
+            data.extend(u16_bytes(tag));
+            data.extend(u16_bytes(field_type));
+            data.extend(u32_bytes(count));
+            data.extend(value);
+        }
+
+        data.extend(u32_bytes(0));
+        data.extend(b"Camera 1\0");
+        data.extend(b"2024:05:01 09:30:12\0");
+
+        data
+    }
+
+    #[test]
+    fn test_summarize_both_byte_orders() {
+        let expected = Summary {
+            make: Some("Ace".to_string()),
+            model: Some("Camera 1".to_string()),
+            date_time: Some("2024:05:01 09:30:12".to_string()),
+            orientation: Some(6),
+        };
+
+        assert_eq!(summarize(&tiff(true)).unwrap(), expected);
+        assert_eq!(summarize(&tiff(false)).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_describe() {
+        let data = tiff(false);
+
+        assert_eq!(
+            describe(&data),
+            format!("present, {} bytes, make Ace, model Camera 1, taken 2024:05:01 09:30:12, orientation 6", data.len())
+        );
+        assert_eq!(describe(b"II*\0\xff\xff\xff\xff"), "present, 8 bytes, unparseable");
+        assert_eq!(describe(b"not tiff"), "present, 8 bytes, unparseable");
+    }
+}
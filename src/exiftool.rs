@@ -0,0 +1,135 @@
+use crate::{ihdr::ImageHeader, json, list::ListedChunk, png::Png, text};
+
+/// What a chunk or header field is called, with its value already serialized as JSON
+type Tag = (String, String);
+
+/// Renders the listing the way `exiftool -j` does, for tools that already ingest it:
+/// an array of one object, holding `SourceFile` and flat tags prefixed by their group.
+/// The image header, text chunks and private chunks of the view become tags.
+pub fn to_json(source_file: &str, png: &Png, view: &[ListedChunk]) -> String {
+
+    let lines: Vec<String> = tags(source_file, png, view).iter()
+        .map(|(name, value)| format!("  {}: {}", json::string(name), value))
+        .collect();
+
+    format!("[{{\n{}\n}}]", lines.join(",\n"))
+}
+
+/// Every tag in order: the header fields under `PNG:` as exiftool names them,
+/// `PNG:Text<keyword>` for text chunks and `PNGME:<type>` for private chunks. Like
+/// exiftool without `-a`, only the first of several tags with the same name is kept.
+pub fn tags(source_file: &str, png: &Png, view: &[ListedChunk]) -> Vec<Tag> {
+
+    let mut tags = vec![("SourceFile".to_string(), json::string(source_file))];
+
+    if let Ok(header) = ImageHeader::from_png(png) {
+        tags.extend(header_tags(&header));
+    }
+
+    for ListedChunk { chunk, .. } in view {
+        let tag = if let Ok(Some((keyword, text))) = text::decode_framed(chunk) {
+            let name: String = keyword.chars().filter(char::is_ascii_alphanumeric).collect();
+
+            (format!("PNG:Text{}", name), json::string(&text))
+        } else if !chunk.chunk_type().is_public() {
+            let value = match chunk.data_as_string() {
+                Ok(data) => json::string(&data),
+                Err(_) => json::string(&format!("(Binary data {} bytes)", chunk.data().len())),
+            };
+
+            (format!("PNGME:{}", chunk.chunk_type()), value)
+        } else {
+            continue
+        };
+
+        if !tags.iter().any(|(name, _)| *name == tag.0) {
+            tags.push(tag);
+        }
+    }
+
+    tags
+}
+
+/// The IHDR fields with exiftool's names and, where it has them, its words for the values
+fn header_tags(header: &ImageHeader) -> Vec<Tag> {
+
+    let named = |value: u8, name: Option<&str>| name.map(json::string).unwrap_or_else(|| value.to_string());
+
+    let color_type = match header.color_type {
+        0 => Some("Grayscale"),
+        2 => Some("RGB"),
+        3 => Some("Palette"),
+        4 => Some("Grayscale with Alpha"),
+        6 => Some("RGB with Alpha"),
+        _ => None,
+    };
+
+    let interlace = match header.interlace_method {
+        0 => Some("Noninterlaced"),
+        1 => Some("Adam7 Interlace"),
+        _ => None,
+    };
+
+    vec![
+        ("PNG:ImageWidth".to_string(), header.width.to_string()),
+        ("PNG:ImageHeight".to_string(), header.height.to_string()),
+        ("PNG:BitDepth".to_string(), header.bit_depth.to_string()),
+        ("PNG:ColorType".to_string(), named(header.color_type, color_type)),
+        ("PNG:Compression".to_string(), named(header.compression_method, (header.compression_method == 0).then_some("Deflate/Inflate"))),
+        ("PNG:Filter".to_string(), named(header.filter_method, (header.filter_method == 0).then_some("Adaptive"))),
+        ("PNG:Interlace".to_string(), named(header.interlace_method, interlace)),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::{args::ListOptions, chunk::Chunk, chunk_type::ChunkType, list};
+
+    fn testing_png() -> Png {
+        let mut ihdr = [800u32.to_be_bytes(), 600u32.to_be_bytes()].concat();
+        ihdr.extend_from_slice(&[8, 6, 0, 0, 0]);
+
+        Png::from_chunks(vec![
+            Chunk::new(ChunkType::IHDR, ihdr),
+            Chunk::new(ChunkType::from_str("tEXt").unwrap(), text::encode_text("Comment", "Made with pngme").unwrap()),
+            Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"hidden".to_vec()),
+            Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"second".to_vec()),
+            Chunk::new(ChunkType::from_str("pmKv").unwrap(), vec![0xff, 0x00]),
+            Chunk::new(ChunkType::IDAT, vec![]),
+            Chunk::new(ChunkType::IEND, vec![]),
+        ])
+    }
+
+    #[test]
+    fn test_tag_names_are_stable() {
+        let png = testing_png();
+        let view = list::view(&png, &ListOptions::default());
+        let tags = tags("images/a.png", &png, &view);
+
+        // Downstream parsers match on these names, they must not change
+        let names: Vec<&str> = tags.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, [
+            "SourceFile", "PNG:ImageWidth", "PNG:ImageHeight", "PNG:BitDepth", "PNG:ColorType",
+            "PNG:Compression", "PNG:Filter", "PNG:Interlace", "PNG:TextComment", "PNGME:ruSt", "PNGME:pmKv",
+        ]);
+
+        let values: Vec<&str> = tags.iter().map(|(_, value)| value.as_str()).collect();
+        assert_eq!(values, [
+            "\"images/a.png\"", "800", "600", "8", "\"RGB with Alpha\"", "\"Deflate/Inflate\"", "\"Adaptive\"",
+            "\"Noninterlaced\"", "\"Made with pngme\"", "\"hidden\"", "\"(Binary data 2 bytes)\"",
+        ]);
+    }
+
+    #[test]
+    fn test_to_json() {
+        let png = testing_png();
+        let json = to_json("a.png", &png, &[]);
+
+        assert!(json.starts_with("[{\n  \"SourceFile\": \"a.png\",\n  \"PNG:ImageWidth\": 800,\n"));
+        assert!(json.ends_with("  \"PNG:Interlace\": \"Noninterlaced\"\n}]"));
+        assert!(crate::json::parse(&json).is_ok());
+    }
+}
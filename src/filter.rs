@@ -0,0 +1,162 @@
+use std::{fmt, str::FromStr};
+
+use crate::{chunk::Chunk, chunk_type::ChunkType, text};
+
+/// Selects chunks by class or by exact type. This is the vocabulary every
+/// command that picks a subset of chunks understands.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChunkFilter {
+    Critical,
+    Ancillary,
+    Private,
+    Standard,
+    Type(String),
+    /// A tEXt, zTXt or iTXt chunk with this keyword
+    TextKeyword(String),
+}
+
+impl ChunkFilter {
+    pub fn matches(&self, chunk: &Chunk) -> bool {
+
+        let chunk_type = chunk.chunk_type();
+
+        match self {
+            ChunkFilter::Critical => chunk_type.is_critical(),
+            ChunkFilter::Ancillary => !chunk_type.is_critical(),
+            ChunkFilter::Private => !chunk_type.is_public(),
+            ChunkFilter::Standard => chunk_type.is_standard(),
+            ChunkFilter::Type(expected) => chunk_type.to_string() == *expected,
+            ChunkFilter::TextKeyword(expected) => text::keyword(chunk).is_some_and(|keyword| keyword == *expected),
+        }
+    }
+}
+
+/// Checks a chunk against a set of filters, any of which may match.
+/// No filters at all selects every chunk.
+pub fn matches_any(filters: &[ChunkFilter], chunk: &Chunk) -> bool {
+
+    filters.is_empty() || filters.iter().any(|filter| filter.matches(chunk))
+}
+
+/// Which chunks a command removes: every chunk matching a dropped filter, or
+/// every chunk matching none of the kept filters. As with `matches_any`, dropping
+/// no filters at all drops every chunk, which strip relies on to remove all the
+/// ancillary chunks.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Selection<'a> {
+    Keep(&'a [ChunkFilter]),
+    Drop(&'a [ChunkFilter]),
+}
+
+impl Selection<'_> {
+    pub fn removes(&self, chunk: &Chunk) -> bool {
+
+        match self {
+            Selection::Keep(filters) => !filters.iter().any(|filter| filter.matches(chunk)),
+            Selection::Drop(filters) => matches_any(filters, chunk),
+        }
+    }
+}
+
+impl FromStr for ChunkFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+
+        match s {
+            "critical" => Ok(ChunkFilter::Critical),
+            "ancillary" => Ok(ChunkFilter::Ancillary),
+            "private" => Ok(ChunkFilter::Private),
+            "standard" => Ok(ChunkFilter::Standard),
+            // A bare chunk type is the same as type:XXXX
+            _ if s.len() == 4 && ChunkType::from_str(s).is_ok() => Ok(ChunkFilter::Type(s.to_string())),
+            _ if s.starts_with("text-keyword:") => match &s["text-keyword:".len()..] {
+                "" => Err("text-keyword: needs a keyword".to_string()),
+                keyword => Ok(ChunkFilter::TextKeyword(keyword.to_string())),
+            },
+            _ => match s.strip_prefix("type:") {
+                Some(chunk_type) if chunk_type.len() == 4 => Ok(ChunkFilter::Type(chunk_type.to_string())),
+                Some(chunk_type) => Err(format!("'{}' is not a 4 character chunk type", chunk_type)),
+                None => Err(format!(
+                    "Unknown filter '{}', expected critical, ancillary, private, standard, a chunk type, type:XXXX or text-keyword:KEYWORD", s
+                )),
+            }
+        }
+    }
+}
+
+impl fmt::Display for ChunkFilter {
+
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+
+        match self {
+            ChunkFilter::Critical => write!(f, "critical"),
+            ChunkFilter::Ancillary => write!(f, "ancillary"),
+            ChunkFilter::Private => write!(f, "private"),
+            ChunkFilter::Standard => write!(f, "standard"),
+            ChunkFilter::Type(chunk_type) => write!(f, "type:{}", chunk_type),
+            ChunkFilter::TextKeyword(keyword) => write!(f, "text-keyword:{}", keyword),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk_type::ChunkType;
+
+    fn chunk(chunk_type: &str) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), vec![])
+    }
+
+    #[test]
+    fn test_parse_filters() {
+        assert_eq!(ChunkFilter::from_str("private").unwrap(), ChunkFilter::Private);
+        assert_eq!(ChunkFilter::from_str("type:ruSt").unwrap(), ChunkFilter::Type("ruSt".to_string()));
+        assert!(ChunkFilter::from_str("type:ruSty").is_err());
+        assert!(ChunkFilter::from_str("public").is_err());
+        assert_eq!(ChunkFilter::from_str("IHDR").unwrap(), ChunkFilter::Type("IHDR".to_string()));
+        assert!(ChunkFilter::from_str("IH1R").is_err());
+    }
+
+    #[test]
+    fn test_class_filters() {
+        assert!(ChunkFilter::Critical.matches(&chunk("IHDR")));
+        assert!(ChunkFilter::Ancillary.matches(&chunk("tEXt")));
+        assert!(ChunkFilter::Private.matches(&chunk("ruSt")));
+        assert!(ChunkFilter::Standard.matches(&chunk("tEXt")));
+        assert!(!ChunkFilter::Standard.matches(&chunk("ruSt")));
+    }
+
+    #[test]
+    fn test_text_keyword_filter() {
+        let filter = ChunkFilter::from_str("text-keyword:Secret").unwrap();
+        let secret = Chunk::new(ChunkType::from_str("tEXt").unwrap(), b"Secret\0value".to_vec());
+        let title = Chunk::new(ChunkType::from_str("tEXt").unwrap(), b"Title\0Secret".to_vec());
+
+        assert!(filter.matches(&secret));
+        assert!(!filter.matches(&title));
+        assert_eq!(filter.to_string(), "text-keyword:Secret");
+        assert!(ChunkFilter::from_str("text-keyword:").is_err());
+    }
+
+    #[test]
+    fn test_matches_any() {
+        let filters = [ChunkFilter::Critical, ChunkFilter::Type("ruSt".to_string())];
+        assert!(matches_any(&filters, &chunk("IDAT")));
+        assert!(matches_any(&filters, &chunk("ruSt")));
+        assert!(!matches_any(&filters, &chunk("tEXt")));
+        assert!(matches_any(&[], &chunk("tEXt")));
+    }
+
+    #[test]
+    fn test_selection() {
+        let filters = [ChunkFilter::Type("IHDR".to_string()), ChunkFilter::Private];
+
+        assert!(!Selection::Keep(&filters).removes(&chunk("ruSt")));
+        assert!(Selection::Keep(&filters).removes(&chunk("tEXt")));
+        assert!(Selection::Drop(&filters).removes(&chunk("ruSt")));
+        assert!(!Selection::Drop(&filters).removes(&chunk("tEXt")));
+        assert!(Selection::Drop(&[]).removes(&chunk("tEXt")));
+    }
+}
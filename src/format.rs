@@ -0,0 +1,71 @@
+use std::fmt;
+
+use crate::png;
+
+/// An image format recognized by the magic bytes at the start of a file
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Png,
+    Jpeg,
+    Gif,
+    Webp,
+    Bmp,
+    Unknown,
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+
+        let name = match self {
+            Format::Png => "PNG",
+            Format::Jpeg => "JPEG",
+            Format::Gif => "GIF",
+            Format::Webp => "WEBP",
+            Format::Bmp => "BMP",
+            Format::Unknown => "unknown",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+/// Identifies the format of a file from its first bytes
+pub fn identify(bytes: &[u8]) -> Format {
+
+    if png::is_png(bytes) {
+        Format::Png
+    } else if bytes.starts_with(&[0xff, 0xd8, 0xff]) {
+        Format::Jpeg
+    } else if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+        Format::Gif
+    } else if bytes.len() >= 12 && bytes.starts_with(b"RIFF") && &bytes[8..12] == b"WEBP" {
+        Format::Webp
+    } else if bytes.starts_with(b"BM") && bytes.len() >= 14 {
+        Format::Bmp
+    } else {
+        Format::Unknown
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_identify() {
+        assert_eq!(identify(&[137, 80, 78, 71, 13, 10, 26, 10, 0, 0]), Format::Png);
+        assert_eq!(identify(&[0xff, 0xd8, 0xff, 0xe0, 0, 16, b'J', b'F', b'I', b'F']), Format::Jpeg);
+        assert_eq!(identify(b"GIF89a\x01\x00\x01\x00"), Format::Gif);
+        assert_eq!(identify(b"RIFF\x24\x00\x00\x00WEBPVP8 "), Format::Webp);
+        assert_eq!(identify(b"BM\x3a\x00\x00\x00\x00\x00\x00\x00\x36\x00\x00\x00"), Format::Bmp);
+    }
+
+    #[test]
+    fn test_identify_unknown() {
+        assert_eq!(identify(b""), Format::Unknown);
+        assert_eq!(identify(b"RIFF\x24\x00\x00\x00WAVEfmt "), Format::Unknown);
+        assert_eq!(identify(b"BM"), Format::Unknown);
+        assert_eq!(identify(&[137, 80, 78, 71]), Format::Unknown);
+        assert_eq!(Format::Unknown.to_string(), "unknown");
+    }
+}
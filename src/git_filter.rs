@@ -0,0 +1,98 @@
+use crc::Crc;
+
+use crate::{chunk::Chunk, filter::{ChunkFilter, Selection}, png::Png, Result};
+
+/// The git config key listing the chunk types `clean` strips when `--types` is not given
+pub const CLEAN_TYPES_KEY: &str = "pngme.cleanTypes";
+
+/// Removes the selected ancillary chunks from a PNG file and returns the
+/// sanitized file along with the removed chunks, in file order. Critical
+/// chunks are always kept, so that the image stays whole.
+pub fn clean(bytes: &[u8], filters: &[ChunkFilter], crc: &Crc<u32>) -> Result<(Vec<u8>, Vec<Chunk>)> {
+
+    let mut png = Png::try_from_with_crc(bytes, crc)?;
+    let removed = png.retain_chunks(|chunk| chunk.chunk_type().is_critical() || !Selection::Drop(filters).removes(chunk));
+
+    Ok((png.as_bytes(), removed))
+}
+
+/// Puts chunks saved by `clean` back into a PNG file, before IEND
+pub fn smudge(bytes: &[u8], sidecar: &[u8], crc: &Crc<u32>) -> Result<Vec<u8>> {
+
+    let mut png = Png::try_from_with_crc(bytes, crc)?;
+    let start = png.position_of_type("IEND").unwrap_or(png.chunk_count());
+
+    for (index, chunk) in (start..).zip(read_sidecar(sidecar, crc)?) {
+        png.insert_chunk(index, chunk)?;
+    }
+
+    Ok(png.as_bytes())
+}
+
+/// The chunks removed by `clean`, stored one after the other as they are in a PNG file
+pub fn to_sidecar(chunks: &[Chunk]) -> Vec<u8> {
+
+    let mut bytes = vec![];
+
+    for chunk in chunks {
+        chunk.extend_bytes(&mut bytes);
+    }
+
+    bytes
+}
+
+fn read_sidecar(mut bytes: &[u8], crc: &Crc<u32>) -> Result<Vec<Chunk>> {
+
+    let mut chunks = vec![];
+
+    while !bytes.is_empty() {
+        let (chunk, length) = Chunk::read_one_with_crc(bytes, crc)?;
+        chunks.push(chunk);
+        bytes = &bytes[length..];
+    }
+
+    Ok(chunks)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::chunk_type::ChunkType;
+
+    const PNG_CRC: Crc<u32> = Crc::<u32>::new(&crc::CRC_32_ISO_HDLC);
+
+    fn chunk(chunk_type: &str, data: &[u8]) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec())
+    }
+
+    fn testing_png() -> Png {
+        Png::from_chunks(vec![chunk("IHDR", &[0; 13]), chunk("tEXt", b"Title\0Logo"), chunk("ruSt", b"secret"), chunk("IEND", b"")])
+    }
+
+    #[test]
+    fn test_clean_and_smudge_round_trip() {
+        let bytes = testing_png().as_bytes();
+        let filters = [ChunkFilter::Private, ChunkFilter::Critical];
+
+        let (cleaned, removed) = clean(&bytes, &filters, &PNG_CRC).unwrap();
+
+        let png = Png::try_from_with_crc(&cleaned, &PNG_CRC).unwrap();
+        let types: Vec<String> = png.chunks().iter().map(|chunk| chunk.chunk_type().to_string()).collect();
+        assert_eq!(types, ["IHDR", "tEXt", "IEND"]);
+        assert_eq!(removed.len(), 1);
+
+        // Cleaning twice changes nothing, as git expects from a clean filter
+        assert_eq!(clean(&cleaned, &filters, &PNG_CRC).unwrap().0, cleaned);
+
+        assert_eq!(smudge(&cleaned, &to_sidecar(&removed), &PNG_CRC).unwrap(), bytes);
+        assert_eq!(smudge(&cleaned, b"", &PNG_CRC).unwrap(), cleaned);
+    }
+
+    #[test]
+    fn test_not_a_png() {
+        assert!(clean(b"GIF89a", &[ChunkFilter::Private], &PNG_CRC).is_err());
+        assert!(smudge(&testing_png().as_bytes(), b"garbage", &PNG_CRC).is_err());
+    }
+}
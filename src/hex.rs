@@ -0,0 +1,77 @@
+use crate::Result;
+
+/// Lowercase hex, two digits per byte
+pub fn encode(data: &[u8]) -> String {
+
+    data.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Decodes hex digits in either case. Whitespace between the digits is ignored,
+/// errors point at the offending character (counted from 1).
+pub fn decode(text: &str) -> Result<Vec<u8>> {
+
+    let digits: Vec<(usize, char)> = text.chars()
+        .enumerate()
+        .filter(|(_, character)| !character.is_whitespace())
+        .map(|(index, character)| (index + 1, character))
+        .collect();
+
+    if let Some((position, character)) = digits.iter().find(|(_, character)| !character.is_ascii_hexdigit()) {
+        return Err(format!("'{}' at character {} is not a hex digit", character, position).into())
+    }
+
+    if let [.., (position, _)] = digits.as_slice() {
+        if !digits.len().is_multiple_of(2) {
+            return Err(format!("Odd number of hex digits, the digit at character {} has no pair", position).into())
+        }
+    }
+
+    Ok(digits.chunks(2)
+        .map(|pair| (pair[0].1.to_digit(16).unwrap() << 4 | pair[1].1.to_digit(16).unwrap()) as u8)
+        .collect())
+}
+
+/// Hexdump lines of 16 bytes, each with its offset (counted from `start`), the bytes in
+/// hex and the printable ones as ASCII
+pub fn dump(start: u64, data: &[u8]) -> Vec<String> {
+
+    data.chunks(16)
+        .enumerate()
+        .map(|(line, bytes)| {
+            let hex: Vec<String> = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+            let ascii: String = bytes.iter()
+                .map(|byte| if byte.is_ascii_graphic() || *byte == b' ' { *byte as char } else { '.' })
+                .collect();
+
+            format!("{:#010x}  {:<47}  |{}|", start + line as u64 * 16, hex.join(" "), ascii)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        assert_eq!(encode(&[0xde, 0xad, 0x00, 0xef]), "dead00ef");
+        assert_eq!(decode("DEad 00ef\n").unwrap(), [0xde, 0xad, 0x00, 0xef]);
+        assert_eq!(decode("").unwrap(), []);
+    }
+
+    #[test]
+    fn test_errors_point_at_the_character() {
+        assert_eq!(decode("dead beef f").unwrap_err().to_string(), "Odd number of hex digits, the digit at character 11 has no pair");
+        assert_eq!(decode("de ag").unwrap_err().to_string(), "'g' at character 5 is not a hex digit");
+    }
+
+    #[test]
+    fn test_dump() {
+        let lines = dump(0x10, b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR and more");
+
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0], "0x00000010  89 50 4e 47 0d 0a 1a 0a 00 00 00 0d 49 48 44 52  |.PNG........IHDR|");
+        assert_eq!(lines[1], "0x00000020  20 61 6e 64 20 6d 6f 72 65                       | and more|");
+        assert!(dump(0, b"").is_empty());
+    }
+}
@@ -0,0 +1,205 @@
+use std::{fmt, str::FromStr};
+
+use crc::Crc;
+
+use crate::{chunk::Chunk, chunk_type::ChunkType, filter::Selection, manifest::Operation, png::Png, timestamp, Result};
+
+/// The chunk holding the history log
+pub const HISTORY_CHUNK_TYPE: &str = "pmHi";
+
+/// The log is rotated, dropping its oldest entries, to stay under this many bytes by default
+pub const DEFAULT_HISTORY_LIMIT: usize = 64 * 1024;
+
+/// Whether mutating commands record what they did, and how large the log may grow
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Recording {
+    pub enabled: bool,
+    pub limit: usize,
+}
+
+/// One line of the log. The payload itself is never recorded, only its length.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub timestamp: String,
+    pub command: String,
+    pub chunk_type: String,
+    pub length: usize,
+}
+
+impl Entry {
+    /// An entry for a change made now
+    pub fn new(command: &str, chunk_type: &str, length: usize) -> Entry {
+
+        Entry {
+            timestamp: timestamp::now(),
+            command: command.to_string(),
+            chunk_type: chunk_type.to_string(),
+            length,
+        }
+    }
+}
+
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+
+        write!(f, "{} {} {} {}", self.timestamp, self.command, self.chunk_type, self.length)
+    }
+}
+
+impl FromStr for Entry {
+    type Err = crate::Error;
+
+    fn from_str(line: &str) -> Result<Entry> {
+
+        let fields: Vec<&str> = line.split(' ').collect();
+
+        let [timestamp, command, chunk_type, length] = fields.as_slice() else {
+            return Err(format!("Invalid history entry '{}'", line).into())
+        };
+
+        Ok(Entry {
+            timestamp: timestamp.to_string(),
+            command: command.to_string(),
+            chunk_type: chunk_type.to_string(),
+            length: length.parse().map_err(|_| format!("Invalid payload length in history entry '{}'", line))?,
+        })
+    }
+}
+
+/// The append-only log of the changes made to a file, oldest first
+#[derive(Debug, Default, PartialEq)]
+pub struct Log {
+    entries: Vec<Entry>,
+}
+
+impl Log {
+    /// Removes the log chunk from the file and parses it. A file without
+    /// history has an empty log.
+    pub fn take_from(png: &mut Png) -> Result<Log> {
+
+        let removed = png.retain_chunks(|chunk| chunk.chunk_type().to_string() != HISTORY_CHUNK_TYPE);
+
+        let mut entries = vec![];
+
+        for chunk in &removed {
+            let text = std::str::from_utf8(chunk.data()).map_err(|_| "The history log is not valid UTF-8")?;
+
+            for line in text.lines().filter(|line| !line.is_empty()) {
+                entries.push(Entry::from_str(line)?);
+            }
+        }
+
+        Ok(Log { entries })
+    }
+
+    /// Puts the log chunk back, right before IEND, unless it is empty
+    pub fn store(&self, png: &mut Png, crc: &Crc<u32>) -> Result<()> {
+
+        if self.entries.is_empty() {
+            return Ok(())
+        }
+
+        png.insert_before_iend(Chunk::new_with_crc(ChunkType::from_str(HISTORY_CHUNK_TYPE)?, self.as_bytes(), crc));
+
+        Ok(())
+    }
+
+    /// Appends the entry, then drops the oldest entries until the log fits in `limit` bytes
+    pub fn record(&mut self, entry: Entry, limit: usize) {
+
+        self.entries.push(entry);
+
+        while !self.entries.is_empty() && self.as_bytes().len() > limit {
+            self.entries.remove(0);
+        }
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+
+        &self.entries
+    }
+
+    fn as_bytes(&self) -> Vec<u8> {
+
+        self.entries.iter().map(|entry| format!("{}\n", entry)).collect::<String>().into_bytes()
+    }
+}
+
+/// The entries an operation of a manifest will record, worked out before it is applied
+pub fn entries_for(operation: &Operation, png: &Png) -> Vec<Entry> {
+
+    match operation {
+        Operation::Encode { chunk_type, message } => vec![Entry::new("encode", &chunk_type.to_string(), message.len())],
+        Operation::Remove { chunk_type } => png.chunk_by_type(chunk_type)
+            .map(|chunk| Entry::new("remove", chunk_type, chunk.length() as usize))
+            .into_iter()
+            .collect(),
+        Operation::TextSet { keyword, text } => vec![Entry::new("text-set", "tEXt", keyword.len() + 1 + text.len())],
+        Operation::Strip { filters } => png.chunks()
+            .iter()
+            .filter(|chunk| !chunk.chunk_type().is_critical() && Selection::Drop(filters).removes(chunk))
+            .map(|chunk| Entry::new("strip", &chunk.chunk_type().to_string(), chunk.length() as usize))
+            .collect(),
+        Operation::Rename { from, .. } => png.chunks()
+            .iter()
+            .filter(|chunk| chunk.chunk_type().to_string() == *from)
+            .map(|chunk| Entry::new("rename", from, chunk.length() as usize))
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PNG_CRC: Crc<u32> = Crc::<u32>::new(&crc::CRC_32_ISO_HDLC);
+
+    fn entry(command: &str, length: usize) -> Entry {
+        Entry { timestamp: "2024-01-01T00:00:00Z".to_string(), command: command.to_string(), chunk_type: "ruSt".to_string(), length }
+    }
+
+    #[test]
+    fn test_log_round_trip() {
+        let mut png = Png::from_chunks(vec![]);
+        let mut log = Log::default();
+        log.record(entry("encode", 7), DEFAULT_HISTORY_LIMIT);
+        log.record(entry("remove", 7), DEFAULT_HISTORY_LIMIT);
+        log.store(&mut png, &PNG_CRC).unwrap();
+
+        assert_eq!(png.chunk_by_type(HISTORY_CHUNK_TYPE).unwrap().data(), b"2024-01-01T00:00:00Z encode ruSt 7\n2024-01-01T00:00:00Z remove ruSt 7\n");
+        assert_eq!(Log::take_from(&mut png).unwrap(), log);
+        assert!(png.chunks().is_empty());
+    }
+
+    #[test]
+    fn test_log_stored_before_iend() {
+        let mut png = Png::from_chunks(vec![
+            Chunk::new(ChunkType::IHDR, vec![0; 13]),
+            Chunk::new(ChunkType::IEND, vec![]),
+        ]);
+        let mut log = Log::default();
+        log.record(entry("encode", 7), DEFAULT_HISTORY_LIMIT);
+        log.store(&mut png, &PNG_CRC).unwrap();
+
+        assert_eq!(png.position_of_type(HISTORY_CHUNK_TYPE), Some(1));
+        assert!(png.order_violations().iter().all(|violation| !violation.contains("after IEND")));
+    }
+
+    #[test]
+    fn test_log_drops_oldest_entries() {
+        let mut log = Log::default();
+
+        for length in 0..10 {
+            log.record(entry("encode", length), 100);
+        }
+
+        let lengths: Vec<usize> = log.entries().iter().map(|entry| entry.length).collect();
+        assert_eq!(lengths, [8, 9]);
+    }
+
+    #[test]
+    fn test_invalid_entry() {
+        assert!(Entry::from_str("2024-01-01T00:00:00Z encode ruSt").is_err());
+        assert!(Entry::from_str("2024-01-01T00:00:00Z encode ruSt many").is_err());
+    }
+}
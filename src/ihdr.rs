@@ -0,0 +1,195 @@
+use crate::{png::Png, Result};
+
+pub const COLOR_GRAYSCALE: u8 = 0;
+pub const COLOR_RGB: u8 = 2;
+pub const COLOR_PALETTE: u8 = 3;
+pub const COLOR_GRAYSCALE_ALPHA: u8 = 4;
+pub const COLOR_RGBA: u8 = 6;
+
+/// The first column, first row, column step and row step of the seven Adam7 passes
+const ADAM7_PASSES: [(usize, usize, usize, usize); 7] = [
+    (0, 0, 8, 8), (4, 0, 8, 8), (0, 4, 4, 8), (2, 0, 4, 4), (0, 2, 2, 4), (1, 0, 2, 2), (0, 1, 1, 2),
+];
+
+/// The pixels of the image data filtered together: the whole image, or one Adam7 pass
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SubImage {
+    pub width: usize,
+    pub height: usize,
+    /// The number of bytes in a scanline, without its filter type byte
+    pub row_length: usize,
+}
+
+/// The image properties stored in the IHDR chunk
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ImageHeader {
+    pub width: u32,
+    pub height: u32,
+    pub bit_depth: u8,
+    pub color_type: u8,
+    pub compression_method: u8,
+    pub filter_method: u8,
+    pub interlace_method: u8,
+}
+
+impl ImageHeader {
+    /// Reads the header of the image, which the IHDR chunk must hold as the first chunk
+    pub fn from_png(png: &Png) -> Result<ImageHeader> {
+
+        let first = png.chunks().first().ok_or("The file has no chunks")?;
+
+        if first.chunk_type().to_string() != "IHDR" {
+            return Err(format!("The first chunk is {} instead of IHDR", first.chunk_type()).into())
+        }
+
+        ImageHeader::try_from(first.data())
+    }
+
+    /// The number of samples in every pixel
+    pub fn channels(&self) -> usize {
+
+        match self.color_type {
+            COLOR_RGB => 3,
+            COLOR_GRAYSCALE_ALPHA => 2,
+            COLOR_RGBA => 4,
+            _ => 1,
+        }
+    }
+
+    pub fn color_type_name(&self) -> &'static str {
+
+        match self.color_type {
+            COLOR_GRAYSCALE => "grayscale",
+            COLOR_RGB => "RGB",
+            COLOR_PALETTE => "palette",
+            COLOR_GRAYSCALE_ALPHA => "grayscale with alpha",
+            COLOR_RGBA => "RGBA",
+            _ => "unknown",
+        }
+    }
+
+    /// The number of bytes in a scanline, without its filter type byte
+    pub fn row_length(&self) -> usize {
+
+        (self.width as usize * self.channels() * self.bit_depth as usize).div_ceil(8)
+    }
+
+    /// The number of bytes a pixel takes, at least one, which the scanline filters work with
+    pub fn bytes_per_pixel(&self) -> usize {
+
+        (self.channels() * self.bit_depth as usize).div_ceil(8)
+    }
+
+    /// The sub-images the image data holds one after the other: the image itself,
+    /// or the Adam7 passes of an interlaced image. The passes a small image leaves
+    /// without a column or a row take no bytes at all, so they are left out.
+    /// Fails when a scanline is too long to count in a `usize`.
+    pub fn sub_images(&self) -> Result<Vec<SubImage>> {
+
+        let (width, height) = (self.width as usize, self.height as usize);
+        let bits_per_pixel = self.channels() * self.bit_depth as usize;
+        let sub_image = |width: usize, height: usize| -> Result<SubImage> {
+            let bits = width.checked_mul(bits_per_pixel).ok_or_else(|| self.too_large())?;
+
+            Ok(SubImage { width, height, row_length: bits.div_ceil(8) })
+        };
+
+        if self.interlace_method == 0 {
+            return Ok(vec![sub_image(width, height)?])
+        }
+
+        ADAM7_PASSES.iter()
+            .map(|(column, row, column_step, row_step)| (width.saturating_sub(*column).div_ceil(*column_step), height.saturating_sub(*row).div_ceil(*row_step)))
+            .filter(|(width, height)| *width > 0 && *height > 0)
+            .map(|(width, height)| sub_image(width, height))
+            .collect()
+    }
+
+    /// The number of bytes the image data inflates to, filter type bytes included.
+    /// Fails when that number cannot be counted in a `usize`.
+    pub fn data_length(&self) -> Result<usize> {
+
+        self.sub_images()?.iter()
+            .try_fold(0usize, |total, sub_image| {
+                sub_image.row_length.checked_add(1)?.checked_mul(sub_image.height)?.checked_add(total)
+            })
+            .ok_or_else(|| self.too_large())
+    }
+
+    fn too_large(&self) -> crate::Error {
+
+        format!("A {}x{} {} image with {}-bit samples is too large to handle", self.width, self.height, self.color_type_name(), self.bit_depth).into()
+    }
+}
+
+impl TryFrom<&[u8]> for ImageHeader {
+    type Error = crate::Error;
+
+    fn try_from(data: &[u8]) -> Result<ImageHeader> {
+
+        if data.len() != 13 {
+            return Err(format!("The IHDR chunk holds {} bytes instead of 13", data.len()).into())
+        }
+
+        Ok(ImageHeader {
+            width: u32::from_be_bytes(data[0..4].try_into()?),
+            height: u32::from_be_bytes(data[4..8].try_into()?),
+            bit_depth: data[8],
+            color_type: data[9],
+            compression_method: data[10],
+            filter_method: data[11],
+            interlace_method: data[12],
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_image_header() {
+        let header = ImageHeader::try_from(&[0, 0, 1, 0, 0, 0, 0, 10, 8, 6, 0, 0, 1][..]).unwrap();
+
+        assert_eq!((header.width, header.height), (256, 10));
+        assert_eq!(header.color_type_name(), "RGBA");
+        assert_eq!(header.interlace_method, 1);
+        assert_eq!(header.row_length(), 1024);
+        assert_eq!(header.bytes_per_pixel(), 4);
+    }
+
+    #[test]
+    fn test_adam7_sub_images() {
+        let header = ImageHeader::try_from(&[0, 0, 0, 8, 0, 0, 0, 8, 8, 2, 0, 0, 1][..]).unwrap();
+        let sizes: Vec<(usize, usize)> = header.sub_images().unwrap().iter().map(|pass| (pass.width, pass.height)).collect();
+
+        assert_eq!(sizes, [(1, 1), (1, 1), (2, 1), (2, 2), (4, 2), (4, 4), (8, 4)]);
+        assert_eq!(header.sub_images().unwrap().iter().map(|pass| pass.width * pass.height).sum::<usize>(), 64);
+        assert_eq!(header.data_length().unwrap(), 4 + 4 + 7 + 2 * 7 + 2 * 13 + 4 * 13 + 4 * 25);
+
+        // A 3x2 image leaves the second, third and fifth passes empty
+        let tiny = ImageHeader::try_from(&[0, 0, 0, 3, 0, 0, 0, 2, 8, 6, 0, 0, 1][..]).unwrap();
+        let sizes: Vec<(usize, usize)> = tiny.sub_images().unwrap().iter().map(|pass| (pass.width, pass.height)).collect();
+        assert_eq!(sizes, [(1, 1), (1, 1), (1, 1), (3, 1)]);
+
+        let plain = ImageHeader::try_from(&[0, 0, 0, 3, 0, 0, 0, 2, 8, 6, 0, 0, 0][..]).unwrap();
+        assert_eq!(plain.sub_images().unwrap(), [SubImage { width: 3, height: 2, row_length: 12 }]);
+        assert_eq!(plain.data_length().unwrap(), 26);
+    }
+
+    #[test]
+    fn test_row_length_of_packed_pixels() {
+        let header = ImageHeader::try_from(&[0, 0, 0, 10, 0, 0, 0, 1, 1, 0, 0, 0, 0][..]).unwrap();
+
+        assert_eq!(header.row_length(), 2);
+        assert_eq!(header.bytes_per_pixel(), 1);
+        assert!(ImageHeader::try_from(&[0; 12][..]).is_err());
+    }
+
+    #[test]
+    fn test_data_length_too_large() {
+        let header = ImageHeader::try_from(&[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 16, 6, 0, 0, 0][..]).unwrap();
+
+        assert!(header.data_length().unwrap_err().to_string().contains("too large"));
+    }
+}
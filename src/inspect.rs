@@ -0,0 +1,178 @@
+use std::fmt;
+
+use crate::{json, png::{ChunkSpan, Png}};
+
+/// A field of a chunk, in the order they are stored
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Field {
+    Length,
+    Type,
+    Data,
+    Crc,
+}
+
+impl fmt::Display for Field {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+
+        match self {
+            Field::Length => write!(f, "length"),
+            Field::Type => write!(f, "type"),
+            Field::Data => write!(f, "data"),
+            Field::Crc => write!(f, "crc"),
+        }
+    }
+}
+
+/// What a byte offset of a file points at
+#[derive(Debug, Clone, PartialEq)]
+pub enum Location {
+    /// One of the 8 bytes of the PNG signature
+    Signature,
+    /// A byte of a chunk, `within` bytes into `field`
+    Chunk { span: ChunkSpan, field: Field, within: u64 },
+    /// A byte after IEND that is not part of any chunk, `within` bytes into the `length` there are
+    Trailing { within: u64, length: u64 },
+    /// No byte at all, the file is `file_size` bytes long
+    PastEnd { file_size: u64 },
+}
+
+/// Finds what `offset` points at in the file `png` was parsed from
+pub fn locate(png: &Png, offset: u64) -> Location {
+
+    let spans = png.chunk_positions();
+    let chunks_end = spans.last().map_or(Png::STANDARD_HEADER.len() as u64, |span| span.range.end);
+    let file_size = chunks_end + png.trailing_bytes().len() as u64;
+
+    if offset < Png::STANDARD_HEADER.len() as u64 {
+        return Location::Signature
+    }
+
+    if offset >= file_size {
+        return Location::PastEnd { file_size }
+    }
+
+    match spans.into_iter().find(|span| span.range.contains(&offset)) {
+        Some(span) => {
+            let into_chunk = offset - span.range.start;
+            let data_end = span.range.end - span.range.start - 4;
+
+            let (field, within) = match into_chunk {
+                0..=3 => (Field::Length, into_chunk),
+                4..=7 => (Field::Type, into_chunk - 4),
+                _ if into_chunk < data_end => (Field::Data, into_chunk - 8),
+                _ => (Field::Crc, into_chunk - data_end),
+            };
+
+            Location::Chunk { span, field, within }
+        },
+        None => Location::Trailing { within: offset - chunks_end, length: file_size - chunks_end },
+    }
+}
+
+impl Location {
+    /// The location as a JSON object, with `offset` for the byte it describes
+    pub fn to_json(&self, offset: u64) -> String {
+
+        match self {
+            Location::Signature => format!("{{\"offset\": {}, \"region\": \"signature\"}}", offset),
+            Location::Chunk { span, field, within } => format!(
+                "{{\"offset\": {}, \"region\": \"chunk\", \"index\": {}, \"type\": {}, \"chunk_start\": {}, \"field\": \"{}\", \"field_offset\": {}}}",
+                offset, span.index, json::string(&span.chunk_type), span.range.start, field, within
+            ),
+            Location::Trailing { within, length } => format!(
+                "{{\"offset\": {}, \"region\": \"trailing\", \"trailing_offset\": {}, \"trailing_length\": {}}}",
+                offset, within, length
+            ),
+            Location::PastEnd { file_size } => format!("{{\"offset\": {}, \"region\": \"past_end\", \"file_size\": {}}}", offset, file_size),
+        }
+    }
+}
+
+impl fmt::Display for Location {
+
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+
+        match self {
+            Location::Signature => write!(f, "in the 8-byte PNG signature"),
+            Location::Chunk { span, field: Field::Data, within } => write!(
+                f, "in chunk {} ({}) starting at {:#x}, byte {} of its data", span.index, span.chunk_type, span.range.start, within
+            ),
+            Location::Chunk { span, field, within } => write!(
+                f, "in chunk {} ({}) starting at {:#x}, byte {} of its {} field", span.index, span.chunk_type, span.range.start, within, field
+            ),
+            Location::Trailing { within, length } => write!(f, "in the {} bytes after IEND that are not a chunk, byte {} of them", length, within),
+            Location::PastEnd { file_size } => write!(f, "past the end of the file, which is {} bytes long", file_size),
+        }
+    }
+}
+
+/// Parses an offset in decimal, or in hex with a 0x prefix
+pub fn parse_offset(text: &str) -> Result<u64, String> {
+
+    let text = text.trim();
+
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(digits) => u64::from_str_radix(digits, 16),
+        None => text.parse(),
+    }
+    .map_err(|_| format!("'{}' is not an offset, expected a number such as 1234 or 0x4d2", text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{chunk::Chunk, chunk_type::ChunkType};
+    use std::str::FromStr;
+
+    fn testing_png() -> Png {
+        let chunks = vec![
+            Chunk::new(ChunkType::IHDR, vec![0; 13]),
+            Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"message".to_vec()),
+            Chunk::new(ChunkType::IEND, vec![]),
+        ];
+
+        let bytes = [&Png::from_chunks(chunks).as_bytes()[..], b"junk"].concat();
+
+        Png::try_from(bytes.as_slice()).unwrap()
+    }
+
+    fn field_at(png: &Png, offset: u64) -> (usize, Field, u64) {
+        match locate(png, offset) {
+            Location::Chunk { span, field, within } => (span.index, field, within),
+            other => panic!("{} is {:?}", offset, other),
+        }
+    }
+
+    #[test]
+    fn test_locate_fields() {
+        let png = testing_png();
+
+        // IHDR takes bytes 8 to 32, ruSt 33 to 51
+        assert_eq!(field_at(&png, 8), (0, Field::Length, 0));
+        assert_eq!(field_at(&png, 13), (0, Field::Type, 1));
+        assert_eq!(field_at(&png, 16), (0, Field::Data, 0));
+        assert_eq!(field_at(&png, 32), (0, Field::Crc, 3));
+        assert_eq!(field_at(&png, 33 + 8 + 3), (1, Field::Data, 3));
+        assert_eq!(field_at(&png, 33 + 8 + 7), (1, Field::Crc, 0));
+    }
+
+    #[test]
+    fn test_locate_outside_chunks() {
+        let png = testing_png();
+        let size = png.as_bytes().len() as u64;
+
+        assert_eq!(locate(&png, 0), Location::Signature);
+        assert_eq!(locate(&png, 7), Location::Signature);
+        assert_eq!(locate(&png, size - 4), Location::Trailing { within: 0, length: 4 });
+        assert_eq!(locate(&png, size), Location::PastEnd { file_size: size });
+        assert!(locate(&png, size).to_json(size).contains("\"region\": \"past_end\""));
+    }
+
+    #[test]
+    fn test_parse_offset() {
+        assert_eq!(parse_offset("0x4A31C"), Ok(0x4a31c));
+        assert_eq!(parse_offset("1234"), Ok(1234));
+        assert!(parse_offset("0x").is_err());
+        assert!(parse_offset("twelve").is_err());
+    }
+}
@@ -0,0 +1,171 @@
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use crate::{error::PngMeError, Result};
+
+/// Set by Ctrl-C (or SIGTERM). Commands check it between stages, so that they stop
+/// before they start writing rather than halfway through.
+pub static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// How many writes are under way. A second Ctrl-C ends the process at once, but not
+/// while a write could be left half done.
+static WRITING: AtomicUsize = AtomicUsize::new(0);
+
+#[cfg(test)]
+thread_local! {
+    /// Lets a test interrupt only the commands of its own thread
+    static SIMULATED: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
+}
+
+/// Whether the user asked to stop
+pub fn interrupted() -> bool {
+
+    #[cfg(test)]
+    if SIMULATED.with(|simulated| simulated.get()) {
+        return true
+    }
+
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// Fails with the interrupted error, exit code 130, once the user asked to stop
+pub fn check() -> Result<()> {
+
+    if interrupted() {
+        return Err(PngMeError::Interrupted("Interrupted".to_string()).into())
+    }
+
+    Ok(())
+}
+
+/// Runs a write that must not be cut short: a Ctrl-C only takes effect once it is done
+pub fn deferred<T>(write: impl FnOnce() -> T) -> T {
+
+    WRITING.fetch_add(1, Ordering::SeqCst);
+    let value = write();
+    WRITING.fetch_sub(1, Ordering::SeqCst);
+
+    value
+}
+
+#[cfg(test)]
+pub fn simulate(interrupted: bool) {
+
+    SIMULATED.with(|simulated| simulated.set(interrupted));
+}
+
+/// The first Ctrl-C only sets the flag. A second one, when no write is under way,
+/// ends the process right away for a command stuck in a long read.
+fn on_interrupt() {
+
+    if INTERRUPTED.swap(true, Ordering::SeqCst) && WRITING.load(Ordering::SeqCst) == 0 {
+        platform::exit_now();
+    }
+}
+
+#[cfg(unix)]
+mod platform {
+    use std::ffi::c_int;
+
+    const SIGINT: c_int = 2;
+    const SIGTERM: c_int = 15;
+
+    extern "C" {
+        fn signal(signum: c_int, handler: extern "C" fn(c_int)) -> usize;
+        fn _exit(status: c_int) -> !;
+    }
+
+    extern "C" fn handle(_signum: c_int) {
+
+        super::on_interrupt();
+    }
+
+    pub fn install() {
+
+        // Only atomics are touched in the handler, which is safe to do in a signal handler
+        unsafe {
+            signal(SIGINT, handle);
+            signal(SIGTERM, handle);
+        }
+    }
+
+    pub fn exit_now() -> ! {
+
+        // Unlike process::exit, _exit is safe to call in a signal handler
+        unsafe { _exit(crate::error::EXIT_INTERRUPTED) }
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    extern "system" {
+        fn SetConsoleCtrlHandler(handler: extern "system" fn(u32) -> i32, add: i32) -> i32;
+    }
+
+    extern "system" fn handle(_event: u32) -> i32 {
+
+        super::on_interrupt();
+
+        // Handled, so that Windows does not end the process itself
+        1
+    }
+
+    pub fn install() {
+
+        unsafe {
+            SetConsoleCtrlHandler(handle, 1);
+        }
+    }
+
+    pub fn exit_now() -> ! {
+
+        std::process::exit(crate::error::EXIT_INTERRUPTED)
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+mod platform {
+    pub fn install() {}
+
+    pub fn exit_now() -> ! {
+
+        std::process::exit(crate::error::EXIT_INTERRUPTED)
+    }
+}
+
+/// Installs the Ctrl-C handler. Only the binary does this, the library leaves the
+/// signals of the programs using it alone.
+pub fn install() {
+
+    platform::install();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_and_simulate() {
+        assert!(check().is_ok());
+
+        simulate(true);
+        let error = check().unwrap_err();
+        simulate(false);
+
+        assert_eq!(crate::error::exit_code(&error), crate::error::EXIT_INTERRUPTED);
+        assert!(check().is_ok());
+    }
+
+    #[test]
+    fn test_deferred_write_finishes() {
+        let written = deferred(|| {
+            simulate(true);
+            "the whole file"
+        });
+        let after = check();
+        simulate(false);
+
+        assert_eq!(written, "the whole file");
+        assert!(after.is_err());
+        assert_eq!(WRITING.load(Ordering::SeqCst), 0);
+    }
+}
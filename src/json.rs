@@ -0,0 +1,358 @@
+use crate::{error::PngMeError, Result};
+
+/// Quotes a string as a JSON string literal, escaping what needs escaping
+pub fn string(value: &str) -> String {
+
+    let mut result = String::with_capacity(value.len() + 2);
+    result.push('"');
+
+    for character in value.chars() {
+        match character {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            character if (character as u32) < 0x20 => result.push_str(&format!("\\u{:04x}", character as u32)),
+            character => result.push(character),
+        }
+    }
+
+    result.push('"');
+    result
+}
+
+/// Joins already serialized values into a JSON array, one value per line
+pub fn array(values: &[String]) -> String {
+
+    if values.is_empty() {
+        return "[]".to_string()
+    }
+
+    format!("[\n  {}\n]", values.join(",\n  "))
+}
+
+/// A parsed JSON value. Objects keep their keys in file order.
+#[derive(Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    /// The value of `key` when this is an object holding it
+    pub fn get(&self, key: &str) -> Option<&Value> {
+
+        match self {
+            Value::Object(fields) => fields.iter().find(|(name, _)| name == key).map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+
+        match self {
+            Value::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// The number when it is a whole number that fits in a u64
+    pub fn as_u64(&self) -> Option<u64> {
+
+        match self {
+            Value::Number(number) if number.fract() == 0.0 && *number >= 0.0 && *number <= u64::MAX as f64 => Some(*number as u64),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+
+        match self {
+            Value::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+}
+
+/// Arrays and objects nest at most this deep, so that a crafted document cannot
+/// overflow the stack of the recursive parser
+pub const MAX_DEPTH: usize = 128;
+
+/// Parses a JSON document
+pub fn parse(text: &str) -> Result<Value> {
+
+    let mut parser = Parser { text: text.as_bytes(), position: 0, depth: 0 };
+    let value = parser.value()?;
+
+    parser.skip_whitespace();
+
+    if parser.position != text.len() {
+        return Err(parser.error("unexpected data after the JSON value"))
+    }
+
+    Ok(value)
+}
+
+struct Parser<'a> {
+    text: &'a [u8],
+    position: usize,
+    /// The arrays and objects the parser is inside of
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &str) -> crate::Error {
+
+        format!("Invalid JSON at byte {}: {}", self.position, message).into()
+    }
+
+    fn skip_whitespace(&mut self) {
+
+        while matches!(self.text.get(self.position), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+            self.position += 1;
+        }
+    }
+
+    fn expect(&mut self, literal: &str, value: Value) -> Result<Value> {
+
+        if !self.text[self.position..].starts_with(literal.as_bytes()) {
+            return Err(self.error(&format!("expected {}", literal)))
+        }
+
+        self.position += literal.len();
+
+        Ok(value)
+    }
+
+    fn value(&mut self) -> Result<Value> {
+
+        self.skip_whitespace();
+
+        match self.text.get(self.position) {
+            Some(b'{' | b'[') => self.nested(),
+            Some(b'"') => Ok(Value::String(self.string()?)),
+            Some(b't') => self.expect("true", Value::Bool(true)),
+            Some(b'f') => self.expect("false", Value::Bool(false)),
+            Some(b'n') => self.expect("null", Value::Null),
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            Some(_) => Err(self.error("unexpected character")),
+            None => Err(self.error("unexpected end of data")),
+        }
+    }
+
+    fn nested(&mut self) -> Result<Value> {
+
+        if self.depth == MAX_DEPTH {
+            return Err(self.error(&format!("arrays and objects nest deeper than {} levels", MAX_DEPTH)))
+        }
+
+        self.depth += 1;
+        let value = if self.text[self.position] == b'{' { self.object() } else { self.array() };
+        self.depth -= 1;
+
+        value
+    }
+
+    fn object(&mut self) -> Result<Value> {
+
+        self.position += 1;
+        let mut fields = vec![];
+
+        self.skip_whitespace();
+
+        if self.text.get(self.position) == Some(&b'}') {
+            self.position += 1;
+            return Ok(Value::Object(fields))
+        }
+
+        loop {
+            self.skip_whitespace();
+
+            if self.text.get(self.position) != Some(&b'"') {
+                return Err(self.error("expected a key"))
+            }
+
+            let key = self.string()?;
+
+            self.skip_whitespace();
+
+            if self.text.get(self.position) != Some(&b':') {
+                return Err(self.error("expected ':'"))
+            }
+
+            self.position += 1;
+            fields.push((key, self.value()?));
+
+            self.skip_whitespace();
+
+            match self.text.get(self.position) {
+                Some(b',') => self.position += 1,
+                Some(b'}') => {
+                    self.position += 1;
+                    return Ok(Value::Object(fields))
+                },
+                _ => return Err(self.error("expected ',' or '}'")),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Value> {
+
+        self.position += 1;
+        let mut values = vec![];
+
+        self.skip_whitespace();
+
+        if self.text.get(self.position) == Some(&b']') {
+            self.position += 1;
+            return Ok(Value::Array(values))
+        }
+
+        loop {
+            values.push(self.value()?);
+
+            self.skip_whitespace();
+
+            match self.text.get(self.position) {
+                Some(b',') => self.position += 1,
+                Some(b']') => {
+                    self.position += 1;
+                    return Ok(Value::Array(values))
+                },
+                _ => return Err(self.error("expected ',' or ']'")),
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<Value> {
+
+        let start = self.position;
+
+        while matches!(self.text.get(self.position), Some(b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9')) {
+            self.position += 1;
+        }
+
+        let number = std::str::from_utf8(&self.text[start..self.position]).map_err(PngMeError::from)?;
+
+        number.parse().map(Value::Number).map_err(|_| self.error("invalid number"))
+    }
+
+    fn hex_escape(&mut self) -> Result<u32> {
+
+        let digits = self.text.get(self.position..self.position + 4).ok_or_else(|| self.error("truncated \\u escape"))?;
+        let digits = std::str::from_utf8(digits).map_err(PngMeError::from)?;
+        let value = u32::from_str_radix(digits, 16).map_err(|_| self.error("invalid \\u escape"))?;
+
+        self.position += 4;
+
+        Ok(value)
+    }
+
+    fn string(&mut self) -> Result<String> {
+
+        self.position += 1;
+        let mut bytes = vec![];
+
+        loop {
+            let byte = *self.text.get(self.position).ok_or_else(|| self.error("unterminated string"))?;
+            self.position += 1;
+
+            match byte {
+                b'"' => break,
+                b'\\' => {
+                    let escape = *self.text.get(self.position).ok_or_else(|| self.error("unterminated string"))?;
+                    self.position += 1;
+
+                    let character = match escape {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => {
+                            let mut code = self.hex_escape()?;
+
+                            // Characters outside the basic plane are written as a surrogate pair
+                            if (0xd800..0xdc00).contains(&code) && self.text[self.position..].starts_with(b"\\u") {
+                                self.position += 2;
+                                let low = self.hex_escape()?;
+                                code = 0x10000 + ((code - 0xd800) << 10) + (low.wrapping_sub(0xdc00) & 0x3ff);
+                            }
+
+                            char::from_u32(code).ok_or_else(|| self.error("invalid \\u escape"))?
+                        },
+                        _ => return Err(self.error("unknown escape")),
+                    };
+
+                    bytes.extend(character.to_string().as_bytes());
+                },
+                byte => bytes.push(byte),
+            }
+        }
+
+        Ok(String::from_utf8(bytes).map_err(|error| PngMeError::from(error.utf8_error()))?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_string_escapes() {
+        assert_eq!(string("ruSt"), "\"ruSt\"");
+        assert_eq!(string("a \"b\"\\\n\u{1}"), "\"a \\\"b\\\"\\\\\\n\\u0001\"");
+    }
+
+    #[test]
+    fn test_parse() {
+        let value = parse(r#" {"version": 1, "chunks": [{"type": "ruSt", "ok": true}, null], "name": "a\"b\u00e9\ud83d\ude00"} "#).unwrap();
+
+        assert_eq!(value.get("version").and_then(Value::as_u64), Some(1));
+        assert_eq!(value.get("name").and_then(Value::as_str), Some("a\"b\u{e9}\u{1f600}"));
+
+        let chunks = value.get("chunks").and_then(Value::as_array).unwrap();
+        assert_eq!(chunks[0].get("type").and_then(Value::as_str), Some("ruSt"));
+        assert_eq!(chunks[0].get("ok"), Some(&Value::Bool(true)));
+        assert_eq!(chunks[1], Value::Null);
+    }
+
+    #[test]
+    fn test_parse_round_trips_string() {
+        let text = "tab\t \"quoted\" \u{1} \u{e9}";
+
+        assert_eq!(parse(&string(text)).unwrap(), Value::String(text.to_string()));
+    }
+
+    #[test]
+    fn test_parse_rejects_invalid_json() {
+        assert!(parse("{\"a\": 1,}").is_err());
+        assert!(parse("[1 2]").is_err());
+        assert!(parse("\"unterminated").is_err());
+        assert!(parse("1 2").is_err());
+        assert!(parse("").is_err());
+    }
+
+    #[test]
+    fn test_parse_limits_nesting() {
+        let nested = |depth: usize| format!("{}{}", "[".repeat(depth), "]".repeat(depth));
+
+        assert!(parse(&nested(MAX_DEPTH)).is_ok());
+        assert!(parse(&nested(MAX_DEPTH + 1)).unwrap_err().to_string().contains("nest deeper"));
+        assert!(parse(&"{\"a\":".repeat(100_000)).is_err());
+    }
+
+    #[test]
+    fn test_array() {
+        assert_eq!(array(&[]), "[]");
+        assert_eq!(array(&["1".to_string(), "2".to_string()]), "[\n  1,\n  2\n]");
+    }
+}
@@ -0,0 +1,179 @@
+use std::{io::Write, process::{Command, Output, Stdio}};
+
+use crate::{error::PngMeError, Result};
+
+/// Every passphrase is stored under this account name, with the service given to --use-keyring
+const ACCOUNT: &str = "pngme";
+
+/// The passphrase stored for `service`, or `None` when the keychain holds none. Uses
+/// `security` on macOS and `secret-tool` (Secret Service) elsewhere.
+pub fn get(service: &str) -> Result<Option<String>> {
+
+    let output = run(&lookup_command(service)?, None)?;
+
+    // Both tools fail with no output when there is no such item
+    if !output.status.success() {
+        return if output.stdout.is_empty() && not_found(&output) {
+            Ok(None)
+        } else {
+            Err(tool_error(&output).into())
+        }
+    }
+
+    let passphrase = String::from_utf8(output.stdout).map_err(|_| "The keychain item is not text")?;
+
+    Ok(Some(passphrase.trim_end_matches('\n').to_string()))
+}
+
+/// Stores the passphrase for `service`, replacing any stored before. It is handed to
+/// the tool on stdin, never on the command line where other users could see it.
+pub fn store(service: &str, passphrase: &str) -> Result<()> {
+
+    let (command, input) = store_command(service, passphrase)?;
+    let output = run(&command, Some(&input))?;
+
+    if output.status.success() { Ok(()) } else { Err(tool_error(&output).into()) }
+}
+
+/// Removes the passphrase stored for `service`, returning whether there was one
+pub fn forget(service: &str) -> Result<bool> {
+
+    // secret-tool clear succeeds whether or not there was an item, so look first
+    if get(service)?.is_none() {
+        return Ok(false)
+    }
+
+    let output = run(&clear_command(service)?, None)?;
+
+    if output.status.success() { Ok(true) } else { Err(tool_error(&output).into()) }
+}
+
+fn run(command: &[String], input: Option<&str>) -> Result<Output> {
+
+    let mut child = Command::new(&command[0])
+        .args(&command[1..])
+        .stdin(if input.is_some() { Stdio::piped() } else { Stdio::null() })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|error| format!("Cannot run {}: {}", command[0], PngMeError::from(error)))?;
+
+    if let (Some(input), Some(mut stdin)) = (input, child.stdin.take()) {
+        stdin.write_all(input.as_bytes()).map_err(PngMeError::from)?;
+    }
+
+    Ok(child.wait_with_output().map_err(PngMeError::from)?)
+}
+
+fn tool_error(output: &Output) -> String {
+
+    format!("The keychain tool failed: {}", String::from_utf8_lossy(&output.stderr).trim())
+}
+
+#[cfg(unix)]
+fn strings(parts: &[&str]) -> Vec<String> {
+
+    parts.iter().map(|part| part.to_string()).collect()
+}
+
+#[cfg(target_os = "macos")]
+fn not_found(output: &Output) -> bool {
+
+    // errSecItemNotFound
+    output.status.code() == Some(44)
+}
+
+#[cfg(target_os = "macos")]
+fn lookup_command(service: &str) -> Result<Vec<String>> {
+
+    Ok(strings(&["security", "find-generic-password", "-s", service, "-a", ACCOUNT, "-w"]))
+}
+
+#[cfg(target_os = "macos")]
+fn store_command(service: &str, passphrase: &str) -> Result<(Vec<String>, String)> {
+
+    // `security -i` reads the command from stdin, which keeps the passphrase out of `ps`
+    let input = format!("add-generic-password -U -s {} -a {} -w {}\n", quoted(service), ACCOUNT, quoted(passphrase));
+
+    Ok((strings(&["security", "-i"]), input))
+}
+
+#[cfg(target_os = "macos")]
+fn clear_command(service: &str) -> Result<Vec<String>> {
+
+    Ok(strings(&["security", "delete-generic-password", "-s", service, "-a", ACCOUNT]))
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+fn not_found(output: &Output) -> bool {
+
+    output.status.code() == Some(1) && output.stderr.is_empty()
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+fn lookup_command(service: &str) -> Result<Vec<String>> {
+
+    Ok(strings(&["secret-tool", "lookup", "service", service, "account", ACCOUNT]))
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+fn store_command(service: &str, passphrase: &str) -> Result<(Vec<String>, String)> {
+
+    let label = format!("pngme passphrase for {}", service);
+
+    Ok((strings(&["secret-tool", "store", "--label", &label, "service", service, "account", ACCOUNT]), passphrase.to_string()))
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+fn clear_command(service: &str) -> Result<Vec<String>> {
+
+    Ok(strings(&["secret-tool", "clear", "service", service, "account", ACCOUNT]))
+}
+
+#[cfg(not(unix))]
+fn not_found(_output: &Output) -> bool {
+
+    false
+}
+
+#[cfg(not(unix))]
+fn lookup_command(_service: &str) -> Result<Vec<String>> {
+
+    Err(unsupported())
+}
+
+#[cfg(not(unix))]
+fn store_command(_service: &str, _passphrase: &str) -> Result<(Vec<String>, String)> {
+
+    Err(unsupported())
+}
+
+#[cfg(not(unix))]
+fn clear_command(_service: &str) -> Result<Vec<String>> {
+
+    Err(unsupported())
+}
+
+#[cfg(not(unix))]
+fn unsupported() -> crate::Error {
+
+    "pngme has no keychain tool to use on this platform".into()
+}
+
+/// Quotes a word for `security -i`, which splits its input like a shell
+#[cfg(any(target_os = "macos", test))]
+fn quoted(word: &str) -> String {
+
+    format!("\"{}\"", word.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quoted() {
+        assert_eq!(quoted("plain"), "\"plain\"");
+        assert_eq!(quoted("say \"hi\" \\ bye"), "\"say \\\"hi\\\" \\\\ bye\"");
+    }
+}
@@ -0,0 +1,83 @@
+use std::str::FromStr;
+
+use crate::chunk_type::ChunkType;
+
+/// Every standard chunk type with what it holds, in the order of the specification
+pub const KNOWN_TYPES: [(&str, &str); 25] = [
+    ("IHDR", "Image header: width, height, bit depth, color type and interlacing"),
+    ("PLTE", "Palette of the colors of an indexed image"),
+    ("IDAT", "Compressed image data, split over several chunks"),
+    ("IEND", "Marks the end of the file"),
+    ("cHRM", "Chromaticities of the primaries and the white point"),
+    ("gAMA", "Gamma of the image"),
+    ("iCCP", "Embedded ICC color profile"),
+    ("sBIT", "Number of significant bits in the samples"),
+    ("sRGB", "The image uses the sRGB color space"),
+    ("cICP", "Coding-independent code points for the color space"),
+    ("mDCv", "Color volume of the mastering display"),
+    ("cLLi", "Content light level information"),
+    ("tEXt", "Latin-1 text with a keyword"),
+    ("zTXt", "Compressed Latin-1 text with a keyword"),
+    ("iTXt", "UTF-8 text with a keyword and a language tag, optionally compressed"),
+    ("bKGD", "Default background color"),
+    ("hIST", "Usage frequencies of the palette colors"),
+    ("tRNS", "Transparency without a full alpha channel"),
+    ("pHYs", "Physical pixel size or aspect ratio"),
+    ("sPLT", "Suggested palette"),
+    ("eXIf", "Exif metadata"),
+    ("tIME", "Time of the last modification of the image"),
+    ("acTL", "Animation control: number of frames and plays (APNG)"),
+    ("fcTL", "Frame control: size, position and delay of a frame (APNG)"),
+    ("fdAT", "Frame data of an animation frame after the first (APNG)"),
+];
+
+/// The description of a standard chunk type
+pub fn describe(chunk_type: &str) -> Option<&'static str> {
+
+    KNOWN_TYPES.iter().find(|(known, _)| *known == chunk_type).map(|(_, description)| *description)
+}
+
+/// The reference table printed by `pngme types`
+pub fn table() -> Vec<String> {
+
+    let mut lines = vec![format!("{:<4}  {:<9}  {:<7}  {:<12}  {}", "Type", "Critical", "Public", "Safe to copy", "Description")];
+
+    for (chunk_type, description) in KNOWN_TYPES {
+        let parsed = ChunkType::from_str(chunk_type).expect("The known chunk types are valid");
+        let flag = |set: bool| if set { "yes" } else { "no" };
+
+        lines.push(format!(
+            "{:<4}  {:<9}  {:<7}  {:<12}  {}",
+            chunk_type, flag(parsed.is_critical()), flag(parsed.is_public()), flag(parsed.is_safe_to_copy()), description
+        ));
+    }
+
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk_type::STANDARD_TYPES;
+
+    #[test]
+    fn test_table_flags() {
+        let table = table();
+        let row = |chunk_type: &str| table.iter().find(|line| line.starts_with(chunk_type)).unwrap().clone();
+
+        assert!(row("IHDR").starts_with("IHDR  yes        yes      no            Image header"));
+        assert!(row("IDAT").starts_with("IDAT  yes        yes      no"));
+        assert!(row("IEND").starts_with("IEND  yes        yes      no"));
+        assert!(row("tEXt").starts_with("tEXt  no         yes      yes"));
+        assert_eq!(table.len(), KNOWN_TYPES.len() + 1);
+    }
+
+    #[test]
+    fn test_covers_the_standard_types() {
+        let known: Vec<&str> = KNOWN_TYPES.iter().map(|(chunk_type, _)| *chunk_type).collect();
+
+        assert_eq!(known, STANDARD_TYPES);
+        assert_eq!(describe("gAMA"), Some("Gamma of the image"));
+        assert_eq!(describe("ruSt"), None);
+    }
+}
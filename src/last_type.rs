@@ -0,0 +1,69 @@
+use std::{fs, path::{Path, PathBuf}};
+
+use crate::{chunk_type::ChunkType, error::PngMeError, Result};
+
+/// Where the chunk type of the last encode is kept: `$XDG_STATE_HOME/pngme/last-type`,
+/// `~/.local/state/pngme/last-type` without it, and `%LOCALAPPDATA%\pngme\last-type`
+/// on Windows. `None` when there is no such directory to use.
+pub fn location() -> Option<PathBuf> {
+
+    // Tests must not change the state of whoever runs them
+    if cfg!(test) {
+        return None
+    }
+
+    let non_empty = |name: &str| std::env::var_os(name).filter(|value| !value.is_empty()).map(PathBuf::from);
+
+    let directory = if cfg!(windows) {
+        non_empty("LOCALAPPDATA")?
+    } else {
+        non_empty("XDG_STATE_HOME").or_else(|| non_empty("HOME").map(|home| home.join(".local").join("state")))?
+    };
+
+    Some(directory.join("pngme").join("last-type"))
+}
+
+/// The chunk type stored at `path`, or `None` when there is none or it is not a valid type
+pub fn read(path: &Path) -> Option<String> {
+
+    let stored = fs::read_to_string(path).ok()?;
+    let chunk_type = stored.trim();
+
+    ChunkType::parse_strict(chunk_type).ok()?;
+
+    Some(chunk_type.to_string())
+}
+
+/// Stores the chunk type at `path`, creating its directory when needed
+pub fn write(path: &Path, chunk_type: &str) -> Result<()> {
+
+    if let Some(directory) = path.parent() {
+        fs::create_dir_all(directory).map_err(PngMeError::from)?;
+    }
+
+    fs::write(path, format!("{}\n", chunk_type)).map_err(PngMeError::from)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_and_read() {
+        let directory = std::env::temp_dir().join(format!("pngme-test-{}-last-type", std::process::id()));
+        let path = directory.join("pngme").join("last-type");
+
+        assert_eq!(read(&path), None);
+
+        write(&path, "ruSt").unwrap();
+        assert_eq!(read(&path), Some("ruSt".to_string()));
+
+        fs::write(&path, "not a type").unwrap();
+        assert_eq!(read(&path), None);
+
+        fs::remove_dir_all(&directory).unwrap();
+        assert_eq!(location(), None);
+    }
+}
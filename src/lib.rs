@@ -0,0 +1,59 @@
+pub mod alias;
+pub mod args;
+pub mod base64;
+pub mod baseline;
+pub mod chaff;
+pub mod chunk;
+pub mod chunk_type;
+#[cfg(feature = "clipboard")]
+pub mod clipboard;
+pub mod commands;
+#[cfg(feature = "image")]
+pub mod convert;
+pub mod crypt;
+pub mod csv;
+pub mod data_uri;
+pub mod detect;
+pub mod editor;
+pub mod embed;
+pub mod error;
+pub mod exif;
+pub mod exiftool;
+pub mod filter;
+pub mod format;
+pub mod git_filter;
+pub mod hex;
+pub mod history;
+pub mod interrupt;
+pub mod ihdr;
+pub mod inspect;
+pub mod json;
+#[cfg(feature = "keyring")]
+pub mod keyring;
+pub mod known_types;
+pub mod last_type;
+pub mod list;
+pub mod lsb;
+pub mod manifest;
+pub mod metrics;
+pub mod optimize;
+pub mod ordering;
+pub mod png;
+pub mod policy;
+pub mod prompt;
+pub mod retry;
+pub mod scanline;
+pub mod sha256;
+pub mod shred;
+pub mod size;
+pub mod split;
+pub mod template;
+pub mod text;
+pub mod timestamp;
+pub mod undo;
+pub mod verify;
+pub mod zip;
+pub mod zlib;
+
+pub type Error = Box<dyn std::error::Error + Send + Sync>;
+pub type Result<T> = std::result::Result<T, Error>;
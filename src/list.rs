@@ -0,0 +1,445 @@
+use std::{cmp::Ordering, path::Path};
+
+use crc::Crc;
+
+use crate::{args::{ListOptions, OutputFormat, SortKey}, chunk::Chunk, csv, detect, exif, exiftool, filter, json, png::Png};
+
+/// Marks a chunk whose stored crc does not match its contents
+pub const CRC_MISMATCH_MARKER: &str = "⚠ crc mismatch";
+
+/// A chunk along with where it sits in the file
+#[derive(Debug, Clone, Copy)]
+pub struct ListedChunk<'a> {
+    pub index: usize,
+    pub offset: u64,
+    pub chunk: &'a Chunk,
+}
+
+/// Picks and orders the chunks to list. Every chunk keeps the index and offset it has in the file.
+pub fn view<'a>(png: &'a Png, options: &ListOptions) -> Vec<ListedChunk<'a>> {
+
+    let mut view: Vec<ListedChunk> = png.chunks()
+        .iter()
+        .zip(png.byte_offsets())
+        .enumerate()
+        .map(|(index, (chunk, offset))| ListedChunk { index, offset, chunk })
+        .filter(|listed| filter::matches_any(&options.filters, listed.chunk))
+        .filter(|listed| options.min_data.is_none_or(|min| listed.chunk.length() >= min))
+        .filter(|listed| options.max_data.is_none_or(|max| listed.chunk.length() <= max))
+        .collect();
+
+    if let Some(top) = options.top {
+        view.sort_by(|a, b| compare(SortKey::Size, a, b));
+        view.truncate(top);
+
+        // Back to file order, so that the sort below breaks ties the same way with or without --top
+        view.sort_by_key(|listed| listed.index);
+    }
+
+    let key = options.sort
+        .or(options.top.map(|_| SortKey::Size))
+        .unwrap_or(SortKey::Offset);
+
+    // Sorting is stable, equal chunks stay in file order even when reversed
+    view.sort_by(|a, b| {
+        let ordering = compare(key, a, b);
+
+        if options.reverse { ordering.reverse() } else { ordering }
+    });
+
+    view
+}
+
+fn compare(key: SortKey, a: &ListedChunk, b: &ListedChunk) -> Ordering {
+
+    match key {
+        SortKey::Size => b.chunk.length().cmp(&a.chunk.length()),
+        SortKey::Type => a.chunk.chunk_type().to_string().cmp(&b.chunk.chunk_type().to_string()),
+        SortKey::Offset => a.index.cmp(&b.index),
+    }
+}
+
+/// Renders the listing in the format asked for, ending with a line break. The
+/// exiftool format describes the whole file, so it also takes its path and header.
+pub fn render(source_file: &Path, png: &Png, view: &[ListedChunk], options: &ListOptions, crc: &Crc<u32>) -> String {
+
+    match options.output_format() {
+        OutputFormat::Table => table(view, options, crc).join("\n") + "\n",
+        OutputFormat::Json => to_json(view, options, crc) + "\n",
+        OutputFormat::Csv => to_csv(view, crc),
+        OutputFormat::Exiftool => exiftool::to_json(&source_file.to_string_lossy(), png, view) + "\n",
+    }
+}
+
+/// Renders up to `width` bytes as printable ASCII, with a dot for every other byte.
+/// The result is always exactly `width` characters wide.
+pub fn preview(data: &[u8], width: usize) -> String {
+
+    let mut preview: String = data.iter()
+        .take(width)
+        .map(|byte| if (0x20..0x7f).contains(byte) { *byte as char } else { '.' })
+        .collect();
+
+    while preview.len() < width {
+        preview.push(' ');
+    }
+
+    preview
+}
+
+/// The preview of a chunk, or nothing for image data unless it was asked for
+fn chunk_preview(chunk: &Chunk, options: &ListOptions) -> Option<String> {
+
+    let width = options.preview?;
+
+    if chunk.chunk_type().to_string() == "IDAT" && !options.include_idat {
+        return None
+    }
+
+    Some(preview(chunk.data(), width))
+}
+
+pub fn table(view: &[ListedChunk], options: &ListOptions, crc: &Crc<u32>) -> Vec<String> {
+
+    let mut lines = vec![];
+
+    let mut header = if options.compare_crc {
+        format!("{:>5}  {:<4}  {:>10}  {:<10}  {:<10}", "Index", "Type", "Length", "Stored", "Computed")
+    } else {
+        format!("{:>5}  {:<4}  {:>10}  {:<10}", "Index", "Type", "Length", "Crc")
+    };
+
+    if options.entropy {
+        header.push_str(&format!("  {:>7}", "Entropy"));
+    }
+
+    if let Some(width) = options.preview {
+        header.push_str(&format!("  {:<width$}", "Preview", width = width));
+    }
+
+    lines.push(header.trim_end().to_string());
+
+    for ListedChunk { index, chunk, .. } in view {
+
+        let mut line = format!("{:>5}  {:<4}  {:>10}  {:#010x}", index, chunk.chunk_type(), chunk.length(), chunk.crc());
+
+        if options.compare_crc {
+            line.push_str(&format!("  {:#010x}", chunk.computed_crc_with(crc)));
+        }
+
+        if options.entropy {
+            line.push_str(&format!("  {:>7.2}", detect::entropy(chunk.data())));
+        }
+
+        if let Some(width) = options.preview {
+            let preview = chunk_preview(chunk, options).unwrap_or_else(|| " ".repeat(width));
+            line.push_str(&format!("  {}", preview));
+        }
+
+        if options.compare_crc && chunk.crc() != chunk.computed_crc_with(crc) {
+            line.push_str(&format!("  {}", CRC_MISMATCH_MARKER));
+        }
+
+        if chunk.chunk_type().to_string() == "eXIf" {
+            line.push_str(&format!("  exif: {}", exif::describe(chunk.data())));
+        }
+
+        lines.push(line.trim_end().to_string());
+    }
+
+    lines
+}
+
+pub fn to_json(view: &[ListedChunk], options: &ListOptions, crc: &Crc<u32>) -> String {
+
+    let objects: Vec<String> = view.iter()
+        .map(|ListedChunk { index, offset, chunk }| {
+            let mut fields = vec![
+                format!("\"index\": {}", index),
+                format!("\"offset\": {}", offset),
+                format!("\"type\": {}", json::string(&chunk.chunk_type().to_string())),
+                format!("\"length\": {}", chunk.length()),
+                format!("\"crc\": {}", chunk.crc()),
+            ];
+
+            if options.compare_crc {
+                fields.push(format!("\"computed_crc\": {}", chunk.computed_crc_with(crc)));
+                fields.push(format!("\"crc_ok\": {}", chunk.crc() == chunk.computed_crc_with(crc)));
+            }
+
+            if options.entropy {
+                fields.push(format!("\"entropy\": {:.3}", detect::entropy(chunk.data())));
+            }
+
+            if options.preview.is_some() {
+                let preview = chunk_preview(chunk, options)
+                    .map(|preview| json::string(preview.trim_end()))
+                    .unwrap_or_else(|| "null".to_string());
+
+                fields.push(format!("\"preview\": {}", preview));
+            }
+
+            format!("{{{}}}", fields.join(", "))
+        })
+        .collect();
+
+    json::array(&objects)
+}
+
+/// Every chunk type in the view, in order of first appearance, with the bytes its chunks take
+pub fn sizes(png: &Png, view: &[ListedChunk]) -> Vec<(String, usize, usize)> {
+
+    let mut sizes: Vec<(String, usize, usize)> = vec![];
+
+    for listed in view {
+        let chunk_type = listed.chunk.chunk_type().to_string();
+
+        if !sizes.iter().any(|(existing, _, _)| *existing == chunk_type) {
+            let count = png.chunks().iter().filter(|chunk| chunk.chunk_type().to_string() == chunk_type).count();
+            let size = png.size_of_type(&chunk_type);
+
+            sizes.push((chunk_type, count, size));
+        }
+    }
+
+    sizes
+}
+
+pub fn sizes_table(sizes: &[(String, usize, usize)]) -> Vec<String> {
+
+    let mut lines = vec![format!("{:<4}  {:>6}  {:>10}", "Type", "Chunks", "Bytes")];
+
+    for (chunk_type, count, size) in sizes {
+        lines.push(format!("{:<4}  {:>6}  {:>10}", chunk_type, count, size));
+    }
+
+    lines.push(format!("{:<4}  {:>6}  {:>10}", "All", sizes.iter().map(|(_, count, _)| count).sum::<usize>(), sizes.iter().map(|(_, _, size)| size).sum::<usize>()));
+
+    lines
+}
+
+pub const CSV_HEADER: [&str; 11] = [
+    "index", "offset", "type", "length", "crc_hex", "critical", "public",
+    "reserved_valid", "safe_to_copy", "standard", "crc_ok",
+];
+
+pub fn to_csv(view: &[ListedChunk], crc: &Crc<u32>) -> String {
+
+    let mut rows = vec![csv::row(&CSV_HEADER)];
+
+    for ListedChunk { index, offset, chunk } in view {
+        let chunk_type = chunk.chunk_type();
+
+        rows.push(csv::row(&[
+            index.to_string(),
+            offset.to_string(),
+            chunk_type.to_string(),
+            chunk.length().to_string(),
+            format!("{:08x}", chunk.crc()),
+            chunk_type.is_critical().to_string(),
+            chunk_type.is_public().to_string(),
+            chunk_type.is_reserved_bit_valid().to_string(),
+            chunk_type.is_safe_to_copy().to_string(),
+            chunk_type.is_standard().to_string(),
+            (chunk.crc() == chunk.computed_crc_with(crc)).to_string(),
+        ]));
+    }
+
+    rows.concat()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{chunk_type::ChunkType, filter::ChunkFilter};
+    use std::str::FromStr;
+
+    const PNG_CRC: Crc<u32> = Crc::<u32>::new(&crc::CRC_32_ISO_HDLC);
+
+    fn chunk(chunk_type: &str, data: &str) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.as_bytes().to_vec())
+    }
+
+    fn testing_png() -> Png {
+        Png::from_chunks(vec![
+            chunk("IHDR", "0123456789abc"),
+            chunk("teSt", "four"),
+            chunk("IDAT", "a much larger chunk of image data"),
+            chunk("ruSt", "same"),
+            chunk("tEXt", "also"),
+            chunk("IEND", ""),
+        ])
+    }
+
+    fn indices(view: &[ListedChunk]) -> Vec<usize> {
+        view.iter().map(|listed| listed.index).collect()
+    }
+
+    #[test]
+    fn test_compare_crc_flags_mismatch() {
+        let mut bytes = testing_png().as_bytes();
+
+        // Corrupt the crc of the second chunk
+        let second_crc_end = 8 + 12 + 13 + 12 + 4;
+        bytes[second_crc_end - 1] ^= 0xff;
+
+        let png = Png::try_from_unverified(bytes.as_ref()).unwrap();
+        let options = ListOptions { compare_crc: true, ..Default::default() };
+        let lines = table(&view(&png, &options), &options, &PNG_CRC);
+
+        assert_eq!(lines.len(), 7);
+        assert!(!lines[1].contains(CRC_MISMATCH_MARKER));
+        assert!(lines[2].contains("teSt") && lines[2].contains(CRC_MISMATCH_MARKER));
+        assert!(lines[3..].iter().all(|line| !line.contains(CRC_MISMATCH_MARKER)));
+    }
+
+    #[test]
+    fn test_exif_is_summarized() {
+        let mut png = testing_png();
+        png.insert_chunk(1, chunk("eXIf", "corrupt")).unwrap();
+
+        let options = ListOptions::default();
+        let lines = table(&view(&png, &options), &options, &PNG_CRC);
+
+        assert!(lines[2].ends_with("exif: present, 7 bytes, unparseable"));
+    }
+
+    #[test]
+    fn test_filters_match_any() {
+        let png = testing_png();
+        let options = ListOptions {
+            filters: vec![ChunkFilter::Private, ChunkFilter::Type("IEND".to_string())],
+            ..Default::default()
+        };
+
+        assert_eq!(indices(&view(&png, &options)), vec![1, 3, 5]);
+    }
+
+    #[test]
+    fn test_data_size_bounds_are_inclusive() {
+        let png = testing_png();
+
+        let options = ListOptions { min_data: Some(4), ..Default::default() };
+        assert_eq!(indices(&view(&png, &options)), vec![0, 1, 2, 3, 4]);
+
+        let options = ListOptions { max_data: Some(4), ..Default::default() };
+        assert_eq!(indices(&view(&png, &options)), vec![1, 3, 4, 5]);
+
+        let options = ListOptions { min_data: Some(4), max_data: Some(13), ..Default::default() };
+        assert_eq!(indices(&view(&png, &options)), vec![0, 1, 3, 4]);
+
+        let options = ListOptions { min_data: Some(4), max_data: Some(4), filters: vec![ChunkFilter::Private], ..Default::default() };
+        assert_eq!(indices(&view(&png, &options)), vec![1, 3]);
+
+        let options = ListOptions { min_data: Some(5), max_data: Some(12), ..Default::default() };
+        assert!(view(&png, &options).is_empty());
+    }
+
+    #[test]
+    fn test_sort_by_size_is_stable() {
+        let png = testing_png();
+
+        let options = ListOptions { sort: Some(SortKey::Size), ..Default::default() };
+        assert_eq!(indices(&view(&png, &options)), vec![2, 0, 1, 3, 4, 5]);
+
+        let options = ListOptions { sort: Some(SortKey::Size), reverse: true, ..Default::default() };
+        assert_eq!(indices(&view(&png, &options)), vec![5, 1, 3, 4, 0, 2]);
+    }
+
+    #[test]
+    fn test_sort_by_type_and_offset() {
+        let png = testing_png();
+
+        let options = ListOptions { sort: Some(SortKey::Type), ..Default::default() };
+        assert_eq!(indices(&view(&png, &options)), vec![2, 5, 0, 3, 4, 1]);
+
+        let options = ListOptions { sort: Some(SortKey::Offset), reverse: true, ..Default::default() };
+        assert_eq!(indices(&view(&png, &options)), vec![5, 4, 3, 2, 1, 0]);
+    }
+
+    #[test]
+    fn test_top() {
+        let png = testing_png();
+
+        let options = ListOptions { top: Some(3), ..Default::default() };
+        assert_eq!(indices(&view(&png, &options)), vec![2, 0, 1]);
+
+        let options = ListOptions { top: Some(3), sort: Some(SortKey::Offset), ..Default::default() };
+        assert_eq!(indices(&view(&png, &options)), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn test_preview_masks_and_pads() {
+        assert_eq!(preview(b"ab\x00\xffcd", 8), "ab..cd  ");
+        assert_eq!(preview(&[0xe2, 0x9a, 0xa0, b'x'], 2), "..");
+        assert_eq!(preview(b"", 3), "   ");
+    }
+
+    #[test]
+    fn test_preview_column_is_aligned() {
+        let png = testing_png();
+        let options = ListOptions { preview: Some(8), compare_crc: true, ..Default::default() };
+        let lines = table(&view(&png, &options), &options, &PNG_CRC);
+
+        assert!(lines[1].ends_with("01234567"));
+        assert!(lines[2].ends_with("four"));
+        assert!(!lines[3].contains("a much"));
+        assert_eq!(lines[1].len(), lines[2].len() + 4);
+    }
+
+    #[test]
+    fn test_preview_includes_idat_on_request() {
+        let png = testing_png();
+        let options = ListOptions { preview: Some(6), include_idat: true, ..Default::default() };
+        let lines = table(&view(&png, &options), &options, &PNG_CRC);
+
+        assert!(lines[3].ends_with("a much"));
+    }
+
+    #[test]
+    fn test_entropy_column() {
+        let mut png = testing_png();
+        png.insert_chunk(1, Chunk::new(ChunkType::from_str("heXx").unwrap(), (0..=255).collect())).unwrap();
+
+        let options = ListOptions { entropy: true, ..Default::default() };
+        let lines = table(&view(&png, &options), &options, &PNG_CRC);
+
+        assert!(lines[0].ends_with("Crc         Entropy"));
+        assert!(lines[2].ends_with("   8.00"));
+        assert!(lines[7].ends_with("   0.00"));
+        assert!(to_json(&view(&png, &options), &options, &PNG_CRC).contains("\"entropy\": 8.000"));
+    }
+
+    #[test]
+    fn test_json_reflects_view() {
+        let png = testing_png();
+        let options = ListOptions { filters: vec![ChunkFilter::Private], reverse: true, json: true, ..Default::default() };
+        let json = to_json(&view(&png, &options), &options, &PNG_CRC);
+
+        assert_eq!(json.matches("\"index\"").count(), 2);
+        assert!(json.find("ruSt").unwrap() < json.find("teSt").unwrap());
+    }
+
+    #[test]
+    fn test_sizes_per_type() {
+        let mut png = testing_png();
+        png.append_chunk(chunk("teSt", "again"));
+
+        let options = ListOptions { filters: vec![ChunkFilter::Private], sizes: true, ..Default::default() };
+        let sizes = sizes(&png, &view(&png, &options));
+
+        assert_eq!(sizes, vec![("teSt".to_string(), 2, 12 + 4 + 12 + 5), ("ruSt".to_string(), 1, 12 + 4)]);
+        assert_eq!(sizes_table(&sizes).last().unwrap(), &format!("{:<4}  {:>6}  {:>10}", "All", 3, 49));
+    }
+
+    #[test]
+    fn test_csv_has_a_row_per_chunk() {
+        let png = testing_png();
+        let options = ListOptions { format: OutputFormat::Csv, ..Default::default() };
+        let rows = csv::parse(&render(Path::new("a.png"), &png, &view(&png, &options), &options, &PNG_CRC)).unwrap();
+
+        assert_eq!(rows.len(), png.chunks().len() + 1);
+        assert_eq!(rows[0], CSV_HEADER);
+        assert_eq!(rows[2], ["1", "33", "teSt", "4", &format!("{:08x}", png.chunks()[1].crc()), "false", "false", "true", "true", "false", "true"]);
+    }
+}
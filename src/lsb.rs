@@ -0,0 +1,315 @@
+use std::str::FromStr;
+
+use crc::Crc;
+
+use crate::{
+    chunk::Chunk, chunk_type::ChunkType, error::PngMeError, ihdr::{ImageHeader, COLOR_PALETTE, COLOR_RGB, COLOR_RGBA},
+    png::Png, scanline, zlib, Result,
+};
+
+/// The payload is stored after its length, as a big endian u32
+pub const LENGTH_PREFIX: usize = 4;
+
+/// The color samples of every pixel that hide data; alpha is never changed
+pub const COLOR_SAMPLES: usize = 3;
+
+/// The number of payload bytes the image can hide using the `bits` low bits
+/// of every color sample, after the length prefix
+pub fn capacity(header: &ImageHeader, bits: u8) -> usize {
+
+    hidden_bytes(header, bits).saturating_sub(LENGTH_PREFIX)
+}
+
+/// The number of bytes the color samples can hide, length prefix included. Every
+/// pixel of an interlaced image sits in exactly one Adam7 pass, so the passes add
+/// up to the pixels of the image.
+pub fn hidden_bytes(header: &ImageHeader, bits: u8) -> usize {
+
+    // Saturating, so that the width and height of a huge image cannot wrap around on a 32-bit target.
+    // An image whose scanlines cannot be counted cannot be read either, so it hides nothing.
+    let pixels = header.sub_images().unwrap_or_default().iter()
+        .map(|sub_image| sub_image.width.saturating_mul(sub_image.height))
+        .fold(0usize, usize::saturating_add);
+
+    pixels.saturating_mul(COLOR_SAMPLES * bits as usize) / 8
+}
+
+/// Hides the payload in the `bits` least significant bits of the color samples
+/// and rebuilds the image data as a single IDAT chunk. No chunk is added. The
+/// samples of an interlaced image are taken pass after pass, in the order they are stored.
+pub fn embed(png: &mut Png, payload: &[u8], bits: u8, crc: &Crc<u32>) -> Result<()> {
+
+    let header = supported_header(png)?;
+    let capacity = capacity(&header, bits);
+
+    if payload.len() > capacity {
+        return Err(format!("The message is {} bytes long but this image can only hide {} bytes with {} bits per sample", payload.len(), capacity, bits).into())
+    }
+
+    let mut pixels = read_pixels(png, &header)?;
+
+    let length = u32::try_from(payload.len()).map_err(|_| "The message is longer than the 4 GB a hidden length can count")?;
+
+    let stream: Vec<u8> = length.to_be_bytes()
+        .into_iter()
+        .chain(payload.iter().copied())
+        .flat_map(|byte| (0..8).rev().map(move |bit| (byte >> bit) & 1))
+        .collect();
+
+    let mask = (1 << bits) - 1;
+
+    for (index, group) in color_samples(&header).zip(stream.chunks(bits as usize)) {
+        // The last group can be short, its bits still go to the top of the low bits
+        let value = group.iter().fold(0, |value, bit| value << 1 | bit) << (bits as usize - group.len());
+
+        pixels[index] = pixels[index] & !mask | value;
+    }
+
+    write_pixels(png, &header, &pixels, crc)
+}
+
+/// Reads back a payload hidden by `embed` with the same number of bits
+pub fn extract(png: &Png, bits: u8) -> Result<Vec<u8>> {
+
+    let header = supported_header(png)?;
+    let pixels = read_pixels(png, &header)?;
+
+    let mut bytes = color_samples(&header)
+        .flat_map(|index| {
+            let sample = pixels[index];
+
+            (0..bits).rev().map(move |bit| (sample >> bit) & 1)
+        })
+        .collect::<Vec<u8>>()
+        .chunks_exact(8)
+        .map(|bits| bits.iter().fold(0, |byte, bit| byte << 1 | bit))
+        .collect::<Vec<u8>>();
+
+    let length = u32::from_be_bytes(bytes.get(..LENGTH_PREFIX).ok_or("The image is too small to hide a message")?.try_into()?) as usize;
+
+    if length > capacity(&header, bits) {
+        return Err(PngMeError::NotFound("No message hidden in the pixels of this image".to_string()).into())
+    }
+
+    bytes.truncate(LENGTH_PREFIX + length);
+
+    Ok(bytes.split_off(LENGTH_PREFIX))
+}
+
+/// Reads the image header, failing with the reason when the lsb mode cannot use the image
+pub fn supported_header(png: &Png) -> Result<ImageHeader> {
+
+    let header = ImageHeader::from_png(png)?;
+
+    let unsupported = if header.color_type == COLOR_PALETTE {
+        Some("palette images".to_string())
+    } else if header.color_type != COLOR_RGB && header.color_type != COLOR_RGBA {
+        Some(format!("{} images", header.color_type_name()))
+    } else if header.bit_depth != 8 {
+        Some(format!("{}-bit images", header.bit_depth))
+    } else {
+        None
+    };
+
+    match unsupported {
+        Some(unsupported) => Err(format!("The lsb mode does not support {}, only 8-bit RGB and RGBA images", unsupported).into()),
+        None => Ok(header),
+    }
+}
+
+/// The indices of the red, green and blue samples in the raw image bytes, skipping alpha.
+/// The bytes of the Adam7 passes hold whole pixels, so this holds for them as well.
+fn color_samples(header: &ImageHeader) -> impl Iterator<Item = usize> {
+
+    let channels = header.channels();
+    let samples = header.width as usize * header.height as usize * channels;
+
+    (0..samples).filter(move |index| index % channels < COLOR_SAMPLES)
+}
+
+/// The unfiltered bytes of the image, or of its Adam7 passes one after the other
+fn read_pixels(png: &Png, header: &ImageHeader) -> Result<Vec<u8>> {
+
+    let compressed: Vec<u8> = png.chunks()
+        .iter()
+        .filter(|chunk| chunk.chunk_type().to_string() == "IDAT")
+        .flat_map(|chunk| chunk.data().iter().copied())
+        .collect();
+
+    if compressed.is_empty() {
+        return Err("The image has no IDAT chunk".into())
+    }
+
+    let expected = header.data_length()?;
+    let data = zlib::decompress(&compressed, expected)?;
+
+    if data.len() != expected {
+        return Err(format!("The image data holds {} bytes instead of {}", data.len(), expected).into())
+    }
+
+    let mut pixels = Vec::with_capacity(expected);
+    let mut rest = data.as_slice();
+
+    for sub_image in header.sub_images()? {
+        let (filtered, next) = rest.split_at((sub_image.row_length + 1) * sub_image.height);
+
+        pixels.extend(scanline::unfilter(filtered, sub_image.row_length, header.bytes_per_pixel())?);
+        rest = next;
+    }
+
+    Ok(pixels)
+}
+
+/// Replaces the IDAT chunks with a single one holding the pixels, where the first one was
+fn write_pixels(png: &mut Png, header: &ImageHeader, pixels: &[u8], crc: &Crc<u32>) -> Result<()> {
+
+    let position = png.position_of_type("IDAT").ok_or("The image has no IDAT chunk")?;
+
+    let mut filtered = Vec::with_capacity(header.data_length()?);
+    let mut rest = pixels;
+
+    for sub_image in header.sub_images()? {
+        let (sub_pixels, next) = rest.split_at(sub_image.row_length * sub_image.height);
+
+        filtered.extend(scanline::filter_none(sub_pixels, sub_image.row_length));
+        rest = next;
+    }
+
+    let data = zlib::compress(&filtered);
+
+    png.retain_chunks(|chunk| chunk.chunk_type().to_string() != "IDAT");
+    png.insert_chunk(position, Chunk::new_with_crc(ChunkType::from_str("IDAT")?, data, crc))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PNG_CRC: Crc<u32> = Crc::<u32>::new(&crc::CRC_32_ISO_HDLC);
+
+    fn chunk(chunk_type: &str, data: Vec<u8>) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data)
+    }
+
+    /// A 16x16 image whose scanlines use the Sub filter, with its IDAT split in two
+    fn testing_image(color_type: u8, bit_depth: u8, interlace: u8) -> Png {
+        sized_image(16, 16, color_type, bit_depth, interlace)
+    }
+
+    /// An image whose scanlines, in every Adam7 pass when interlaced, use the Sub filter
+    fn sized_image(width: u8, height: u8, color_type: u8, bit_depth: u8, interlace: u8) -> Png {
+        let header_chunk = chunk("IHDR", vec![0, 0, 0, width, 0, 0, 0, height, bit_depth, color_type, 0, 0, interlace]);
+        let image = ImageHeader::try_from(header_chunk.data()).unwrap();
+
+        let filtered: Vec<u8> = image.sub_images().unwrap().iter()
+            .flat_map(|sub_image| (0..sub_image.height).flat_map(move |row| {
+                std::iter::once(1).chain((0..sub_image.row_length).map(move |column| (row * 7 + column) as u8))
+            }))
+            .collect();
+        let compressed = zlib::compress(&filtered);
+        let (first, second) = compressed.split_at(compressed.len() / 2);
+
+        Png::from_chunks(vec![
+            header_chunk,
+            chunk("IDAT", first.to_vec()),
+            chunk("IDAT", second.to_vec()),
+            chunk("IEND", vec![]),
+        ])
+    }
+
+    #[test]
+    fn test_embed_and_extract() {
+        let mut png = testing_image(COLOR_RGBA, 8, 0);
+        let original = read_pixels(&png, &ImageHeader::from_png(&png).unwrap()).unwrap();
+
+        embed(&mut png, b"hidden in the pixels", 1, &PNG_CRC).unwrap();
+
+        let types: Vec<String> = png.chunks().iter().map(|chunk| chunk.chunk_type().to_string()).collect();
+        assert_eq!(types, ["IHDR", "IDAT", "IEND"]);
+
+        // The image data still inflates and every sample is at most one off
+        let png = Png::try_from(png.as_bytes().as_slice()).unwrap();
+        let changed = read_pixels(&png, &ImageHeader::from_png(&png).unwrap()).unwrap();
+        assert_eq!(changed.len(), original.len());
+        assert!(changed.iter().zip(&original).all(|(changed, original)| changed.abs_diff(*original) <= 1));
+        assert!(changed.iter().zip(&original).skip(3).step_by(4).all(|(changed, original)| changed == original));
+
+        assert_eq!(extract(&png, 1).unwrap(), b"hidden in the pixels");
+    }
+
+    #[test]
+    fn test_embed_with_two_bits() {
+        let mut png = testing_image(COLOR_RGBA, 8, 0);
+        let original = read_pixels(&png, &ImageHeader::from_png(&png).unwrap()).unwrap();
+
+        // 16x16 pixels with 3 color samples of 2 bits hide 192 bytes, 4 of them the length
+        let payload: Vec<u8> = (0..188).map(|value| value as u8).collect();
+        embed(&mut png, &payload, 2, &PNG_CRC).unwrap();
+
+        let changed = read_pixels(&png, &ImageHeader::from_png(&png).unwrap()).unwrap();
+        assert!(changed.iter().zip(&original).all(|(changed, original)| changed & !3 == original & !3));
+
+        assert_eq!(extract(&png, 2).unwrap(), payload);
+        assert!(embed(&mut png, &[0; 189], 2, &PNG_CRC).is_err());
+    }
+
+    #[test]
+    fn test_embed_rgb_round_trip() {
+        let mut png = testing_image(COLOR_RGB, 8, 0);
+        let payload = vec![0xa5; capacity(&ImageHeader::from_png(&png).unwrap(), 1)];
+
+        embed(&mut png, &payload, 1, &PNG_CRC).unwrap();
+        assert_eq!(extract(&png, 1).unwrap(), payload);
+
+        assert!(embed(&mut png, &[0; 93], 1, &PNG_CRC).unwrap_err().to_string().contains("can only hide 92 bytes"));
+    }
+
+    #[test]
+    fn test_embed_interlaced_round_trip() {
+        for (width, height, color_type) in [(16, 16, COLOR_RGBA), (13, 7, COLOR_RGB), (5, 3, COLOR_RGB)] {
+            let mut png = sized_image(width, height, color_type, 8, 1);
+            let header = ImageHeader::from_png(&png).unwrap();
+            let original = read_pixels(&png, &header).unwrap();
+
+            let payload: Vec<u8> = (0..capacity(&header, 2)).map(|value| value as u8 ^ 0x5a).collect();
+            embed(&mut png, &payload, 2, &PNG_CRC).unwrap();
+
+            // Still an interlaced image whose data inflates to the whole of every pass
+            let png = Png::try_from(png.as_bytes().as_slice()).unwrap();
+            assert_eq!(ImageHeader::from_png(&png).unwrap().interlace_method, 1);
+            assert_eq!(crate::verify::check_image_data(&png).unwrap(), crate::verify::ImageData::Interlaced { length: header.data_length().unwrap() });
+
+            let changed = read_pixels(&png, &header).unwrap();
+            assert!(changed.iter().zip(&original).all(|(changed, original)| changed & !3 == original & !3));
+            assert_eq!(extract(&png, 2).unwrap(), payload);
+        }
+    }
+
+    #[test]
+    fn test_capacity_of_tiny_interlaced_images() {
+        // 5x3 leaves the third Adam7 pass empty; its 15 pixels hide 11 bytes with 2 bits
+        let header = ImageHeader::from_png(&sized_image(5, 3, COLOR_RGB, 8, 1)).unwrap();
+        assert_eq!(hidden_bytes(&header, 2), 11);
+        assert_eq!(capacity(&header, 2), 7);
+
+        let mut png = sized_image(1, 1, COLOR_RGB, 8, 1);
+        assert_eq!(capacity(&ImageHeader::from_png(&png).unwrap(), 2), 0);
+        assert!(embed(&mut png, b"x", 2, &PNG_CRC).unwrap_err().to_string().contains("can only hide 0 bytes"));
+    }
+
+    #[test]
+    fn test_unsupported_images() {
+        for (color_type, bit_depth, interlace, expected) in [
+            (COLOR_PALETTE, 8, 0, "palette images"),
+            (COLOR_RGB, 16, 0, "16-bit images"),
+            (COLOR_RGB, 16, 1, "16-bit images"),
+            (0, 8, 0, "grayscale images"),
+        ] {
+            let mut png = testing_image(color_type, bit_depth, interlace);
+
+            let error = embed(&mut png, b"message", 1, &PNG_CRC).unwrap_err();
+            assert!(error.to_string().contains(expected), "{}", error);
+            assert!(extract(&png, 1).is_err());
+        }
+    }
+}
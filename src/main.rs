@@ -1,24 +1,16 @@
 use std::process;
 
 use clap::Parser;
-use commands::run;
-
-mod args;
-mod chunk;
-mod chunk_type;
-mod commands;
-mod png;
-
-pub type Error = Box<dyn std::error::Error>;
-pub type Result<T> = std::result::Result<T, Error>;
+use pngme::{args, commands::run, error, interrupt, Result};
 
 fn main() -> Result<()> {
     let args = args::Cli::parse();
+    interrupt::install();
 
     if let Err(error) = run(&args) {
         eprintln!("An error occurred: {}", error);
-        process::exit(1);
+        process::exit(error::exit_code(&error));
     }
 
     Ok(())
-}
\ No newline at end of file
+}
@@ -0,0 +1,426 @@
+use std::{collections::BTreeMap, str::FromStr};
+
+use crc::Crc;
+
+use crate::{chunk::Chunk, chunk_type::ChunkType, filter::{ChunkFilter, Selection}, png::Png, text, Result};
+
+/// One change to make to the PNG file
+#[derive(Debug, PartialEq)]
+pub enum Operation {
+    /// Appends a chunk holding the message
+    Encode { chunk_type: ChunkType, message: String },
+    /// Removes the first chunk of the type
+    Remove { chunk_type: String },
+    /// Replaces the tEXt chunk with this keyword, or appends one
+    TextSet { keyword: String, text: String },
+    /// Removes every ancillary chunk matching any of the filters
+    Strip { filters: Vec<ChunkFilter> },
+    /// Gives every chunk of one type another type
+    Rename { from: String, to: ChunkType },
+}
+
+/// An operation along with the manifest line it starts on
+#[derive(Debug, PartialEq)]
+pub struct Step {
+    pub line: usize,
+    pub operation: Operation,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Scalar(String),
+    List(Vec<String>),
+}
+
+/// The fields of one manifest entry, each with the line it was found on
+struct Entry {
+    line: usize,
+    fields: BTreeMap<String, (Value, usize)>,
+}
+
+/// Parses a manifest: a YAML list of operations, each a mapping with an `op` field
+/// and the fields of that operation, e.g.
+///
+/// ```yaml
+/// - op: encode
+///   type: ruSt
+///   message: "build 42"
+/// - op: strip
+///   filters: [private]
+/// ```
+///
+/// Every operation is validated, so a manifest that parses can be applied without surprises.
+pub fn parse(text: &str) -> Result<Vec<Step>> {
+
+    entries(text)?
+        .into_iter()
+        .map(step)
+        .collect()
+}
+
+fn entries(text: &str) -> Result<Vec<Entry>> {
+
+    let mut entries: Vec<Entry> = vec![];
+
+    for (index, raw_line) in text.lines().enumerate() {
+        let line = index + 1;
+        let trimmed = raw_line.trim();
+
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue
+        }
+
+        let field = if let Some(rest) = trimmed.strip_prefix("- ") {
+            if raw_line.starts_with(' ') {
+                return Err(format!("line {}: entries must start at the beginning of the line", line).into())
+            }
+
+            entries.push(Entry { line, fields: BTreeMap::new() });
+
+            rest
+        } else if raw_line.starts_with(' ') && !entries.is_empty() {
+            trimmed
+        } else {
+            return Err(format!("line {}: expected an entry starting with '- '", line).into())
+        };
+
+        let (key, value) = field.split_once(':')
+            .ok_or_else(|| format!("line {}: expected 'field: value'", line))?;
+        let key = key.trim().to_string();
+        let value = value_of(value.trim(), line)?;
+
+        let entry = entries.last_mut().expect("an entry was started above");
+
+        if entry.fields.insert(key.clone(), (value, line)).is_some() {
+            return Err(format!("line {}: duplicate field '{}'", line, key).into())
+        }
+    }
+
+    Ok(entries)
+}
+
+fn value_of(value: &str, line: usize) -> Result<Value> {
+
+    if let Some(list) = value.strip_prefix('[') {
+        let list = list.strip_suffix(']')
+            .ok_or_else(|| format!("line {}: unterminated list", line))?;
+
+        let items = list.split(',')
+            .map(|item| scalar(item.trim(), line))
+            .filter(|item| !matches!(item, Ok(item) if item.is_empty()))
+            .collect::<Result<Vec<String>>>()?;
+
+        return Ok(Value::List(items))
+    }
+
+    Ok(Value::Scalar(scalar(value, line)?))
+}
+
+fn scalar(value: &str, line: usize) -> Result<String> {
+
+    if let Some(quoted) = value.strip_prefix('"') {
+        let mut result = String::new();
+        let mut characters = quoted.char_indices();
+
+        while let Some((index, character)) = characters.next() {
+            match character {
+                '"' => return after_quote(result, &quoted[index + 1..], line),
+                '\\' => match characters.next().map(|(_, character)| character) {
+                    Some('n') => result.push('\n'),
+                    Some('t') => result.push('\t'),
+                    Some('"') => result.push('"'),
+                    Some('\\') => result.push('\\'),
+                    _ => return Err(format!("line {}: unknown escape sequence", line).into()),
+                },
+                character => result.push(character),
+            }
+        }
+
+        return Err(format!("line {}: unterminated string", line).into())
+    }
+
+    if let Some(quoted) = value.strip_prefix('\'') {
+        let mut result = String::new();
+        let mut characters = quoted.char_indices().peekable();
+
+        while let Some((index, character)) = characters.next() {
+            match character {
+                '\'' if characters.peek().is_some_and(|(_, next)| *next == '\'') => {
+                    characters.next();
+                    result.push('\'');
+                },
+                '\'' => return after_quote(result, &quoted[index + 1..], line),
+                character => result.push(character),
+            }
+        }
+
+        return Err(format!("line {}: unterminated string", line).into())
+    }
+
+    // An unquoted value ends where a comment starts
+    let value = match value.find(" #") {
+        Some(comment) => &value[..comment],
+        None => value,
+    };
+
+    Ok(value.trim().to_string())
+}
+
+/// Only a comment may follow a quoted value
+fn after_quote(value: String, rest: &str, line: usize) -> Result<String> {
+
+    let rest = rest.trim_start();
+
+    if !rest.is_empty() && !rest.starts_with('#') {
+        return Err(format!("line {}: unexpected '{}' after a quoted value", line, rest).into())
+    }
+
+    Ok(value)
+}
+
+fn step(mut entry: Entry) -> Result<Step> {
+
+    let line = entry.line;
+    let op = take_scalar(&mut entry, "op")?;
+
+    let operation = match op.as_str() {
+        "encode" => Operation::Encode {
+            chunk_type: take_chunk_type(&mut entry, "type")?,
+            message: take_scalar(&mut entry, "message")?,
+        },
+        "remove" => Operation::Remove {
+            chunk_type: take_chunk_type(&mut entry, "type")?.to_string(),
+        },
+        "text-set" => {
+            let keyword = take_scalar(&mut entry, "keyword")?;
+            let text = take_scalar(&mut entry, "text")?;
+
+            // Catches keywords and text a tEXt chunk cannot hold
+            text::encode_text(&keyword, &text).map_err(|error| format!("line {}: {}", line, error))?;
+
+            Operation::TextSet { keyword, text }
+        },
+        "strip" => Operation::Strip {
+            filters: take_filters(&mut entry, "filters")?,
+        },
+        "rename" => Operation::Rename {
+            from: take_chunk_type(&mut entry, "from")?.to_string(),
+            to: take_chunk_type(&mut entry, "to")?,
+        },
+        op => return Err(format!("line {}: unknown operation '{}', expected encode, remove, text-set, strip or rename", line, op).into()),
+    };
+
+    if let Some((field, (_, field_line))) = entry.fields.iter().next() {
+        return Err(format!("line {}: unknown field '{}' for operation {}", field_line, field, op).into())
+    }
+
+    Ok(Step { line, operation })
+}
+
+fn take_scalar(entry: &mut Entry, field: &str) -> Result<String> {
+
+    match entry.fields.remove(field) {
+        Some((Value::Scalar(value), _)) => Ok(value),
+        Some((Value::List(_), line)) => Err(format!("line {}: field '{}' takes a single value, not a list", line, field).into()),
+        None => Err(format!("line {}: missing field '{}'", entry.line, field).into()),
+    }
+}
+
+fn take_chunk_type(entry: &mut Entry, field: &str) -> Result<ChunkType> {
+
+    let line = entry.fields.get(field).map(|(_, line)| *line).unwrap_or(entry.line);
+    let value = take_scalar(entry, field)?;
+
+    ChunkType::from_str(&value)
+        .map_err(|error| format!("line {}: field '{}': {}", line, field, error).into())
+}
+
+/// Filters default to the private chunks when the field is left out
+fn take_filters(entry: &mut Entry, field: &str) -> Result<Vec<ChunkFilter>> {
+
+    let (values, line) = match entry.fields.remove(field) {
+        Some((Value::List(values), line)) => (values, line),
+        Some((Value::Scalar(value), line)) => (vec![value], line),
+        None => return Ok(vec![ChunkFilter::Private]),
+    };
+
+    values.iter()
+        .map(|value| {
+            ChunkFilter::from_str(value)
+                .map_err(|error| format!("line {}: field '{}': {}", line, field, error).into())
+        })
+        .collect()
+}
+
+/// Applies one operation to the PNG file and describes what it did
+pub fn apply(png: &mut Png, operation: &Operation, crc: &Crc<u32>) -> Result<String> {
+
+    match operation {
+        Operation::Encode { chunk_type, message } => {
+            png.insert_before_iend(Chunk::new_with_crc(chunk_type.clone(), message.as_bytes().to_vec(), crc));
+
+            Ok(format!("encode {}: added {} bytes", chunk_type, message.len()))
+        },
+        Operation::Remove { chunk_type } => {
+            let removed = png.remove_chunk(chunk_type)?;
+
+            Ok(format!("remove {}: removed {} bytes", chunk_type, removed.length()))
+        },
+        Operation::TextSet { keyword, text } => {
+            let chunk = Chunk::new_with_crc(ChunkType::from_str("tEXt")?, text::encode_text(keyword, text)?, crc);
+
+            let existing = png.chunks_mut()
+                .iter_mut()
+                .find(|chunk| {
+                    chunk.chunk_type().to_string() == "tEXt"
+                        && text::decode_text(chunk.data()).is_ok_and(|(existing, _)| existing == *keyword)
+                });
+
+            match existing {
+                Some(existing) => {
+                    *existing = chunk;
+
+                    Ok(format!("text-set {}: replaced", keyword))
+                },
+                None => {
+                    png.insert_before_iend(chunk);
+
+                    Ok(format!("text-set {}: added", keyword))
+                }
+            }
+        },
+        Operation::Strip { filters } => {
+            // Critical chunks are never stripped, whatever the filters say
+            let selection = Selection::Drop(filters);
+            let removed = png.retain_chunks(|chunk| chunk.chunk_type().is_critical() || !selection.removes(chunk));
+
+            let names: Vec<String> = filters.iter().map(|filter| filter.to_string()).collect();
+
+            Ok(format!("strip {}: removed {} chunks", names.join(","), removed.len()))
+        },
+        Operation::Rename { from, to } => {
+            let mut renamed = 0;
+
+            for chunk in png.chunks_mut().iter_mut().filter(|chunk| chunk.chunk_type().to_string() == *from) {
+                *chunk = Chunk::new_with_crc(to.clone(), chunk.data().to_vec(), crc);
+                renamed += 1;
+            }
+
+            if renamed == 0 {
+                return Err(format!("rename {}: there is no chunk of this type", from).into())
+            }
+
+            Ok(format!("rename {} to {}: renamed {} chunks", from, to, renamed))
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PNG_CRC: Crc<u32> = Crc::<u32>::new(&crc::CRC_32_ISO_HDLC);
+
+    const MANIFEST: &str = r#"
+# Provisioning for release images
+- op: encode
+  type: ruSt
+  message: "build 42, \"stable\""
+- op: text-set
+  keyword: Comment
+  text: 'it''s final' # trailing comment
+- op: remove
+  type: teSt
+- op: strip
+  filters: [type:miDl]
+- op: rename
+  from: ruSt
+  to: reSt
+"#;
+
+    fn chunk(chunk_type: &str, data: &str) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.as_bytes().to_vec())
+    }
+
+    fn testing_png() -> Png {
+        Png::from_chunks(vec![
+            chunk("IHDR", "header"),
+            chunk("teSt", "remove me"),
+            chunk("miDl", "strip me"),
+            chunk("IEND", ""),
+        ])
+    }
+
+    #[test]
+    fn test_parse_manifest() {
+        let steps = parse(MANIFEST).unwrap();
+
+        assert_eq!(steps.len(), 5);
+        assert_eq!(steps[0], Step {
+            line: 3,
+            operation: Operation::Encode { chunk_type: ChunkType::from_str("ruSt").unwrap(), message: "build 42, \"stable\"".to_string() },
+        });
+        assert_eq!(steps[1].operation, Operation::TextSet { keyword: "Comment".to_string(), text: "it's final".to_string() });
+        assert_eq!(steps[3].operation, Operation::Strip { filters: vec![ChunkFilter::Type("miDl".to_string())] });
+    }
+
+    #[test]
+    fn test_errors_name_field_and_line() {
+        let error = parse("- op: encode\n  type: ruSt\n  mesage: hi\n").unwrap_err();
+        assert_eq!(error.to_string(), "line 1: missing field 'message'");
+
+        let error = parse("- op: remove\n  type: ruSt\n  message: hi\n").unwrap_err();
+        assert_eq!(error.to_string(), "line 3: unknown field 'message' for operation remove");
+
+        let error = parse("- op: remove\n  type: ruSt\n- op: rename\n  from: ruSt\n  to: r5St\n").unwrap_err();
+        assert!(error.to_string().starts_with("line 5: field 'to'"));
+
+        let error = parse("- op: shrink\n").unwrap_err();
+        assert!(error.to_string().starts_with("line 1: unknown operation 'shrink'"));
+    }
+
+    #[test]
+    fn test_apply_in_order() {
+        let mut png = testing_png();
+        let results: Vec<String> = parse(MANIFEST).unwrap()
+            .iter()
+            .map(|step| apply(&mut png, &step.operation, &PNG_CRC).unwrap())
+            .collect();
+
+        assert_eq!(results[2], "remove teSt: removed 9 bytes");
+        assert_eq!(results[3], "strip type:miDl: removed 1 chunks");
+
+        let types: Vec<String> = png.chunks().iter().map(|chunk| chunk.chunk_type().to_string()).collect();
+        assert_eq!(types, ["IHDR", "reSt", "tEXt", "IEND"]);
+        assert_eq!(png.chunk_by_type("reSt").unwrap().data(), b"build 42, \"stable\"");
+    }
+
+    #[test]
+    fn test_text_set_replaces_keyword() {
+        let mut png = testing_png();
+        let operation = |text: &str| Operation::TextSet { keyword: "Comment".to_string(), text: text.to_string() };
+
+        assert_eq!(apply(&mut png, &operation("first"), &PNG_CRC).unwrap(), "text-set Comment: added");
+        assert_eq!(apply(&mut png, &operation("second"), &PNG_CRC).unwrap(), "text-set Comment: replaced");
+        assert_eq!(png.chunk_by_type("tEXt").unwrap().data(), b"Comment\0second");
+    }
+
+    #[test]
+    fn test_strip_keeps_critical_chunks() {
+        let mut png = testing_png();
+        apply(&mut png, &Operation::Strip { filters: vec![ChunkFilter::Critical] }, &PNG_CRC).unwrap();
+
+        assert_eq!(png.chunks().len(), 4);
+    }
+
+    #[test]
+    fn test_strip_without_filters_removes_every_ancillary_chunk() {
+        let mut png = testing_png();
+        let entries = crate::history::entries_for(&Operation::Strip { filters: vec![] }, &png);
+        apply(&mut png, &Operation::Strip { filters: vec![] }, &PNG_CRC).unwrap();
+
+        let types: Vec<String> = png.chunks().iter().map(|chunk| chunk.chunk_type().to_string()).collect();
+        assert_eq!(types, ["IHDR", "IEND"]);
+        assert_eq!(entries.len(), 2);
+    }
+}
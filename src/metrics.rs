@@ -0,0 +1,155 @@
+use std::{cell::RefCell, fmt, time::{Duration, Instant}};
+
+thread_local! {
+    /// `None` unless --explain turned collection on, which is all a disabled stage checks
+    static METRICS: RefCell<Option<Metrics>> = const { RefCell::new(None) };
+}
+
+/// Where the time of a command went, for --explain
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Metrics {
+    pub bytes_read: usize,
+    pub chunks_parsed: usize,
+    pub read: Duration,
+    pub parse: Duration,
+    pub crc: Duration,
+    /// Compressing, decompressing, encrypting and decrypting
+    pub transform: Duration,
+    pub bytes_written: usize,
+    pub write: Duration,
+    /// All the time recorded so far, so that a stage inside another is not counted twice
+    recorded: Duration,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Stage {
+    Read,
+    Parse,
+    Crc,
+    Transform,
+    Write,
+}
+
+/// Starts collecting metrics on this thread
+pub fn enable() {
+
+    METRICS.with(|metrics| *metrics.borrow_mut() = Some(Metrics::default()));
+}
+
+/// The metrics collected since `enable`, which stops collecting
+pub fn take() -> Option<Metrics> {
+
+    METRICS.with(|metrics| metrics.borrow_mut().take())
+}
+
+/// Changes the metrics when they are being collected, and does nothing otherwise
+pub fn update(change: impl FnOnce(&mut Metrics)) {
+
+    METRICS.with(|metrics| {
+        if let Some(metrics) = metrics.borrow_mut().as_mut() {
+            change(metrics);
+        }
+    })
+}
+
+fn enabled() -> bool {
+
+    METRICS.with(|metrics| metrics.borrow().is_some())
+}
+
+fn recorded() -> Duration {
+
+    METRICS.with(|metrics| metrics.borrow().as_ref().map(|metrics| metrics.recorded).unwrap_or_default())
+}
+
+/// Runs `work` and adds the time it took to `stage`, minus the time of any stage
+/// timed inside it, such as the crcs checked while parsing
+pub fn time<T>(stage: Stage, work: impl FnOnce() -> T) -> T {
+
+    if !enabled() {
+        return work()
+    }
+
+    let nested_before = recorded();
+    let start = Instant::now();
+    let value = work();
+    let elapsed = start.elapsed().saturating_sub(recorded().saturating_sub(nested_before));
+
+    update(|metrics| {
+        let total = match stage {
+            Stage::Read => &mut metrics.read,
+            Stage::Parse => &mut metrics.parse,
+            Stage::Crc => &mut metrics.crc,
+            Stage::Transform => &mut metrics.transform,
+            Stage::Write => &mut metrics.write,
+        };
+
+        *total += elapsed;
+        metrics.recorded += elapsed;
+    });
+
+    value
+}
+
+fn milliseconds(duration: Duration) -> f64 {
+
+    duration.as_secs_f64() * 1000.0
+}
+
+impl Metrics {
+    pub fn to_json(&self) -> String {
+
+        format!(
+            "{{\"bytes_read\": {}, \"chunks_parsed\": {}, \"read_ms\": {:.3}, \"parse_ms\": {:.3}, \"crc_ms\": {:.3}, \"transform_ms\": {:.3}, \"bytes_written\": {}, \"write_ms\": {:.3}}}",
+            self.bytes_read, self.chunks_parsed, milliseconds(self.read), milliseconds(self.parse), milliseconds(self.crc),
+            milliseconds(self.transform), self.bytes_written, milliseconds(self.write)
+        )
+    }
+}
+
+impl fmt::Display for Metrics {
+
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+
+        writeln!(f, "Read:      {} bytes in {:.3} ms", self.bytes_read, milliseconds(self.read))?;
+        writeln!(f, "Parsed:    {} chunks in {:.3} ms", self.chunks_parsed, milliseconds(self.parse))?;
+        writeln!(f, "Crc:       {:.3} ms", milliseconds(self.crc))?;
+        writeln!(f, "Transform: {:.3} ms", milliseconds(self.transform))?;
+        write!(f, "Written:   {} bytes in {:.3} ms", self.bytes_written, milliseconds(self.write))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_disabled_records_nothing() {
+        let _ = take();
+
+        assert_eq!(time(Stage::Parse, || 7), 7);
+        update(|metrics| metrics.bytes_read += 10);
+
+        assert_eq!(take(), None);
+    }
+
+    #[test]
+    fn test_nested_stages_are_not_counted_twice() {
+        enable();
+
+        time(Stage::Parse, || {
+            std::thread::sleep(Duration::from_millis(20));
+            time(Stage::Crc, || std::thread::sleep(Duration::from_millis(20)));
+        });
+        update(|metrics| metrics.chunks_parsed += 3);
+
+        let metrics = take().unwrap();
+
+        assert_eq!(metrics.chunks_parsed, 3);
+        assert!(metrics.crc >= Duration::from_millis(20));
+        assert!(metrics.parse >= Duration::from_millis(20));
+        assert_eq!(metrics.parse + metrics.crc, metrics.recorded);
+        assert!(metrics.to_json().starts_with("{\"bytes_read\": 0, \"chunks_parsed\": 3, "));
+        assert_eq!(take(), None);
+    }
+}
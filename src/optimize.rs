@@ -0,0 +1,136 @@
+use std::{collections::{HashMap, HashSet}, fmt};
+
+use crate::{chaff, png::Png};
+
+/// The APNG chunks, whose order and sequence numbers matter even when two look alike
+const APNG_TYPES: [&str; 3] = ["acTL", "fcTL", "fdAT"];
+
+/// Why a chunk can go
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Reason {
+    Empty,
+    /// Same type and data as the chunk at this index
+    DuplicateOf(usize),
+    /// A decoy added with `encode --chaff`
+    Chaff,
+}
+
+/// A chunk the optimization removes
+#[derive(Debug, Clone, PartialEq)]
+pub struct Removal {
+    pub index: usize,
+    pub chunk_type: String,
+    /// Bytes the chunk takes in the file, including its length, type and crc
+    pub size: usize,
+    pub reason: Reason,
+}
+
+impl fmt::Display for Removal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+
+        match self.reason {
+            Reason::Empty => write!(f, "{} at index {}: empty, saves {} bytes", self.chunk_type, self.index, self.size),
+            Reason::DuplicateOf(original) => write!(
+                f, "{} at index {}: duplicate of index {}, saves {} bytes", self.chunk_type, self.index, original, self.size
+            ),
+            Reason::Chaff => write!(f, "{} at index {}: decoy chunk, saves {} bytes", self.chunk_type, self.index, self.size),
+        }
+    }
+}
+
+/// Finds the ancillary chunks without data and the ancillary chunks identical to
+/// an earlier one, and with `drop_chaff` the decoys tagged with that key. Critical and APNG chunks are
+/// never part of the plan.
+pub fn plan(png: &Png, drop_chaff: Option<&[u8]>) -> Vec<Removal> {
+
+    // Chunks are hashed by type and data, so each one is compared with its equals only
+    let mut seen: HashMap<([u8; 4], &[u8]), usize> = HashMap::new();
+    let mut removals = vec![];
+
+    for (index, chunk) in png.chunks().iter().enumerate() {
+        let chunk_type = chunk.chunk_type();
+
+        if chunk_type.is_critical() || APNG_TYPES.contains(&chunk_type.to_string().as_str()) {
+            continue
+        }
+
+        let reason = if chunk.data().is_empty() {
+            Reason::Empty
+        } else if drop_chaff.is_some_and(|key| chaff::is_chaff(chunk, key)) {
+            Reason::Chaff
+        } else {
+            match seen.get(&(chunk_type.bytes(), chunk.data())) {
+                Some(original) => Reason::DuplicateOf(*original),
+                None => {
+                    seen.insert((chunk_type.bytes(), chunk.data()), index);
+                    continue
+                },
+            }
+        };
+
+        removals.push(Removal { index, chunk_type: chunk_type.to_string(), size: 12 + chunk.data().len(), reason });
+    }
+
+    removals
+}
+
+/// Removes the chunks of the plan
+pub fn apply(png: &mut Png, plan: &[Removal]) {
+
+    let removed: HashSet<usize> = plan.iter().map(|removal| removal.index).collect();
+    let mut index = 0;
+
+    png.retain_chunks(|_| {
+        let keep = !removed.contains(&index);
+        index += 1;
+
+        keep
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::{chunk::Chunk, chunk_type::ChunkType};
+
+    fn chunk(chunk_type: &str, data: &str) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.as_bytes().to_vec())
+    }
+
+    #[test]
+    fn test_plan() {
+        let png = Png::from_chunks(vec![
+            chunk("IHDR", "header"),
+            chunk("tEXt", "Comment\0same"),
+            chunk("ruSt", ""),
+            chunk("fcTL", "frame"),
+            chunk("IDAT", "pixels"),
+            chunk("IDAT", "pixels"),
+            chunk("fcTL", "frame"),
+            chunk("tEXt", "Comment\0same"),
+            chunk("tEXt", "Comment\0other"),
+            chunk("zTXt", "Comment\0same"),
+            chunk("tEXt", "Comment\0same"),
+            chunk("IEND", ""),
+        ]);
+
+        let plan = plan(&png, None);
+        let summary: Vec<(usize, Reason)> = plan.iter().map(|removal| (removal.index, removal.reason)).collect();
+
+        assert_eq!(summary, vec![(2, Reason::Empty), (7, Reason::DuplicateOf(1)), (10, Reason::DuplicateOf(1))]);
+        assert_eq!(plan[1].to_string(), "tEXt at index 7: duplicate of index 1, saves 24 bytes");
+    }
+
+    #[test]
+    fn test_apply() {
+        let mut png = Png::from_chunks(vec![chunk("IHDR", "header"), chunk("tEXt", "a"), chunk("tEXt", "a"), chunk("IEND", "")]);
+
+        let plan = plan(&png, None);
+        apply(&mut png, &plan);
+
+        let types: Vec<String> = png.chunks().iter().map(|chunk| chunk.chunk_type().to_string()).collect();
+        assert_eq!(types, ["IHDR", "tEXt", "IEND"]);
+    }
+}
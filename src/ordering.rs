@@ -0,0 +1,214 @@
+use crate::{chunk::Chunk, png::Png, Result};
+
+/// Ancillary chunks the PNG specification wants before PLTE (and so before IDAT)
+pub const BEFORE_PLTE: [&str; 8] = ["cHRM", "gAMA", "iCCP", "sBIT", "sRGB", "cICP", "mDCv", "cLLi"];
+
+/// Ancillary chunks the PNG specification wants after PLTE and before IDAT
+const AFTER_PLTE: [&str; 3] = ["bKGD", "hIST", "tRNS"];
+
+/// Ancillary chunks the PNG specification wants before IDAT, anywhere after IHDR
+const BEFORE_IDAT: [&str; 4] = ["pHYs", "sPLT", "eXIf", "acTL"];
+
+/// The APNG frame chunks, whose place between the IDAT and fdAT chunks makes the animation
+const FRAME_TYPES: [&str; 2] = ["fcTL", "fdAT"];
+
+/// Whether the specification wants chunks of this type before the image data
+pub fn precedes_idat(chunk_type: &str) -> bool {
+
+    BEFORE_PLTE.contains(&chunk_type) || AFTER_PLTE.contains(&chunk_type) || BEFORE_IDAT.contains(&chunk_type)
+}
+
+/// Whether the file has APNG frame chunks, which reordering would tear from their frames
+pub fn is_animated(png: &Png) -> bool {
+
+    png.chunks().iter().any(|chunk| FRAME_TYPES.contains(&chunk.chunk_type().to_string().as_str()))
+}
+
+/// Where an ancillary chunk goes in a normalized file: just before PLTE (after
+/// IHDR), just before the first IDAT (after PLTE) or just before IEND (after the last IDAT)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Before {
+    Plte,
+    Idat,
+    Iend,
+}
+
+/// The zone of a chunk found at `index`. Chunks the specification puts no
+/// constraint on go after the image data, except unknown ones, which stay on
+/// their side of it in case a reader relies on that.
+fn zone(chunk: &Chunk, index: usize, first_idat: usize) -> Before {
+
+    let chunk_type = chunk.chunk_type().to_string();
+
+    if BEFORE_PLTE.contains(&chunk_type.as_str()) {
+        Before::Plte
+    } else if AFTER_PLTE.contains(&chunk_type.as_str()) || BEFORE_IDAT.contains(&chunk_type.as_str()) {
+        Before::Idat
+    } else if chunk.chunk_type().is_standard() || index > first_idat {
+        Before::Iend
+    } else {
+        Before::Idat
+    }
+}
+
+/// Lists the ways the chunks break the ordering rules of the PNG specification,
+/// for the ancillary chunks on top of the critical ones
+pub fn violations(png: &Png) -> Vec<String> {
+
+    let types: Vec<String> = png.chunks().iter().map(|chunk| chunk.chunk_type().to_string()).collect();
+    let position = |chunk_type: &str| types.iter().position(|existing| existing == chunk_type);
+    let mut violations = png.order_violations();
+
+    let plte = position("PLTE");
+    let first_idat = position("IDAT");
+
+    for (index, chunk_type) in types.iter().enumerate() {
+        let after_idat = first_idat.is_some_and(|first| index > first);
+
+        if BEFORE_PLTE.contains(&chunk_type.as_str()) && (plte.is_some_and(|plte| index > plte) || after_idat) {
+            violations.push(format!("{} at index {} comes after PLTE or IDAT", chunk_type, index));
+        }
+
+        if AFTER_PLTE.contains(&chunk_type.as_str()) && plte.is_some_and(|plte| index < plte) {
+            violations.push(format!("{} at index {} comes before PLTE", chunk_type, index));
+        }
+
+        if (AFTER_PLTE.contains(&chunk_type.as_str()) || BEFORE_IDAT.contains(&chunk_type.as_str())) && after_idat {
+            violations.push(format!("{} at index {} comes after IDAT", chunk_type, index));
+        }
+    }
+
+    violations
+}
+
+/// Moves every ancillary chunk to its zone, sorted by type and then by its
+/// original order. Critical chunks keep their order and no data is changed.
+pub fn normalize(png: &mut Png) -> Result<()> {
+
+    if is_animated(png) {
+        return Err("Animated PNG files are not normalized, their frame chunks have to stay where they are".into())
+    }
+
+    let first_idat = png.position_of_type("IDAT").ok_or("The file has no IDAT chunk")?;
+
+    for required in ["IHDR", "IEND"] {
+        png.position_of_type(required).ok_or_else(|| format!("The file has no {} chunk", required))?;
+    }
+
+    let mut critical = vec![];
+    let mut ancillary = vec![];
+
+    for (index, chunk) in png.retain_chunks(|_| false).into_iter().enumerate() {
+        if chunk.chunk_type().is_critical() {
+            critical.push(chunk);
+        } else {
+            ancillary.push((zone(&chunk, index, first_idat), chunk.chunk_type().to_string(), index, chunk));
+        }
+    }
+
+    ancillary.sort_by(|a, b| (a.0, &a.1, a.2).cmp(&(b.0, &b.1, b.2)));
+
+    let mut ancillary = ancillary.into_iter().peekable();
+    let mut take_zone = |png: &mut Png, zone: Before| {
+        while let Some((_, _, _, chunk)) = ancillary.next_if(|(next, ..)| *next == zone) {
+            png.append_chunk(chunk);
+        }
+    };
+
+    for chunk in critical {
+        match chunk.chunk_type().to_string().as_str() {
+            "PLTE" => take_zone(png, Before::Plte),
+            "IDAT" => {
+                take_zone(png, Before::Plte);
+                take_zone(png, Before::Idat);
+            },
+            "IEND" => {
+                take_zone(png, Before::Plte);
+                take_zone(png, Before::Idat);
+                take_zone(png, Before::Iend);
+            },
+            _ => {},
+        }
+
+        png.append_chunk(chunk);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::chunk_type::ChunkType;
+
+    fn chunk(chunk_type: &str, data: &str) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.as_bytes().to_vec())
+    }
+
+    fn types(png: &Png) -> Vec<String> {
+        png.chunks().iter().map(|chunk| chunk.chunk_type().to_string()).collect()
+    }
+
+    fn scattered_png() -> Png {
+        Png::from_chunks(vec![
+            chunk("IHDR", "header"),
+            chunk("tEXt", "Title\0b"),
+            chunk("ruSt", "before"),
+            chunk("PLTE", "palette"),
+            chunk("gAMA", "gamma"),
+            chunk("IDAT", "first"),
+            chunk("IDAT", "second"),
+            chunk("tIME", "time"),
+            chunk("pHYs", "dpi"),
+            chunk("tEXt", "Title\0a"),
+            chunk("IEND", ""),
+            chunk("teSt", "after"),
+        ])
+    }
+
+    #[test]
+    fn test_normalize() {
+        let mut png = scattered_png();
+        assert!(!violations(&png).is_empty());
+
+        normalize(&mut png).unwrap();
+
+        assert_eq!(types(&png), ["IHDR", "gAMA", "PLTE", "pHYs", "ruSt", "IDAT", "IDAT", "tEXt", "tEXt", "tIME", "teSt", "IEND"]);
+        assert!(violations(&png).is_empty());
+
+        // Chunks of the same type keep their original order, and the image data is untouched
+        assert_eq!(png.chunks()[7].data(), b"Title\0b");
+        let idat: Vec<&[u8]> = png.chunks().iter().filter(|chunk| chunk.chunk_type().to_string() == "IDAT").map(Chunk::data).collect();
+        assert_eq!(idat, [&b"first"[..], &b"second"[..]]);
+    }
+
+    #[test]
+    fn test_normalize_is_idempotent() {
+        let mut png = scattered_png();
+        normalize(&mut png).unwrap();
+
+        let once = png.as_bytes();
+        normalize(&mut png).unwrap();
+
+        assert_eq!(png.as_bytes(), once);
+    }
+
+    #[test]
+    fn test_violations() {
+        let png = Png::from_chunks(vec![chunk("IHDR", ""), chunk("tRNS", ""), chunk("PLTE", ""), chunk("IDAT", ""), chunk("sRGB", ""), chunk("IEND", "")]);
+
+        assert_eq!(violations(&png), [
+            "tRNS at index 1 comes before PLTE",
+            "sRGB at index 4 comes after PLTE or IDAT",
+        ]);
+    }
+
+    #[test]
+    fn test_animated_files_are_refused() {
+        let mut png = Png::from_chunks(vec![chunk("IHDR", ""), chunk("acTL", ""), chunk("fcTL", ""), chunk("IDAT", ""), chunk("IEND", "")]);
+
+        assert!(normalize(&mut png).is_err());
+    }
+}
@@ -1,18 +1,114 @@
-use std::{fmt, process};
+use std::{collections::HashSet, fmt, io::{Read, Write}, ops::Range};
+
+use clap::ValueEnum;
+use crc::Crc;
+
+use crate::{chunk::{self, Chunk}, chunk_type::ChunkType, error::PngMeError, format::{self, Format}, ordering, text, Error, Result};
+
+/// Which chunks have their crc left unchecked when parsing, to save the time
+/// it takes on large image data when only the structure of the file matters
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum CrcSkip {
+    /// Every crc is checked
+    #[default]
+    Nothing,
+    /// IDAT crcs are not checked
+    Idat,
+    /// The crcs of all critical chunks (IHDR, PLTE, IDAT, IEND) are not checked
+    Critical,
+    /// No crc is checked, so that a report can say which ones are bad
+    All,
+}
+
+impl CrcSkip {
+    /// Whether the crc of a chunk of this type is left unchecked
+    pub fn skips(&self, chunk_type: &[u8]) -> bool {
+
+        match self {
+            CrcSkip::Nothing => false,
+            CrcSkip::Idat => chunk_type == b"IDAT",
+            // The case of the first letter marks critical chunks
+            CrcSkip::Critical => chunk_type.first().is_some_and(|byte| byte.is_ascii_uppercase()),
+            CrcSkip::All => true,
+        }
+    }
+}
+
+/// What `Png::merge` does with a chunk the file already has one of: a chunk of the same
+/// type, or for text chunks a chunk of the same type and keyword
+#[derive(Debug, Default, Clone, Copy, PartialEq, ValueEnum)]
+pub enum Duplicates {
+    /// Keep the chunk of the file and leave the other out
+    #[default]
+    Skip,
+    /// Remove the chunks of the file and take the other
+    Replace,
+    /// Take the other chunk as well
+    KeepBoth,
+}
+
+/// Which of identical chunks `Png::dedupe` keeps
+#[derive(Debug, Default, Clone, Copy, PartialEq, ValueEnum)]
+pub enum Keep {
+    /// The one nearest the start of the file
+    #[default]
+    First,
+    /// The one nearest IEND
+    Last,
+}
+
+/// Which ancillary chunks `Png::merge` takes from the other file
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct MergePolicy {
+    pub duplicates: Duplicates,
+    /// Also take the standard text chunks (tEXt, zTXt and iTXt), which describe the
+    /// other file more often than they carry anything worth copying. tIME, not safe
+    /// to copy, is never taken.
+    pub include_text: bool,
+}
 
-use crate::{chunk::Chunk, chunk_type::ChunkType, Error, Result};
+/// The chunk types `Png::merge` took, left out and replaced, one entry per chunk
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct MergeReport {
+    pub taken: Vec<String>,
+    pub skipped: Vec<String>,
+    pub replaced: Vec<String>,
+    /// Where the chunks taken ended up in this file, in the order of `taken`
+    pub positions: Vec<usize>,
+}
+
+/// Where a chunk sits in the bytes of `Png::as_bytes`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChunkSpan {
+    /// The position of the chunk among the chunks of the file
+    pub index: usize,
+    pub chunk_type: String,
+    /// From the first byte of its length to the last byte of its crc
+    pub range: Range<u64>,
+}
+
+/// Whether the bytes start with the PNG signature. Nothing past it is read, so this is
+/// the cheap test before parsing; `format::identify` names the other formats.
+pub fn is_png(bytes: &[u8]) -> bool {
+
+    bytes.starts_with(&Png::STANDARD_HEADER)
+}
 
+#[derive(Debug)]
 pub struct Png {
     header: [u8; 8],
     chunks: Vec<Chunk>,
+    trailing: Vec<u8>,
 }
 
 impl Png {
+    /// The signature every PNG file starts with: 0x89, "PNG", CR LF, Ctrl-Z and LF, chosen
+    /// so that a transfer mangling line endings or the high bit breaks it
     pub const STANDARD_HEADER: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
 
     pub fn from_chunks(chunks: Vec<Chunk>) -> Png {
 
-        Png { header: Png::STANDARD_HEADER, chunks }
+        Png { header: Png::STANDARD_HEADER, chunks, trailing: vec![] }
     }
 
     pub fn chunks(&self) -> &[Chunk] {
@@ -20,16 +116,402 @@ impl Png {
         &self.chunks[..]
     }
 
+    /// Bytes after IEND that are not a chunk, such as a thumbnail or a ZIP archive some
+    /// tools append. They are written back after the last chunk.
+    pub fn trailing_bytes(&self) -> &[u8] {
+
+        &self.trailing
+    }
+
+    /// Consumes the file and hands back its chunks, without the signature or trailing bytes, so that
+    /// they can be rearranged and rebuilt with `from_chunks` without cloning
+    pub fn into_chunks(self) -> Vec<Chunk> {
+
+        self.chunks
+    }
+
+    /// Consumes the file and rebuilds it from what `f` makes of each chunk, in order, such as
+    /// every tEXt chunk recompressed. The signature and trailing bytes are kept.
+    pub fn map_chunks<F: FnMut(Chunk) -> Chunk>(self, f: F) -> Png {
+
+        let Png { header, chunks, trailing } = self;
+
+        Png { header, chunks: chunks.into_iter().map(f).collect(), trailing }
+    }
+
+    pub fn chunk_count(&self) -> usize {
+
+        self.chunks.len()
+    }
+
+    /// True when the file has no chunks at all, only a header
+    pub fn is_empty(&self) -> bool {
+
+        self.chunks.is_empty()
+    }
+
+    pub fn chunks_mut(&mut self) -> &mut [Chunk] {
+
+        &mut self.chunks[..]
+    }
+
     pub fn chunk_by_type(&self, chunk_type: &str) -> Option<&Chunk> {
 
         self.chunks.iter().find(|chunk| chunk.chunk_type().to_string() == chunk_type)
     }
 
+    pub fn contains_type(&self, chunk_type: &str) -> bool {
+
+        self.chunk_by_type(chunk_type).is_some()
+    }
+
+    /// The chunk of this type at `index` among the chunks of that type, counting from 0
+    pub fn chunk_by_type_mut(&mut self, chunk_type: &str, index: usize) -> Option<&mut Chunk> {
+
+        self.chunks.iter_mut().filter(|chunk| chunk.chunk_type().to_string() == chunk_type).nth(index)
+    }
+
+    /// The bytes taken in the file by all chunks of this type, including their length, type and crc
+    pub fn size_of_type(&self, chunk_type: &str) -> usize {
+
+        self.chunks.iter()
+            .filter(|chunk| chunk.chunk_type().to_string() == chunk_type)
+            .map(Chunk::size)
+            .sum()
+    }
+
+    /// The bytes of data held by the chunks of this type, without their length, type and crc
+    pub fn data_size_of_type(&self, chunk_type: &str) -> usize {
+
+        self.chunks.iter()
+            .filter(|chunk| chunk.chunk_type().to_string() == chunk_type)
+            .map(|chunk| chunk.data().len())
+            .sum()
+    }
+
+    /// The length of `as_bytes()`, the header and every chunk with its length, type and crc,
+    /// worked out without serializing the file
+    pub fn total_size(&self) -> usize {
+
+        self.header.len() + self.chunks.iter().map(Chunk::size).sum::<usize>() + self.trailing.len()
+    }
+
+    /// The bytes taken in the file by all ancillary chunks, including their length, type and crc
+    pub fn ancillary_size(&self) -> usize {
+
+        self.chunks.iter()
+            .filter(|chunk| !chunk.chunk_type().is_critical())
+            .map(Chunk::size)
+            .sum()
+    }
+
+    /// The position of every chunk in the file, counted in bytes from the start of the header
+    pub fn byte_offsets(&self) -> Vec<u64> {
+
+        offsets(self.header.len() as u64, self.chunks.iter().map(|chunk| chunk.size() as u64))
+    }
+
+    /// The type of every chunk with whether its stored crc matches its contents. Only a file
+    /// parsed with `try_from_unverified` or `CrcSkip` can hold a chunk whose crc does not.
+    pub fn verify_all_crcs(&self) -> Vec<(String, bool)> {
+
+        self.chunks.iter()
+            .map(|chunk| (chunk.chunk_type().to_string(), chunk.crc() == chunk.computed_crc()))
+            .collect()
+    }
+
+    /// `verify_all_crcs` for crcs generated with another algorithm than the one of the PNG specification
+    pub fn verify_all_crcs_with(&self, crc: &Crc<u32>) -> Vec<(String, bool)> {
+
+        self.chunks.iter()
+            .map(|chunk| (chunk.chunk_type().to_string(), chunk.crc() == chunk.computed_crc_with(crc)))
+            .collect()
+    }
+
+    /// Where every chunk sits in the file, for patching it in place with other tools
+    pub fn chunk_positions(&self) -> Vec<ChunkSpan> {
+
+        self.chunks.iter()
+            .zip(self.byte_offsets())
+            .enumerate()
+            .map(|(index, (chunk, offset))| ChunkSpan {
+                index,
+                chunk_type: chunk.chunk_type().to_string(),
+                range: offset..offset.saturating_add(chunk.size() as u64),
+            })
+            .collect()
+    }
+
+    /// Sets the data of every chunk of this type and returns how many were changed
+    pub fn replace_all_of_type(&mut self, chunk_type: &str, new_data: Vec<u8>) -> usize {
+
+        let mut count = 0;
+
+        for chunk in self.chunks.iter_mut().filter(|chunk| chunk.chunk_type().to_string() == chunk_type) {
+            chunk.set_data(new_data.clone());
+            count += 1;
+        }
+
+        count
+    }
+
+    /// Walks the chunks through the order the PNG specification sets for the
+    /// critical ones (IHDR first, PLTE before IDAT, IDAT contiguous, IEND last)
+    /// and describes every step that breaks it
+    pub fn order_violations(&self) -> Vec<String> {
+
+        #[derive(PartialEq)]
+        enum State { Start, Header, Palette, Data, AfterData, End }
+
+        let mut state = State::Start;
+        let mut violations = vec![];
+
+        for chunk in &self.chunks {
+            let chunk_type = chunk.chunk_type();
+
+            if state == State::End {
+                violations.push(format!("{} appears after IEND", chunk_type));
+                continue
+            }
+
+            state = match (chunk_type, state) {
+                (ChunkType::IHDR, State::Start) => State::Header,
+                (ChunkType::IHDR, state) => {
+                    violations.push("IHDR appears more than once".to_string());
+                    state
+                },
+                (chunk_type, State::Start) => {
+                    violations.push(format!("{} appears before IHDR", chunk_type));
+                    State::Header
+                },
+                (ChunkType::PLTE, State::Header) => State::Palette,
+                (ChunkType::PLTE, State::Palette) => {
+                    violations.push("PLTE appears more than once".to_string());
+                    State::Palette
+                },
+                (ChunkType::PLTE, _) => {
+                    violations.push("PLTE appears after IDAT".to_string());
+                    State::AfterData
+                },
+                (ChunkType::IDAT, State::AfterData) => {
+                    violations.push("IDAT chunks are not contiguous".to_string());
+                    State::Data
+                },
+                (ChunkType::IDAT, _) => State::Data,
+                (ChunkType::IEND, _) => State::End,
+                (_, State::Data) => State::AfterData,
+                (_, state) => state,
+            };
+        }
+
+        if !self.chunks.iter().any(|chunk| chunk.chunk_type() == ChunkType::IDAT) {
+            violations.push("IDAT is missing".to_string());
+        }
+
+        if state != State::End {
+            violations.push("IEND is missing".to_string());
+        }
+
+        violations
+    }
+
+    /// Reorders the chunks into the arrangement the specification prefers: IHDR,
+    /// the standard ancillary chunks that must precede PLTE (gAMA, cHRM, sRGB, ...),
+    /// PLTE, the other standard ancillary chunks, the IDAT chunks together, custom
+    /// chunks and IEND last. Chunks of the same rank keep their order, so the image
+    /// data is not reordered, only gathered. Animated files are refused, as
+    /// their fcTL and fdAT chunks have to stay between the frames they describe.
+    pub fn sort_chunks_canonical(&mut self) -> Result<()> {
+
+        if ordering::is_animated(self) {
+            return Err("Animated PNG files are not sorted, their frame chunks have to stay where they are".into())
+        }
+
+        self.chunks.sort_by_key(|chunk| match chunk.chunk_type() {
+            ChunkType::IHDR => 0,
+            ChunkType::PLTE => 2,
+            ChunkType::IDAT => 4,
+            ChunkType::IEND => 6,
+            chunk_type if ordering::BEFORE_PLTE.contains(&chunk_type.to_string().as_str()) => 1,
+            chunk_type if chunk_type.is_standard() => 3,
+            _ => 5,
+        });
+
+        Ok(())
+    }
+
     pub fn append_chunk(&mut self, chunk: Chunk) {
 
         self.chunks.push(chunk)
     }
 
+    /// Inserts the chunk right before IEND, where the PNG specification allows any
+    /// ancillary chunk, or at the end when the file has no IEND
+    pub fn insert_before_iend(&mut self, chunk: Chunk) {
+
+        let index = self.position_of_type("IEND").unwrap_or(self.chunks.len());
+
+        self.chunks.insert(index, chunk);
+    }
+
+    /// Inserts the chunk so that it ends up at `index`, shifting the chunks after it
+    pub fn insert_chunk(&mut self, index: usize, chunk: Chunk) -> Result<()> {
+
+        if index > self.chunks.len() {
+            return Err(format!("Cannot insert a chunk at index {}, the file has {} chunks", index, self.chunks.len()).into())
+        }
+
+        self.chunks.insert(index, chunk);
+
+        Ok(())
+    }
+
+    /// Removes every ancillary chunk with the same type and data as another, keeping the
+    /// first or the last of them, and returns the removed chunks. Critical chunks are left
+    /// alone: two identical IDAT chunks are both part of the image.
+    pub fn dedupe(&mut self, keep: Keep) -> Vec<Chunk> {
+
+        let mut seen = HashSet::new();
+        let mut duplicate = vec![false; self.chunks.len()];
+
+        let indices: Vec<usize> = match keep {
+            Keep::First => (0..self.chunks.len()).collect(),
+            Keep::Last => (0..self.chunks.len()).rev().collect(),
+        };
+
+        for index in indices {
+            let chunk = &self.chunks[index];
+
+            if !chunk.chunk_type().is_critical() && !seen.insert((chunk.chunk_type().bytes(), chunk.data())) {
+                duplicate[index] = true;
+            }
+        }
+
+        let (removed, kept) = std::mem::take(&mut self.chunks)
+            .into_iter()
+            .zip(duplicate)
+            .partition::<Vec<_>, _>(|(_, duplicate)| *duplicate);
+
+        self.chunks = kept.into_iter().map(|(chunk, _)| chunk).collect();
+
+        removed.into_iter().map(|(chunk, _)| chunk).collect()
+    }
+
+    /// Takes the ancillary chunks of `other` into this file in the order they have there:
+    /// the ones the specification wants before the image data right before the first IDAT,
+    /// the others right before IEND. Critical chunks are never taken, they describe the
+    /// image of the other file, and neither are chunks that are not safe to copy, which
+    /// depend on it. Chunks are duplicates of the ones this file had before the merge.
+    pub fn merge(&mut self, other: &Png, policy: MergePolicy) -> MergeReport {
+
+        let mut report = MergeReport::default();
+
+        let duplicate_key = |chunk: &Chunk| (chunk.chunk_type(), text::keyword(chunk));
+        let existing: Vec<_> = self.chunks.iter().map(duplicate_key).collect();
+
+        let mut taken = vec![];
+        let mut replaced_keys = vec![];
+
+        for chunk in other.chunks.iter().filter(|chunk| !chunk.chunk_type().is_critical()) {
+            let chunk_type = chunk.chunk_type().to_string();
+
+            if !policy.include_text && matches!(chunk_type.as_str(), "tEXt" | "zTXt" | "iTXt") {
+                report.skipped.push(chunk_type);
+                continue
+            }
+
+            if !chunk.chunk_type().is_safe_to_copy() {
+                report.skipped.push(chunk_type);
+                continue
+            }
+
+            let key = duplicate_key(chunk);
+
+            if existing.contains(&key) {
+                match policy.duplicates {
+                    Duplicates::Skip => {
+                        report.skipped.push(chunk_type);
+                        continue
+                    },
+                    Duplicates::Replace => {
+                        report.replaced.push(chunk_type.clone());
+                        replaced_keys.push(key);
+                    },
+                    Duplicates::KeepBoth => {},
+                }
+            }
+
+            report.taken.push(chunk_type);
+            taken.push(chunk.clone());
+        }
+
+        // The chunks of this file are removed before any is taken, so that a taken chunk is never replaced
+        self.chunks.retain(|chunk| !replaced_keys.contains(&duplicate_key(chunk)));
+
+        let (before_idat, before_iend): (Vec<_>, Vec<_>) = taken.into_iter().enumerate()
+            .partition(|(_, chunk)| ordering::precedes_idat(&chunk.chunk_type().to_string()));
+
+        let end = self.position_of_type("IEND").unwrap_or(self.chunks.len());
+        let idat = self.position_of_type("IDAT").unwrap_or(end);
+        report.positions = vec![0; before_idat.len() + before_iend.len()];
+
+        for (offset, (order, _)) in before_idat.iter().enumerate() {
+            report.positions[*order] = idat + offset;
+        }
+
+        for (offset, (order, _)) in before_iend.iter().enumerate() {
+            report.positions[*order] = end + before_idat.len() + offset;
+        }
+
+        self.chunks.splice(end..end, before_iend.into_iter().map(|(_, chunk)| chunk));
+        self.chunks.splice(idat..idat, before_idat.into_iter().map(|(_, chunk)| chunk));
+
+        report
+    }
+
+    /// Inserts the chunk right after the first chunk of this type, as when a message
+    /// goes after IHDR where readers that stop early still find it
+    pub fn insert_after_type(&mut self, chunk_type: &str, chunk: Chunk) -> Result<()> {
+
+        let index = self.position_of_type(chunk_type)
+            .ok_or_else(|| format!("There is no {} chunk to insert the chunk after", chunk_type))?;
+
+        self.insert_chunk(index + 1, chunk)
+    }
+
+    /// The index of the first chunk of this type
+    pub fn position_of_type(&self, chunk_type: &str) -> Option<usize> {
+
+        self.chunks.iter().position(|chunk| chunk.chunk_type().to_string() == chunk_type)
+    }
+
+    pub fn remove_chunk_at(&mut self, index: usize) -> Result<Chunk> {
+
+        if index >= self.chunks.len() {
+            return Err(PngMeError::NotFound(format!("No chunk at index {}, the file has {} chunks", index, self.chunks.len())).into())
+        }
+
+        Ok(self.chunks.remove(index))
+    }
+
+    /// Keeps only the chunks for which `keep` returns true and returns the removed ones, in file order
+    pub fn retain_chunks<F: FnMut(&Chunk) -> bool>(&mut self, mut keep: F) -> Vec<Chunk> {
+
+        let (kept, removed) = std::mem::take(&mut self.chunks)
+            .into_iter()
+            .partition(|chunk| keep(chunk));
+
+        self.chunks = kept;
+
+        removed
+    }
+
+    /// The first chunk for which `predicate` returns true
+    pub fn find<F: Fn(&Chunk) -> bool>(&self, predicate: F) -> Option<&Chunk> {
+
+        self.chunks.iter().find(|chunk| predicate(chunk))
+    }
+
     pub fn header(&self) -> &[u8; 8] {
 
         &self.header
@@ -37,67 +519,143 @@ impl Png {
 
     pub fn remove_chunk(&mut self, chunk_type: &str) -> Result<Chunk> {
 
-        let index = self.chunks.iter()
-                                        .position(|val| val.chunk_type().to_string() == chunk_type)
-                                        .unwrap_or_else(|| {
-                                            eprintln!("No chunk with this type");
-                                            process::exit(1);
-                                        });
+        let index = self.position_of_type(chunk_type)
+                                        .ok_or_else(|| PngMeError::NotFound(format!("No chunk of type '{}' in this file", chunk_type)))?;
 
         Ok(self.chunks.remove(index))
     }
 
-    pub fn as_bytes(&self) -> Vec<u8> {
+    /// Parses a PNG file without verifying the crc of its chunks, so that files
+    /// with damaged chunks can still be inspected
+    pub fn try_from_unverified(value: &[u8]) -> Result<Png> {
 
-        // This will turn the vector of chunks into an array of bytes
-        let chunks: Vec<u8> = self.chunks
-                                    .iter()
-                                    .map(|val| val.as_bytes())
-                                    .into_iter()
-                                    .flatten()
-                                    .collect::<Vec<u8>>();
+        Png::parse(value, Chunk::try_from_unverified)
+    }
 
-        let data = self.header
-                                .iter()
-                                .cloned()
-                                .chain(chunks.iter().cloned())
-                                .collect();
+    /// Parses a PNG file whose chunk crcs were generated with another algorithm
+    /// than the one of the PNG specification
+    pub fn try_from_with_crc(value: &[u8], crc: &Crc<u32>) -> Result<Png> {
 
-        data
+        Png::parse(value, |bytes| Chunk::try_from_with_crc(bytes, crc))
     }
-}
 
-impl TryFrom<&[u8]> for Png {
-    type Error = Error;
-    fn try_from(value: &[u8]) -> Result<Self> {
-        let header: [u8; 8] = value[..8].try_into().unwrap();
+    /// Parses a PNG file, checking the crcs of all chunks but the ones `skip` leaves out
+    pub fn try_from_skipping_crc(value: &[u8], crc: &Crc<u32>, skip: CrcSkip) -> Result<Png> {
+
+        Png::parse(value, |bytes| {
+            if skip.skips(&bytes[4..8]) {
+                Chunk::try_from_unverified(bytes)
+            } else {
+                Chunk::try_from_with_crc(bytes, crc)
+            }
+        })
+    }
+
+    /// Reads a whole PNG file from a stream, such as stdin
+    pub fn read_from<R: Read>(reader: &mut R, crc: &Crc<u32>) -> Result<Png> {
+
+        let mut bytes = vec![];
+        reader.read_to_end(&mut bytes).map_err(PngMeError::from)?;
+
+        Png::try_from_with_crc(&bytes, crc)
+    }
+
+    /// Writes the PNG file to a stream one chunk at a time, without building
+    /// the whole file in memory first
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> Result<()> {
+
+        writer.write_all(&self.header)?;
+
+        for chunk in &self.chunks {
+            writer.write_all(&chunk.length().to_be_bytes())?;
+            writer.write_all(&chunk.chunk_type().bytes())?;
+            writer.write_all(chunk.data())?;
+            writer.write_all(&chunk.crc().to_be_bytes())?;
+        }
+
+        writer.write_all(&self.trailing)?;
+        writer.flush()?;
+
+        Ok(())
+    }
+
+    fn parse(value: &[u8], parse_chunk: impl Fn(&[u8]) -> Result<Chunk>) -> Result<Png> {
 
-        if header != Png::STANDARD_HEADER {
+        if !is_png(value) {
 
-            return Err("Invalid header".into())
+            return match format::identify(value) {
+                Format::Unknown => Err("Invalid header".into()),
+                format => Err(format!("Invalid header, this is a {} file, not a PNG", format).into()),
+            }
         }
 
+        let header = Png::STANDARD_HEADER;
         let mut chunks: Vec<Chunk> = vec![];
         let mut start = 8;
-        let crc_bytes_length: usize = 8;
-        let chunk_type_bytes_length = 4;
-
-        while start < value.len() && start != value.len() {
-            
-            let data_length_bytes: [u8; 4] = value[start..start + 4].try_into().unwrap();
-            let data_length = u32::from_be_bytes(data_length_bytes);
-            
-            // how this line I wrote works is a miracle that I do not understand
-            // there must have been some extra 4 bytes added somewhere
-            let end = start + chunk_type_bytes_length + data_length as usize + crc_bytes_length;
-            
-            let chunk = Chunk::try_from(&value[start..end]).unwrap();
+
+        while start < value.len() {
+
+            // A length that would take the end past usize::MAX cannot fit in the file either
+            let end = value.get(start..start + 4).and_then(|length| {
+                chunk::framed_end(start, u32::from_be_bytes(length.try_into().expect("The slice has 4 bytes")))
+            });
+            let framed = end.is_some_and(|end| end <= value.len() && value[start + 4..start + 8].iter().all(u8::is_ascii_alphabetic));
+
+            // After IEND, whatever does not even look like a chunk is data appended by another tool
+            if !framed && chunks.iter().any(|chunk| chunk.chunk_type() == ChunkType::IEND) {
+                return Ok(Png { header, chunks, trailing: value[start..].to_vec() })
+            }
+
+            let end = match end {
+                Some(end) if end <= value.len() => end,
+                _ => return Err("Unexpected end of file while reading a chunk".into()),
+            };
+
+            let chunk = parse_chunk(&value[start..end])?;
             start = end;
 
             chunks.push(chunk);
         }
 
-        Ok( Png { header, chunks } )
+        Ok( Png { header, chunks, trailing: vec![] } )
+    }
+
+    pub fn as_bytes(&self) -> Vec<u8> {
+
+        let mut bytes = Vec::with_capacity(self.total_size());
+        bytes.extend_from_slice(&self.header);
+
+        for chunk in &self.chunks {
+            chunk.extend_bytes(&mut bytes);
+        }
+
+        bytes.extend_from_slice(&self.trailing);
+        bytes
+    }
+}
+
+/// The offset of every item of `sizes` laid out one after the other from `start`.
+/// Counted in u64, so that the offsets of a file larger than 4 GB are right even
+/// on a 32-bit target; they saturate rather than wrap past u64::MAX.
+fn offsets(start: u64, sizes: impl IntoIterator<Item = u64>) -> Vec<u64> {
+
+    let mut offset = start;
+
+    sizes.into_iter()
+        .map(|size| {
+            let item_offset = offset;
+            offset = offset.saturating_add(size);
+
+            item_offset
+        })
+        .collect()
+}
+
+impl TryFrom<&[u8]> for Png {
+    type Error = Error;
+    fn try_from(value: &[u8]) -> Result<Self> {
+
+        Png::parse(value, |bytes| Chunk::try_from(bytes))
     }
 }
 
@@ -105,10 +663,16 @@ impl fmt::Display for Png {
 
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         
-        write!(f, " header: {:?} ", self.header);
-        write!(f, " chunks: {:?} ", self.chunks);
-
-        Ok(())
+        let types: Vec<String> = self.chunks.iter().map(|chunk| chunk.chunk_type().to_string()).collect();
+
+        write!(
+            f,
+            "{} {}, {} bytes: {}",
+            self.chunks.len(),
+            if self.chunks.len() == 1 { "chunk" } else { "chunks" },
+            self.total_size(),
+            types.join(", ")
+        )
     }
 }
 
@@ -119,17 +683,14 @@ mod tests {
     use super::*;
     use crate::chunk_type::ChunkType;
     use crate::chunk::Chunk;
-    use std::str::FromStr;
     use std::convert::TryFrom;
 
     fn testing_chunks() -> Vec<Chunk> {
-        let mut chunks = Vec::new();
-
-        chunks.push(chunk_from_strings("FrSt", "I am the first chunk").unwrap());
-        chunks.push(chunk_from_strings("miDl", "I am another chunk").unwrap());
-        chunks.push(chunk_from_strings("LASt", "I am the last chunk").unwrap());
-
-        chunks
+        vec![
+            chunk_from_strings("FrSt", "I am the first chunk").unwrap(),
+            chunk_from_strings("miDl", "I am another chunk").unwrap(),
+            chunk_from_strings("LASt", "I am the last chunk").unwrap(),
+        ]
     }
 
     fn testing_png() -> Png {
@@ -154,6 +715,45 @@ mod tests {
         assert_eq!(png.chunks().len(), 3);
     }
 
+    #[test]
+    fn test_into_chunks() {
+        let png = testing_png();
+        let bytes = png.as_bytes();
+
+        let mut chunks = png.into_chunks();
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks[0].chunk_type().to_string(), "FrSt");
+
+        let rebuilt = Png::from_chunks(chunks);
+        assert_eq!(rebuilt.as_bytes(), bytes);
+
+        chunks = rebuilt.into_chunks();
+        chunks.swap(0, 2);
+        let types: Vec<String> = Png::from_chunks(chunks).chunks().iter().map(|chunk| chunk.chunk_type().to_string()).collect();
+        assert_eq!(types, ["LASt", "miDl", "FrSt"]);
+    }
+
+    #[test]
+    fn test_is_png() {
+        assert!(is_png(&Png::STANDARD_HEADER));
+        assert!(is_png(&testing_png().as_bytes()));
+        assert!(!is_png(&Png::STANDARD_HEADER[..7]));
+        assert!(!is_png(b""));
+
+        let mut offset = vec![0];
+        offset.extend(Png::STANDARD_HEADER);
+        assert!(!is_png(&offset));
+    }
+
+    #[test]
+    fn test_map_chunks() {
+        let png = testing_png().map_chunks(|chunk| Chunk::new(chunk.chunk_type(), chunk.data().to_ascii_uppercase()));
+
+        assert_eq!(png.chunks().len(), 3);
+        assert_eq!(png.chunk_by_type("miDl").unwrap().data_as_string().unwrap(), "I AM ANOTHER CHUNK");
+        assert_eq!(png.chunks()[2].crc(), chunk_from_strings("LASt", "I AM THE LAST CHUNK").unwrap().crc());
+    }
+
     #[test]
     fn test_valid_from_bytes() {
         let chunk_bytes: Vec<u8> = testing_chunks()
@@ -190,6 +790,25 @@ mod tests {
         assert!(png.is_err());
     }
 
+    #[test]
+    fn test_other_format_header() {
+        let Err(error) = Png::try_from(&b"RIFF\x24\x00\x00\x00WEBPVP8 "[..]) else {
+            panic!("a WEBP file was parsed as a PNG")
+        };
+
+        assert_eq!(error.to_string(), "Invalid header, this is a WEBP file, not a PNG");
+    }
+
+    #[test]
+    fn test_debug_keeps_the_full_dump() {
+        let png = testing_png();
+        let debug = format!("{:?}", png);
+
+        assert!(debug.starts_with("Png { header: [137, 80, 78, 71, 13, 10, 26, 10], chunks: ["));
+        assert_eq!(debug.matches("Chunk {").count(), png.chunk_count());
+        assert_ne!(debug, png.to_string());
+    }
+
     #[test]
     fn test_invalid_chunk() {
         let mut chunk_bytes: Vec<u8> = testing_chunks()
@@ -229,6 +848,336 @@ mod tests {
 
     }
 
+    #[test]
+    fn test_chunk_by_type_mut() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("TeSt", "Message").unwrap());
+        png.append_chunk(chunk_from_strings("TeSt", "Another message").unwrap());
+
+        png.chunk_by_type_mut("TeSt", 1).unwrap().set_data(b"Changed".to_vec());
+
+        assert_eq!(png.chunks()[4].data(), b"Changed");
+        assert_eq!(png.chunks()[3].data(), b"Message");
+        assert!(png.chunk_by_type_mut("TeSt", 2).is_none());
+    }
+
+    #[test]
+    fn test_size_of_type() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("TeSt", "Message").unwrap());
+        png.append_chunk(chunk_from_strings("TeSt", "Another message").unwrap());
+
+        assert_eq!(png.size_of_type("TeSt"), 12 + 7 + 12 + 15);
+        assert_eq!(png.size_of_type("FrSt"), 12 + 20);
+        assert_eq!(png.size_of_type("NoNe"), 0);
+        assert_eq!(png.data_size_of_type("TeSt"), 7 + 15);
+        assert_eq!(png.data_size_of_type("NoNe"), 0);
+    }
+
+    #[test]
+    fn test_replace_all_of_type() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("miDl", "I am a second chunk").unwrap());
+
+        assert_eq!(png.replace_all_of_type("miDl", b"new value".to_vec()), 2);
+        assert_eq!(png.replace_all_of_type("nOne", b"new value".to_vec()), 0);
+
+        let replaced: Vec<&Chunk> = png.chunks().iter().filter(|chunk| chunk.chunk_type().to_string() == "miDl").collect();
+        assert_eq!(replaced.len(), 2);
+
+        for chunk in replaced {
+            assert_eq!(chunk.data(), b"new value");
+            assert_eq!(chunk.length(), 9);
+            assert_eq!(chunk.crc(), chunk.computed_crc());
+        }
+
+        assert_eq!(png.chunk_by_type("FrSt").unwrap().data(), b"I am the first chunk");
+    }
+
+    #[test]
+    fn test_chunk_count() {
+        let png = testing_png();
+        assert_eq!(png.chunk_count(), 3);
+        assert!(!png.is_empty());
+
+        let empty = Png::from_chunks(vec![]);
+        assert_eq!(empty.chunk_count(), 0);
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn test_order_violations() {
+        let ordered = Png::from_chunks(vec![
+            chunk_from_strings("IHDR", "header").unwrap(),
+            chunk_from_strings("PLTE", "palette").unwrap(),
+            chunk_from_strings("tEXt", "text").unwrap(),
+            chunk_from_strings("IDAT", "first").unwrap(),
+            chunk_from_strings("IDAT", "second").unwrap(),
+            chunk_from_strings("tIME", "time").unwrap(),
+            chunk_from_strings("IEND", "").unwrap(),
+        ]);
+        assert!(ordered.order_violations().is_empty());
+
+        let mut encoded = ordered;
+        encoded.insert_before_iend(chunk_from_strings("ruSt", "message").unwrap());
+        assert_eq!(encoded.chunks()[7].chunk_type(), ChunkType::IEND);
+        assert!(encoded.order_violations().is_empty());
+
+        let misplaced = Png::from_chunks(vec![
+            chunk_from_strings("IHDR", "header").unwrap(),
+            chunk_from_strings("IDAT", "first").unwrap(),
+            chunk_from_strings("PLTE", "palette").unwrap(),
+            chunk_from_strings("IDAT", "second").unwrap(),
+            chunk_from_strings("IEND", "").unwrap(),
+            chunk_from_strings("ruSt", "trailing").unwrap(),
+        ]);
+        assert_eq!(misplaced.order_violations(), [
+            "PLTE appears after IDAT",
+            "IDAT chunks are not contiguous",
+            "ruSt appears after IEND",
+        ]);
+
+        assert_eq!(testing_png().order_violations(), [
+            "FrSt appears before IHDR",
+            "IDAT is missing",
+            "IEND is missing",
+        ]);
+    }
+
+    fn dedupe_target() -> Png {
+        Png::from_chunks(vec![
+            chunk_from_strings("IHDR", "header").unwrap(),
+            chunk_from_strings("ruSt", "same").unwrap(),
+            chunk_from_strings("IDAT", "pixels").unwrap(),
+            chunk_from_strings("ruSt", "same").unwrap(),
+            chunk_from_strings("ruSt", "other").unwrap(),
+            chunk_from_strings("IDAT", "pixels").unwrap(),
+            chunk_from_strings("ruSt", "same").unwrap(),
+            chunk_from_strings("IEND", "").unwrap(),
+        ])
+    }
+
+    #[test]
+    fn test_dedupe_keep_first() {
+        let mut png = dedupe_target();
+        let removed = png.dedupe(Keep::First);
+
+        assert_eq!(removed.len(), 2);
+        assert_eq!(chunk_list(&png), ["IHDR header", "ruSt same", "IDAT pixels", "ruSt other", "IDAT pixels", "IEND "]);
+        assert_eq!(png.position_of_type("ruSt"), Some(1));
+    }
+
+    #[test]
+    fn test_dedupe_keep_last() {
+        let mut png = dedupe_target();
+        let removed = png.dedupe(Keep::Last);
+
+        assert_eq!(removed.len(), 2);
+        assert_eq!(chunk_list(&png), ["IHDR header", "IDAT pixels", "ruSt other", "IDAT pixels", "ruSt same", "IEND "]);
+        assert_eq!(png.position_of_type("ruSt"), Some(2));
+    }
+
+    fn merge_target() -> Png {
+        Png::from_chunks(vec![
+            chunk_from_strings("IHDR", "header").unwrap(),
+            chunk_from_strings("ruSt", "mine").unwrap(),
+            chunk_from_strings("tEXt", "Author\0me").unwrap(),
+            chunk_from_strings("IDAT", "pixels").unwrap(),
+            chunk_from_strings("IEND", "").unwrap(),
+        ])
+    }
+
+    fn merge_source() -> Png {
+        Png::from_chunks(vec![
+            chunk_from_strings("IHDR", "other header").unwrap(),
+            chunk_from_strings("teSt", "disjoint").unwrap(),
+            chunk_from_strings("ruSt", "theirs").unwrap(),
+            chunk_from_strings("tEXt", "Author\0them").unwrap(),
+            chunk_from_strings("tEXt", "Title\0their title").unwrap(),
+            chunk_from_strings("IDAT", "other pixels").unwrap(),
+            chunk_from_strings("zzZz", "last").unwrap(),
+            chunk_from_strings("IEND", "").unwrap(),
+        ])
+    }
+
+    fn chunk_list(png: &Png) -> Vec<String> {
+        png.chunks().iter().map(|chunk| format!("{} {}", chunk.chunk_type(), chunk.data_as_string().unwrap().replace('\0', ":"))).collect()
+    }
+
+    #[test]
+    fn test_merge_skip() {
+        let mut png = merge_target();
+        let report = png.merge(&merge_source(), MergePolicy::default());
+
+        assert_eq!(chunk_list(&png), [
+            "IHDR header", "ruSt mine", "tEXt Author:me", "IDAT pixels", "teSt disjoint", "zzZz last", "IEND ",
+        ]);
+        assert_eq!(report.taken, ["teSt", "zzZz"]);
+        assert_eq!(report.skipped, ["ruSt", "tEXt", "tEXt"]);
+        assert_eq!(report.positions, [4, 5]);
+        assert!(report.replaced.is_empty());
+    }
+
+    #[test]
+    fn test_merge_replace() {
+        let mut png = merge_target();
+        let report = png.merge(&merge_source(), MergePolicy { duplicates: Duplicates::Replace, include_text: true });
+
+        assert_eq!(chunk_list(&png), [
+            "IHDR header", "IDAT pixels", "teSt disjoint", "ruSt theirs", "tEXt Author:them", "tEXt Title:their title", "zzZz last", "IEND ",
+        ]);
+        assert_eq!(report.replaced, ["ruSt", "tEXt"]);
+        assert_eq!(report.taken.len(), 5);
+        assert!(report.skipped.is_empty());
+    }
+
+    #[test]
+    fn test_merge_keep_both() {
+        let mut png = merge_target();
+        let report = png.merge(&merge_source(), MergePolicy { duplicates: Duplicates::KeepBoth, include_text: true });
+
+        assert_eq!(chunk_list(&png), [
+            "IHDR header", "ruSt mine", "tEXt Author:me", "IDAT pixels",
+            "teSt disjoint", "ruSt theirs", "tEXt Author:them", "tEXt Title:their title", "zzZz last", "IEND ",
+        ]);
+        assert_eq!(report.taken, ["teSt", "ruSt", "tEXt", "tEXt", "zzZz"]);
+    }
+
+    #[test]
+    fn test_merge_disjoint() {
+        let mut png = Png::from_chunks(vec![chunk_from_strings("IHDR", "header").unwrap(), chunk_from_strings("IEND", "").unwrap()]);
+        let report = png.merge(&merge_source(), MergePolicy { duplicates: Duplicates::Skip, include_text: true });
+
+        assert_eq!(report.taken, ["teSt", "ruSt", "tEXt", "tEXt", "zzZz"]);
+        assert_eq!(png.chunks().last().unwrap().chunk_type(), ChunkType::IEND);
+        assert_eq!(png.chunk_count(), 7);
+    }
+
+    #[test]
+    fn test_merge_skips_unsafe_chunks_and_places_the_others() {
+        let mut png = merge_target();
+        let source = Png::from_chunks(vec![
+            chunk_from_strings("IHDR", "other header").unwrap(),
+            chunk_from_strings("gAMA", "gamma").unwrap(),
+            chunk_from_strings("pHYs", "density").unwrap(),
+            chunk_from_strings("tRNS", "alpha").unwrap(),
+            chunk_from_strings("IDAT", "other pixels").unwrap(),
+            chunk_from_strings("zzZz", "last").unwrap(),
+            chunk_from_strings("bKGD", "background").unwrap(),
+            chunk_from_strings("IEND", "").unwrap(),
+        ]);
+
+        let report = png.merge(&source, MergePolicy::default());
+
+        assert_eq!(chunk_list(&png), [
+            "IHDR header", "ruSt mine", "tEXt Author:me", "pHYs density", "IDAT pixels", "zzZz last", "IEND ",
+        ]);
+        assert_eq!(report.taken, ["pHYs", "zzZz"]);
+        assert_eq!(report.skipped, ["gAMA", "tRNS", "bKGD"]);
+        assert_eq!(report.positions, [3, 5]);
+    }
+
+    #[test]
+    fn test_insert_after_type() {
+        let mut png = testing_png();
+        png.insert_after_type("FrSt", chunk_from_strings("ruSt", "after the first").unwrap()).unwrap();
+
+        assert_eq!(png.chunks()[1].chunk_type().to_string(), "ruSt");
+        assert_eq!(png.chunk_count(), 4);
+        assert!(png.insert_after_type("IHDR", chunk_from_strings("ruSt", "").unwrap()).unwrap_err().to_string().contains("no IHDR chunk"));
+    }
+
+    #[test]
+    fn test_sort_chunks_canonical() {
+        let mut png = Png::from_chunks(vec![
+            chunk_from_strings("IEND", "").unwrap(),
+            chunk_from_strings("ruSt", "custom").unwrap(),
+            chunk_from_strings("IDAT", "first").unwrap(),
+            chunk_from_strings("tRNS", "alpha").unwrap(),
+            chunk_from_strings("tEXt", "text").unwrap(),
+            chunk_from_strings("IDAT", "second").unwrap(),
+            chunk_from_strings("PLTE", "palette").unwrap(),
+            chunk_from_strings("IHDR", "header").unwrap(),
+            chunk_from_strings("gAMA", "gamma").unwrap(),
+            chunk_from_strings("sRGB", "srgb").unwrap(),
+            chunk_from_strings("IDAT", "third").unwrap(),
+        ]);
+
+        png.sort_chunks_canonical().unwrap();
+
+        let order: Vec<String> = png.chunks().iter().map(|chunk| format!("{} {}", chunk.chunk_type(), chunk.data_as_string().unwrap())).collect();
+        assert_eq!(order, [
+            "IHDR header", "gAMA gamma", "sRGB srgb", "PLTE palette", "tRNS alpha", "tEXt text",
+            "IDAT first", "IDAT second", "IDAT third", "ruSt custom", "IEND ",
+        ]);
+        assert!(png.order_violations().is_empty());
+        assert!(ordering::violations(&png).is_empty());
+
+        // The frame chunks of an animation are left where they are
+        let animated = vec![
+            chunk_from_strings("IHDR", "header").unwrap(),
+            chunk_from_strings("acTL", "control").unwrap(),
+            chunk_from_strings("fcTL", "first frame").unwrap(),
+            chunk_from_strings("IDAT", "default image").unwrap(),
+            chunk_from_strings("fcTL", "second frame").unwrap(),
+            chunk_from_strings("fdAT", "frame data").unwrap(),
+            chunk_from_strings("IEND", "").unwrap(),
+        ];
+        let mut png = Png::from_chunks(animated.clone());
+        assert!(png.sort_chunks_canonical().unwrap_err().to_string().contains("Animated"));
+        assert_eq!(png.chunks(), animated.as_slice());
+    }
+
+    #[test]
+    fn test_byte_offsets() {
+        let png = testing_png();
+        let first = 12 + "I am the first chunk".len() as u64;
+        let second = 12 + "I am another chunk".len() as u64;
+
+        assert_eq!(png.byte_offsets(), vec![8, 8 + first, 8 + first + second]);
+    }
+
+    #[test]
+    fn test_chunk_positions() {
+        let mut png = testing_png();
+        png.trailing = b"trailing".to_vec();
+        let bytes = png.as_bytes();
+        let spans = png.chunk_positions();
+
+        assert_eq!(spans.len(), 3);
+        assert_eq!(spans[1].index, 1);
+        assert_eq!(spans[1].chunk_type, "miDl");
+
+        for (span, chunk) in spans.iter().zip(png.chunks()) {
+            assert_eq!(bytes[span.range.start as usize..span.range.end as usize], chunk.as_bytes()[..]);
+        }
+
+        assert_eq!(spans[2].range.end as usize, bytes.len() - b"trailing".len());
+    }
+
+    #[test]
+    fn test_offsets_past_4_gb() {
+        // Three chunks of the largest length the specification allows, without allocating them
+        let largest = 12 + (1u64 << 31) - 1;
+        let positions = offsets(8, [largest, largest, largest, 12]);
+
+        assert_eq!(positions, [8, 8 + largest, 8 + 2 * largest, 8 + 3 * largest]);
+        assert!(positions[3] > u64::from(u32::MAX));
+        assert_eq!(offsets(u64::MAX - 1, [4, 4]), [u64::MAX - 1, u64::MAX]);
+    }
+
+    #[test]
+    fn test_chunk_length_past_the_end_of_memory() {
+        // A length field of 0xffffffff near the top of the address space must not wrap around
+        assert_eq!(chunk::framed_end(usize::MAX - 20, u32::MAX), None);
+        assert_eq!(chunk::framed_end(8, 0), Some(20));
+
+        let mut bytes = Png::STANDARD_HEADER.to_vec();
+        bytes.extend_from_slice(&u32::MAX.to_be_bytes());
+        bytes.extend_from_slice(b"IDAT");
+        assert_eq!(Png::try_from(bytes.as_slice()).unwrap_err().to_string(), "Unexpected end of file while reading a chunk");
+    }
+
     #[test]
     fn test_append_chunk() {
         let mut png = testing_png();
@@ -247,6 +1196,145 @@ mod tests {
         assert!(chunk.is_none());
     }
 
+    #[test]
+    fn test_retain_chunks() {
+        let mut png = testing_png();
+        let removed = png.retain_chunks(|chunk| chunk.chunk_type().is_critical());
+
+        assert_eq!(png.chunks().len(), 2);
+        assert_eq!(removed.len(), 1);
+        assert_eq!(removed[0].chunk_type().to_string(), "miDl");
+    }
+
+    #[test]
+    fn test_total_size() {
+        let mut pngs = vec![Png::from_chunks(vec![]), testing_png()];
+
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("TeSt", "").unwrap());
+        png.append_chunk(chunk_from_strings("IDAT", &"x".repeat(5000)).unwrap());
+        pngs.push(png);
+
+        for png in &pngs {
+            assert_eq!(png.total_size(), png.as_bytes().len());
+        }
+
+        assert_eq!(pngs[0].total_size(), 8);
+    }
+
+    #[test]
+    fn test_ancillary_size() {
+        let mut png = testing_png();
+        assert_eq!(png.ancillary_size(), 12 + 18);
+
+        png.append_chunk(chunk_from_strings("teSt", "Message").unwrap());
+        assert_eq!(png.ancillary_size(), 12 + 18 + 12 + 7);
+        assert_eq!(png.ancillary_size(), png.total_size() - 8 - png.size_of_type("FrSt") - png.size_of_type("LASt"));
+    }
+
+    #[test]
+    fn test_display_summary() {
+        let png = testing_png();
+
+        assert_eq!(png.to_string(), format!("3 chunks, {} bytes: FrSt, miDl, LASt", png.total_size()));
+        assert_eq!(Png::from_chunks(vec![chunk_from_strings("IEND", "").unwrap()]).to_string(), "1 chunk, 20 bytes: IEND");
+    }
+
+    #[test]
+    fn test_find() {
+        let png = testing_png();
+
+        assert_eq!(png.find(|chunk| chunk.length() > 18).unwrap().chunk_type().to_string(), "FrSt");
+        assert_eq!(png.find(|chunk| chunk.length() < 20).unwrap().chunk_type().to_string(), "miDl");
+        assert!(png.find(|chunk| chunk.length() > 20).is_none());
+    }
+
+    #[test]
+    fn test_insert_and_remove_at() {
+        let mut png = testing_png();
+        png.insert_chunk(1, chunk_from_strings("TeSt", "Message").unwrap()).unwrap();
+
+        assert_eq!(png.position_of_type("TeSt"), Some(1));
+        assert_eq!(png.position_of_type("miDl"), Some(2));
+        assert!(png.insert_chunk(5, chunk_from_strings("TeSt", "Message").unwrap()).is_err());
+
+        let removed = png.remove_chunk_at(1).unwrap();
+        assert_eq!(removed.chunk_type().to_string(), "TeSt");
+        assert!(png.remove_chunk_at(3).is_err());
+    }
+
+    #[test]
+    fn test_remove_missing_chunk() {
+        let mut png = testing_png();
+        let error = png.remove_chunk("TeSt").unwrap_err();
+        assert!(error.to_string().contains("TeSt"));
+        assert_eq!(crate::error::exit_code(&error), crate::error::EXIT_NOT_FOUND);
+    }
+
+    #[test]
+    fn test_unverified_keeps_bad_crc() {
+        let mut bytes = testing_png().as_bytes();
+
+        // Corrupt the last byte of the crc of the first chunk
+        let first_crc_end = 8 + 12 + "I am the first chunk".len();
+        bytes[first_crc_end - 1] ^= 0xff;
+
+        assert!(Png::try_from(bytes.as_ref()).is_err());
+
+        let png = Png::try_from_unverified(bytes.as_ref()).unwrap();
+        let chunk = &png.chunks()[0];
+        assert_ne!(chunk.crc(), chunk.computed_crc());
+    }
+
+    #[test]
+    fn test_verify_all_crcs() {
+        let mut bytes = testing_png().as_bytes();
+        let second_crc_end = 8 + 24 + "I am the first chunk".len() + "I am another chunk".len();
+        bytes[second_crc_end - 1] ^= 0xff;
+
+        let png = Png::try_from_unverified(bytes.as_ref()).unwrap();
+        let results = png.verify_all_crcs();
+
+        assert_eq!(results, [("FrSt".to_string(), true), ("miDl".to_string(), false), ("LASt".to_string(), true)]);
+        assert_eq!(results.iter().filter(|(_, valid)| !valid).count(), 1);
+
+        let crc = Crc::<u32>::new(&crc::CRC_32_BZIP2);
+        assert!(png.verify_all_crcs_with(&crc).iter().all(|(_, valid)| !valid));
+    }
+
+    #[test]
+    fn test_skip_idat_crc() {
+        let crc = Crc::<u32>::new(&crc::CRC_32_ISO_HDLC);
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+
+        // Give the IDAT chunk a wrong crc
+        let index = png.position_of_type("IDAT").unwrap();
+        let idat = &png.chunks()[index];
+        let mut damaged = idat.as_bytes();
+        let last = damaged.len() - 1;
+        damaged[last] ^= 0xff;
+        png.chunks_mut()[index] = Chunk::try_from_unverified(&damaged).unwrap();
+        let bytes = png.as_bytes();
+
+        assert!(Png::try_from_skipping_crc(&bytes, &crc, CrcSkip::Nothing).is_err());
+
+        let parsed = Png::try_from_skipping_crc(&bytes, &crc, CrcSkip::Idat).unwrap();
+        assert_eq!(parsed.as_bytes(), bytes);
+        assert!(Png::try_from_skipping_crc(&bytes, &crc, CrcSkip::Critical).is_ok());
+    }
+
+    #[test]
+    fn test_skip_still_checks_ancillary_crc() {
+        let crc = Crc::<u32>::new(&crc::CRC_32_ISO_HDLC);
+        let mut bytes = testing_png().as_bytes();
+
+        // Corrupt the crc of the second chunk, the ancillary miDl
+        let second_crc_end = 8 + 12 + "I am the first chunk".len() + 12 + "I am another chunk".len();
+        bytes[second_crc_end - 1] ^= 0xff;
+
+        assert!(Png::try_from_skipping_crc(&bytes, &crc, CrcSkip::Critical).is_err());
+    }
+
     #[test]
     fn test_png_from_image_file() {
         let png = Png::try_from(&PNG_FILE[..]);
@@ -261,6 +1349,39 @@ mod tests {
         assert_eq!(actual, expected);
     }
 
+    #[test]
+    fn test_trailing_bytes_round_trip() {
+        let mut bytes = PNG_FILE.to_vec();
+        bytes.extend_from_slice(b"PK\x03\x04zipped");
+
+        let png = Png::try_from(bytes.as_ref()).unwrap();
+        let mut written = vec![];
+        png.write_to(&mut written).unwrap();
+
+        assert_eq!(png.trailing_bytes(), b"PK\x03\x04zipped");
+        assert_eq!(png.chunk_count(), Png::try_from(&PNG_FILE[..]).unwrap().chunk_count());
+        assert_eq!(png.total_size(), bytes.len());
+        assert_eq!(png.as_bytes(), bytes);
+        assert_eq!(written, bytes);
+        assert!(Png::try_from(&PNG_FILE[..]).unwrap().trailing_bytes().is_empty());
+
+        // Before IEND the same bytes are a broken file, not trailing data
+        let mut no_iend = testing_png().as_bytes();
+        no_iend.extend_from_slice(b"PK\x03\x04zipped");
+        assert!(Png::try_from(no_iend.as_ref()).is_err());
+    }
+
+    #[test]
+    fn test_read_from_and_write_to() {
+        let png = Png::read_from(&mut &PNG_FILE[..], &Crc::<u32>::new(&crc::CRC_32_ISO_HDLC)).unwrap();
+
+        let mut written = vec![];
+        png.write_to(&mut written).unwrap();
+
+        assert_eq!(written, PNG_FILE.to_vec());
+        assert_eq!(png.as_bytes(), PNG_FILE.to_vec());
+    }
+
     #[test]
     fn test_png_trait_impls() {
         let chunk_bytes: Vec<u8> = testing_chunks()
@@ -0,0 +1,408 @@
+use std::{fs, path::{Path, PathBuf}, str::FromStr, sync::atomic::{AtomicBool, Ordering}};
+
+use crc::Crc;
+
+use crate::{error::PngMeError, filter::ChunkFilter, png::{self, Png}, verify, zip, Result};
+
+/// The policy file `check-tree` reads from the root of the tree it scans
+pub const POLICY_FILE: &str = "pngme-policy.toml";
+
+/// Which chunks a tree of PNG files may not contain. A chunk breaks the policy
+/// when it matches a deny rule and no allow rule.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Policy {
+    pub deny: Vec<ChunkFilter>,
+    pub allow: Vec<ChunkFilter>,
+}
+
+/// A chunk that breaks the policy
+#[derive(Debug, Clone, PartialEq)]
+pub struct Violation {
+    pub chunk_type: String,
+    pub length: u32,
+    pub rule: ChunkFilter,
+}
+
+impl Policy {
+    /// Parses a policy file. Only the subset of TOML a policy needs is understood:
+    /// `deny` and `allow` keys holding a string or an array of strings, and comments.
+    ///
+    /// ```toml
+    /// deny = ["private", "text-keyword:Secret"]
+    /// allow = ["type:ruSt"]
+    /// ```
+    pub fn parse(text: &str) -> Result<Policy> {
+
+        let mut policy = Policy::default();
+
+        for (line_number, statement) in statements(text)? {
+            let (key, value) = statement.split_once('=')
+                .ok_or_else(|| format!("line {}: expected 'deny = [...]' or 'allow = [...]'", line_number))?;
+
+            let rules = strings(value.trim())
+                .map_err(|error| format!("line {}: {}", line_number, error))?
+                .iter()
+                .map(|rule| ChunkFilter::from_str(rule).map_err(|error| format!("line {}: {}", line_number, error)))
+                .collect::<std::result::Result<Vec<_>, _>>()?;
+
+            match key.trim() {
+                "deny" => policy.deny.extend(rules),
+                "allow" => policy.allow.extend(rules),
+                key => return Err(format!("line {}: unknown key '{}', expected deny or allow", line_number, key).into()),
+            }
+        }
+
+        Ok(policy)
+    }
+
+    /// Adds the rules of another policy to this one
+    pub fn extend(&mut self, other: Policy) {
+
+        self.deny.extend(other.deny);
+        self.allow.extend(other.allow);
+    }
+
+    /// Every chunk of the file that breaks the policy, with the first deny rule it matches
+    pub fn violations(&self, png: &Png) -> Vec<Violation> {
+
+        png.chunks().iter()
+            .filter(|chunk| !self.allow.iter().any(|rule| rule.matches(chunk)))
+            .filter_map(|chunk| {
+                let rule = self.deny.iter().find(|rule| rule.matches(chunk))?;
+
+                Some(Violation { chunk_type: chunk.chunk_type().to_string(), length: chunk.length(), rule: rule.clone() })
+            })
+            .collect()
+    }
+}
+
+/// What checking one PNG file, or one PNG inside an archive, found
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    Violations(Vec<Violation>),
+    /// The file could not be read or parsed
+    Unreadable(String),
+    /// An archive entry that was left alone, like an encrypted one
+    Skipped(String),
+    /// The file was not checked, because the run was interrupted first
+    Interrupted,
+}
+
+/// The outcome of a PNG file, labelled with its path, or `archive.zip!path/inside.png`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Checked {
+    pub label: String,
+    pub outcome: Outcome,
+}
+
+/// Every `.png` file under `root`, along with ZIP based archives when `archives`
+/// is set, sorted by path. A `root` that is a file is returned as is. Symbolic
+/// links to directories are not followed, so that a link cannot make the walk loop.
+pub fn find_files(root: &Path, archives: bool) -> Result<Vec<PathBuf>> {
+
+    if root.is_file() {
+        return Ok(vec![root.to_path_buf()])
+    }
+
+    let mut files = vec![];
+    let mut directories = vec![root.to_path_buf()];
+
+    while let Some(directory) = directories.pop() {
+        for entry in fs::read_dir(&directory).map_err(PngMeError::from)? {
+            let entry = entry.map_err(PngMeError::from)?;
+            let path = entry.path();
+
+            let is_png = path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("png"));
+
+            if entry.file_type().map_err(PngMeError::from)?.is_dir() {
+                directories.push(path);
+            } else if path.is_file() && (is_png || archives && zip::is_archive(&path)) {
+                files.push(path);
+            }
+        }
+    }
+
+    files.sort();
+
+    Ok(files)
+}
+
+/// Checks every file on up to `threads` threads, in the order of `files`. With
+/// `archives`, the PNG files inside archives are checked too, found by their
+/// signature; `force_archive` reads every file as an archive, whatever its extension.
+/// Once `interrupted` is set, the files not started yet are left unchecked.
+pub fn check_files(files: &[PathBuf], policy: &Policy, crc: &Crc<u32>, threads: usize, archives: bool, force_archive: bool, interrupted: &AtomicBool) -> Vec<Checked> {
+
+    verify::parallel_map(files, threads, |file| {
+        let label = file.display().to_string();
+
+        if interrupted.load(Ordering::SeqCst) {
+            return vec![Checked { label, outcome: Outcome::Interrupted }]
+        }
+
+        let bytes = match fs::read(file) {
+            Ok(bytes) => bytes,
+            Err(error) => return vec![Checked { label, outcome: Outcome::Unreadable(error.to_string()) }],
+        };
+
+        if archives && (force_archive || zip::is_archive(file)) {
+            check_archive(&label, &bytes, policy, crc)
+        } else {
+            vec![Checked { label, outcome: check_png(&bytes, policy, crc) }]
+        }
+    })
+    .into_iter()
+    .flatten()
+    .collect()
+}
+
+fn check_png(bytes: &[u8], policy: &Policy, crc: &Crc<u32>) -> Outcome {
+
+    match Png::try_from_with_crc(bytes, crc) {
+        Ok(png) => Outcome::Violations(policy.violations(&png)),
+        Err(error) => Outcome::Unreadable(error.to_string()),
+    }
+}
+
+/// Checks the PNG entries of an archive in memory, without extracting them
+fn check_archive(label: &str, archive: &[u8], policy: &Policy, crc: &Crc<u32>) -> Vec<Checked> {
+
+    let entries = match zip::entries(archive) {
+        Ok(entries) => entries,
+        Err(error) => return vec![Checked { label: label.to_string(), outcome: Outcome::Unreadable(error.to_string()) }],
+    };
+
+    entries.iter()
+        .filter(|entry| !entry.is_directory())
+        .filter_map(|entry| {
+            let label = format!("{}!{}", label, entry.name);
+
+            let outcome = if entry.encrypted {
+                Outcome::Skipped("the entry is encrypted".to_string())
+            } else if entry.size > zip::ENTRY_LIMIT {
+                Outcome::Skipped(format!("the entry is larger than {} bytes", zip::ENTRY_LIMIT))
+            } else {
+                match entry.read(archive, zip::ENTRY_LIMIT) {
+                    Ok(data) if png::is_png(&data) => check_png(&data, policy, crc),
+                    Ok(_) => return None,
+                    Err(error) => Outcome::Unreadable(error.to_string()),
+                }
+            };
+
+            Some(Checked { label, outcome })
+        })
+        .collect()
+}
+
+/// Splits a policy file into `key = value` statements with the line they start
+/// on, joining arrays that span several lines and dropping comments
+fn statements(text: &str) -> Result<Vec<(usize, String)>> {
+
+    let mut statements = vec![];
+    let mut current = String::new();
+    let mut start = 0;
+    let mut depth = 0;
+
+    for (index, line) in text.lines().enumerate() {
+        let line = strip_comment(line);
+
+        if line.trim().is_empty() {
+            continue
+        }
+
+        if line.trim_start().starts_with('[') && depth == 0 {
+            return Err(format!("line {}: tables are not supported in {}", index + 1, POLICY_FILE).into())
+        }
+
+        if current.is_empty() {
+            start = index + 1;
+        }
+
+        current.push_str(line);
+        current.push(' ');
+        depth += brackets(line);
+
+        if depth == 0 {
+            statements.push((start, std::mem::take(&mut current)));
+        }
+    }
+
+    if !current.is_empty() {
+        return Err(format!("line {}: unterminated array", start).into())
+    }
+
+    Ok(statements)
+}
+
+/// The line up to a `#` that is not inside a string
+fn strip_comment(line: &str) -> &str {
+
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for (index, character) in line.char_indices() {
+        match character {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..index],
+            _ => {},
+        }
+    }
+
+    line
+}
+
+/// How many more arrays the line opens than it closes, outside strings
+fn brackets(line: &str) -> i32 {
+
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for character in line.chars() {
+        match character {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            '[' if !in_string => depth += 1,
+            ']' if !in_string => depth -= 1,
+            _ => {},
+        }
+    }
+
+    depth
+}
+
+/// Parses a string, or an array of strings allowing a trailing comma
+fn strings(value: &str) -> Result<Vec<String>> {
+
+    let (inner, is_array) = match value.strip_prefix('[').and_then(|value| value.strip_suffix(']')) {
+        Some(inner) => (inner, true),
+        None => (value, false),
+    };
+
+    let mut strings = vec![];
+    let mut characters = inner.trim().chars().peekable();
+
+    while let Some(character) = characters.next() {
+        match character {
+            '"' => {
+                let mut string = String::new();
+                let mut closed = false;
+
+                while let Some(character) = characters.next() {
+                    match character {
+                        '"' => {
+                            closed = true;
+                            break
+                        },
+                        '\\' => match characters.next() {
+                            Some(escaped @ ('"' | '\\')) => string.push(escaped),
+                            _ => return Err("only \\\" and \\\\ escapes are supported".into()),
+                        },
+                        character => string.push(character),
+                    }
+                }
+
+                if !closed {
+                    return Err("unterminated string".into())
+                }
+
+                strings.push(string);
+            },
+            ',' if is_array => {},
+            character if character.is_whitespace() => {},
+            character => return Err(format!("unexpected '{}', expected a quoted string", character).into()),
+        }
+    }
+
+    if !is_array && strings.len() != 1 {
+        return Err("expected a quoted string or an array of quoted strings".into())
+    }
+
+    Ok(strings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+
+    #[test]
+    fn test_parse_policy() {
+        let policy = Policy::parse(
+            "# Nothing private ships\ndeny = [\n  \"private\",  # ruSt included\n  \"text-keyword:Secret\",\n]\nallow = \"type:ruSt\"\n"
+        ).unwrap();
+
+        assert_eq!(policy.deny, [ChunkFilter::Private, ChunkFilter::TextKeyword("Secret".to_string())]);
+        assert_eq!(policy.allow, [ChunkFilter::Type("ruSt".to_string())]);
+        assert_eq!(Policy::parse("").unwrap(), Policy::default());
+    }
+
+    #[test]
+    fn test_parse_invalid_policy() {
+        assert!(Policy::parse("deny = [\"private\"").unwrap_err().to_string().contains("unterminated array"));
+        assert!(Policy::parse("refuse = [\"private\"]").unwrap_err().to_string().contains("unknown key 'refuse'"));
+        assert!(Policy::parse("deny = [\"publik\"]").unwrap_err().to_string().starts_with("line 1: Unknown filter"));
+        assert!(Policy::parse("[policy]\ndeny = []").is_err());
+        assert!(Policy::parse("deny = private").is_err());
+    }
+
+    #[test]
+    fn test_check_archive() {
+        let png = Png::from_chunks(vec![
+            Chunk::with_type_and_data("IHDR", &[0; 13]).unwrap(),
+            Chunk::with_type_and_data("teSt", b"denied").unwrap(),
+            Chunk::with_type_and_data("IEND", b"").unwrap(),
+        ]);
+
+        let archive = zip::tests::build_zip(&[
+            ("res/", b"", false, false),
+            ("res/icon.png", &png.as_bytes(), true, false),
+            ("res/named.png.txt", &png.as_bytes(), false, false),
+            ("notes.txt", b"not a png", true, false),
+            ("secret.png", b"encrypted", false, true),
+        ]);
+
+        let policy = Policy { deny: vec![ChunkFilter::Private], allow: vec![] };
+        let checked = check_archive("app.apk", &archive, &policy, &crc::Crc::<u32>::new(&crc::CRC_32_ISO_HDLC));
+
+        let labels: Vec<&str> = checked.iter().map(|checked| checked.label.as_str()).collect();
+        assert_eq!(labels, ["app.apk!res/icon.png", "app.apk!res/named.png.txt", "app.apk!secret.png"]);
+        assert!(matches!(&checked[0].outcome, Outcome::Violations(violations) if violations.len() == 1));
+        assert_eq!(checked[2].outcome, Outcome::Skipped("the entry is encrypted".to_string()));
+    }
+
+    #[test]
+    fn test_check_files_stops_when_interrupted() {
+        let policy = Policy { deny: vec![ChunkFilter::Private], allow: vec![] };
+        let files = [PathBuf::from("does/not/exist.png"), PathBuf::from("nor/this.png")];
+        let crc = crc::Crc::<u32>::new(&crc::CRC_32_ISO_HDLC);
+
+        let checked = check_files(&files, &policy, &crc, 2, false, false, &AtomicBool::new(true));
+        assert!(checked.iter().all(|checked| checked.outcome == Outcome::Interrupted));
+
+        let checked = check_files(&files, &policy, &crc, 2, false, false, &AtomicBool::new(false));
+        assert!(checked.iter().all(|checked| matches!(checked.outcome, Outcome::Unreadable(_))));
+    }
+
+    #[test]
+    fn test_violations() {
+        let png = Png::from_chunks(vec![
+            Chunk::with_type_and_data("IHDR", &[0; 13]).unwrap(),
+            Chunk::with_type_and_data("ruSt", b"allowed").unwrap(),
+            Chunk::with_type_and_data("teSt", b"denied").unwrap(),
+            Chunk::with_type_and_data("tEXt", b"Secret\0value").unwrap(),
+            Chunk::with_type_and_data("IEND", b"").unwrap(),
+        ]);
+
+        let policy = Policy::parse("deny = [\"private\", \"text-keyword:Secret\"]\nallow = [\"ruSt\"]").unwrap();
+        let violations = policy.violations(&png);
+
+        assert_eq!(violations, [
+            Violation { chunk_type: "teSt".to_string(), length: 6, rule: ChunkFilter::Private },
+            Violation { chunk_type: "tEXt".to_string(), length: 12, rule: ChunkFilter::TextKeyword("Secret".to_string()) },
+        ]);
+    }
+}
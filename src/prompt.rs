@@ -0,0 +1,76 @@
+use std::io::{BufRead, BufReader, Write};
+
+use crate::{error::PngMeError, Result};
+
+/// Asks for a passphrase on the terminal without echoing it. With `confirm` it is asked
+/// twice, for a new passphrase that a typo would otherwise make unrecoverable.
+pub fn passphrase(confirm: bool) -> Result<String> {
+
+    let passphrase = read_hidden("Passphrase: ")?;
+
+    if passphrase.is_empty() {
+        return Err("The passphrase is empty".into())
+    }
+
+    if confirm && read_hidden("Repeat the passphrase: ")? != passphrase {
+        return Err("The passphrases do not match".into())
+    }
+
+    Ok(passphrase)
+}
+
+/// Reads from /dev/tty with echo turned off by `stty`, so that it works even when
+/// stdin and stdout are redirected
+#[cfg(unix)]
+fn read_hidden(prompt: &str) -> Result<String> {
+
+    use std::{fs::File, process::{Command, Stdio}};
+
+    let tty = File::options().read(true).write(true).open("/dev/tty")
+        .map_err(|error| format!("Cannot ask for a passphrase without a terminal: {}", error))?;
+
+    let stty = |setting: &str| -> Result<()> {
+        let status = Command::new("stty").arg(setting).stdin(Stdio::from(tty.try_clone()?)).status()
+            .map_err(|error| format!("Cannot run stty to hide the passphrase: {}", error))?;
+
+        if status.success() { Ok(()) } else { Err("stty cannot hide the passphrase on this terminal".into()) }
+    };
+
+    write!(&tty, "{}", prompt).map_err(PngMeError::from)?;
+    stty("-echo")?;
+
+    let line = read_line(&tty);
+
+    // The echo comes back even when reading failed
+    stty("echo")?;
+    writeln!(&tty).map_err(PngMeError::from)?;
+
+    line
+}
+
+#[cfg(not(unix))]
+fn read_hidden(prompt: &str) -> Result<String> {
+
+    eprint!("{}(it will show as you type) ", prompt);
+
+    read_line(std::io::stdin())
+}
+
+fn read_line(reader: impl std::io::Read) -> Result<String> {
+
+    let mut line = String::new();
+    BufReader::new(reader).read_line(&mut line).map_err(PngMeError::from)?;
+
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_line() {
+        assert_eq!(read_line(&b"correct horse\r\nrest"[..]).unwrap(), "correct horse");
+        assert_eq!(read_line(&b""[..]).unwrap(), "");
+    }
+}
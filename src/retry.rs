@@ -0,0 +1,159 @@
+use std::{cell::Cell, io, path::Path, thread, time::Duration};
+
+use crate::Error;
+
+thread_local! {
+    /// Set from --retry and --retry-delay when a command starts
+    static RETRY: Cell<Retry> = const { Cell::new(Retry::NONE) };
+}
+
+/// How often to try again to write a file another program holds open, and how
+/// long to wait before the first retry. The wait doubles after every attempt.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Retry {
+    pub attempts: u32,
+    pub delay: Duration,
+}
+
+impl Retry {
+    pub const NONE: Retry = Retry { attempts: 0, delay: Duration::ZERO };
+}
+
+/// Sets how writes on this thread retry
+pub fn configure(retry: Retry) {
+
+    RETRY.with(|current| current.set(retry));
+}
+
+/// How writes on this thread retry
+pub fn current() -> Retry {
+
+    RETRY.with(|current| current.get())
+}
+
+/// Parses a delay in milliseconds, optionally followed by ms or s, like `200`, `200ms` or `2s`
+pub fn parse_delay(text: &str) -> Result<Duration, String> {
+
+    let text = text.trim();
+    let digits = text.find(|character: char| !character.is_ascii_digit()).unwrap_or(text.len());
+    let (number, unit) = text.split_at(digits);
+
+    let number: u64 = number.parse().map_err(|_| format!("'{}' does not start with a number", text))?;
+
+    match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "ms" => Ok(Duration::from_millis(number)),
+        "s" => Ok(Duration::from_secs(number)),
+        _ => Err(format!("Unknown delay unit '{}' in '{}', expected ms or s", unit, text)),
+    }
+}
+
+/// Runs `operation`, and runs it again up to `retry.attempts` times while it fails
+/// with an error `is_locked` accepts, waiting longer each time
+pub fn retrying<T>(retry: Retry, is_locked: impl Fn(&io::Error) -> bool, mut operation: impl FnMut() -> io::Result<T>) -> io::Result<T> {
+
+    let mut delay = retry.delay;
+
+    for _ in 0..retry.attempts {
+        match operation() {
+            Err(error) if is_locked(&error) => {
+                thread::sleep(delay);
+                delay = delay.saturating_mul(2);
+            },
+            result => return result,
+        }
+    }
+
+    operation()
+}
+
+/// Whether another program holding the file open made the operation fail. Only
+/// Windows refuses to replace or write a file that is open elsewhere.
+#[cfg(windows)]
+pub fn is_sharing_violation(error: &io::Error) -> bool {
+
+    // ERROR_SHARING_VIOLATION and ERROR_LOCK_VIOLATION
+    matches!(error.raw_os_error(), Some(32) | Some(33))
+}
+
+#[cfg(not(windows))]
+pub fn is_sharing_violation(_error: &io::Error) -> bool {
+
+    false
+}
+
+/// Explains a sharing violation, instead of the bare os error 32
+pub fn explain(file_path: &Path, error: io::Error, is_locked: impl Fn(&io::Error) -> bool) -> Error {
+
+    if !is_locked(&error) {
+        return crate::error::PngMeError::from(error).into()
+    }
+
+    let hint = if current().attempts == 0 { ", or pass --retry to wait for it" } else { "" };
+
+    format!(
+        "Another program has {} open ({}), close it and try again{}",
+        file_path.display(), error, hint
+    ).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn locked() -> io::Error {
+        io::Error::new(io::ErrorKind::ResourceBusy, "the file is open elsewhere")
+    }
+
+    fn is_busy(error: &io::Error) -> bool {
+        error.kind() == io::ErrorKind::ResourceBusy
+    }
+
+    #[test]
+    fn test_parse_delay() {
+        assert_eq!(parse_delay("200"), Ok(Duration::from_millis(200)));
+        assert_eq!(parse_delay("200ms"), Ok(Duration::from_millis(200)));
+        assert_eq!(parse_delay("2s"), Ok(Duration::from_secs(2)));
+        assert!(parse_delay("ms").is_err());
+        assert!(parse_delay("2h").is_err());
+    }
+
+    #[test]
+    fn test_retrying_until_unlocked() {
+        let retry = Retry { attempts: 3, delay: Duration::from_millis(1) };
+        let mut calls = 0;
+
+        let result = retrying(retry, is_busy, || {
+            calls += 1;
+            if calls < 3 { Err(locked()) } else { Ok(calls) }
+        });
+
+        assert_eq!(result.unwrap(), 3);
+    }
+
+    #[test]
+    fn test_retrying_gives_up() {
+        let retry = Retry { attempts: 2, delay: Duration::from_millis(1) };
+        let mut calls = 0;
+
+        let error = retrying(retry, is_busy, || -> io::Result<()> {
+            calls += 1;
+            Err(locked())
+        }).unwrap_err();
+
+        assert_eq!(calls, 3);
+        assert!(explain(Path::new("image.png"), error, is_busy).to_string().starts_with("Another program has image.png open"));
+    }
+
+    #[test]
+    fn test_other_errors_are_not_retried() {
+        let mut calls = 0;
+
+        let error = retrying(Retry { attempts: 3, delay: Duration::from_millis(1) }, is_busy, || -> io::Result<()> {
+            calls += 1;
+            Err(io::Error::from(io::ErrorKind::PermissionDenied))
+        }).unwrap_err();
+
+        assert_eq!(calls, 1);
+        assert!(!explain(Path::new("image.png"), error, is_busy).to_string().contains("Another program"));
+    }
+}
@@ -0,0 +1,99 @@
+use crate::Result;
+
+const FILTER_NONE: u8 = 0;
+const FILTER_SUB: u8 = 1;
+const FILTER_UP: u8 = 2;
+const FILTER_AVERAGE: u8 = 3;
+const FILTER_PAETH: u8 = 4;
+
+/// Reverses the filter of every scanline, returning the rows of raw bytes
+/// back to back without their filter type bytes
+pub fn unfilter(data: &[u8], row_length: usize, bytes_per_pixel: usize) -> Result<Vec<u8>> {
+
+    if !data.len().is_multiple_of(row_length + 1) {
+        return Err(format!("The image data holds {} bytes, which is not a whole number of {} byte scanlines", data.len(), row_length + 1).into())
+    }
+
+    let mut pixels = Vec::with_capacity(data.len() / (row_length + 1) * row_length);
+
+    for (row, line) in data.chunks(row_length + 1).enumerate() {
+        let (filter, line) = line.split_first().ok_or("The image data has an empty scanline")?;
+        let start = row * row_length;
+
+        for (index, byte) in line.iter().enumerate() {
+            let left = if index >= bytes_per_pixel { pixels[start + index - bytes_per_pixel] } else { 0 };
+            let up = if row > 0 { pixels[start - row_length + index] } else { 0 };
+            let up_left = if row > 0 && index >= bytes_per_pixel { pixels[start - row_length + index - bytes_per_pixel] } else { 0 };
+
+            let prediction = match *filter {
+                FILTER_NONE => 0,
+                FILTER_SUB => left,
+                FILTER_UP => up,
+                FILTER_AVERAGE => ((u16::from(left) + u16::from(up)) / 2) as u8,
+                FILTER_PAETH => paeth(left, up, up_left),
+                filter => return Err(format!("Unknown filter type {} on scanline {}", filter, row).into()),
+            };
+
+            pixels.push(byte.wrapping_add(prediction));
+        }
+    }
+
+    Ok(pixels)
+}
+
+/// Prefixes every row with the filter type None. Compression suffers a little,
+/// but every decoder reads it.
+pub fn filter_none(pixels: &[u8], row_length: usize) -> Vec<u8> {
+
+    pixels.chunks(row_length)
+        .flat_map(|row| std::iter::once(FILTER_NONE).chain(row.iter().copied()))
+        .collect()
+}
+
+fn paeth(left: u8, up: u8, up_left: u8) -> u8 {
+
+    let estimate = i16::from(left) + i16::from(up) - i16::from(up_left);
+    let (to_left, to_up, to_up_left) = ((estimate - i16::from(left)).abs(), (estimate - i16::from(up)).abs(), (estimate - i16::from(up_left)).abs());
+
+    if to_left <= to_up && to_left <= to_up_left {
+        left
+    } else if to_up <= to_up_left {
+        up
+    } else {
+        up_left
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Two RGB pixels per row, the rows filtered with None, Sub, Up, Average and Paeth
+    const FILTERED: [u8; 35] = [
+        0, 0, 18, 50, 96, 156, 230, 1, 37, 55, 87, 96, 138, 180, 2, 37, 37, 37, 37, 37, 37,
+        3, 74, 83, 99, 67, 88, 237, 4, 37, 37, 37, 37, 37, 37,
+    ];
+    const PIXELS: [u8; 30] = [
+        0, 18, 50, 96, 156, 230, 37, 55, 87, 133, 193, 11, 74, 92, 124, 170, 230, 48,
+        111, 129, 161, 207, 11, 85, 148, 166, 198, 244, 48, 122,
+    ];
+
+    #[test]
+    fn test_unfilter() {
+        assert_eq!(unfilter(&FILTERED, 6, 3).unwrap(), PIXELS);
+    }
+
+    #[test]
+    fn test_filter_none_round_trip() {
+        assert_eq!(unfilter(&filter_none(&PIXELS, 6), 6, 3).unwrap(), PIXELS);
+    }
+
+    #[test]
+    fn test_unfilter_rejects_bad_data() {
+        assert!(unfilter(&FILTERED[..34], 6, 3).is_err());
+
+        let mut unknown = FILTERED;
+        unknown[7] = 5;
+        assert!(unfilter(&unknown, 6, 3).is_err());
+    }
+}
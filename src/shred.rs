@@ -0,0 +1,57 @@
+use std::{fs::File, io::{Seek, SeekFrom, Write}, path::Path};
+
+use crate::{error::PngMeError, Result};
+
+/// Zeroes are written in blocks of this size, so that large chunks do not need a buffer as large
+const BLOCK_SIZE: usize = 64 * 1024;
+
+/// Overwrites `length` bytes of the file at `offset` with zeroes, in place, and waits
+/// for them to reach the disk.
+///
+/// This is best effort: copy-on-write filesystems (btrfs, ZFS, APFS), SSD wear
+/// levelling, snapshots and backups can all keep the old bytes around.
+pub fn zero_range(file_path: &Path, offset: u64, length: usize) -> Result<()> {
+
+    let mut file = File::options().write(true).open(file_path).map_err(PngMeError::from)?;
+    let file_length = file.metadata().map_err(PngMeError::from)?.len();
+
+    if offset + length as u64 > file_length {
+        return Err(format!("Cannot zero {} bytes at offset {}, the file is only {} bytes", length, offset, file_length).into())
+    }
+
+    file.seek(SeekFrom::Start(offset)).map_err(PngMeError::from)?;
+
+    let zeroes = vec![0; length.min(BLOCK_SIZE)];
+    let mut remaining = length;
+
+    while remaining > 0 {
+        let block = remaining.min(BLOCK_SIZE);
+        file.write_all(&zeroes[..block]).map_err(PngMeError::from)?;
+        remaining -= block;
+    }
+
+    file.flush().map_err(PngMeError::from)?;
+    file.sync_all().map_err(PngMeError::from)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_zero_range() {
+        let path = std::env::temp_dir().join(format!("pngme-test-{}-shred.bin", std::process::id()));
+        fs::write(&path, b"keep secret keep").unwrap();
+
+        zero_range(&path, 5, 6).unwrap();
+        let too_far = zero_range(&path, 10, 7);
+        let bytes = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(bytes, b"keep \0\0\0\0\0\0 keep");
+        assert!(too_far.is_err());
+    }
+}
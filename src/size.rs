@@ -0,0 +1,45 @@
+/// Parses a size in bytes, optionally followed by K, M or G for KiB, MiB and GiB
+/// (either case, with an optional B or iB), like `512`, `10K` or `2MiB`
+pub fn parse(text: &str) -> Result<u64, String> {
+
+    let text = text.trim();
+    let digits = text.find(|character: char| !character.is_ascii_digit()).unwrap_or(text.len());
+    let (number, unit) = text.split_at(digits);
+
+    let number: u64 = number.parse().map_err(|_| format!("'{}' does not start with a number of bytes", text))?;
+
+    let multiplier: u64 = match unit.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" | "KIB" => 1024,
+        "M" | "MB" | "MIB" => 1024 * 1024,
+        "G" | "GB" | "GIB" => 1024 * 1024 * 1024,
+        _ => return Err(format!("Unknown size unit '{}' in '{}', expected K, M or G", unit, text)),
+    };
+
+    number.checked_mul(multiplier).ok_or_else(|| format!("'{}' is too large", text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse("512"), Ok(512));
+        assert_eq!(parse("10K"), Ok(10 * 1024));
+        assert_eq!(parse("10k"), Ok(10 * 1024));
+        assert_eq!(parse("2M"), Ok(2 * 1024 * 1024));
+        assert_eq!(parse("2 MiB"), Ok(2 * 1024 * 1024));
+        assert_eq!(parse("1G"), Ok(1024 * 1024 * 1024));
+        assert_eq!(parse("0"), Ok(0));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(parse("").is_err());
+        assert!(parse("K").is_err());
+        assert!(parse("10X").is_err());
+        assert!(parse("-1").is_err());
+        assert!(parse("99999999999999999999G").is_err());
+    }
+}
@@ -0,0 +1,244 @@
+use crc::{Crc, CRC_32_ISO_HDLC};
+
+use crate::{chunk, Result};
+
+/// Every piece starts with its position in the message as a big-endian u16
+pub const INDEX_LEN: usize = 2;
+
+/// A part of a payload spread over several files starts with the id of the payload
+/// (8 bytes), the number of parts (u16) and the crc of the rest (u32), all big-endian.
+/// The rest is a piece as `split` makes it, with its index.
+pub const PART_HEADER_LEN: usize = 8 + 2 + 4;
+
+const PART_CRC: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+
+fn numbered(index: usize, piece: &[u8]) -> Vec<u8> {
+
+    [&(index as u16).to_be_bytes()[..], piece].concat()
+}
+
+/// Cuts the message into pieces of at most `piece_size` bytes, each prefixed with
+/// its sequence index. An empty message still makes one, empty, piece.
+pub fn split(message: &[u8], piece_size: usize) -> Result<Vec<Vec<u8>>> {
+
+    if piece_size == 0 {
+        return Err("The pieces of a split message must hold at least 1 byte".into())
+    }
+
+    let pieces: Vec<&[u8]> = if message.is_empty() { vec![message] } else { message.chunks(piece_size).collect() };
+
+    if pieces.len() > u16::MAX as usize + 1 {
+        return Err(format!(
+            "Splitting {} bytes into pieces of {} bytes makes {} pieces, more than the {} a 2-byte index can number",
+            message.len(), piece_size, pieces.len(), u16::MAX as usize + 1
+        ).into())
+    }
+
+    Ok(pieces.iter()
+        .enumerate()
+        .map(|(index, piece)| numbered(index, piece))
+        .collect())
+}
+
+/// Puts the message back together from its pieces, in the order they are stored.
+/// A piece that is missing, repeated or out of place is an error.
+pub fn join(pieces: &[&[u8]]) -> Result<Vec<u8>> {
+
+    let mut indexes = Vec::with_capacity(pieces.len());
+
+    for (position, piece) in pieces.iter().enumerate() {
+        let index = piece.get(..INDEX_LEN)
+            .ok_or_else(|| format!("Piece {} holds {} bytes, too few for a sequence index", position, piece.len()))?;
+
+        indexes.push(u16::from_be_bytes([index[0], index[1]]) as usize);
+    }
+
+    let mut sorted = indexes.clone();
+    sorted.sort_unstable();
+
+    for (expected, index) in sorted.iter().enumerate() {
+        if *index > expected {
+            return Err(format!("Piece {} of the split message is missing", expected).into())
+        }
+
+        if *index < expected {
+            return Err(format!("Piece {} of the split message appears more than once", index).into())
+        }
+    }
+
+    if let Some((position, index)) = indexes.iter().enumerate().find(|(position, index)| *position != **index) {
+        return Err(format!("The pieces are out of order: piece {} is stored where piece {} belongs", index, position).into())
+    }
+
+    Ok(pieces.iter().flat_map(|piece| piece[INDEX_LEN..].iter().copied()).collect())
+}
+
+/// The size of the largest part `split_parts` makes of `length` bytes
+fn largest_part(length: usize, count: usize) -> usize {
+
+    PART_HEADER_LEN + INDEX_LEN + length.div_ceil(count)
+}
+
+/// Cuts the payload into `count` parts as even as can be, one for each file to hide it in.
+/// Each part carries `id`, which should be random, so that `join_parts` can tell parts of
+/// another payload apart. Every part has to fit in the data of a chunk.
+pub fn split_parts(payload: &[u8], count: usize, id: [u8; 8]) -> Result<Vec<Vec<u8>>> {
+
+    if count == 0 || count > u16::MAX as usize {
+        return Err(format!("A payload can be split into 1 to {} parts, not {}", u16::MAX, count).into())
+    }
+
+    let largest = largest_part(payload.len(), count);
+
+    if largest > chunk::MAX_LENGTH as usize {
+        return Err(format!(
+            "Split into {} parts, the payload makes parts of {} bytes, more than the {} bytes a chunk can hold. Use more files",
+            count, largest, chunk::MAX_LENGTH
+        ).into())
+    }
+
+    Ok((0..count)
+        .map(|index| {
+            let piece = numbered(index, &payload[index * payload.len() / count..(index + 1) * payload.len() / count]);
+
+            [&id[..], &(count as u16).to_be_bytes(), &PART_CRC.checksum(&piece).to_be_bytes(), &piece].concat()
+        })
+        .collect())
+}
+
+/// Puts the payload back together from its parts, given in any order. A part that is
+/// damaged, missing, repeated or from another payload is an error.
+pub fn join_parts(parts: &[&[u8]]) -> Result<Vec<u8>> {
+
+    let mut pieces: Vec<&[u8]> = Vec::with_capacity(parts.len());
+    let mut header: Option<(&[u8], u16)> = None;
+
+    for (position, part) in parts.iter().enumerate() {
+        if part.len() < PART_HEADER_LEN + INDEX_LEN {
+            return Err(format!("Part {} holds {} bytes, too few for a part header", position, part.len()).into())
+        }
+
+        let (id, total) = (&part[..8], u16::from_be_bytes([part[8], part[9]]));
+        let checksum = u32::from_be_bytes([part[10], part[11], part[12], part[13]]);
+        let piece = &part[PART_HEADER_LEN..];
+
+        if PART_CRC.checksum(piece) != checksum {
+            return Err(format!("Part {} is damaged, its checksum does not match", position).into())
+        }
+
+        match header {
+            None => header = Some((id, total)),
+            Some((first_id, _)) if first_id != id => return Err("The parts come from different payloads".into()),
+            Some((_, first_total)) if first_total != total => {
+                return Err(format!("The parts disagree on their number: {} and {}", first_total, total).into())
+            },
+            Some(_) => {},
+        }
+
+        pieces.push(piece);
+    }
+
+    let (_, total) = header.ok_or("There are no parts to join")?;
+
+    // join checks that no piece is missing before the last one or repeated
+    pieces.sort_by_key(|piece| [piece[0], piece[1]]);
+    let payload = join(&pieces)?;
+
+    if pieces.len() < total as usize {
+        return Err(format!("Piece {} of the split message is missing", pieces.len()).into())
+    }
+
+    if pieces.len() > total as usize {
+        return Err(format!("There are {} parts of a payload split into {}", pieces.len(), total).into())
+    }
+
+    Ok(payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn joined(pieces: &[Vec<u8>]) -> Result<Vec<u8>> {
+        join(&pieces.iter().map(Vec::as_slice).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn test_split_and_join() {
+        let pieces = split(b"abcdefgh", 3).unwrap();
+
+        assert_eq!(pieces, [b"\0\0abc".to_vec(), b"\0\x01def".to_vec(), b"\0\x02gh".to_vec()]);
+        assert_eq!(joined(&pieces).unwrap(), b"abcdefgh");
+        assert_eq!(split(b"", 3).unwrap(), [vec![0, 0]]);
+        assert!(split(b"abc", 0).is_err());
+    }
+
+    #[test]
+    fn test_join_errors() {
+        let pieces = split(b"abcdefgh", 3).unwrap();
+
+        let missing = joined(&[pieces[0].clone(), pieces[2].clone()]).unwrap_err();
+        assert_eq!(missing.to_string(), "Piece 1 of the split message is missing");
+
+        let swapped = joined(&[pieces[0].clone(), pieces[2].clone(), pieces[1].clone()]).unwrap_err();
+        assert!(swapped.to_string().contains("out of order"));
+
+        let repeated = joined(&[pieces[0].clone(), pieces[0].clone()]).unwrap_err();
+        assert!(repeated.to_string().contains("more than once"));
+
+        assert!(joined(&[vec![0]]).unwrap_err().to_string().contains("too few"));
+    }
+
+    const ID: [u8; 8] = *b"testpart";
+
+    fn joined_parts(parts: &[Vec<u8>]) -> Result<Vec<u8>> {
+        join_parts(&parts.iter().map(Vec::as_slice).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn test_split_and_join_parts() {
+        let parts = split_parts(b"a payload to spread", 3, ID).unwrap();
+
+        assert_eq!(parts.len(), 3);
+        assert_eq!(&parts[0][..8], &parts[2][..8]);
+        assert_eq!(joined_parts(&[parts[2].clone(), parts[0].clone(), parts[1].clone()]).unwrap(), b"a payload to spread");
+
+        // More parts than bytes still makes every part, some of them empty
+        let tiny = split_parts(b"ab", 4, ID).unwrap();
+        assert_eq!(joined_parts(&tiny).unwrap(), b"ab");
+        assert!(split_parts(b"ab", 0, ID).is_err());
+    }
+
+    #[test]
+    fn test_join_parts_errors() {
+        let parts = split_parts(b"a payload to spread", 3, ID).unwrap();
+        let other = split_parts(b"another payload", 3, *b"otherone").unwrap();
+
+        let missing_last = joined_parts(&[parts[1].clone(), parts[0].clone()]).unwrap_err();
+        assert_eq!(missing_last.to_string(), "Piece 2 of the split message is missing");
+
+        let missing_first = joined_parts(&[parts[1].clone(), parts[2].clone()]).unwrap_err();
+        assert_eq!(missing_first.to_string(), "Piece 0 of the split message is missing");
+
+        let repeated = joined_parts(&[parts[0].clone(), parts[1].clone(), parts[1].clone()]).unwrap_err();
+        assert!(repeated.to_string().contains("more than once"));
+
+        let mixed = joined_parts(&[parts[0].clone(), other[1].clone(), parts[2].clone()]).unwrap_err();
+        assert_eq!(mixed.to_string(), "The parts come from different payloads");
+
+        let mut damaged = parts[1].clone();
+        *damaged.last_mut().unwrap() ^= 0xff;
+        let damaged = joined_parts(&[parts[0].clone(), damaged, parts[2].clone()]).unwrap_err();
+        assert!(damaged.to_string().contains("damaged"));
+    }
+
+    #[test]
+    fn test_largest_part() {
+        let fits = chunk::MAX_LENGTH as usize - PART_HEADER_LEN - INDEX_LEN;
+
+        assert_eq!(largest_part(19, 3), PART_HEADER_LEN + INDEX_LEN + 7);
+        assert_eq!(largest_part(fits, 1), chunk::MAX_LENGTH as usize);
+        assert!(largest_part(fits + 1, 1) > chunk::MAX_LENGTH as usize);
+        assert!(largest_part(2 * fits + 1, 2) > chunk::MAX_LENGTH as usize);
+    }
+}
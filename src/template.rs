@@ -0,0 +1,207 @@
+use std::{fs, path::Path};
+
+use crate::{timestamp, Result};
+
+/// A parsed piece of a message template
+#[derive(Debug, PartialEq)]
+pub enum Piece {
+    Literal(String),
+    /// `{date}`, the current time as an RFC 3339 timestamp
+    Date,
+    /// `{hostname}`, the name of the machine
+    Hostname,
+    /// `{env:NAME}`, an environment variable that has to be set
+    Env(String),
+    /// `{file}`, the path of the file being encoded
+    File,
+    /// `{basename}`, the file name of the file being encoded
+    Basename,
+}
+
+/// What the placeholders expand to
+pub struct Context<'a> {
+    pub date: String,
+    /// Empty when the name of the machine cannot be found
+    pub hostname: String,
+    pub file: &'a Path,
+    pub env: &'a dyn Fn(&str) -> Option<String>,
+}
+
+impl<'a> Context<'a> {
+    /// The context of encoding `file` on this machine, now
+    pub fn current(file: &'a Path) -> Context<'a> {
+
+        Context {
+            date: timestamp::now(),
+            hostname: hostname().unwrap_or_default(),
+            file,
+            env: &|name| std::env::var(name).ok(),
+        }
+    }
+}
+
+/// Parses a template, so that mistakes are found before any file is modified.
+/// `{{` and `}}` stand for literal braces.
+pub fn parse(template: &str) -> Result<Vec<Piece>> {
+
+    let mut pieces = vec![];
+    let mut literal = String::new();
+    let mut characters = template.chars().peekable();
+
+    while let Some(character) = characters.next() {
+        match character {
+            '{' if characters.peek() == Some(&'{') => {
+                characters.next();
+                literal.push('{');
+            },
+            '}' if characters.peek() == Some(&'}') => {
+                characters.next();
+                literal.push('}');
+            },
+            '}' => return Err("Unmatched '}' in the template, write '}}' for a literal brace".into()),
+            '{' => {
+                let mut name = String::new();
+                let mut closed = false;
+
+                for character in characters.by_ref() {
+                    if character == '}' {
+                        closed = true;
+                        break
+                    }
+
+                    name.push(character);
+                }
+
+                if !closed {
+                    return Err(format!("Unterminated placeholder '{{{}' in the template", name).into())
+                }
+
+                let piece = match name.as_str() {
+                    "date" => Piece::Date,
+                    "hostname" => Piece::Hostname,
+                    "file" => Piece::File,
+                    "basename" => Piece::Basename,
+                    _ => match name.strip_prefix("env:") {
+                        Some(variable) if !variable.is_empty() => Piece::Env(variable.to_string()),
+                        _ => return Err(format!(
+                            "Unknown placeholder '{{{}}}' in the template, expected date, hostname, env:NAME, file or basename", name
+                        ).into()),
+                    },
+                };
+
+                if !literal.is_empty() {
+                    pieces.push(Piece::Literal(std::mem::take(&mut literal)));
+                }
+
+                pieces.push(piece);
+            },
+            character => literal.push(character),
+        }
+    }
+
+    if !literal.is_empty() {
+        pieces.push(Piece::Literal(literal));
+    }
+
+    Ok(pieces)
+}
+
+/// Expands the placeholders of a parsed template
+pub fn render(pieces: &[Piece], context: &Context) -> Result<String> {
+
+    let mut message = String::new();
+
+    for piece in pieces {
+        match piece {
+            Piece::Literal(literal) => message.push_str(literal),
+            Piece::Date => message.push_str(&context.date),
+            Piece::Hostname => {
+                if context.hostname.is_empty() {
+                    return Err("Cannot find the name of this machine for {hostname}".into())
+                }
+
+                message.push_str(&context.hostname)
+            },
+            Piece::Env(name) => {
+                let value = (context.env)(name)
+                    .ok_or_else(|| format!("The template uses {{env:{}}} but the environment variable {} is not set", name, name))?;
+
+                message.push_str(&value);
+            },
+            Piece::File => message.push_str(&context.file.display().to_string()),
+            Piece::Basename => {
+                let basename = context.file.file_name().ok_or("The file has no name for {basename}")?;
+
+                message.push_str(&basename.to_string_lossy());
+            },
+        }
+    }
+
+    Ok(message)
+}
+
+fn hostname() -> Option<String> {
+
+    let from_env = ["HOSTNAME", "COMPUTERNAME"].iter().find_map(|name| std::env::var(name).ok());
+    let from_file = || ["/etc/hostname", "/proc/sys/kernel/hostname"].iter().find_map(|path| fs::read_to_string(path).ok());
+
+    from_env.or_else(from_file)
+        .map(|hostname| hostname.trim().to_string())
+        .filter(|hostname| !hostname.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context(file: &Path) -> Context<'_> {
+        Context {
+            date: "2024-05-01T09:30:12Z".to_string(),
+            hostname: "builder".to_string(),
+            file,
+            env: &|name| (name == "GIT_SHA").then(|| "abc123".to_string()),
+        }
+    }
+
+    fn expand(template: &str) -> Result<String> {
+        render(&parse(template)?, &context(Path::new("images/logo.png")))
+    }
+
+    #[test]
+    fn test_placeholders() {
+        assert_eq!(
+            expand("build {date} on {hostname} commit {env:GIT_SHA}").unwrap(),
+            "build 2024-05-01T09:30:12Z on builder commit abc123"
+        );
+        assert_eq!(expand("{file} is {basename}").unwrap(), "images/logo.png is logo.png");
+    }
+
+    #[test]
+    fn test_escaped_braces() {
+        assert_eq!(expand("{{date}} {{{date}}}").unwrap(), "{date} {2024-05-01T09:30:12Z}");
+        assert_eq!(parse("plain").unwrap(), vec![Piece::Literal("plain".to_string())]);
+    }
+
+    #[test]
+    fn test_invalid_templates() {
+        assert!(parse("{nope}").unwrap_err().to_string().contains("Unknown placeholder '{nope}'"));
+        assert!(parse("{env:}").is_err());
+        assert!(parse("unterminated {date").unwrap_err().to_string().contains("Unterminated"));
+        assert!(parse("stray }").is_err());
+    }
+
+    #[test]
+    fn test_unset_variable() {
+        let error = expand("{env:MISSING}").unwrap_err();
+
+        assert!(error.to_string().contains("MISSING is not set"));
+    }
+
+    #[test]
+    fn test_unknown_hostname() {
+        let context = Context { hostname: String::new(), ..context(Path::new("logo.png")) };
+
+        assert!(render(&parse("{hostname}").unwrap(), &context).is_err());
+        assert_eq!(render(&parse("{basename}").unwrap(), &context).unwrap(), "logo.png");
+    }
+}
@@ -0,0 +1,220 @@
+use crate::{chunk::Chunk, zlib, Result};
+
+/// Compressed text is never inflated past this many bytes
+pub const TEXT_DECOMPRESS_LIMIT: usize = 16 * 1024 * 1024;
+
+/// Builds the data of a tEXt chunk: a Latin-1 keyword of 1 to 79 characters,
+/// a null separator and the Latin-1 text
+pub fn encode_text(keyword: &str, text: &str) -> Result<Vec<u8>> {
+
+    if keyword.is_empty() || keyword.chars().count() > 79 {
+        return Err(format!("The keyword '{}' must be 1 to 79 characters long", keyword).into())
+    }
+
+    if keyword.contains('\0') || text.contains('\0') {
+        return Err("tEXt keywords and text cannot contain null characters".into())
+    }
+
+    let mut data = latin1(keyword)?;
+    data.push(0);
+    data.extend(latin1(text)?);
+
+    Ok(data)
+}
+
+/// Splits the data of a tEXt chunk into its keyword and text
+pub fn decode_text(data: &[u8]) -> Result<(String, String)> {
+
+    let separator = data.iter()
+        .position(|byte| *byte == 0)
+        .ok_or("The tEXt chunk has no null separator after its keyword")?;
+
+    let keyword = data[..separator].iter().map(|byte| *byte as char).collect();
+    let text = data[separator + 1..].iter().map(|byte| *byte as char).collect();
+
+    Ok((keyword, text))
+}
+
+/// Builds the data of a zTXt chunk: like tEXt, with the text zlib compressed
+/// after a compression method byte
+pub fn encode_ztxt(keyword: &str, text: &str) -> Result<Vec<u8>> {
+
+    let plain = encode_text(keyword, text)?;
+    let separator = keyword.chars().count();
+
+    let mut data = plain[..=separator].to_vec();
+    data.push(0);
+    data.extend(zlib::compress(&plain[separator + 1..]));
+
+    Ok(data)
+}
+
+/// Splits the data of a zTXt chunk into its keyword and decompressed text
+pub fn decode_ztxt(data: &[u8]) -> Result<(String, String)> {
+
+    let (keyword, rest) = split_keyword(data, "zTXt")?;
+    let (method, compressed) = rest.split_first().ok_or("The zTXt chunk has no compression method")?;
+
+    if *method != 0 {
+        return Err(format!("Unknown zTXt compression method {}", method).into())
+    }
+
+    let text = zlib::decompress(compressed, TEXT_DECOMPRESS_LIMIT)?;
+
+    Ok((keyword, text.iter().map(|byte| *byte as char).collect()))
+}
+
+/// Splits the data of an iTXt chunk into its keyword and UTF-8 text,
+/// decompressing the text when the chunk says it is compressed. The language
+/// tag and translated keyword are skipped.
+pub fn decode_itxt(data: &[u8]) -> Result<(String, String)> {
+
+    let (keyword, rest) = split_keyword(data, "iTXt")?;
+
+    let [flag, method, rest @ ..] = rest else {
+        return Err("The iTXt chunk is missing its compression flag and method".into())
+    };
+
+    let mut fields = rest.splitn(3, |byte| *byte == 0);
+    let (_language, _translated, text) = match (fields.next(), fields.next(), fields.next()) {
+        (Some(language), Some(translated), Some(text)) => (language, translated, text),
+        _ => return Err("The iTXt chunk is missing its language tag or translated keyword".into()),
+    };
+
+    let text = match (flag, method) {
+        (0, _) => text.to_vec(),
+        (1, 0) => zlib::decompress(text, TEXT_DECOMPRESS_LIMIT)?,
+        (1, method) => return Err(format!("Unknown iTXt compression method {}", method).into()),
+        (flag, _) => return Err(format!("Invalid iTXt compression flag {}", flag).into()),
+    };
+
+    let text = String::from_utf8(text).map_err(|_| "The iTXt text is not valid UTF-8")?;
+
+    Ok((keyword, text))
+}
+
+/// Decodes the keyword and text of a tEXt, zTXt or iTXt chunk. Other chunks
+/// have no text framing and give `None`.
+pub fn decode_framed(chunk: &Chunk) -> Result<Option<(String, String)>> {
+
+    let decoded = match chunk.chunk_type().to_string().as_str() {
+        "tEXt" => decode_text(chunk.data())?,
+        "zTXt" => decode_ztxt(chunk.data())?,
+        "iTXt" => decode_itxt(chunk.data())?,
+        _ => return Ok(None),
+    };
+
+    Ok(Some(decoded))
+}
+
+/// The keyword of a tEXt, zTXt or iTXt chunk, read without decompressing its text.
+/// Other chunks, and text chunks without a keyword separator, give `None`.
+pub fn keyword(chunk: &Chunk) -> Option<String> {
+
+    if !matches!(chunk.chunk_type().to_string().as_str(), "tEXt" | "zTXt" | "iTXt") {
+        return None
+    }
+
+    split_keyword(chunk.data(), "text").ok().map(|(keyword, _)| keyword)
+}
+
+fn split_keyword<'a>(data: &'a [u8], chunk_type: &str) -> Result<(String, &'a [u8])> {
+
+    let separator = data.iter()
+        .position(|byte| *byte == 0)
+        .ok_or_else(|| format!("The {} chunk has no null separator after its keyword", chunk_type))?;
+
+    let keyword = data[..separator].iter().map(|byte| *byte as char).collect();
+
+    Ok((keyword, &data[separator + 1..]))
+}
+
+fn latin1(value: &str) -> Result<Vec<u8>> {
+
+    value.chars()
+        .map(|character| {
+            u8::try_from(character as u32)
+                .map_err(|_| format!("'{}' cannot be stored in a tEXt chunk, which only holds Latin-1 text", character).into())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::chunk_type::ChunkType;
+
+    fn chunk(chunk_type: &str, data: Vec<u8>) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data)
+    }
+
+    #[test]
+    fn test_text_round_trip() {
+        let data = encode_text("Comment", "café").unwrap();
+        assert_eq!(data, b"Comment\0caf\xe9");
+        assert_eq!(decode_text(&data).unwrap(), ("Comment".to_string(), "café".to_string()));
+    }
+
+    #[test]
+    fn test_text_rejects_invalid_input() {
+        assert!(encode_text("", "text").is_err());
+        assert!(encode_text("Comment", "⚠").is_err());
+        assert!(decode_text(b"no separator").is_err());
+    }
+
+    #[test]
+    fn test_decode_framed_text() {
+        let chunk = chunk("tEXt", encode_text("Author", "Jane Doe").unwrap());
+
+        assert_eq!(decode_framed(&chunk).unwrap(), Some(("Author".to_string(), "Jane Doe".to_string())));
+    }
+
+    #[test]
+    fn test_decode_framed_ztxt() {
+        // b"Comment\0\0" + zlib.compress("Hidden in plain sight, café".encode("latin-1"))
+        let mut data = b"Comment\0\0".to_vec();
+        data.extend([
+            120, 156, 243, 200, 76, 73, 73, 205, 83, 200, 204, 83, 40, 200, 73, 4, 146, 197, 153, 233, 25, 37, 58, 10,
+            201, 137, 105, 47, 1, 135, 27, 10, 22,
+        ]);
+
+        let decoded = decode_framed(&chunk("zTXt", data)).unwrap();
+
+        assert_eq!(decoded, Some(("Comment".to_string(), "Hidden in plain sight, café".to_string())));
+    }
+
+    #[test]
+    fn test_ztxt_round_trip() {
+        let data = encode_ztxt("Comment", "café").unwrap();
+
+        assert_eq!(decode_ztxt(&data).unwrap(), ("Comment".to_string(), "café".to_string()));
+    }
+
+    #[test]
+    fn test_decode_framed_itxt() {
+        let mut data = b"Title\0\x01\0de\0Titel\0".to_vec();
+        data.extend([120, 156, 59, 60, 39, 41, 181, 40, 49, 39, 71, 225, 209, 156, 201, 0, 39, 17, 6, 3]);
+
+        let decoded = decode_framed(&chunk("iTXt", data)).unwrap();
+        assert_eq!(decoded, Some(("Title".to_string(), "Überall ✓".to_string())));
+
+        let plain = chunk("iTXt", b"Title\0\0\0\0\0plain".to_vec());
+        assert_eq!(decode_framed(&plain).unwrap(), Some(("Title".to_string(), "plain".to_string())));
+    }
+
+    #[test]
+    fn test_keyword() {
+        assert_eq!(keyword(&chunk("tEXt", b"Author\0Jane Doe".to_vec())), Some("Author".to_string()));
+        assert_eq!(keyword(&chunk("zTXt", b"Comment\0\0not zlib".to_vec())), Some("Comment".to_string()));
+        assert_eq!(keyword(&chunk("tEXt", b"no separator".to_vec())), None);
+        assert_eq!(keyword(&chunk("ruSt", b"Author\0Jane Doe".to_vec())), None);
+    }
+
+    #[test]
+    fn test_decode_framed_other_types() {
+        assert_eq!(decode_framed(&chunk("ruSt", b"message".to_vec())).unwrap(), None);
+        assert!(decode_framed(&chunk("zTXt", b"Comment\0\0not zlib".to_vec())).is_err());
+    }
+}
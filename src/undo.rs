@@ -0,0 +1,314 @@
+use std::str::FromStr;
+
+use crc::Crc;
+
+use crate::{chunk::Chunk, chunk_type::ChunkType, error::PngMeError, png::Png, Result};
+
+/// The chunk holding the undo history
+pub const UNDO_CHUNK_TYPE: &str = "pmUn";
+
+/// The version of the history format, stored as its first byte
+pub const UNDO_VERSION: u8 = 1;
+
+/// The history is never allowed to grow past this many bytes
+pub const UNDO_SIZE_LIMIT: usize = 1024 * 1024;
+
+const ADDED: u8 = 1;
+const REMOVED: u8 = 2;
+
+/// What an operation changed, enough to revert it. Indices count the chunks
+/// of the file without the history chunk itself.
+#[derive(Debug, PartialEq)]
+pub enum Record {
+    /// A chunk was added at `index`; its type and crc tell it apart from a chunk added later by other means
+    Added { index: u32, chunk_type: [u8; 4], crc: u32 },
+    /// The chunk whose bytes are kept here was removed from `index`
+    Removed { index: u32, chunk: Vec<u8> },
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct History {
+    records: Vec<Record>,
+}
+
+impl History {
+    /// Removes the history chunk from the file and parses it. A file without
+    /// history has an empty one.
+    pub fn take_from(png: &mut Png) -> Result<History> {
+
+        let removed = png.retain_chunks(|chunk| chunk.chunk_type().to_string() != UNDO_CHUNK_TYPE);
+
+        match removed.as_slice() {
+            [] => Ok(History::default()),
+            [chunk] => History::from_bytes(chunk.data()),
+            _ => Err(format!("The file has {} {} chunks, its undo history is ambiguous", removed.len(), UNDO_CHUNK_TYPE).into()),
+        }
+    }
+
+    /// Puts the history chunk back, right before IEND, unless there is nothing left to undo
+    pub fn store(&self, png: &mut Png, crc: &Crc<u32>) -> Result<()> {
+
+        if self.records.is_empty() {
+            return Ok(())
+        }
+
+        let bytes = self.as_bytes();
+
+        if bytes.len() > UNDO_SIZE_LIMIT {
+            return Err(format!(
+                "Recording this operation would grow the undo history to {} bytes, past the limit of {} bytes. Run it without --undoable or undo older operations first",
+                bytes.len(), UNDO_SIZE_LIMIT
+            ).into())
+        }
+
+        png.insert_before_iend(Chunk::new_with_crc(ChunkType::from_str(UNDO_CHUNK_TYPE)?, bytes, crc));
+
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+
+        self.records.is_empty()
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<History> {
+
+        let (version, mut rest) = bytes.split_first().ok_or("The undo history is empty")?;
+
+        if *version != UNDO_VERSION {
+            return Err(format!("Unsupported undo history version {}, this pngme reads version {}", version, UNDO_VERSION).into())
+        }
+
+        let mut records = vec![];
+
+        while let Some((tag, after_tag)) = rest.split_first() {
+            let index = read_u32(after_tag)?;
+
+            let record = match *tag {
+                ADDED => {
+                    let chunk_type: [u8; 4] = after_tag.get(4..8).ok_or("The undo history is truncated")?.try_into()?;
+                    let crc = read_u32(&after_tag[8..])?;
+                    rest = &after_tag[12..];
+
+                    Record::Added { index, chunk_type, crc }
+                },
+                REMOVED => {
+                    let length = read_u32(&after_tag[4..])? as usize;
+                    let chunk = after_tag.get(8..8 + length).ok_or("The undo history is truncated")?.to_vec();
+                    rest = &after_tag[8 + length..];
+
+                    Record::Removed { index, chunk }
+                },
+                tag => return Err(format!("Unknown record {} in the undo history", tag).into()),
+            };
+
+            records.push(record);
+        }
+
+        Ok(History { records })
+    }
+
+    fn as_bytes(&self) -> Vec<u8> {
+
+        let mut bytes = vec![UNDO_VERSION];
+
+        for record in &self.records {
+            match record {
+                Record::Added { index, chunk_type, crc } => {
+                    bytes.push(ADDED);
+                    bytes.extend_from_slice(&index.to_be_bytes());
+                    bytes.extend_from_slice(chunk_type);
+                    bytes.extend_from_slice(&crc.to_be_bytes());
+                },
+                Record::Removed { index, chunk } => {
+                    bytes.push(REMOVED);
+                    bytes.extend_from_slice(&index.to_be_bytes());
+                    bytes.extend_from_slice(&(chunk.len() as u32).to_be_bytes());
+                    bytes.extend_from_slice(chunk);
+                },
+            }
+        }
+
+        bytes
+    }
+
+    /// Reverts the most recent record and describes what was reverted
+    fn undo_last(&mut self, png: &mut Png, crc: &Crc<u32>) -> Result<String> {
+
+        let record = self.records.pop()
+            .ok_or_else(|| PngMeError::NotFound("Nothing to undo, the file has no undo history".to_string()))?;
+
+        match record {
+            Record::Added { index, chunk_type, crc: added_crc } => {
+                let matches = png.chunks()
+                    .get(index as usize)
+                    .is_some_and(|chunk| chunk.chunk_type().bytes() == chunk_type && chunk.crc() == added_crc);
+
+                if !matches {
+                    return Err(format!(
+                        "The {} chunk added at index {} is no longer there, the file was changed without --undoable",
+                        ChunkType::try_from(chunk_type)?, index
+                    ).into())
+                }
+
+                let removed = png.remove_chunk_at(index as usize)?;
+
+                Ok(format!("removed the {} chunk added at index {}", removed.chunk_type(), index))
+            },
+            Record::Removed { index, chunk } => {
+                let chunk = Chunk::try_from_with_crc(&chunk, crc)?;
+                let chunk_type = chunk.chunk_type();
+                let index = (index as usize).min(png.chunks().len());
+
+                png.insert_chunk(index, chunk)?;
+
+                Ok(format!("restored the {} chunk at index {}", chunk_type, index))
+            },
+        }
+    }
+}
+
+fn read_u32(bytes: &[u8]) -> Result<u32> {
+
+    let bytes: [u8; 4] = bytes.get(..4).ok_or("The undo history is truncated")?.try_into()?;
+
+    Ok(u32::from_be_bytes(bytes))
+}
+
+/// Adds the chunk right before IEND and records how to take it out again
+pub fn append_undoable(png: &mut Png, chunk: Chunk, crc: &Crc<u32>) -> Result<()> {
+
+    let mut history = History::take_from(png)?;
+
+    history.records.push(Record::Added {
+        index: png.position_of_type("IEND").unwrap_or(png.chunks().len()) as u32,
+        chunk_type: chunk.chunk_type().bytes(),
+        crc: chunk.crc(),
+    });
+    png.insert_before_iend(chunk);
+
+    history.store(png, crc)
+}
+
+/// Removes the first chunk of this type and records how to put it back
+pub fn remove_undoable(png: &mut Png, chunk_type: &str, crc: &Crc<u32>) -> Result<Chunk> {
+
+    let mut history = History::take_from(png)?;
+
+    let index = png.position_of_type(chunk_type)
+        .ok_or_else(|| PngMeError::NotFound(format!("No chunk of type '{}' in this file", chunk_type)))?;
+    let removed = png.remove_chunk_at(index)?;
+
+    history.records.push(Record::Removed { index: index as u32, chunk: removed.as_bytes() });
+    history.store(png, crc)?;
+
+    Ok(removed)
+}
+
+/// Reverts the most recent recorded operation, or all of them, most recent first
+pub fn undo(png: &mut Png, all: bool, crc: &Crc<u32>) -> Result<Vec<String>> {
+
+    let mut history = History::take_from(png)?;
+    let mut reverted = vec![history.undo_last(png, crc)?];
+
+    while all && !history.is_empty() {
+        reverted.push(history.undo_last(png, crc)?);
+    }
+
+    history.store(png, crc)?;
+
+    Ok(reverted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PNG_CRC: Crc<u32> = Crc::<u32>::new(&crc::CRC_32_ISO_HDLC);
+
+    fn chunk(chunk_type: &str, data: &str) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.as_bytes().to_vec())
+    }
+
+    fn testing_png() -> Png {
+        Png::from_chunks(vec![chunk("IHDR", "header"), chunk("teSt", "existing"), chunk("IEND", "")])
+    }
+
+    fn types(png: &Png) -> Vec<String> {
+        png.chunks().iter().map(|chunk| chunk.chunk_type().to_string()).collect()
+    }
+
+    #[test]
+    fn test_encode_then_undo() {
+        let mut png = testing_png();
+        append_undoable(&mut png, chunk("ruSt", "message"), &PNG_CRC).unwrap();
+        assert_eq!(types(&png), ["IHDR", "teSt", "ruSt", UNDO_CHUNK_TYPE, "IEND"]);
+
+        let reverted = undo(&mut png, false, &PNG_CRC).unwrap();
+
+        assert_eq!(reverted, ["removed the ruSt chunk added at index 2"]);
+        assert_eq!(png.as_bytes(), testing_png().as_bytes());
+    }
+
+    #[test]
+    fn test_remove_then_undo() {
+        let mut png = testing_png();
+        let removed = remove_undoable(&mut png, "teSt", &PNG_CRC).unwrap();
+        assert_eq!(removed.data(), b"existing");
+        assert_eq!(types(&png), ["IHDR", UNDO_CHUNK_TYPE, "IEND"]);
+
+        undo(&mut png, false, &PNG_CRC).unwrap();
+
+        assert_eq!(png.as_bytes(), testing_png().as_bytes());
+    }
+
+    #[test]
+    fn test_undo_without_history() {
+        let mut png = testing_png();
+        let error = undo(&mut png, false, &PNG_CRC).unwrap_err();
+
+        assert_eq!(crate::error::exit_code(&error), crate::error::EXIT_NOT_FOUND);
+        assert_eq!(png.as_bytes(), testing_png().as_bytes());
+    }
+
+    #[test]
+    fn test_undo_is_last_in_first_out() {
+        let mut png = testing_png();
+        append_undoable(&mut png, chunk("ruSt", "first"), &PNG_CRC).unwrap();
+        remove_undoable(&mut png, "teSt", &PNG_CRC).unwrap();
+        append_undoable(&mut png, chunk("ruSt", "second"), &PNG_CRC).unwrap();
+
+        undo(&mut png, false, &PNG_CRC).unwrap();
+        assert_eq!(types(&png), ["IHDR", "ruSt", UNDO_CHUNK_TYPE, "IEND"]);
+        let mut copy = Png::try_from(png.as_bytes().as_slice()).unwrap();
+        assert_eq!(History::take_from(&mut copy).unwrap().len(), 2);
+
+        let reverted = undo(&mut png, true, &PNG_CRC).unwrap();
+        assert_eq!(reverted.len(), 2);
+        assert_eq!(png.as_bytes(), testing_png().as_bytes());
+    }
+
+    #[test]
+    fn test_history_version_is_checked() {
+        let mut png = testing_png();
+        png.append_chunk(chunk(UNDO_CHUNK_TYPE, "\u{9}"));
+
+        let error = History::take_from(&mut png).unwrap_err();
+        assert!(error.to_string().contains("Unsupported undo history version 9"));
+    }
+
+    #[test]
+    fn test_history_size_is_capped() {
+        let mut png = testing_png();
+        let large = "x".repeat(UNDO_SIZE_LIMIT);
+        png.append_chunk(chunk("laRg", &large));
+
+        let error = remove_undoable(&mut png, "laRg", &PNG_CRC).unwrap_err();
+        assert!(error.to_string().contains("past the limit"));
+    }
+}
@@ -0,0 +1,348 @@
+use std::{ops::Range, thread};
+
+use crc::Crc;
+
+use crate::{chunk, chunk_type::ChunkType, csv, ihdr::ImageHeader, json, list, png::Png, zlib, Result};
+
+/// Where a chunk sits in the bytes of a file, found without copying its data
+#[derive(Debug, Clone, PartialEq)]
+pub struct Span {
+    pub offset: usize,
+    pub chunk_type: [u8; 4],
+    pub data: Range<usize>,
+    pub stored_crc: u32,
+}
+
+/// A chunk whose stored crc does not match its type and data
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mismatch {
+    pub offset: usize,
+    pub chunk_type: String,
+    pub stored: u32,
+    pub computed: u32,
+}
+
+/// The chunks `scan` found, and how many bytes follow IEND
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scan {
+    pub spans: Vec<Span>,
+    /// Data some tools append after IEND, such as a ZIP archive, which is not made of chunks
+    pub trailing: usize,
+}
+
+/// Finds every chunk of a PNG file without parsing or checking them, up to IEND
+pub fn scan(bytes: &[u8]) -> Result<Scan> {
+
+    if !bytes.starts_with(&Png::STANDARD_HEADER) {
+        return Err("Invalid header".into())
+    }
+
+    let mut spans = vec![];
+    let mut offset = Png::STANDARD_HEADER.len();
+
+    while offset < bytes.len() {
+        let frame = bytes.get(offset..offset + 8).ok_or("Unexpected end of file while reading a chunk")?;
+        let length = u32::from_be_bytes([frame[0], frame[1], frame[2], frame[3]]);
+        let end = chunk::framed_end(offset, length).ok_or("The chunk is too large for this platform")?;
+        let data = offset + 8..end - 4;
+
+        let crc = bytes.get(data.end..end).ok_or("Unexpected end of file while reading a chunk")?;
+
+        spans.push(Span {
+            offset,
+            chunk_type: [frame[4], frame[5], frame[6], frame[7]],
+            stored_crc: u32::from_be_bytes([crc[0], crc[1], crc[2], crc[3]]),
+            data: data.clone(),
+        });
+
+        offset = end;
+
+        if spans.last().is_some_and(|span| span.chunk_type == *b"IEND") {
+            break
+        }
+    }
+
+    Ok(Scan { spans, trailing: bytes.len() - offset })
+}
+
+/// Checks the crc of every span, spreading the spans over `threads` threads.
+/// The mismatches come back in file order whatever the number of threads.
+pub fn check(bytes: &[u8], spans: &[Span], crc: &Crc<u32>, threads: usize) -> Vec<Mismatch> {
+
+    let check_one = |span: &Span| {
+        let mut digest = crc.digest();
+        digest.update(&span.chunk_type);
+        digest.update(&bytes[span.data.clone()]);
+        let computed = digest.finalize();
+
+        (computed != span.stored_crc).then(|| Mismatch {
+            offset: span.offset,
+            chunk_type: String::from_utf8_lossy(&span.chunk_type).into_owned(),
+            stored: span.stored_crc,
+            computed,
+        })
+    };
+
+    parallel_map(spans, threads, check_one).into_iter().flatten().collect()
+}
+
+/// Every span as a CSV row with the columns of `list --format csv`, crc_ok telling
+/// whether it is among the mismatches
+pub fn to_csv(spans: &[Span], mismatches: &[Mismatch]) -> String {
+
+    let mut rows = vec![csv::row(&list::CSV_HEADER)];
+
+    for (index, span) in spans.iter().enumerate() {
+        // The bytes come straight from the file, so the flags are read off them
+        // rather than from a ChunkType that might not accept them
+        let bytes = span.chunk_type;
+        let standard = ChunkType::try_from(bytes).is_ok_and(|chunk_type| chunk_type.is_standard());
+
+        rows.push(csv::row(&[
+            index.to_string(),
+            span.offset.to_string(),
+            String::from_utf8_lossy(&span.chunk_type).into_owned(),
+            span.data.len().to_string(),
+            format!("{:08x}", span.stored_crc),
+            bytes[0].is_ascii_uppercase().to_string(),
+            bytes[1].is_ascii_uppercase().to_string(),
+            bytes[2].is_ascii_uppercase().to_string(),
+            bytes[3].is_ascii_lowercase().to_string(),
+            standard.to_string(),
+            is_intact(span, mismatches).to_string(),
+        ]));
+    }
+
+    rows.concat()
+}
+
+/// Every span as a JSON object with its index, offset, type, length, stored crc and crc_ok
+pub fn to_json(spans: &[Span], mismatches: &[Mismatch]) -> String {
+
+    let objects: Vec<String> = spans.iter()
+        .enumerate()
+        .map(|(index, span)| format!(
+            "{{\"index\": {}, \"offset\": {}, \"type\": {}, \"length\": {}, \"crc\": {}, \"crc_ok\": {}}}",
+            index, span.offset, json::string(&String::from_utf8_lossy(&span.chunk_type)), span.data.len(), span.stored_crc,
+            is_intact(span, mismatches)
+        ))
+        .collect();
+
+    json::array(&objects)
+}
+
+fn is_intact(span: &Span, mismatches: &[Mismatch]) -> bool {
+
+    !mismatches.iter().any(|mismatch| mismatch.offset == span.offset)
+}
+
+/// What decompressing the image data found
+#[derive(Debug, Clone, PartialEq)]
+pub enum ImageData {
+    /// Exactly the scanlines, filter bytes included, that the header declares
+    Complete { length: usize },
+    /// Adam7 images are decompressed, but the length of their passes is not checked
+    Interlaced { length: usize },
+}
+
+/// Concatenates the IDAT chunks and decompresses them, checking that the stream is
+/// sound and, for images that are not interlaced, that it holds as many bytes as
+/// the dimensions, bit depth and color type of IHDR call for
+pub fn check_image_data(png: &Png) -> Result<ImageData> {
+
+    let header = ImageHeader::from_png(png)?;
+
+    let stream: Vec<u8> = png.chunks().iter()
+        .filter(|chunk| chunk.chunk_type() == ChunkType::IDAT)
+        .flat_map(|chunk| chunk.data().iter().copied())
+        .collect();
+
+    if stream.is_empty() {
+        return Err("The file has no image data, there is no IDAT chunk".into())
+    }
+
+    let expected = header.data_length()?;
+    let interlaced = header.interlace_method != 0;
+
+    let data = zlib::decompress_tracked(&stream, expected).map_err(|error| format!("The image data is corrupt: {}", error))?;
+
+    if interlaced {
+        return Ok(ImageData::Interlaced { length: data.len() })
+    }
+
+    if data.len() != expected {
+        return Err(format!(
+            "The image data decompresses to {} bytes, but a {}x{} {} image with {}-bit samples needs {}",
+            data.len(), header.width, header.height, header.color_type_name(), header.bit_depth, expected
+        ).into())
+    }
+
+    Ok(ImageData::Complete { length: data.len() })
+}
+
+/// Maps every item on up to `threads` threads, each taking a contiguous run of
+/// the items, and returns the results in the order of the items
+pub fn parallel_map<T: Sync, R: Send>(items: &[T], threads: usize, map: impl Fn(&T) -> R + Sync) -> Vec<R> {
+
+    if threads <= 1 || items.len() <= 1 {
+        return items.iter().map(map).collect()
+    }
+
+    let per_thread = items.len().div_ceil(threads);
+    let map = &map;
+
+    thread::scope(|scope| {
+        let handles: Vec<_> = items.chunks(per_thread)
+            .map(|group| scope.spawn(move || group.iter().map(map).collect::<Vec<R>>()))
+            .collect();
+
+        handles.into_iter().flat_map(|handle| handle.join().unwrap()).collect()
+    })
+}
+
+/// The number of threads to use when none is given
+pub fn default_threads() -> usize {
+
+    thread::available_parallelism().map_or(1, |threads| threads.get())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::{chunk::Chunk, chunk_type::ChunkType};
+
+    const PNG_CRC: Crc<u32> = Crc::<u32>::new(&crc::CRC_32_ISO_HDLC);
+
+    fn testing_bytes() -> Vec<u8> {
+        let chunks = (0..20)
+            .map(|index| Chunk::new(ChunkType::from_str("IDAT").unwrap(), vec![index; 100 * index as usize]))
+            .collect();
+
+        Png::from_chunks(chunks).as_bytes()
+    }
+
+    #[test]
+    fn test_scan() {
+        let bytes = testing_bytes();
+        let Scan { spans, trailing } = scan(&bytes).unwrap();
+
+        assert_eq!(spans.len(), 20);
+        assert_eq!(trailing, 0);
+        assert_eq!(spans[0], Span { offset: 8, chunk_type: *b"IDAT", data: 16..16, stored_crc: spans[0].stored_crc });
+        assert_eq!(spans[1].offset, 20);
+        assert_eq!(spans[1].data, 28..128);
+        assert!(scan(&bytes[..bytes.len() - 1]).is_err());
+        assert!(scan(b"GIF89a").is_err());
+    }
+
+    #[test]
+    fn test_scan_stops_at_iend() {
+        let mut bytes = image(2, 2, 0, &[0; 14], 5).as_bytes();
+        bytes.extend_from_slice(b"PK\x03\x04 a zip archive");
+
+        let scanned = scan(&bytes).unwrap();
+
+        assert_eq!(scanned.spans.len(), 4);
+        assert_eq!(&scanned.spans[3].chunk_type, b"IEND");
+        assert_eq!(scanned.trailing, 18);
+    }
+
+    fn image(width: u32, height: u32, interlace: u8, pixels: &[u8], split_at: usize) -> Png {
+        let mut ihdr = [width.to_be_bytes(), height.to_be_bytes()].concat();
+        ihdr.extend_from_slice(&[8, 2, 0, 0, interlace]);
+
+        let stream = zlib::compress(pixels);
+        let (first, second) = stream.split_at(split_at.min(stream.len()));
+
+        Png::from_chunks(vec![
+            Chunk::new(ChunkType::from_str("IHDR").unwrap(), ihdr),
+            Chunk::new(ChunkType::from_str("IDAT").unwrap(), first.to_vec()),
+            Chunk::new(ChunkType::from_str("IDAT").unwrap(), second.to_vec()),
+            Chunk::new(ChunkType::from_str("IEND").unwrap(), vec![]),
+        ])
+    }
+
+    #[test]
+    fn test_check_image_data() {
+        // Two RGB rows of two pixels, each row behind its filter byte
+        let scanlines = [0, 1, 2, 3, 4, 5, 6, 0, 7, 8, 9, 10, 11, 12];
+
+        assert_eq!(check_image_data(&image(2, 2, 0, &scanlines, 5)).unwrap(), ImageData::Complete { length: 14 });
+        assert_eq!(check_image_data(&image(2, 2, 1, &scanlines, 5)).unwrap(), ImageData::Interlaced { length: 14 });
+
+        let short = check_image_data(&image(2, 2, 0, &scanlines[..13], 5)).unwrap_err();
+        assert_eq!(short.to_string(), "The image data decompresses to 13 bytes, but a 2x2 RGB image with 8-bit samples needs 14");
+
+        let long = check_image_data(&image(2, 1, 0, &scanlines, 5)).unwrap_err();
+        assert!(long.to_string().contains("larger than the limit of 7 bytes"));
+
+        let mut corrupt = image(2, 2, 0, &scanlines, 5);
+        corrupt.chunks_mut()[2].set_data(vec![0xff; 4]);
+        assert!(check_image_data(&corrupt).unwrap_err().to_string().starts_with("The image data is corrupt"));
+
+        // Dimensions whose data length cannot be counted are refused instead of wrapping around
+        let huge = check_image_data(&image(u32::MAX, u32::MAX, 0, &scanlines, 5)).unwrap_err();
+        assert!(huge.to_string().contains("too large to handle"));
+        assert!(check_image_data(&image(u32::MAX, u32::MAX, 1, &scanlines, 5)).is_err());
+    }
+
+    #[test]
+    fn test_csv_and_json_reports() {
+        let mut bytes = testing_bytes();
+        let spans = scan(&bytes).unwrap().spans;
+        bytes[spans[2].data.start] ^= 0xff;
+        let mismatches = check(&bytes, &spans, &PNG_CRC, 1);
+
+        let rows = csv::parse(&to_csv(&spans, &mismatches)).unwrap();
+        assert_eq!(rows.len(), spans.len() + 1);
+        assert_eq!(rows[0], list::CSV_HEADER);
+        assert_eq!(rows[1][..4], ["0", "8", "IDAT", "0"]);
+        assert_eq!(rows[3][10], "false");
+        assert_eq!(rows.iter().skip(1).filter(|row| row[10] == "true").count(), spans.len() - 1);
+        assert_eq!(rows[1][5..10], ["true", "true", "true", "false", "true"]);
+
+        // Types that are not letters still get a row instead of a panic
+        let odd = Span { offset: 8, chunk_type: [0xff, b'1', b'a', b'b'], data: 16..16, stored_crc: 0 };
+        let rows = csv::parse(&to_csv(&[odd], &[])).unwrap();
+        assert_eq!(rows[1][5..10], ["false", "false", "false", "true", "false"]);
+
+        let parsed = json::parse(&to_json(&spans, &mismatches)).unwrap();
+        let objects = parsed.as_array().unwrap();
+        assert_eq!(objects.len(), spans.len());
+        assert_eq!(objects[2].get("crc_ok"), Some(&json::Value::Bool(false)));
+        assert_eq!(objects[1].get("offset").and_then(json::Value::as_u64), Some(20));
+    }
+
+    #[test]
+    fn test_parallel_map_keeps_the_order() {
+        let items: Vec<usize> = (0..100).collect();
+
+        for threads in [1, 3, 7, 200] {
+            assert_eq!(parallel_map(&items, threads, |item| item * 2), (0..100).map(|item| item * 2).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn test_check_is_the_same_with_any_number_of_threads() {
+        let mut bytes = testing_bytes();
+        let spans = scan(&bytes).unwrap().spans;
+
+        assert!(check(&bytes, &spans, &PNG_CRC, 4).is_empty());
+
+        // Flip a byte of data in two chunks
+        bytes[spans[5].data.start] ^= 0xff;
+        bytes[spans[17].data.end - 1] ^= 0xff;
+
+        let sequential = check(&bytes, &spans, &PNG_CRC, 1);
+
+        assert_eq!(sequential.len(), 2);
+        assert_eq!(sequential[0].offset, spans[5].offset);
+        assert_eq!(sequential[1].offset, spans[17].offset);
+
+        for threads in [2, 3, 8, 64] {
+            assert_eq!(check(&bytes, &spans, &PNG_CRC, threads), sequential);
+        }
+    }
+}
@@ -0,0 +1,220 @@
+use std::path::Path;
+
+use crc::{Crc, CRC_32_ISO_HDLC};
+
+use crate::{zlib, Result};
+
+/// Extensions of the containers that are ZIP archives underneath
+pub const ARCHIVE_EXTENSIONS: [&str; 11] = ["zip", "apk", "aar", "jar", "docx", "xlsx", "pptx", "odt", "ods", "odp", "epub"];
+
+/// No entry is decompressed past this many bytes
+pub const ENTRY_LIMIT: usize = 256 * 1024 * 1024;
+
+const END_OF_CENTRAL_DIRECTORY: u32 = 0x0605_4b50;
+const CENTRAL_DIRECTORY_HEADER: u32 = 0x0201_4b50;
+const LOCAL_FILE_HEADER: u32 = 0x0403_4b50;
+
+/// ZIP archives use the same crc as PNG chunks
+const ZIP_CRC: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+
+/// A file stored in an archive, as listed by its central directory
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub name: String,
+    pub method: u16,
+    pub encrypted: bool,
+    pub crc: u32,
+    pub compressed_size: usize,
+    pub size: usize,
+    local_header_offset: usize,
+}
+
+impl Entry {
+    pub fn is_directory(&self) -> bool {
+
+        self.name.ends_with('/')
+    }
+
+    /// Decompresses the entry out of the bytes of its archive, checking its crc
+    pub fn read(&self, archive: &[u8], limit: usize) -> Result<Vec<u8>> {
+
+        if self.encrypted {
+            return Err("The entry is encrypted".into())
+        }
+
+        if self.size > limit {
+            return Err(format!("The entry is {} bytes, larger than the limit of {} bytes", self.size, limit).into())
+        }
+
+        let header = archive.get(self.local_header_offset..self.local_header_offset + 30).ok_or("The archive is truncated")?;
+
+        if u32_at(header, 0) != LOCAL_FILE_HEADER {
+            return Err("The local header of the entry is missing".into())
+        }
+
+        let start = self.local_header_offset + 30 + u16_at(header, 26) as usize + u16_at(header, 28) as usize;
+        let compressed = archive.get(start..start + self.compressed_size).ok_or("The archive is truncated")?;
+
+        let data = match self.method {
+            0 => compressed.to_vec(),
+            8 => zlib::inflate(compressed, limit)?.0,
+            method => return Err(format!("Unsupported compression method {}", method).into()),
+        };
+
+        if ZIP_CRC.checksum(&data) != self.crc {
+            return Err("The crc of the entry does not match its data".into())
+        }
+
+        Ok(data)
+    }
+}
+
+/// Whether the path has the extension of a ZIP based container
+pub fn is_archive(path: &Path) -> bool {
+
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| ARCHIVE_EXTENSIONS.iter().any(|known| known.eq_ignore_ascii_case(extension)))
+}
+
+/// Lists the entries of a ZIP archive from its central directory. ZIP64
+/// archives, over 4 GB or 65535 entries, are not supported.
+pub fn entries(archive: &[u8]) -> Result<Vec<Entry>> {
+
+    // The end of central directory record takes 22 bytes, followed by a comment of up to 65535 bytes
+    let search_start = archive.len().saturating_sub(22 + 65535);
+    let end = (search_start..=archive.len().saturating_sub(22))
+        .rev()
+        .find(|offset| u32_at(archive, *offset) == END_OF_CENTRAL_DIRECTORY)
+        .ok_or("Not a ZIP archive, the end of central directory record is missing")?;
+
+    let count = u16_at(archive, end + 10) as usize;
+    let mut offset = u32_at(archive, end + 16) as usize;
+
+    if count == 0xffff || offset == 0xffff_ffff {
+        return Err("ZIP64 archives are not supported".into())
+    }
+
+    let mut entries = Vec::with_capacity(count);
+
+    for _ in 0..count {
+        let header = archive.get(offset..offset + 46).ok_or("The central directory is truncated")?;
+
+        if u32_at(header, 0) != CENTRAL_DIRECTORY_HEADER {
+            return Err("Corrupt central directory".into())
+        }
+
+        let name_length = u16_at(header, 28) as usize;
+        let name = archive.get(offset + 46..offset + 46 + name_length).ok_or("The central directory is truncated")?;
+
+        entries.push(Entry {
+            name: String::from_utf8_lossy(name).into_owned(),
+            method: u16_at(header, 10),
+            encrypted: u16_at(header, 8) & 1 == 1,
+            crc: u32_at(header, 16),
+            compressed_size: u32_at(header, 20) as usize,
+            size: u32_at(header, 24) as usize,
+            local_header_offset: u32_at(header, 42) as usize,
+        });
+
+        offset += 46 + name_length + u16_at(header, 30) as usize + u16_at(header, 32) as usize;
+    }
+
+    Ok(entries)
+}
+
+/// Reads a little endian number, or 0 past the end of the bytes
+fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+
+    bytes.get(offset..offset + 2).map_or(0, |bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+
+    bytes.get(offset..offset + 4).map_or(0, |bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Builds an archive of (name, stored data, deflate or not, encrypted or not) entries
+    pub(crate) fn build_zip(files: &[(&str, &[u8], bool, bool)]) -> Vec<u8> {
+        let mut archive = vec![];
+        let mut directory = vec![];
+
+        for (name, data, deflate, encrypted) in files {
+            // A zlib stream is a 2 byte header, the raw deflate data and a 4 byte checksum
+            let compressed = if *deflate { zlib::compress(data)[2..].to_vec() } else { data.to_vec() };
+            let compressed = if *deflate { compressed[..compressed.len() - 4].to_vec() } else { compressed };
+
+            let method: u16 = if *deflate { 8 } else { 0 };
+            let flags = u16::from(*encrypted);
+            let crc = ZIP_CRC.checksum(data);
+            let offset = archive.len() as u32;
+
+            let mut fields = vec![];
+            fields.extend(flags.to_le_bytes());
+            fields.extend(method.to_le_bytes());
+            fields.extend([0; 4]);
+            fields.extend(crc.to_le_bytes());
+            fields.extend((compressed.len() as u32).to_le_bytes());
+            fields.extend((data.len() as u32).to_le_bytes());
+            fields.extend((name.len() as u16).to_le_bytes());
+            fields.extend([0; 2]);
+
+            archive.extend(LOCAL_FILE_HEADER.to_le_bytes());
+            archive.extend([20, 0]);
+            archive.extend(&fields);
+            archive.extend(name.as_bytes());
+            archive.extend(&compressed);
+
+            directory.extend(CENTRAL_DIRECTORY_HEADER.to_le_bytes());
+            directory.extend([20, 0, 20, 0]);
+            directory.extend(&fields);
+            directory.extend([0; 10]);
+            directory.extend(offset.to_le_bytes());
+            directory.extend(name.as_bytes());
+        }
+
+        let directory_offset = archive.len() as u32;
+        archive.extend(&directory);
+
+        archive.extend(END_OF_CENTRAL_DIRECTORY.to_le_bytes());
+        archive.extend([0; 4]);
+        archive.extend((files.len() as u16).to_le_bytes());
+        archive.extend((files.len() as u16).to_le_bytes());
+        archive.extend((directory.len() as u32).to_le_bytes());
+        archive.extend(directory_offset.to_le_bytes());
+        archive.extend([0; 2]);
+
+        archive
+    }
+
+    #[test]
+    fn test_read_entries() {
+        let archive = build_zip(&[
+            ("stored.txt", b"stored as is", false, false),
+            ("res/deflated.txt", &b"deflated ".repeat(100), true, false),
+            ("secret.txt", b"encrypted", false, true),
+        ]);
+
+        let entries = entries(&archive).unwrap();
+        let names: Vec<&str> = entries.iter().map(|entry| entry.name.as_str()).collect();
+
+        assert_eq!(names, ["stored.txt", "res/deflated.txt", "secret.txt"]);
+        assert_eq!(entries[0].read(&archive, ENTRY_LIMIT).unwrap(), b"stored as is");
+        assert_eq!(entries[1].read(&archive, ENTRY_LIMIT).unwrap(), b"deflated ".repeat(100));
+        assert!(entries[2].encrypted);
+        assert!(entries[2].read(&archive, ENTRY_LIMIT).is_err());
+        assert!(entries[1].read(&archive, 100).unwrap_err().to_string().contains("limit"));
+    }
+
+    #[test]
+    fn test_not_an_archive() {
+        assert!(entries(b"").is_err());
+        assert!(entries(b"plainly not a zip archive").is_err());
+        assert!(is_archive(Path::new("app.APK")));
+        assert!(!is_archive(Path::new("image.png")));
+    }
+}
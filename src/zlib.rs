@@ -0,0 +1,554 @@
+use crate::{metrics::{self, Stage}, Result};
+
+/// How far back the compressor looks for repeated data
+const WINDOW_SIZE: usize = 32 * 1024;
+
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+
+/// Decompresses a zlib stream (RFC 1950), refusing to produce more than `limit` bytes
+pub fn decompress(data: &[u8], limit: usize) -> Result<Vec<u8>> {
+
+    decompress_tracked(data, limit).map_err(|error| error.message.into())
+}
+
+/// Why a zlib stream cannot be decompressed, and the byte of the stream where that shows
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamError {
+    pub offset: usize,
+    pub message: String,
+}
+
+impl std::fmt::Display for StreamError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+
+        write!(f, "{} (at byte {} of the stream)", self.message, self.offset)
+    }
+}
+
+impl std::error::Error for StreamError {}
+
+/// Decompresses a zlib stream like `decompress`, telling where a damaged stream goes wrong
+pub fn decompress_tracked(data: &[u8], limit: usize) -> std::result::Result<Vec<u8>, StreamError> {
+
+    metrics::time(Stage::Transform, || unzlib(data, limit))
+}
+
+fn unzlib(data: &[u8], limit: usize) -> std::result::Result<Vec<u8>, StreamError> {
+
+    let error = |offset: usize, message: &str| StreamError { offset, message: message.to_string() };
+
+    if data.len() < 6 {
+        return Err(error(data.len(), "The zlib stream is truncated"))
+    }
+
+    let (cmf, flg) = (data[0], data[1]);
+
+    if cmf & 0x0f != 8 {
+        return Err(error(0, &format!("Unsupported zlib compression method {}", cmf & 0x0f)))
+    }
+
+    if (u16::from(cmf) << 8 | u16::from(flg)) % 31 != 0 {
+        return Err(error(1, "Invalid zlib header checksum"))
+    }
+
+    if flg & 0x20 != 0 {
+        return Err(error(1, "zlib streams with a preset dictionary are not supported"))
+    }
+
+    let (output, consumed) = inflate_tracked(&data[2..], limit)
+        .map_err(|(message, position)| error(2 + position, &message.to_string()))?;
+
+    let checksum = data.get(2 + consumed..2 + consumed + 4)
+        .ok_or_else(|| error(2 + consumed, "The zlib stream is missing its adler32 checksum"))?;
+
+    if u32::from_be_bytes([checksum[0], checksum[1], checksum[2], checksum[3]]) != adler32(&output) {
+        return Err(error(2 + consumed, "The zlib adler32 checksum does not match the data"))
+    }
+
+    Ok(output)
+}
+
+/// Compresses the data into a zlib stream, as a single deflate block using the
+/// fixed Huffman codes and greedy LZ77 matching. It trades some compression
+/// ratio for a compressor small enough to keep in this crate.
+pub fn compress(data: &[u8]) -> Vec<u8> {
+
+    metrics::time(Stage::Transform, || deflate_fixed(data))
+}
+
+fn deflate_fixed(data: &[u8]) -> Vec<u8> {
+
+    let mut writer = BitWriter::default();
+
+    // Deflate with a 32 KiB window, no dictionary, default compression level
+    writer.bytes.extend_from_slice(&[0x78, 0x9c]);
+
+    // The last block, compressed with the fixed codes
+    writer.bits(1, 1);
+    writer.bits(1, 2);
+
+    // The most recent position of every hashed 3 byte sequence, plus one
+    let mut head = vec![0usize; 1 << 15];
+    let hash = |position: usize| {
+        let value = u32::from(data[position]) << 16 | u32::from(data[position + 1]) << 8 | u32::from(data[position + 2]);
+
+        (value.wrapping_mul(2654435761) >> 17) as usize
+    };
+
+    let mut position = 0;
+
+    while position < data.len() {
+        let mut length = 0;
+        let mut distance = 0;
+
+        if position + MIN_MATCH <= data.len() {
+            let slot = hash(position);
+            let candidate = head[slot];
+            head[slot] = position + 1;
+
+            if candidate != 0 && position - (candidate - 1) <= WINDOW_SIZE {
+                let candidate = candidate - 1;
+                let longest = (data.len() - position).min(MAX_MATCH);
+
+                length = (0..longest).take_while(|offset| data[candidate + offset] == data[position + offset]).count();
+                distance = position - candidate;
+            }
+        }
+
+        if length >= MIN_MATCH {
+            write_length(&mut writer, length);
+            write_distance(&mut writer, distance);
+
+            for skipped in position + 1..(position + length).min(data.len().saturating_sub(MIN_MATCH - 1)) {
+                head[hash(skipped)] = skipped + 1;
+            }
+
+            position += length;
+        } else {
+            write_literal(&mut writer, u16::from(data[position]));
+            position += 1;
+        }
+    }
+
+    write_literal(&mut writer, 256);
+
+    let mut output = writer.finish();
+    output.extend_from_slice(&adler32(data).to_be_bytes());
+
+    output
+}
+
+/// Writes bits least significant first, as deflate stores them
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    current: u32,
+    count: u32,
+}
+
+impl BitWriter {
+    fn bits(&mut self, value: u32, count: u32) {
+
+        self.current |= value << self.count;
+        self.count += count;
+
+        while self.count >= 8 {
+            self.bytes.push(self.current as u8);
+            self.current >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    /// Huffman codes are stored most significant bit first
+    fn code(&mut self, code: u32, length: u32) {
+
+        self.bits(code.reverse_bits() >> (32 - length), length);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+
+        if self.count > 0 {
+            self.bytes.push(self.current as u8);
+        }
+
+        self.bytes
+    }
+}
+
+fn write_literal(writer: &mut BitWriter, symbol: u16) {
+
+    let symbol = u32::from(symbol);
+
+    match symbol {
+        0..=143 => writer.code(0x30 + symbol, 8),
+        144..=255 => writer.code(0x190 + symbol - 144, 9),
+        256..=279 => writer.code(symbol - 256, 7),
+        _ => writer.code(0xc0 + symbol - 280, 8),
+    }
+}
+
+fn write_length(writer: &mut BitWriter, length: usize) {
+
+    let index = LENGTH_BASE.iter().rposition(|base| *base as usize <= length).unwrap_or(0);
+
+    write_literal(writer, 257 + index as u16);
+    writer.bits((length - LENGTH_BASE[index] as usize) as u32, u32::from(LENGTH_EXTRA[index]));
+}
+
+fn write_distance(writer: &mut BitWriter, distance: usize) {
+
+    let index = DISTANCE_BASE.iter().rposition(|base| *base as usize <= distance).unwrap_or(0);
+
+    writer.code(index as u32, 5);
+    writer.bits((distance - DISTANCE_BASE[index] as usize) as u32, u32::from(DISTANCE_EXTRA[index]));
+}
+
+pub fn adler32(data: &[u8]) -> u32 {
+
+    const MODULO: u32 = 65521;
+
+    let (mut a, mut b) = (1u32, 0u32);
+
+    // 5552 is the most bytes that can be summed before b could overflow
+    for chunk in data.chunks(5552) {
+        for byte in chunk {
+            a += u32::from(*byte);
+            b += a;
+        }
+
+        a %= MODULO;
+        b %= MODULO;
+    }
+
+    b << 16 | a
+}
+
+/// Reads bits least significant first, as deflate stores them
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    bit: u32,
+}
+
+impl BitReader<'_> {
+    fn bits(&mut self, count: u32) -> Result<u32> {
+
+        let mut value = 0;
+
+        for shift in 0..count {
+            let byte = self.data.get(self.position).ok_or("The deflate stream is truncated")?;
+            value |= u32::from((byte >> self.bit) & 1) << shift;
+
+            self.bit += 1;
+
+            if self.bit == 8 {
+                self.bit = 0;
+                self.position += 1;
+            }
+        }
+
+        Ok(value)
+    }
+
+    fn align_to_byte(&mut self) {
+
+        if self.bit != 0 {
+            self.bit = 0;
+            self.position += 1;
+        }
+    }
+}
+
+/// A canonical Huffman code, stored as the number of codes of each length
+/// and the symbols ordered by code
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Huffman {
+
+        let mut counts = [0u16; 16];
+
+        for length in lengths {
+            counts[*length as usize] += 1;
+        }
+
+        counts[0] = 0;
+
+        let mut offsets = [0u16; 16];
+
+        for length in 1..16 {
+            offsets[length] = offsets[length - 1] + counts[length - 1];
+        }
+
+        let mut symbols = vec![0; lengths.len()];
+
+        for (symbol, length) in lengths.iter().enumerate() {
+            if *length != 0 {
+                symbols[offsets[*length as usize] as usize] = symbol as u16;
+                offsets[*length as usize] += 1;
+            }
+        }
+
+        Huffman { counts, symbols }
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u16> {
+
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+
+        for length in 1..16 {
+            code |= reader.bits(1)? as i32;
+
+            let count = i32::from(self.counts[length]);
+
+            if code - count < first {
+                return Ok(self.symbols[(index + code - first) as usize])
+            }
+
+            index += count;
+            first += count;
+            first <<= 1;
+            code <<= 1;
+        }
+
+        Err("Invalid Huffman code in the deflate stream".into())
+    }
+}
+
+const LENGTH_BASE: [u16; 29] = [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258];
+const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DISTANCE_BASE: [u16; 30] = [1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577];
+const DISTANCE_EXTRA: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+
+/// The order in which code length code lengths are stored in a dynamic block
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+/// Decompresses a raw deflate stream (RFC 1951), returning the data and the number of bytes read
+pub fn inflate(data: &[u8], limit: usize) -> Result<(Vec<u8>, usize)> {
+
+    inflate_tracked(data, limit).map_err(|(error, _)| error)
+}
+
+/// Inflates the stream, or fails with the position the reader had reached
+fn inflate_tracked(data: &[u8], limit: usize) -> std::result::Result<(Vec<u8>, usize), (crate::Error, usize)> {
+
+    let mut reader = BitReader { data, position: 0, bit: 0 };
+    let mut output = vec![];
+
+    match inflate_blocks(&mut reader, &mut output, limit) {
+        Ok(()) => Ok((output, reader.position)),
+        Err(error) => Err((error, reader.position)),
+    }
+}
+
+fn inflate_blocks(reader: &mut BitReader, output: &mut Vec<u8>, limit: usize) -> Result<()> {
+
+    loop {
+        let last = reader.bits(1)? == 1;
+
+        match reader.bits(2)? {
+            0 => {
+                reader.align_to_byte();
+
+                let header = reader.data.get(reader.position..reader.position + 4).ok_or("The deflate stream is truncated")?;
+                let length = u16::from_le_bytes([header[0], header[1]]);
+
+                if length != !u16::from_le_bytes([header[2], header[3]]) {
+                    return Err("Corrupt stored block length in the deflate stream".into())
+                }
+
+                let start = reader.position + 4;
+                let block = reader.data.get(start..start + length as usize).ok_or("The deflate stream is truncated")?;
+
+                if output.len() + block.len() > limit {
+                    return Err(format!("The decompressed data is larger than the limit of {} bytes", limit).into())
+                }
+
+                output.extend_from_slice(block);
+                reader.position = start + length as usize;
+            },
+            1 => {
+                let mut lengths = [0u8; 288];
+                lengths[..144].fill(8);
+                lengths[144..256].fill(9);
+                lengths[256..280].fill(7);
+                lengths[280..].fill(8);
+
+                let literals = Huffman::new(&lengths);
+                let distances = Huffman::new(&[5; 30]);
+
+                inflate_block(reader, output, &literals, &distances, limit)?;
+            },
+            2 => {
+                let (literals, distances) = dynamic_codes(reader)?;
+
+                inflate_block(reader, output, &literals, &distances, limit)?;
+            },
+            _ => return Err("Invalid block type in the deflate stream".into()),
+        }
+
+        if last {
+            break
+        }
+    }
+
+    reader.align_to_byte();
+
+    Ok(())
+}
+
+fn dynamic_codes(reader: &mut BitReader) -> Result<(Huffman, Huffman)> {
+
+    let literal_count = reader.bits(5)? as usize + 257;
+    let distance_count = reader.bits(5)? as usize + 1;
+    let code_length_count = reader.bits(4)? as usize + 4;
+
+    let mut code_lengths = [0u8; 19];
+
+    for index in CODE_LENGTH_ORDER.iter().take(code_length_count) {
+        code_lengths[*index] = reader.bits(3)? as u8;
+    }
+
+    let code_lengths = Huffman::new(&code_lengths);
+    let mut lengths = vec![];
+
+    while lengths.len() < literal_count + distance_count {
+        let symbol = code_lengths.decode(reader)?;
+
+        let (length, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => (*lengths.last().ok_or("A repeat code has no previous length to repeat")?, 3 + reader.bits(2)?),
+            17 => (0, 3 + reader.bits(3)?),
+            _ => (0, 11 + reader.bits(7)?),
+        };
+
+        lengths.extend(std::iter::repeat_n(length, repeat as usize));
+    }
+
+    if lengths.len() != literal_count + distance_count {
+        return Err("The code lengths overflow in the deflate stream".into())
+    }
+
+    Ok((Huffman::new(&lengths[..literal_count]), Huffman::new(&lengths[literal_count..])))
+}
+
+fn inflate_block(reader: &mut BitReader, output: &mut Vec<u8>, literals: &Huffman, distances: &Huffman, limit: usize) -> Result<()> {
+
+    loop {
+        let symbol = literals.decode(reader)? as usize;
+
+        match symbol {
+            0..=255 => {
+                if output.len() >= limit {
+                    return Err(format!("The decompressed data is larger than the limit of {} bytes", limit).into())
+                }
+
+                output.push(symbol as u8)
+            },
+            256 => return Ok(()),
+            257..=285 => {
+                let index = symbol - 257;
+                let length = LENGTH_BASE[index] as usize + reader.bits(u32::from(LENGTH_EXTRA[index]))? as usize;
+
+                let index = distances.decode(reader)? as usize;
+
+                if index >= DISTANCE_BASE.len() {
+                    return Err("Invalid distance code in the deflate stream".into())
+                }
+
+                let distance = DISTANCE_BASE[index] as usize + reader.bits(u32::from(DISTANCE_EXTRA[index]))? as usize;
+
+                if distance > output.len() {
+                    return Err("The deflate stream refers to data before its start".into())
+                }
+
+                if output.len() + length > limit {
+                    return Err(format!("The decompressed data is larger than the limit of {} bytes", limit).into())
+                }
+
+                let start = output.len() - distance;
+
+                for offset in 0..length {
+                    output.push(output[start + offset]);
+                }
+            },
+            _ => return Err("Invalid literal or length code in the deflate stream".into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_adler32() {
+        assert_eq!(adler32(b""), 1);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e60398);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let text = b"It was the best of times, it was the worst of times".repeat(100);
+        let noise: Vec<u8> = (0..100_000u32).map(|value| (value.wrapping_mul(2654435761) >> 13) as u8).collect();
+
+        for data in [vec![], b"hello".to_vec(), vec![7; 200_000], text.clone(), noise] {
+            assert_eq!(decompress(&compress(&data), usize::MAX).unwrap(), data);
+        }
+
+        assert!(compress(&text).len() < text.len() / 10);
+    }
+
+    #[test]
+    fn test_fixed_huffman() {
+        // zlib.compress(b"hello hello hello hello")
+        let data = [120, 156, 203, 72, 205, 201, 201, 87, 200, 64, 39, 1, 104, 3, 8, 177];
+        assert_eq!(decompress(&data, 100).unwrap(), b"hello hello hello hello");
+        assert!(decompress(&data, 10).is_err());
+    }
+
+    #[test]
+    fn test_dynamic_huffman() {
+        let expected = "It was the best of times, it was the worst of times, it was the age of wisdom, \
+            it was the age of foolishness, it was the epoch of belief.";
+        let data = [
+            120, 218, 117, 203, 209, 9, 128, 48, 12, 69, 209, 85, 50, 128, 184, 135, 99, 180, 250, 98, 2, 173, 17, 19,
+            200, 250, 210, 47, 17, 244, 251, 220, 187, 4, 101, 113, 10, 1, 85, 120, 144, 49, 133, 118, 248, 68, 250, 72,
+            218, 245, 71, 101, 199, 128, 84, 223, 172, 127, 9, 155, 53, 117, 57, 224, 239, 17, 167, 173, 50, 130, 138,
+            166, 224, 249, 6, 220, 74, 47, 214,
+        ];
+
+        assert_eq!(decompress(&data, usize::MAX).unwrap(), expected.as_bytes());
+    }
+
+    #[test]
+    fn test_corrupt_stream() {
+        let mut data = compress(b"hello");
+        let last = data.len() - 1;
+        data[last] ^= 1;
+
+        assert!(decompress(&data, 100).is_err());
+        assert!(decompress(&data[..4], 100).is_err());
+    }
+
+    #[test]
+    fn test_decompress_tracked_offsets() {
+        let data = compress(b"hello hello");
+
+        let mut bad_checksum = data.clone();
+        let last = bad_checksum.len() - 1;
+        bad_checksum[last] ^= 1;
+        assert_eq!(decompress_tracked(&bad_checksum, 100).unwrap_err().offset, data.len() - 4);
+
+        let error = decompress_tracked(&data[..data.len() - 6], 100).unwrap_err();
+        assert_eq!(error.message, "The deflate stream is truncated");
+        assert_eq!(error.offset, data.len() - 6);
+
+        let error = decompress_tracked(&[0x78, 0x9c, 0xff, 0xff, 0, 0, 0, 0], 100).unwrap_err();
+        assert_eq!(error.to_string(), "Invalid block type in the deflate stream (at byte 2 of the stream)");
+    }
+}